@@ -21,7 +21,8 @@
 ///    patterns are _not sparse_ in the input.
 use crate::arena::{self, Arena};
 use crate::builtins::Function;
-use crate::common::{Either, FileSpec, Stage};
+use crate::common::{Either, FileSpec, InputSpec, Stage};
+use crate::runtime::{BEGINFILE_EVENT, ENDFILE_EVENT};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Unop {
@@ -66,6 +67,8 @@ pub struct Prog<'a, 'b, I> {
     pub begin: arena::Vec<'a, &'a Stmt<'a, 'b, I>>,
     pub prepare: arena::Vec<'a, &'a Stmt<'a, 'b, I>>,
     pub end: arena::Vec<'a, &'a Stmt<'a, 'b, I>>,
+    pub beginfile: arena::Vec<'a, &'a Stmt<'a, 'b, I>>,
+    pub endfile: arena::Vec<'a, &'a Stmt<'a, 'b, I>>,
     pub pats: arena::Vec<'a, (Pattern<'a, 'b, I>, Option<&'a Stmt<'a, 'b, I>>)>,
     pub stage: Stage<()>,
     pub argv: Vec<&'b str>,
@@ -131,6 +134,8 @@ impl<'a, 'b, I: From<&'b str> + Clone> Prog<'a, 'b, I> {
             begin: arena.new_vec(),
             prepare: arena.new_vec(),
             end: arena.new_vec(),
+            beginfile: arena.new_vec(),
+            endfile: arena.new_vec(),
             pats: arena.new_vec(),
             argv: Vec::new(),
             parse_header: false,
@@ -172,11 +177,6 @@ impl<'a, 'b, I: From<&'b str> + Clone> Prog<'a, 'b, I> {
             )))));
         }
 
-        // Assign SUBSEP, which we treat as a normal variable
-        begin.push(arena.alloc(Expr(arena.alloc(Assign(
-            arena.alloc(Var("SUBSEP".into())),
-            arena.alloc(StrLit(&[0o034u8])),
-        )))));
         // Desugar -v flags
         for (ident, exp) in self.prelude_vardecs.iter() {
             begin.push(arena.alloc(Expr(
@@ -282,13 +282,46 @@ impl<'a, 'b, I: From<&'b str> + Clone> Prog<'a, 'b, I> {
             }
         }
 
-        if self.end.len() > 0 || self.prepare.len() > 0 || inner.len() > init_len {
-            // Wrap the whole thing in a while((getline) > 0) { } statement.
-            let main_portion = arena.alloc(While(
-                /*is_toplevel=*/ true,
-                arena.alloc(Binop(GT, arena.alloc(ReadStdin), arena.alloc(ILit(0)))),
-                arena.alloc(Block(inner)),
-            ));
+        let file_events = !self.beginfile.is_empty() || !self.endfile.is_empty();
+        if self.end.len() > 0 || self.prepare.len() > 0 || inner.len() > init_len || file_events {
+            let main_portion = if file_events {
+                // With BEGINFILE or ENDFILE blocks, reads from the main loop also report the
+                // transitions between files (see RegexCache::get_line_stdin_event). We desugar
+                // to:
+                //   while ((EVENT = stdin-event()) > 0) {
+                //     if (EVENT == BEGINFILE_EVENT) { BEGINFILE blocks }
+                //     else if (EVENT == ENDFILE_EVENT) { ENDFILE blocks }
+                //     else { NR++; FNR++; patterns }
+                //   }
+                // Pick an illegal frawk identifier.
+                const EVENT_VAR: &'static str = "--event";
+                let event = arena.alloc(Var(EVENT_VAR.into()));
+                let is_event = |code: i64| arena.alloc(Binop(EQ, event, arena.alloc(ILit(code))));
+                let read = arena.alloc(Assign(
+                    event,
+                    arena.alloc(Call(Either::Right(Function::ReadLineStdinEvent), &[])),
+                ));
+                arena.alloc(While(
+                    /*is_toplevel=*/ true,
+                    arena.alloc(Binop(GT, read, arena.alloc(ILit(0)))),
+                    arena.alloc(If(
+                        is_event(BEGINFILE_EVENT),
+                        arena.alloc(Block(self.beginfile.clone())),
+                        Some(arena.alloc(If(
+                            is_event(ENDFILE_EVENT),
+                            arena.alloc(Block(self.endfile.clone())),
+                            Some(arena.alloc(Block(inner))),
+                        ))),
+                    )),
+                ))
+            } else {
+                // Wrap the whole thing in a while((getline) > 0) { } statement.
+                arena.alloc(While(
+                    /*is_toplevel=*/ true,
+                    arena.alloc(Binop(GT, arena.alloc(ReadStdin), arena.alloc(ILit(0)))),
+                    arena.alloc(Block(inner)),
+                ))
+            };
             main_loop = Some(if self.prepare.len() > 0 {
                 let mut block = arena.vec_with_capacity(self.prepare.len() + 1);
                 block.push(main_portion);
@@ -362,6 +395,8 @@ pub enum Expr<'a, 'b, I> {
     Unop(Unop, &'a Expr<'a, 'b, I>),
     Binop(Binop, &'a Expr<'a, 'b, I>, &'a Expr<'a, 'b, I>),
     Call(Either<I, Function>, &'a [&'a Expr<'a, 'b, I>]),
+    // `@f(args)`: call the function named by the value of `f`.
+    IndirectCall(&'a Expr<'a, 'b, I>, &'a [&'a Expr<'a, 'b, I>]),
     Var(I),
     Index(&'a Expr<'a, 'b, I>, &'a Expr<'a, 'b, I>),
     Assign(
@@ -384,7 +419,7 @@ pub enum Expr<'a, 'b, I> {
     Getline {
         into: Option<&'a Expr<'a, 'b, I>>,
         from: Option<&'a Expr<'a, 'b, I>>,
-        is_file: bool,
+        spec: InputSpec,
     },
     ReadStdin,
     // Used for comma patterns
@@ -402,6 +437,12 @@ pub enum Stmt<'a, 'b, I> {
         &'a [&'a Expr<'a, 'b, I>],
         Option<(&'a Expr<'a, 'b, I>, FileSpec)>,
     ),
+    // Like print, but the arguments are written out as-is: without OFS, ORS, or any escaping from
+    // the output format.
+    PrintBytes(
+        &'a [&'a Expr<'a, 'b, I>],
+        Option<(&'a Expr<'a, 'b, I>, FileSpec)>,
+    ),
     // Unlike print, printf must have at least one argument.
     Printf(
         &'a Expr<'a, 'b, I>,
@@ -428,6 +469,12 @@ pub enum Stmt<'a, 'b, I> {
         &'a Stmt<'a, 'b, I>,
     ),
     ForEach(I, &'a Expr<'a, 'b, I>, &'a Stmt<'a, 'b, I>),
+    // Each case is a constant or regex label (None for `default`) and the statements that follow
+    // it. Control falls through from one case into the next unless it breaks.
+    Switch(
+        &'a Expr<'a, 'b, I>,
+        &'a [(Option<&'a Expr<'a, 'b, I>>, &'a Stmt<'a, 'b, I>)],
+    ),
     Break,
     Continue,
     Next,
@@ -4,11 +4,17 @@ use crate::ast;
 use crate::common::Either;
 use crate::common::{NodeIx, Result};
 use crate::compile;
-use crate::runtime::{Int, IntMap, Str, StrMap};
+use crate::runtime::{
+    printf::{format_float, DEFAULT_NUM_FMT},
+    sort::SortOrder,
+    splitter::fixed::FieldWidths,
+    FieldSplit, Float, Int, IntMap, Str, StrMap,
+};
 use crate::types::{self, SmallVec};
 use smallvec::smallvec;
 
 use std::convert::TryFrom;
+use std::sync::Arc;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Function {
@@ -23,18 +29,26 @@ pub enum Function {
     ReadErrStdin,
     NextlineStdin,
     NextlineCmd,
+    ReadErrCoproc,
+    NextlineCoproc,
     ReadLineStdinFused,
+    // Like ReadLineStdinFused, but also reports the start and end of each input file. Used to
+    // implement BEGINFILE and ENDFILE.
+    ReadLineStdinEvent,
     NextFile,
     Setcol,
     Split,
+    PatSplit,
     Length,
     Contains,
     Delete,
     Clear,
     Match,
+    MatchGroups,
     SubstrIndex,
     Sub,
     GSub,
+    GenSub,
     EscapeCSV,
     EscapeTSV,
     JoinCols,
@@ -47,6 +61,7 @@ pub enum Function {
     Srand,
     ReseedRng,
     System,
+    FFlush,
     // For header-parsing logic
     UpdateUsedFields,
     SetFI,
@@ -54,6 +69,16 @@ pub enum Function {
     ToLower,
     IncMap,
     Exit,
+    Asort,
+    Asorti,
+    Systime,
+    Strftime,
+    Mktime,
+    Strptime,
+    // Dispatch for `switch` statements: see RegexCache::match_cases.
+    MatchCases,
+    // Raises a runtime error for an indirect call (`@f(...)`) whose callee names no function.
+    UndefinedFunc,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -199,10 +224,12 @@ static_map!(
     FUNCTIONS<&'static str, Function>,
     ["close", Function::Close],
     ["split", Function::Split],
+    ["patsplit", Function::PatSplit],
     ["length", Function::Length],
     ["match", Function::Match],
     ["sub", Function::Sub],
     ["gsub", Function::GSub],
+    ["gensub", Function::GenSub],
     ["substr", Function::Substr],
     ["int", Function::ToInt],
     ["hex", Function::HexToInt],
@@ -233,7 +260,14 @@ static_map!(
     ["toupper", Function::ToUpper],
     ["tolower", Function::ToLower],
     ["system", Function::System],
-    ["exit", Function::Exit]
+    ["fflush", Function::FFlush],
+    ["exit", Function::Exit],
+    ["asort", Function::Asort],
+    ["asorti", Function::Asorti],
+    ["systime", Function::Systime],
+    ["strftime", Function::Strftime],
+    ["mktime", Function::Mktime],
+    ["strptime", Function::Strptime]
 );
 
 impl<'a> TryFrom<&'a str> for Function {
@@ -273,6 +307,18 @@ impl Function {
                 );
                 ctx.nw.add_dep(arg1, args[1], Constraint::Flows(()));
             }
+            Function::PatSplit => {
+                for arr in [args[1], args[3]] {
+                    let arr_ty = ctx.constant(
+                        Map {
+                            key: BaseTy::Int,
+                            val: BaseTy::Str,
+                        }
+                        .abs(),
+                    );
+                    ctx.nw.add_dep(arr_ty, arr, Constraint::Flows(()));
+                }
+            }
             Function::Clear => {
                 let is_map = ctx.constant(Some(Map {
                     key: None,
@@ -298,6 +344,42 @@ impl Function {
                 ctx.nw.add_dep(v, arr, Constraint::ValIn(()));
                 ctx.nw.add_dep(arr, v, Constraint::Val(()));
             }
+            Function::MatchGroups => {
+                // The capture group array holds the start and length of each group under
+                // SUBSEP-joined keys alongside the groups themselves, so it must have string keys.
+                let arr = ctx.constant(Some(Map {
+                    key: Some(BaseTy::Str),
+                    val: Some(BaseTy::Str),
+                }));
+                ctx.nw.add_dep(arr, args[2], Constraint::Flows(()));
+            }
+            Function::Asort | Function::Asorti => {
+                // asort(src, dst, how) stores the values of src into dst; asorti stores the keys.
+                // Either way, dst is indexed from 1, so it gets integer keys unless it is also
+                // used with string keys (as when sorting `src` in place). We constrain the
+                // relevant component of `src` and the values of `dst` to have the same type.
+                let (src, dst) = (args[0], args[1]);
+                let is_map = ctx.constant(Some(Map {
+                    key: None,
+                    val: None,
+                }));
+                ctx.nw.add_dep(is_map, src, Constraint::Flows(()));
+                let int_keys = ctx.constant(Some(Map {
+                    key: Some(BaseTy::Int),
+                    val: None,
+                }));
+                ctx.nw.add_dep(int_keys, dst, Constraint::Flows(()));
+                let elt = ctx.fresh_var();
+                if let Function::Asort = self {
+                    ctx.nw.add_dep(src, elt, Constraint::Val(()));
+                    ctx.nw.add_dep(elt, src, Constraint::ValIn(()));
+                } else {
+                    ctx.nw.add_dep(src, elt, Constraint::Key(()));
+                    ctx.nw.add_dep(elt, src, Constraint::KeyIn(()));
+                }
+                ctx.nw.add_dep(dst, elt, Constraint::Val(()));
+                ctx.nw.add_dep(elt, dst, Constraint::ValIn(()));
+            }
             Function::Sub | Function::GSub => {
                 let out_str = args[2];
                 let str_const = ctx.constant(Scalar(BaseTy::Str).abs());
@@ -342,7 +424,7 @@ impl Function {
             },
             Unop(Column) => (smallvec![Int], Str),
             Binop(Concat) => (smallvec![Str; 2], Str),
-            SubstrIndex | Binop(IsMatch) => (smallvec![Str; 2], Int),
+            SubstrIndex | MatchCases | Binop(IsMatch) => (smallvec![Str; 2], Int),
             // Not doesn't unconditionally convert to integers before negating it. Nonempty strings
             // are considered "truthy". Floating point numbers are converted beforehand:
             //    !5 == !1 == 0
@@ -367,21 +449,19 @@ impl Function {
                 arith_sig(incoming[0], incoming[1])
             }
             Binop(Pow) | Binop(Div) => (smallvec![Float;2], Float),
-            Contains => match incoming[0] {
-                MapIntInt | MapIntStr | MapIntFloat => (smallvec![incoming[0], Int], Int),
-                MapStrInt | MapStrStr | MapStrFloat => (smallvec![incoming[0], Str], Int),
-                _ => return err!("invalid input spec fo Contains: {:?}", &incoming[..]),
+            Contains => match incoming[0].key() {
+                Ok(key) => (smallvec![incoming[0], key], Int),
+                Err(_) => return err!("invalid input spec fo Contains: {:?}", &incoming[..]),
             },
-            Delete => match incoming[0] {
-                MapIntInt | MapIntStr | MapIntFloat => (smallvec![incoming[0], Int], Int),
-                MapStrInt | MapStrStr | MapStrFloat => (smallvec![incoming[0], Str], Int),
-                _ => return err!("invalid input spec fo Delete: {:?}", &incoming[..]),
+            Delete => match incoming[0].key() {
+                Ok(key) => (smallvec![incoming[0], key], Int),
+                Err(_) => return err!("invalid input spec fo Delete: {:?}", &incoming[..]),
             },
             IncMap => {
                 let map = incoming[0];
-                if !map.is_array() {
+                if !map.is_array() || map.is_nested_map() {
                     return err!(
-                        "first argument to inc_map must be an array type, got: {:?}",
+                        "first argument to inc_map must be an array of scalars, got: {:?}",
                         map
                     );
                 }
@@ -403,9 +483,13 @@ impl Function {
                 }
             }
             Srand => (smallvec![Int], Int),
-            System | HexToInt => (smallvec![Str], Int),
+            System | FFlush | HexToInt => (smallvec![Str], Int),
             ReseedRng => (smallvec![], Int),
             Rand => (smallvec![], Float),
+            Systime => (smallvec![], Int),
+            Strftime => (smallvec![Str, Int, Int], Str),
+            Mktime => (smallvec![Str, Int], Int),
+            Strptime => (smallvec![Str, Str, Int], Float),
             ToInt => {
                 let inc = incoming[0];
                 match inc {
@@ -418,20 +502,57 @@ impl Function {
                     }
                 }
             }
-            NextlineCmd | Nextline => (smallvec![Str], Str),
-            ReadErrCmd | ReadErr => (smallvec![Str], Int),
+            NextlineCoproc | NextlineCmd | Nextline => (smallvec![Str], Str),
+            ReadErrCoproc | ReadErrCmd | ReadErr => (smallvec![Str], Int),
             UpdateUsedFields | NextFile | ReadLineStdinFused => (smallvec![], Int),
             NextlineStdin => (smallvec![], Str),
-            ReadErrStdin => (smallvec![], Int),
+            ReadErrStdin | ReadLineStdinEvent => (smallvec![], Int),
             // irrelevant return type
             Setcol => (smallvec![Int, Str], Int),
             Length => (smallvec![incoming[0]], Int),
-            Close => (smallvec![Str], Str),
+            Close => (smallvec![Str, Str], Int),
             Sub | GSub => (smallvec![Str, Str, Str], Int),
+            GenSub => (smallvec![Str, Str, Str, Str], Str),
             ToUpper | ToLower | EscapeCSV | EscapeTSV => (smallvec![Str], Str),
             Substr => (smallvec![Str, Int, Int], Str),
             Match => (smallvec![Str, Str], Int),
+            Asort | Asorti => {
+                let src = incoming[0];
+                if !src.is_array() {
+                    return err!(
+                        "first argument to {} must be an array, got: {:?}",
+                        self,
+                        src
+                    );
+                }
+                let dst = src.sorted_ty(*self == Asorti)?;
+                // The destination usually has integer keys, but a string-keyed destination (e.g.
+                // `src` itself, in the one-argument form) receives the indices as strings.
+                let into = incoming[1];
+                if into != dst
+                    && (into.key().ok() != Some(Str) || into.val().ok() != dst.val().ok())
+                {
+                    return err!(
+                        "destination array for {} must have type {:?}, got: {:?}",
+                        self,
+                        dst,
+                        into
+                    );
+                }
+                (smallvec![src, into, Str], Int)
+            }
+            MatchGroups => {
+                if let MapStrStr = incoming[2] {
+                    (smallvec![Str, Str, MapStrStr], Int)
+                } else {
+                    return err!(
+                        "array argument to match must have string keys and values, got: {:?}",
+                        incoming[2]
+                    );
+                }
+            }
             Exit => (smallvec![Int], Null),
+            UndefinedFunc => (smallvec![Str], Null),
             // Split's second input can be a map of either type
             Split => {
                 if let MapIntStr | MapStrStr = incoming[1] {
@@ -440,6 +561,7 @@ impl Function {
                     return err!("invalid input spec for split: {:?}", &incoming[..]);
                 }
             }
+            PatSplit => (smallvec![Str, MapIntStr, Str, MapIntStr], Int),
             JoinCols => (smallvec![Int, Int, Str], Str),
             JoinCSV | JoinTSV => (smallvec![Int, Int], Str),
             SetFI => (smallvec![Int, Int], Int),
@@ -452,13 +574,15 @@ impl Function {
             FloatFunc(ff) => ff.arity(),
             IntFunc(bw) => bw.arity(),
             UpdateUsedFields | Rand | ReseedRng | ReadErrStdin | NextlineStdin | NextFile
-            | ReadLineStdinFused => 0,
-            Exit | ToUpper | ToLower | Clear | Srand | System | HexToInt | ToInt | EscapeCSV
-            | EscapeTSV | Close | Length | ReadErr | ReadErrCmd | Nextline | NextlineCmd
-            | Unop(_) => 1,
-            SetFI | SubstrIndex | Match | Setcol | Binop(_) => 2,
+            | ReadLineStdinFused | ReadLineStdinEvent | Systime => 0,
+            Exit | UndefinedFunc | ToUpper | ToLower | Clear | Srand | System | FFlush
+            | HexToInt | ToInt | EscapeCSV | EscapeTSV | Length | ReadErr | ReadErrCmd
+            | ReadErrCoproc | Nextline | NextlineCmd | NextlineCoproc | Unop(_) => 1,
+            Close | SetFI | SubstrIndex | MatchCases | Match | Setcol | Mktime | Binop(_) => 2,
             JoinCSV | JoinTSV | Delete | Contains => 2,
-            IncMap | JoinCols | Substr | Sub | GSub | Split => 3,
+            IncMap | JoinCols | Substr | Sub | GSub | Split | MatchGroups | Asort | Asorti
+            | Strftime | Strptime => 3,
+            GenSub | PatSplit => 4,
         })
     }
 
@@ -489,18 +613,22 @@ impl Function {
             Binop(Plus) | Binop(Minus) | Binop(Mod) | Binop(Mult) => {
                 Ok(step_arith(&args[0], &args[1]))
             }
-            Rand | Binop(Div) | Binop(Pow) => Ok(Scalar(BaseTy::Float).abs()),
+            Rand | Strptime | Binop(Div) | Binop(Pow) => Ok(Scalar(BaseTy::Float).abs()),
             Setcol => Ok(Scalar(BaseTy::Null).abs()),
-            Clear | SubstrIndex | Srand | ReseedRng | Unop(Not) | Binop(IsMatch) | Binop(LT)
-            | Binop(GT) | Binop(LTE) | Binop(GTE) | Binop(EQ) | Length | Split | ReadErr
-            | ReadErrCmd | ReadErrStdin | Contains | Delete | Match | Sub | GSub | ToInt
-            | System | HexToInt => Ok(Scalar(BaseTy::Int).abs()),
-            ToUpper | ToLower | JoinCSV | JoinTSV | JoinCols | EscapeCSV | EscapeTSV | Substr
-            | Unop(Column) | Binop(Concat) | Nextline | NextlineCmd | NextlineStdin => {
-                Ok(Scalar(BaseTy::Str).abs())
+            Clear | SubstrIndex | MatchCases | Srand | ReseedRng | Unop(Not) | Binop(IsMatch)
+            | Binop(LT) | Binop(GT) | Binop(LTE) | Binop(GTE) | Binop(EQ) | Length | Split
+            | PatSplit | ReadErr | ReadErrCmd | ReadErrCoproc | ReadErrStdin
+            | ReadLineStdinEvent | Contains | Delete | Match | MatchGroups | Sub | GSub | Asort
+            | Asorti | ToInt | System | FFlush | Close | HexToInt | Systime | Mktime => {
+                Ok(Scalar(BaseTy::Int).abs())
             }
+            ToUpper | ToLower | JoinCSV | JoinTSV | JoinCols | EscapeCSV | EscapeTSV | Substr
+            | GenSub | Strftime | Unop(Column) | Binop(Concat) | Nextline | NextlineCmd
+            | NextlineCoproc | NextlineStdin => Ok(Scalar(BaseTy::Str).abs()),
             IncMap => Ok(step_arith(&types::val_of(&args[0])?, &args[2])),
-            Exit | SetFI | UpdateUsedFields | NextFile | ReadLineStdinFused | Close => Ok(None),
+            Exit | UndefinedFunc | SetFI | UpdateUsedFields | NextFile | ReadLineStdinFused => {
+                Ok(None)
+            }
         }
     }
 }
@@ -521,20 +649,35 @@ pub(crate) enum Variable {
     FNR = 11,
     PID = 12,
     FI = 13,
+    PROCINFO = 14,
+    ENVIRON = 15,
+    CONVFMT = 16,
+    OFMT = 17,
+    RT = 18,
+    FPAT = 19,
+    FIELDWIDTHS = 20,
+    IGNORECASE = 21,
+    SUBSEP = 22,
 }
 
 impl From<Variable> for compile::Ty {
     fn from(v: Variable) -> compile::Ty {
         use Variable::*;
         match v {
-            FS | OFS | ORS | RS | FILENAME => compile::Ty::Str,
-            PID | ARGC | NF | NR | FNR | RSTART | RLENGTH => compile::Ty::Int,
+            FS | OFS | ORS | RS | FILENAME | CONVFMT | OFMT | RT | FPAT | FIELDWIDTHS | SUBSEP => {
+                compile::Ty::Str
+            }
+            PID | ARGC | NF | NR | FNR | RSTART | RLENGTH | IGNORECASE => compile::Ty::Int,
             ARGV => compile::Ty::MapIntStr,
             FI => compile::Ty::MapStrInt,
+            PROCINFO | ENVIRON => compile::Ty::MapStrStr,
         }
     }
 }
 
+/// The default value of `FPAT`: fields are runs of non-whitespace characters.
+const DEFAULT_FPAT: &str = "[^[:space:]]+";
+
 pub(crate) struct Variables<'a> {
     pub argc: Int,
     pub argv: IntMap<Str<'a>>,
@@ -542,6 +685,9 @@ pub(crate) struct Variables<'a> {
     pub ofs: Str<'a>,
     pub ors: Str<'a>,
     pub rs: Str<'a>,
+    // The separator that splits fields when FS is in effect. This is FS, except in "paragraph
+    // mode", where it also matches newlines.
+    pub field_sep: Str<'a>,
     pub nf: Int,
     pub nr: Int,
     pub fnr: Int,
@@ -550,6 +696,38 @@ pub(crate) struct Variables<'a> {
     pub rlength: Int,
     pub pid: Int,
     pub fi: StrMap<'a, Int>,
+    pub procinfo: StrMap<'a, Str<'a>>,
+    // The order in which `for (k in m)` loops visit keys, parsed from `PROCINFO["sorted_in"]`
+    // whenever PROCINFO is stored. `None` means that the iteration order is unspecified. Invalid
+    // orders are reported by the loops that use them.
+    pub sorted_in: Result<Option<SortOrder>>,
+    // Populated from the process environment on first use.
+    pub environ: Option<StrMap<'a, Str<'a>>>,
+    // CONVFMT and OFMT. These are `None` until they are assigned, which lets number-to-string
+    // conversions use the (faster) default formatting routine.
+    pub convfmt: Option<Str<'a>>,
+    pub ofmt: Option<Str<'a>>,
+    // The record terminator. This is refreshed from the input when it is loaded.
+    pub rt: Str<'a>,
+    pub fpat: Str<'a>,
+    pub fieldwidths: Str<'a>,
+    // Whichever of FS, FPAT and FIELDWIDTHS was assigned last determines how records are split.
+    pub field_mode: FieldMode,
+    // Set if the input is validated as UTF-8, in which case FIELDWIDTHS counts characters rather
+    // than bytes.
+    pub utf8: bool,
+    // Regexes are matched case-insensitively when this is nonzero. The interpreter keeps the
+    // `RegexCache` in sync with it.
+    pub ignorecase: Int,
+    // The separator joining the subscripts of multidimensional array keys.
+    pub subsep: Str<'a>,
+}
+
+#[derive(Clone)]
+pub(crate) enum FieldMode {
+    Sep,
+    Pat,
+    Widths(Arc<FieldWidths>),
 }
 
 impl<'a> Default for Variables<'a> {
@@ -561,6 +739,7 @@ impl<'a> Default for Variables<'a> {
             ofs: " ".into(),
             ors: "\n".into(),
             rs: "\n".into(),
+            field_sep: " ".into(),
             nr: 0,
             fnr: 0,
             nf: 0,
@@ -569,6 +748,18 @@ impl<'a> Default for Variables<'a> {
             pid: 0,
             rlength: -1,
             fi: Default::default(),
+            procinfo: Default::default(),
+            sorted_in: Ok(None),
+            environ: None,
+            convfmt: None,
+            ofmt: None,
+            rt: Default::default(),
+            fpat: DEFAULT_FPAT.into(),
+            fieldwidths: Default::default(),
+            field_mode: FieldMode::Sep,
+            utf8: false,
+            ignorecase: 0,
+            subsep: "\x1c".into(),
         }
     }
 }
@@ -583,7 +774,9 @@ impl<'a> Variables<'a> {
             RSTART => self.rstart,
             RLENGTH => self.rlength,
             PID => self.pid,
-            FI | ORS | OFS | FS | RS | FILENAME | ARGV => return err!("var {} not an int", var),
+            IGNORECASE => self.ignorecase,
+            FI | PROCINFO | ENVIRON | ORS | OFS | FS | RS | FILENAME | CONVFMT | OFMT | RT
+            | FPAT | FIELDWIDTHS | SUBSEP | ARGV => return err!("var {} not an int", var),
         })
     }
 
@@ -597,7 +790,9 @@ impl<'a> Variables<'a> {
             RSTART => self.rstart = i,
             RLENGTH => self.rlength = i,
             PID => self.pid = i,
-            FI | ORS | OFS | FS | RS | FILENAME | ARGV => return err!("var {} not an int", var),
+            IGNORECASE => self.ignorecase = i,
+            FI | PROCINFO | ENVIRON | ORS | OFS | FS | RS | FILENAME | CONVFMT | OFMT | RT
+            | FPAT | FIELDWIDTHS | SUBSEP | ARGV => return err!("var {} not an int", var),
         })
     }
 
@@ -609,23 +804,50 @@ impl<'a> Variables<'a> {
             ORS => self.ors.clone(),
             RS => self.rs.clone(),
             FILENAME => self.filename.clone(),
-            FI | PID | ARGC | ARGV | NF | NR | FNR | RSTART | RLENGTH => {
-                return err!("var {} not a string", var)
-            }
+            CONVFMT => self
+                .convfmt
+                .clone()
+                .unwrap_or_else(|| DEFAULT_NUM_FMT.into()),
+            OFMT => self.ofmt.clone().unwrap_or_else(|| DEFAULT_NUM_FMT.into()),
+            RT => self.rt.clone(),
+            FPAT => self.fpat.clone(),
+            FIELDWIDTHS => self.fieldwidths.clone(),
+            SUBSEP => self.subsep.clone(),
+            FI | PROCINFO | ENVIRON | PID | ARGC | ARGV | NF | NR | FNR | RSTART | RLENGTH
+            | IGNORECASE => return err!("var {} not a string", var),
         })
     }
 
     pub fn store_str(&mut self, var: Variable, s: Str<'a>) -> Result<()> {
         use Variable::*;
         Ok(match var {
-            FS => self.fs = s,
+            FS => {
+                self.fs = s;
+                self.field_mode = FieldMode::Sep;
+                self.update_field_sep();
+            }
             OFS => self.ofs = s,
             ORS => self.ors = s,
-            RS => self.rs = s,
+            RS => {
+                self.rs = s;
+                self.update_field_sep();
+            }
             FILENAME => self.filename = s,
-            FI | PID | ARGC | ARGV | NF | NR | FNR | RSTART | RLENGTH => {
-                return err!("var {} not a string", var)
+            CONVFMT => self.convfmt = Some(s),
+            OFMT => self.ofmt = Some(s),
+            RT => self.rt = s,
+            FPAT => {
+                self.fpat = s;
+                self.field_mode = FieldMode::Pat;
             }
+            FIELDWIDTHS => {
+                let widths = s.with_bytes(|bs| FieldWidths::parse(bs, self.utf8))?;
+                self.fieldwidths = s;
+                self.field_mode = FieldMode::Widths(Arc::new(widths));
+            }
+            SUBSEP => self.subsep = s,
+            FI | PROCINFO | ENVIRON | PID | ARGC | ARGV | NF | NR | FNR | RSTART | RLENGTH
+            | IGNORECASE => return err!("var {} not a string", var),
         })
     }
 
@@ -633,7 +855,9 @@ impl<'a> Variables<'a> {
         use Variable::*;
         match var {
             ARGV => Ok(self.argv.clone()),
-            FI | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | RSTART | RLENGTH => {
+            FI | PROCINFO | ENVIRON | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS
+            | FILENAME | CONVFMT | OFMT | RT | FPAT | FIELDWIDTHS | RSTART | RLENGTH
+            | IGNORECASE | SUBSEP => {
                 err!("var {} is not an int-keyed map", var)
             }
         }
@@ -643,7 +867,9 @@ impl<'a> Variables<'a> {
         use Variable::*;
         match var {
             ARGV => Ok(self.argv = m),
-            FI | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | RSTART | RLENGTH => {
+            FI | PROCINFO | ENVIRON | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS
+            | FILENAME | CONVFMT | OFMT | RT | FPAT | FIELDWIDTHS | RSTART | RLENGTH
+            | IGNORECASE | SUBSEP => {
                 err!("var {} is not an int-keyed map", var)
             }
         }
@@ -652,8 +878,9 @@ impl<'a> Variables<'a> {
         use Variable::*;
         match var {
             FI => Ok(self.fi.clone()),
-            ARGV | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | RSTART
-            | RLENGTH => {
+            PROCINFO | ENVIRON | ARGV | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS
+            | FILENAME | CONVFMT | OFMT | RT | FPAT | FIELDWIDTHS | RSTART | RLENGTH
+            | IGNORECASE | SUBSEP => {
                 err!("var {} is not a string-keyed map", var)
             }
         }
@@ -663,19 +890,119 @@ impl<'a> Variables<'a> {
         use Variable::*;
         match var {
             FI => Ok(self.fi = m),
-            ARGV | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | RSTART
-            | RLENGTH => {
+            PROCINFO | ENVIRON | ARGV | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS
+            | FILENAME | CONVFMT | OFMT | RT | FPAT | FIELDWIDTHS | RSTART | RLENGTH
+            | IGNORECASE | SUBSEP => {
                 err!("var {} is not a string-keyed map", var)
             }
         }
     }
+
+    pub fn load_strstrmap(&mut self, var: Variable) -> Result<StrMap<'a, Str<'a>>> {
+        use Variable::*;
+        match var {
+            PROCINFO => Ok(self.procinfo.clone()),
+            ENVIRON => Ok(self.environ().clone()),
+            FI | ARGV | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | CONVFMT
+            | OFMT | RT | FPAT | FIELDWIDTHS | RSTART | RLENGTH | IGNORECASE | SUBSEP => {
+                err!("var {} is not a string-keyed map of strings", var)
+            }
+        }
+    }
+
+    pub fn store_strstrmap(&mut self, var: Variable, m: StrMap<'a, Str<'a>>) -> Result<()> {
+        use Variable::*;
+        match var {
+            PROCINFO => {
+                self.sorted_in = sorted_in(&m);
+                Ok(self.procinfo = m)
+            }
+            ENVIRON => Ok(self.environ = Some(m)),
+            FI | ARGV | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | CONVFMT
+            | OFMT | RT | FPAT | FIELDWIDTHS | RSTART | RLENGTH | IGNORECASE | SUBSEP => {
+                err!("var {} is not a string-keyed map of strings", var)
+            }
+        }
+    }
+
+    fn environ(&mut self) -> &StrMap<'a, Str<'a>> {
+        // Keep the environment's bytes as they are, even if they are not valid UTF-8.
+        #[cfg(unix)]
+        fn to_str<'a>(s: &std::ffi::OsStr) -> Str<'a> {
+            use std::os::unix::ffi::OsStrExt;
+            Str::from(s.as_bytes()).unmoor().upcast()
+        }
+        #[cfg(not(unix))]
+        fn to_str<'a>(s: &std::ffi::OsStr) -> Str<'a> {
+            Str::from(s.to_string_lossy().into_owned())
+        }
+        self.environ.get_or_insert_with(|| {
+            std::env::vars_os()
+                .map(|(k, v)| (to_str(&k), to_str(&v)))
+                .collect()
+        })
+    }
+
+    /// Convert `f` to a string using `CONVFMT`.
+    pub fn float_to_str(&self, f: Float) -> Result<Str<'a>> {
+        format_float(f, self.convfmt.as_ref())
+    }
+
+    /// Convert `f` to a string using `OFMT`, as `print` does for its arguments.
+    pub fn float_to_str_out(&self, f: Float) -> Result<Str<'a>> {
+        format_float(f, self.ofmt.as_ref())
+    }
+
+    /// How records are split into fields. This is `FS`, unless `FPAT` or `FIELDWIDTHS` was
+    /// assigned more recently. In "paragraph mode" (when `RS` is empty) newlines also separate
+    /// fields.
+    pub fn field_split(&self) -> FieldSplit<'a> {
+        match &self.field_mode {
+            FieldMode::Sep => {}
+            FieldMode::Pat => return FieldSplit::Pat(self.fpat.clone()),
+            FieldMode::Widths(widths) => return FieldSplit::Widths(widths.clone()),
+        }
+        if self.rs.is_empty() && self.fs == Str::from(" ") {
+            return FieldSplit::Whitespace;
+        }
+        FieldSplit::Sep(self.field_sep.clone())
+    }
+
+    // Recompute `field_sep` after an assignment to FS or RS.
+    fn update_field_sep(&mut self) {
+        if !self.rs.is_empty() || self.fs == Str::from(" ") {
+            // `field_split` handles the default FS in paragraph mode.
+            self.field_sep = self.fs.clone();
+            return;
+        }
+        self.field_sep = self
+            .fs
+            .with_bytes(|bs| {
+                let fs = String::from_utf8_lossy(bs);
+                if fs.chars().count() == 1 {
+                    // Single-character separators are matched literally, not as regexes.
+                    format!("(?:{})|\n", regex::escape(&fs))
+                } else {
+                    format!("(?:{})|\n", fs)
+                }
+            })
+            .into();
+    }
+}
+
+fn sorted_in<'a>(procinfo: &StrMap<'a, Str<'a>>) -> Result<Option<SortOrder>> {
+    let key: Str<'a> = "sorted_in".into();
+    match procinfo.0.borrow().get(&key) {
+        Some(order) => order.with_bytes(SortOrder::parse),
+        None => Ok(None),
+    }
 }
 
 impl Variable {
     pub(crate) fn ty(&self) -> types::TVar<types::BaseTy> {
         use Variable::*;
         match self {
-            PID | ARGC | NF | FNR | NR | RSTART | RLENGTH => {
+            PID | ARGC | NF | FNR | NR | RSTART | RLENGTH | IGNORECASE => {
                 types::TVar::Scalar(types::BaseTy::Int)
             }
             // NB: For full compliance, this may have to be Str -> Str
@@ -708,7 +1035,13 @@ impl Variable {
                 key: types::BaseTy::Str,
                 val: types::BaseTy::Int,
             },
-            ORS | OFS | FS | RS | FILENAME => types::TVar::Scalar(types::BaseTy::Str),
+            PROCINFO | ENVIRON => types::TVar::Map {
+                key: types::BaseTy::Str,
+                val: types::BaseTy::Str,
+            },
+            ORS | OFS | FS | RS | FILENAME | CONVFMT | OFMT | RT | FPAT | FIELDWIDTHS | SUBSEP => {
+                types::TVar::Scalar(types::BaseTy::Str)
+            }
         }
     }
 }
@@ -742,6 +1075,15 @@ impl<'a> TryFrom<usize> for Variable {
             11 => Ok(FNR),
             12 => Ok(PID),
             13 => Ok(FI),
+            14 => Ok(PROCINFO),
+            15 => Ok(ENVIRON),
+            16 => Ok(CONVFMT),
+            17 => Ok(OFMT),
+            18 => Ok(RT),
+            19 => Ok(FPAT),
+            20 => Ok(FIELDWIDTHS),
+            21 => Ok(IGNORECASE),
+            22 => Ok(SUBSEP),
             _ => Err(()),
         }
     }
@@ -762,5 +1104,14 @@ static_map!(
     ["RSTART", Variable::RSTART],
     ["RLENGTH", Variable::RLENGTH],
     ["PID", Variable::PID],
-    ["FI", Variable::FI]
+    ["FI", Variable::FI],
+    ["PROCINFO", Variable::PROCINFO],
+    ["ENVIRON", Variable::ENVIRON],
+    ["CONVFMT", Variable::CONVFMT],
+    ["OFMT", Variable::OFMT],
+    ["RT", Variable::RT],
+    ["FPAT", Variable::FPAT],
+    ["FIELDWIDTHS", Variable::FIELDWIDTHS],
+    ["IGNORECASE", Variable::IGNORECASE],
+    ["SUBSEP", Variable::SUBSEP]
 );
@@ -3,12 +3,10 @@ use std::marker::PhantomData;
 use std::sync::Arc;
 
 use crate::builtins::{Bitwise, FloatFunc, Variable};
-use crate::common::{FileSpec, NumTy};
+use crate::common::{FileSpec, InputSpec, NumTy};
 use crate::compile::{self, Ty};
 use crate::interp::{index, index_mut, Storage};
-use crate::runtime::{self, Float, Int, Str, UniqueStr};
-
-use regex::bytes::Regex;
+use crate::runtime::{self, ConstRegex, Float, Int, Str, UniqueStr};
 
 pub(crate) use crate::interp::Interp;
 
@@ -72,6 +70,8 @@ pub(crate) enum Instr<'a> {
     // Conversions
     IntToStr(Reg<Str<'a>>, Reg<Int>),
     FloatToStr(Reg<Str<'a>>, Reg<Float>),
+    // Like FloatToStr, but formatted with OFMT rather than CONVFMT. Used for arguments to print.
+    FloatToStrOut(Reg<Str<'a>>, Reg<Float>),
     StrToInt(Reg<Int>, Reg<Str<'a>>),
     HexStrToInt(Reg<Int>, Reg<Str<'a>>),
     FloatToInt(Reg<Int>, Reg<Float>),
@@ -111,16 +111,50 @@ pub(crate) enum Instr<'a> {
     ),
     ReseedRng(/* previous seed */ Reg<Int>),
 
+    // Time
+    Systime(Reg<Int>),
+    Strftime(
+        Reg<Str<'a>>,
+        /* format */ Reg<Str<'a>>,
+        /* timestamp */ Reg<Int>,
+        /* utc */ Reg<Int>,
+    ),
+    Mktime(
+        Reg<Int>,
+        /* spec */ Reg<Str<'a>>,
+        /* utc */ Reg<Int>,
+    ),
+    Strptime(
+        Reg<Float>,
+        Reg<Str<'a>>,
+        /* format */ Reg<Str<'a>>,
+        /* utc */ Reg<Int>,
+    ),
+
     // String processing
     Concat(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
     StartsWithConst(Reg<Int>, Reg<Str<'a>>, Arc<[u8]>),
     IsMatch(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
-    IsMatchConst(Reg<Int>, Reg<Str<'a>>, Arc<Regex>),
+    IsMatchConst(Reg<Int>, Reg<Str<'a>>, Arc<ConstRegex>),
+    // Index of the first case of a `switch` statement matching a string, or -1 if none do. The
+    // cases are encoded in the last argument, see runtime::encode_cases.
+    MatchCases(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
     Match(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
-    MatchConst(Reg<Int>, Reg<Str<'a>>, Arc<Regex>),
+    MatchConst(Reg<Int>, Reg<Str<'a>>, Arc<ConstRegex>),
+    // match(s, re, arr): also stores the text of each capture group in arr.
+    MatchGroups(
+        Reg<Int>,
+        Reg<Str<'a>>,
+        Reg<Str<'a>>,
+        Reg<runtime::StrMap<'a, Str<'a>>>,
+    ),
     // index(s, t) returns index of substring t in s, 0 if it does not appear.
     SubstrIndex(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
     LenStr(Reg<Int>, Reg<Str<'a>>),
+    // Variants of the above that count characters rather than bytes, used when strings are
+    // interpreted as UTF-8.
+    SubstrIndexChars(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
+    LenStrChars(Reg<Int>, Reg<Str<'a>>),
     Sub(
         Reg<Int>,
         /*pat*/ Reg<Str<'a>>,
@@ -133,9 +167,17 @@ pub(crate) enum Instr<'a> {
         /*for*/ Reg<Str<'a>>,
         /*in*/ Reg<Str<'a>>,
     ),
+    GenSub(
+        Reg<Str<'a>>,
+        /*pat*/ Reg<Str<'a>>,
+        /*for*/ Reg<Str<'a>>,
+        /*how*/ Reg<Str<'a>>,
+        /*in*/ Reg<Str<'a>>,
+    ),
     EscapeCSV(Reg<Str<'a>>, Reg<Str<'a>>),
     EscapeTSV(Reg<Str<'a>>, Reg<Str<'a>>),
     Substr(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Int>, Reg<Int>),
+    SubstrChars(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Int>, Reg<Int>),
 
     // Comparison
     LTFloat(Reg<Int>, Reg<Float>, Reg<Float>),
@@ -153,6 +195,20 @@ pub(crate) enum Instr<'a> {
     EQFloat(Reg<Int>, Reg<Float>, Reg<Float>),
     EQInt(Reg<Int>, Reg<Int>, Reg<Int>),
     EQStr(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
+    // Comparisons between strnums: numeric if both operands look numeric at runtime, string
+    // comparison otherwise.
+    LTStrNum(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
+    GTStrNum(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
+    LTEStrNum(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
+    GTEStrNum(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
+    EQStrNum(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
+    // Comparisons between a strnum and a number: numeric if the strnum looks numeric, otherwise a
+    // string comparison against the number converted with CONVFMT.
+    LTStrNumFloat(Reg<Int>, Reg<Str<'a>>, Reg<Float>),
+    GTStrNumFloat(Reg<Int>, Reg<Str<'a>>, Reg<Float>),
+    LTEStrNumFloat(Reg<Int>, Reg<Str<'a>>, Reg<Float>),
+    GTEStrNumFloat(Reg<Int>, Reg<Str<'a>>, Reg<Float>),
+    EQStrNumFloat(Reg<Int>, Reg<Str<'a>>, Reg<Float>),
 
     // Columns
     SetColumn(Reg<Int> /* dst column */, Reg<Str<'a>>),
@@ -175,14 +231,20 @@ pub(crate) enum Instr<'a> {
     ),
     ToUpperAscii(Reg<Str<'a>>, Reg<Str<'a>>),
     ToLowerAscii(Reg<Str<'a>>, Reg<Str<'a>>),
+    // Full Unicode case mapping.
+    ToUpper(Reg<Str<'a>>, Reg<Str<'a>>),
+    ToLower(Reg<Str<'a>>, Reg<Str<'a>>),
 
     // File reading.
-    ReadErr(Reg<Int>, Reg<Str<'a>>, /*is_file=*/ bool),
-    NextLine(Reg<Str<'a>>, Reg<Str<'a>>, /*is_file=*/ bool),
+    ReadErr(Reg<Int>, Reg<Str<'a>>, InputSpec),
+    NextLine(Reg<Str<'a>>, Reg<Str<'a>>, InputSpec),
     ReadErrStdin(Reg<Int>),
     NextLineStdin(Reg<Str<'a>>),
     // Fetches line directly into $0.
     NextLineStdinFused(),
+    // Read a record into $0 like NextLineStdinFused, storing whether a record was read or an input
+    // file started or ended. See runtime::RegexCache::get_line_stdin_event.
+    NextLineStdinEvent(Reg<Int>),
     // Advances early to the next file in our sequence
     NextFile(),
     UpdateUsedFields(),
@@ -203,6 +265,14 @@ pub(crate) enum Instr<'a> {
         Reg<runtime::StrMap<'a, Str<'a>>>,
         Reg<Str<'a>>,
     ),
+    // patsplit: the last register holds the separators.
+    PatSplit(
+        Reg<Int>,
+        Reg<Str<'a>>,
+        Reg<runtime::IntMap<Str<'a>>>,
+        Reg<Str<'a>>,
+        Reg<runtime::IntMap<Str<'a>>>,
+    ),
     Sprintf {
         dst: Reg<Str<'a>>,
         fmt: Reg<Str<'a>>,
@@ -217,9 +287,11 @@ pub(crate) enum Instr<'a> {
         output: Option<(Reg<Str<'a>>, FileSpec)>,
         args: Vec<Reg<Str<'a>>>,
     },
-    Close(Reg<Str<'a>>),
+    Close(Reg<Int>, Reg<Str<'a>>, /*how=*/ Reg<Str<'a>>),
     RunCmd(Reg<Int>, Reg<Str<'a>>),
+    FFlush(Reg<Int>, Reg<Str<'a>>),
     Exit(Reg<Int>),
+    UndefinedFunc(Reg<Str<'a>>),
 
     // Map operations
     Lookup {
@@ -268,6 +340,24 @@ pub(crate) enum Instr<'a> {
         dst: NumTy,
         by: Reg<Float>,
     },
+    // asort and asorti: `into` is a map of type `into_ty` whose values have the type of the values
+    // (resp. keys) of `map`. Its keys are usually integers, but may be strings.
+    Asort {
+        map_ty: Ty,
+        into_ty: Ty,
+        dst: Reg<Int>,
+        map: NumTy,
+        into: NumTy,
+        how: Reg<Str<'a>>,
+    },
+    Asorti {
+        map_ty: Ty,
+        into_ty: Ty,
+        dst: Reg<Int>,
+        map: NumTy,
+        into: NumTy,
+        how: Reg<Str<'a>>,
+    },
     IterBegin {
         map_ty: Ty,
         dst: NumTy,
@@ -292,6 +382,8 @@ pub(crate) enum Instr<'a> {
     StoreVarIntMap(Variable, Reg<runtime::IntMap<Str<'a>>>),
     LoadVarStrMap(Reg<runtime::StrMap<'a, Int>>, Variable),
     StoreVarStrMap(Variable, Reg<runtime::StrMap<'a, Int>>),
+    LoadVarStrStrMap(Reg<runtime::StrMap<'a, Str<'a>>>, Variable),
+    StoreVarStrStrMap(Variable, Reg<runtime::StrMap<'a, Str<'a>>>),
 
     LoadSlot {
         ty: Ty,
@@ -364,28 +456,28 @@ macro_rules! impl_accum  {
 }
 
 macro_rules! impl_get {
-    ($t:ty, $fld:ident, $ty:tt $(,$lt:tt)*) => {
+    ($t:ty, $($fld:ident).+, $ty:tt $(,$lt:tt)*) => {
         impl_accum!($t, $ty, $($lt),*);
         impl<'a, LR: runtime::LineReader> Get<$t> for Interp<'a, LR> {
             #[inline(always)]
             fn get(&self, r: Reg<$t>) -> &$t {
                 #[cfg(debug_assertions)]
                 _dbg_check_index(
-                    concat!(stringify!($t), "_", stringify!($fld)),
-                    &self.$fld,
+                    concat!(stringify!($t), "_", stringify!($($fld).+)),
+                    &self.$($fld).+,
                     r.index(),
                 );
-                index(&self.$fld, &r)
+                index(&self.$($fld).+, &r)
             }
             #[inline(always)]
             fn get_mut(&mut self, r: Reg<$t>) -> &mut $t {
                 #[cfg(debug_assertions)]
                 _dbg_check_index(
-                    concat!(stringify!($t), "_", stringify!($fld)),
-                    &self.$fld,
+                    concat!(stringify!($t), "_", stringify!($($fld).+)),
+                    &self.$($fld).+,
                     r.index(),
                 );
-                index_mut(&mut self.$fld, &r)
+                index_mut(&mut self.$($fld).+, &r)
             }
         }
     };
@@ -400,6 +492,18 @@ impl_get!(runtime::IntMap<Str<'a>>, maps_int_str, MapIntStr, 'a);
 impl_get!(runtime::StrMap<'a, Float>, maps_str_float, MapStrFloat, 'a);
 impl_get!(runtime::StrMap<'a, Int>, maps_str_int, MapStrInt, 'a);
 impl_get!(runtime::StrMap<'a, Str<'a>>, maps_str_str, MapStrStr, 'a);
+impl_get!(runtime::IntMap<runtime::IntMap<Int>>, nested.int_intint, MapIntMapIntInt, 'a);
+impl_get!(runtime::IntMap<runtime::IntMap<Float>>, nested.int_intfloat, MapIntMapIntFloat, 'a);
+impl_get!(runtime::IntMap<runtime::IntMap<Str<'a>>>, nested.int_intstr, MapIntMapIntStr, 'a);
+impl_get!(runtime::IntMap<runtime::StrMap<'a, Int>>, nested.int_strint, MapIntMapStrInt, 'a);
+impl_get!(runtime::IntMap<runtime::StrMap<'a, Float>>, nested.int_strfloat, MapIntMapStrFloat, 'a);
+impl_get!(runtime::IntMap<runtime::StrMap<'a, Str<'a>>>, nested.int_strstr, MapIntMapStrStr, 'a);
+impl_get!(runtime::StrMap<'a, runtime::IntMap<Int>>, nested.str_intint, MapStrMapIntInt, 'a);
+impl_get!(runtime::StrMap<'a, runtime::IntMap<Float>>, nested.str_intfloat, MapStrMapIntFloat, 'a);
+impl_get!(runtime::StrMap<'a, runtime::IntMap<Str<'a>>>, nested.str_intstr, MapStrMapIntStr, 'a);
+impl_get!(runtime::StrMap<'a, runtime::StrMap<'a, Int>>, nested.str_strint, MapStrMapStrInt, 'a);
+impl_get!(runtime::StrMap<'a, runtime::StrMap<'a, Float>>, nested.str_strfloat, MapStrMapStrFloat, 'a);
+impl_get!(runtime::StrMap<'a, runtime::StrMap<'a, Str<'a>>>, nested.str_strstr, MapStrMapStrStr, 'a);
 impl_get!(runtime::Iter<Int>, iters_int, IterInt);
 impl_get!(runtime::Iter<Str<'a>>, iters_str, IterStr, 'a);
 
@@ -415,7 +519,7 @@ impl<'a> Instr<'a> {
                 sr.accum(&mut f);
                 ir.accum(&mut f)
             }
-            FloatToStr(sr, fr) => {
+            FloatToStr(sr, fr) | FloatToStrOut(sr, fr) => {
                 sr.accum(&mut f);
                 fr.accum(&mut f);
             }
@@ -520,6 +624,24 @@ impl<'a> Instr<'a> {
                 seed.accum(&mut f)
             }
             ReseedRng(res) => res.accum(&mut f),
+            Systime(res) => res.accum(&mut f),
+            Strftime(res, fmt, ts, utc) => {
+                res.accum(&mut f);
+                fmt.accum(&mut f);
+                ts.accum(&mut f);
+                utc.accum(&mut f);
+            }
+            Mktime(res, spec, utc) => {
+                res.accum(&mut f);
+                spec.accum(&mut f);
+                utc.accum(&mut f);
+            }
+            Strptime(res, s, fmt, utc) => {
+                res.accum(&mut f);
+                s.accum(&mut f);
+                fmt.accum(&mut f);
+                utc.accum(&mut f);
+            }
             StartsWithConst(res, s, _) => {
                 res.accum(&mut f);
                 s.accum(&mut f);
@@ -529,12 +651,18 @@ impl<'a> Instr<'a> {
                 l.accum(&mut f);
                 r.accum(&mut f);
             }
+            MatchGroups(res, l, r, arr) => {
+                res.accum(&mut f);
+                l.accum(&mut f);
+                r.accum(&mut f);
+                arr.accum(&mut f);
+            }
             Match(res, l, r) => {
                 res.accum(&mut f);
                 l.accum(&mut f);
                 r.accum(&mut f);
             }
-            IsMatch(res, l, r) => {
+            IsMatch(res, l, r) | MatchCases(res, l, r) => {
                 res.accum(&mut f);
                 l.accum(&mut f);
                 r.accum(&mut f);
@@ -543,12 +671,12 @@ impl<'a> Instr<'a> {
                 res.accum(&mut f);
                 src.accum(&mut f);
             }
-            SubstrIndex(res, s, t) => {
+            SubstrIndex(res, s, t) | SubstrIndexChars(res, s, t) => {
                 res.accum(&mut f);
                 s.accum(&mut f);
                 t.accum(&mut f);
             }
-            LenStr(res, s) => {
+            LenStr(res, s) | LenStrChars(res, s) => {
                 res.accum(&mut f);
                 s.accum(&mut f)
             }
@@ -558,11 +686,18 @@ impl<'a> Instr<'a> {
                 s.accum(&mut f);
                 in_s.accum(&mut f);
             }
+            GenSub(res, pat, s, how, in_s) => {
+                res.accum(&mut f);
+                pat.accum(&mut f);
+                s.accum(&mut f);
+                how.accum(&mut f);
+                in_s.accum(&mut f);
+            }
             EscapeCSV(res, s) | EscapeTSV(res, s) => {
                 res.accum(&mut f);
                 s.accum(&mut f);
             }
-            Substr(res, base, l, r) => {
+            Substr(res, base, l, r) | SubstrChars(res, base, l, r) => {
                 res.accum(&mut f);
                 base.accum(&mut f);
                 l.accum(&mut f);
@@ -643,6 +778,24 @@ impl<'a> Instr<'a> {
                 l.accum(&mut f);
                 r.accum(&mut f);
             }
+            LTStrNum(res, l, r)
+            | GTStrNum(res, l, r)
+            | LTEStrNum(res, l, r)
+            | GTEStrNum(res, l, r)
+            | EQStrNum(res, l, r) => {
+                res.accum(&mut f);
+                l.accum(&mut f);
+                r.accum(&mut f);
+            }
+            LTStrNumFloat(res, l, r)
+            | GTStrNumFloat(res, l, r)
+            | LTEStrNumFloat(res, l, r)
+            | GTEStrNumFloat(res, l, r)
+            | EQStrNumFloat(res, l, r) => {
+                res.accum(&mut f);
+                l.accum(&mut f);
+                r.accum(&mut f);
+            }
             SetColumn(dst, src) => {
                 dst.accum(&mut f);
                 src.accum(&mut f)
@@ -662,7 +815,10 @@ impl<'a> Instr<'a> {
                 end.accum(&mut f);
                 sep.accum(&mut f);
             }
-            ToUpperAscii(dst, src) | ToLowerAscii(dst, src) => {
+            ToUpperAscii(dst, src)
+            | ToLowerAscii(dst, src)
+            | ToUpper(dst, src)
+            | ToLower(dst, src) => {
                 dst.accum(&mut f);
                 src.accum(&mut f);
             }
@@ -678,6 +834,13 @@ impl<'a> Instr<'a> {
                 arr.accum(&mut f);
                 pat.accum(&mut f);
             }
+            PatSplit(flds, to_split, arr, pat, seps) => {
+                flds.accum(&mut f);
+                to_split.accum(&mut f);
+                arr.accum(&mut f);
+                pat.accum(&mut f);
+                seps.accum(&mut f);
+            }
             Sprintf { dst, fmt, args } => {
                 dst.accum(&mut f);
                 fmt.accum(&mut f);
@@ -702,12 +865,17 @@ impl<'a> Instr<'a> {
                     reg.accum(&mut f)
                 }
             }
-            Close(file) => file.accum(&mut f),
-            RunCmd(dst, cmd) => {
+            Close(dst, file, how) => {
+                dst.accum(&mut f);
+                file.accum(&mut f);
+                how.accum(&mut f)
+            }
+            RunCmd(dst, cmd) | FFlush(dst, cmd) => {
                 dst.accum(&mut f);
                 cmd.accum(&mut f);
             }
             Exit(code) => code.accum(&mut f),
+            UndefinedFunc(name) => name.accum(&mut f),
             Lookup {
                 map_ty,
                 dst,
@@ -740,6 +908,32 @@ impl<'a> Instr<'a> {
                 f(*dst, Ty::Int);
                 f(*map, *map_ty);
             }
+            Asort {
+                map_ty,
+                into_ty,
+                dst,
+                map,
+                into,
+                how,
+            } => {
+                dst.accum(&mut f);
+                f(*map, *map_ty);
+                f(*into, *into_ty);
+                how.accum(&mut f);
+            }
+            Asorti {
+                map_ty,
+                into_ty,
+                dst,
+                map,
+                into,
+                how,
+            } => {
+                dst.accum(&mut f);
+                f(*map, *map_ty);
+                f(*into, *into_ty);
+                how.accum(&mut f);
+            }
             IterBegin { map_ty, map, dst } => {
                 f(*dst, map_ty.key_iter().unwrap());
                 f(*map, *map_ty);
@@ -786,6 +980,8 @@ impl<'a> Instr<'a> {
             StoreVarIntMap(_var, src) => src.accum(&mut f),
             LoadVarStrMap(dst, _var) => dst.accum(&mut f),
             StoreVarStrMap(_var, src) => src.accum(&mut f),
+            LoadVarStrStrMap(dst, _var) => dst.accum(&mut f),
+            StoreVarStrStrMap(_var, src) => src.accum(&mut f),
 
             LoadSlot { ty, dst, .. } => f(*dst, *ty),
             StoreSlot { ty, src, .. } => f(*src, *ty),
@@ -811,7 +1007,7 @@ impl<'a> Instr<'a> {
                 dst.accum(&mut f);
                 file.accum(&mut f)
             }
-            ReadErrStdin(dst) => dst.accum(&mut f),
+            ReadErrStdin(dst) | NextLineStdinEvent(dst) => dst.accum(&mut f),
             NextLineStdin(dst) => dst.accum(&mut f),
             JmpIf(cond, _lbl) => cond.accum(&mut f),
             Push(ty, reg) => f(*reg, *ty),
@@ -1,8 +1,9 @@
 use crate::arena;
 use crate::ast::{self, Expr, Stmt, Unop};
 use crate::builtins::{self, IsSprintf};
-use crate::common::{Either, FileSpec, Graph, NodeIx, NumTy, Result, Stage};
+use crate::common::{Either, FileSpec, Graph, InputSpec, NodeIx, NumTy, Result, Stage};
 use crate::dom;
+use crate::runtime;
 
 use hashbrown::{HashMap, HashSet};
 use petgraph::Direction;
@@ -282,6 +283,15 @@ fn valid_lhs<'a, 'b, I>(e: &ast::Expr<'a, 'b, I>) -> bool {
     }
 }
 
+// Malformed directives in a printf format string are printed verbatim at runtime; if the format
+// string is a constant we can reject them at compile time instead.
+fn check_format(fmt: &PrimVal) -> Result<()> {
+    match fmt {
+        PrimVal::StrLit(s) => runtime::printf::validate(s),
+        _ => Ok(()),
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct ProgramContext<'a, I> {
     shared: GlobalContext<I>,
@@ -296,6 +306,14 @@ pub(crate) struct ProgramContext<'a, I> {
     pub fold_regex_constants: bool,
     // Thread through information regarding header columns used.
     pub parse_header: bool,
+    // Have length, substr, index, toupper and tolower treat strings as UTF-8, counting characters
+    // rather than bytes.
+    pub char_semantics: bool,
+    // Compare strings read from input numerically when they look like numbers, as POSIX requires,
+    // rather than using only the types chosen by type inference.
+    pub strnum_compare: bool,
+    // Whether the program has BEGINFILE or ENDFILE patterns.
+    pub file_events: bool,
 }
 
 impl<'a, I> ProgramContext<'a, I> {
@@ -370,11 +388,37 @@ where
             Stage::Par { begin, .. } => begin,
         }
     }
+    /// Whether the program assigns to IGNORECASE. Regex constants compiled ahead of time need a
+    /// case-insensitive variant if so.
+    pub fn assigns_ignorecase(&self) -> bool {
+        self.funcs
+            .iter()
+            .any(|f| f.vars.get(&Some(builtins::Variable::IGNORECASE)).is_some())
+    }
+
     pub fn analyze_sep_assignments(&self) -> SepAssign<'a> {
         let mut field_sep = None;
         let mut record_sep = None;
         let mut has_getline = false;
+        // BEGINFILE and ENDFILE need a reader that stops at the end of each input file, which the
+        // batched readers do not do.
+        if self.file_events {
+            return SepAssign::Unsure;
+        }
         for (i, f) in self.funcs.iter().enumerate() {
+            // Only the regex-based splitter knows how to split fields using FPAT or FIELDWIDTHS,
+            // or how to match separators case-insensitively.
+            for bi in [
+                builtins::Variable::FPAT,
+                builtins::Variable::FIELDWIDTHS,
+                builtins::Variable::IGNORECASE,
+            ]
+            .iter()
+            {
+                if f.vars.get(&Some(*bi)).is_some() {
+                    return SepAssign::Unsure;
+                }
+            }
             if Some(i) == self.begin_offset() {
                 for (bi, sep) in [
                     (builtins::Variable::FS, &mut field_sep),
@@ -451,7 +495,13 @@ where
             max: 1, // 0 reserved for assigning to "unused" var for side-effecting operations
             conds: Default::default(),
             esc,
+            next_flag: None,
         };
+        if p.decs.iter().any(|fundec| has_next(fundec.body)) {
+            let flag = shared.fresh();
+            shared.may_rename.push(flag);
+            shared.next_flag = Some(flag);
+        }
         let mut func_table: HashMap<FunctionName<I>, NumTy> = Default::default();
         let mut funcs: Vec<Function<'a, I>> = Default::default();
         for fundec in p.decs.iter() {
@@ -493,6 +543,8 @@ where
             f.ret = ret;
             funcs.push(f);
         }
+        let stage = p.desugar_stage(arena);
+        let indirect = IndirectCallInfo::new(&p.decs[..], &stage);
         // Now that we have all the functions in place, it's time to fill them up and convert them
         // to SSA.
         macro_rules! fill {
@@ -504,6 +556,8 @@ where
                         ctx: &mut shared,
                         f: &mut func,
                         func_table: &func_table,
+                        indirect: &indirect,
+                        arena,
                         parse_header: p.parse_header,
                     }
                     .fill(s)?;
@@ -524,13 +578,15 @@ where
                 ctx: &mut shared,
                 f: funcs.get_mut(f as usize).unwrap(),
                 func_table: &func_table,
+                indirect: &indirect,
+                arena,
                 parse_header: p.parse_header,
             }
             .fill(fundec.body)?;
         }
 
         // Bind the main function
-        let main_offset = match p.desugar_stage(arena) {
+        let main_offset = match stage {
             Stage::Main(main_stmt) => {
                 Stage::Main(fill!(Some(main_stmt), FunctionName::MainLoop).unwrap())
             }
@@ -559,6 +615,8 @@ where
             }
         };
 
+        check_next_calls(&funcs)?;
+
         Ok(ProgramContext {
             shared,
             funcs,
@@ -566,6 +624,9 @@ where
             allow_arbitrary_commands: false,
             fold_regex_constants: false,
             parse_header: p.parse_header,
+            char_semantics: false,
+            strnum_compare: true,
+            file_events: !p.beginfile.is_empty() || !p.endfile.is_empty(),
         })
     }
 }
@@ -574,6 +635,8 @@ struct View<'a, 'b, I> {
     ctx: &'a mut GlobalContext<I>,
     f: &'a mut Function<'b, I>,
     func_table: &'a HashMap<FunctionName<I>, NumTy>,
+    indirect: &'a IndirectCallInfo<I>,
+    arena: &'b arena::Arena,
     parse_header: bool,
 }
 
@@ -595,6 +658,10 @@ struct GlobalContext<I> {
     max: NumTy,
     conds: HashMap<usize, Ident>,
     esc: Escaper,
+    // A global flag that is set when a function executes `next` or `nextfile`. Callers check it
+    // after each function call, either returning in turn or continuing the toplevel loop. This is
+    // only allocated if some function uses `next` or `nextfile`.
+    next_flag: Option<Ident>,
 }
 
 impl<I> GlobalContext<I> {
@@ -633,14 +700,21 @@ pub(crate) struct Function<'a, I> {
     // We enforce that a single basic block has a return statement. This is to ensure that type
     // inference infers the same type for each return site.
     pub exit: NodeIx,
-    // Stack of the entry and exit nodes for the loops within which the current statement is
-    // nested.
-    loop_ctx: SmallVec<(NodeIx, NodeIx)>,
+    // Stack of the targets of `continue` and `break` for the loops and switch statements within
+    // which the current statement is nested. A switch statement has no `continue` target of its
+    // own: it inherits one from an enclosing loop, if there is one.
+    loop_ctx: SmallVec<(Option<NodeIx>, NodeIx)>,
     // Header node for the toplevel "pattern matching" loop of the AWK program. This is used to
     // implement the nonlocal continue of the `next` and `nextfile` statements.
     //
-    // NB: We only support doing this from main.
+    // This is only set while the body of the loop is being filled.
     toplevel_header: Option<NodeIx>,
+    // Whether this function executes `next` or `nextfile` directly.
+    uses_next: bool,
+    // The functions called from this one, and whether a `next` executed in that function can be
+    // propagated from the call site. It cannot from a BEGIN or END block. Only tracked if some
+    // function uses `next`.
+    callees: Vec<(NumTy, bool)>,
 
     // Variable assignments, used to extract fast paths for splitting.
     // None indicates a call to `getline`.
@@ -669,6 +743,8 @@ impl<'a, I> Function<'a, I> {
             exit,
             loop_ctx: Default::default(),
             toplevel_header: None,
+            uses_next: false,
+            callees: Default::default(),
             vars: Default::default(),
             dt: Default::default(),
             df: Default::default(),
@@ -680,6 +756,225 @@ pub(crate) fn is_unused(i: Ident) -> bool {
     i.low == 0
 }
 
+// Whether `stmt` contains a `next` or `nextfile` statement.
+fn has_next<I>(stmt: &Stmt<I>) -> bool {
+    use Stmt::*;
+    match stmt {
+        Next | NextFile => true,
+        Block(stmts) => stmts.iter().any(|s| has_next(s)),
+        If(_, t, f) => has_next(t) || f.iter().any(|s| has_next(s)),
+        For(init, _, update, body) => {
+            init.iter().chain(update.iter()).any(|s| has_next(s)) || has_next(body)
+        }
+        DoWhile(_, body) | While(_, _, body) | ForEach(_, _, body) => has_next(body),
+        Switch(_, cases) => cases.iter().any(|(_, body)| has_next(body)),
+        StartCond(_) | EndCond(_) | LastCond(_) | Expr(_) | Print(..) | PrintBytes(..)
+        | Printf(..) | Break | Continue | Return(_) => false,
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum UsedAs {
+    Unknown,
+    Scalar,
+    Array,
+}
+
+// Syntactic information about how variables are used, which we use to find the candidates for an
+// indirect call before types are known.
+struct IndirectCallInfo<I> {
+    // The named functions in declaration order, along with how each of their parameters is used.
+    funcs: Vec<(I, SmallVec<UsedAs>)>,
+    // Global variables that are used as arrays.
+    global_arrays: HashSet<I>,
+}
+
+impl<I: Hash + Eq + Clone> IndirectCallInfo<I>
+where
+    builtins::Function: TryFrom<I>,
+{
+    fn new<'a, 'b>(decs: &[ast::FunDec<'a, 'b, I>], main: &Stage<&'a Stmt<'a, 'b, I>>) -> Self {
+        let mut global_arrays = HashSet::default();
+        for stmt in main.iter() {
+            visit_uses(stmt, &mut |v, is_array| {
+                if is_array {
+                    global_arrays.insert(v.clone());
+                }
+            });
+        }
+        let mut funcs = Vec::with_capacity(decs.len());
+        for fundec in decs.iter() {
+            let mut params: SmallVec<UsedAs> = smallvec![UsedAs::Unknown; fundec.args.len()];
+            visit_uses(
+                fundec.body,
+                &mut |v, is_array| match fundec.args.iter().position(|p| p == v) {
+                    Some(i) if is_array => params[i] = UsedAs::Array,
+                    Some(i) if params[i] == UsedAs::Unknown => params[i] = UsedAs::Scalar,
+                    Some(_) => {}
+                    None if is_array => {
+                        global_arrays.insert(v.clone());
+                    }
+                    None => {}
+                },
+            );
+            funcs.push((fundec.name.clone(), params));
+        }
+        IndirectCallInfo {
+            funcs,
+            global_arrays,
+        }
+    }
+}
+
+// Call `f` on variables used as arrays (indexed, iterated over, or passed to `in`, `delete`,
+// `split` and friends), and on variables used as scalars (as operands, in assignments, or
+// printed). Other uses, like arguments to user-defined functions, are ambiguous and skipped.
+fn visit_uses<I: Clone>(stmt: &Stmt<I>, f: &mut impl FnMut(&I, bool /* is_array */))
+where
+    builtins::Function: TryFrom<I>,
+{
+    fn expr<I: Clone>(e: &ast::Expr<I>, scalar: bool, f: &mut impl FnMut(&I, bool))
+    where
+        builtins::Function: TryFrom<I>,
+    {
+        use ast::Expr::*;
+        use builtins::Function::{
+            Asort, Asorti, Clear, Contains, Delete, Match, MatchGroups, PatSplit, Split,
+        };
+        match e {
+            Var(v) if scalar => f(v, false),
+            ILit(_) | FLit(_) | StrLit(_) | PatLit(_) | Var(_) | ReadStdin | Cond(_) => {}
+            Unop(_, x) | Inc { x, .. } => expr(x, true, f),
+            Binop(_, x, y) | Assign(x, y) | AssignOp(x, _, y) | And(x, y) | Or(x, y) => {
+                expr(x, true, f);
+                expr(y, true, f);
+            }
+            Index(arr, ix) => {
+                if let Var(v) = arr {
+                    f(v, true)
+                }
+                expr(arr, false, f);
+                expr(ix, true, f);
+            }
+            ITE(c, t, e) => {
+                expr(c, true, f);
+                expr(t, false, f);
+                expr(e, false, f);
+            }
+            Getline { into, from, .. } => {
+                for x in into.iter().chain(from.iter()) {
+                    expr(x, true, f);
+                }
+            }
+            Call(func, args) => {
+                let bi = match func {
+                    Either::Left(name) => builtins::Function::try_from(name.clone()).ok(),
+                    Either::Right(bi) => Some(*bi),
+                };
+                let arrays: &[usize] = match bi {
+                    Some(Contains | Delete | Clear) => &[0],
+                    Some(Split | PatSplit) => &[1, 3],
+                    Some(Match | MatchGroups) => &[2],
+                    Some(Asort | Asorti) => &[0, 1],
+                    _ => &[],
+                };
+                for (i, a) in args.iter().enumerate() {
+                    match a {
+                        Var(v) if arrays.contains(&i) => f(v, true),
+                        _ => expr(a, false, f),
+                    }
+                }
+            }
+            IndirectCall(func, args) => {
+                expr(func, true, f);
+                for a in args.iter() {
+                    expr(a, false, f);
+                }
+            }
+        }
+    }
+    use Stmt::*;
+    let mut exprs = |es: &[&ast::Expr<I>], out: &Option<(&ast::Expr<I>, FileSpec)>| {
+        for e in es.iter().cloned().chain(out.iter().map(|(e, _)| *e)) {
+            expr(e, true, f)
+        }
+    };
+    match stmt {
+        StartCond(_) | EndCond(_) | LastCond(_) | Break | Continue | Next | NextFile => {}
+        Return(None) => {}
+        Expr(e) | Return(Some(e)) => expr(e, false, f),
+        Print(args, out) | PrintBytes(args, out) => exprs(args, out),
+        Printf(spec, args, out) => {
+            exprs(&[*spec], out);
+            exprs(args, &None);
+        }
+        Block(stmts) => {
+            for s in stmts.iter() {
+                visit_uses(s, f)
+            }
+        }
+        If(c, t, e) => {
+            expr(c, true, f);
+            visit_uses(t, f);
+            if let Some(e) = e {
+                visit_uses(e, f);
+            }
+        }
+        For(init, cond, update, body) => {
+            for s in init.iter().chain(update.iter()) {
+                visit_uses(s, f);
+            }
+            if let Some(c) = cond {
+                expr(c, true, f);
+            }
+            visit_uses(body, f);
+        }
+        DoWhile(c, body) | While(_, c, body) => {
+            expr(c, true, f);
+            visit_uses(body, f);
+        }
+        ForEach(_, arr, body) => {
+            if let ast::Expr::Var(v) = arr {
+                f(v, true)
+            }
+            visit_uses(body, f);
+        }
+        Switch(e, cases) => {
+            expr(e, true, f);
+            for (_, body) in cases.iter() {
+                visit_uses(body, f);
+            }
+        }
+    }
+}
+
+// Reject calls from BEGIN or END blocks to functions that may (transitively) execute `next`.
+fn check_next_calls<I: fmt::Display>(funcs: &[Function<I>]) -> Result<()> {
+    let mut uses_next: Vec<bool> = funcs.iter().map(|f| f.uses_next).collect();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (i, f) in funcs.iter().enumerate() {
+            if !uses_next[i] && f.callees.iter().any(|(c, _)| uses_next[*c as usize]) {
+                uses_next[i] = true;
+                changed = true;
+            }
+        }
+    }
+    for f in funcs.iter() {
+        for (callee, can_propagate) in f.callees.iter().cloned() {
+            if !can_propagate && uses_next[callee as usize] {
+                return err!(
+                    "function {} may execute `next` or `nextfile`, and so cannot be called from \
+                     outside of the toplevel loop (e.g. from a BEGIN or END block)",
+                    funcs[callee as usize].name
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
 fn record_ident(
     defsites: &mut HashMap<Ident, HashSet<NodeIx>>,
     orig: &mut HashMap<NodeIx, HashSet<Ident>>,
@@ -790,8 +1085,15 @@ where
             Expr(e) => {
                 // We need to assign to unused here, otherwise we could generate the expression but
                 // then drop it on the floor.
-                let (next, e) = self.convert_expr(e, current_open)?;
-                self.add_stmt(next, PrimStmt::AsgnVar(Ident::unused(), e))?;
+                let (next, prim) = self.convert_expr(e, current_open)?;
+                self.add_stmt(next, PrimStmt::AsgnVar(Ident::unused(), prim))?;
+                if let ast::Expr::Call(
+                    Either::Right(builtins::Function::Delete | builtins::Function::Clear),
+                    args,
+                ) = e
+                {
+                    self.refresh_procinfo(args[0], next)?;
+                }
                 next
             }
             Block(stmts) => {
@@ -802,6 +1104,7 @@ where
             }
             Printf(fmt, args, out) => {
                 let (mut current_open, fmt_v) = self.convert_val(fmt, current_open)?;
+                check_format(&fmt_v)?;
                 let mut arg_vs = SmallVec::with_capacity(args.len());
                 for a in args.iter() {
                     let (next, arg_v) = self.convert_val(a, current_open)?;
@@ -818,6 +1121,23 @@ where
                 self.add_stmt(current_open, PrimStmt::Printf(fmt_v, arg_vs, out_v))?;
                 current_open
             }
+            PrintBytes(vs, out) => {
+                let mut arg_vs = SmallVec::with_capacity(vs.len());
+                for v in vs.iter() {
+                    let (next, arg_v) = self.convert_val(v, current_open)?;
+                    arg_vs.push(arg_v);
+                    current_open = next;
+                }
+                let out_v = if let Some((out, spec)) = out {
+                    let (next, out_v) = self.convert_val(out, current_open)?;
+                    current_open = next;
+                    Some((out_v, *spec))
+                } else {
+                    None
+                };
+                self.add_stmt(current_open, PrimStmt::PrintAll(arg_vs, out_v))?;
+                current_open
+            }
             Print(vs, out) => {
                 let ors = {
                     let ors = self.fresh_local();
@@ -966,7 +1286,7 @@ where
                 let footer = self.f.cfg.add_node(Default::default());
                 self.add_stmt(footer, PrimStmt::IterDrop(array_iter.clone()))?;
 
-                self.f.loop_ctx.push((cond_block, footer));
+                self.f.loop_ctx.push((Some(cond_block), footer));
 
                 // Create the body, but start by getting the next element from the iterator and
                 // assigning it to `v`
@@ -986,6 +1306,7 @@ where
 
                 footer
             }
+            Switch(subject, cases) => self.do_switch(subject, cases, current_open)?,
             Break => {
                 self.do_break_continue(current_open, /*is_break*/ true)?;
                 current_open
@@ -1114,6 +1435,7 @@ where
                 return Ok((next, PrimExpr::Index(arr_v, ix_v)));
             }
             Call(fname, args) => return self.call(current_open, fname, args),
+            IndirectCall(callee, args) => return self.indirect_call(current_open, callee, args),
             Assign(Index(arr, ix), to) => {
                 return self.do_assign_index(
                     arr,
@@ -1220,11 +1542,7 @@ where
                     current_open,
                 );
             }
-            Getline {
-                from,
-                into,
-                is_file,
-            } => {
+            Getline { from, into, spec } => {
                 // break up getline calls to help the FS analysis out
                 let next = self.f.cfg.add_node(Default::default());
                 self.f.cfg.add_edge(current_open, next, Transition::null());
@@ -1246,10 +1564,14 @@ where
                 //  getline var => getline var < stdin
                 //  getline => getline $0
                 use builtins::Function::{
-                    Nextline, NextlineCmd, NextlineStdin, ReadErr, ReadErrCmd, ReadErrStdin,
+                    Nextline, NextlineCmd, NextlineCoproc, NextlineStdin, ReadErr, ReadErrCmd,
+                    ReadErrCoproc, ReadErrStdin,
+                };
+                let (next_line, read_err) = match spec {
+                    InputSpec::File => (Nextline, ReadErr),
+                    InputSpec::Cmd => (NextlineCmd, ReadErrCmd),
+                    InputSpec::Coproc => (NextlineCoproc, ReadErrCoproc),
                 };
-                let next_line = if *is_file { Nextline } else { NextlineCmd };
-                let read_err = if *is_file { ReadErr } else { ReadErrCmd };
                 match (from, into) {
                     // an unadorned `getline` is uses the "fused" stdin construct, which in turn
                     // enables some optimizations.
@@ -1265,7 +1587,7 @@ where
                             &ast::Expr::Getline {
                                 from: from.clone(),
                                 into: Some(&Unop(ast::Unop::Column, &ast::Expr::ILit(0))),
-                                is_file: *is_file,
+                                spec: *spec,
                             },
                             current_open,
                         )
@@ -1319,6 +1641,7 @@ where
         }
         let mut iter = args.iter();
         let (next, fmt) = self.convert_val(iter.next().unwrap(), current_open)?;
+        check_format(&fmt)?;
         current_open = next;
         let mut res = SmallVec::with_capacity(args.len() - 1);
         for a in iter {
@@ -1420,9 +1743,30 @@ where
             next,
             PrimStmt::AsgnIndex(arr_id, ix_v.clone(), to_e.clone()),
         )?;
+        self.refresh_procinfo(arr, next)?;
         Ok((next, PrimExpr::Index(arr_v, ix_v)))
     }
 
+    /// If `arr` is PROCINFO, store it back into itself after it has been modified. Settings
+    /// derived from its contents, like `PROCINFO["sorted_in"]`, are refreshed when PROCINFO is
+    /// stored rather than looked up on every use.
+    fn refresh_procinfo<'c>(
+        &mut self,
+        arr: &'c Expr<'c, 'b, I>,
+        current_open: NodeIx,
+    ) -> Result<()> {
+        use builtins::Variable::PROCINFO;
+        if let Expr::Var(id) = arr {
+            if let Ok(PROCINFO) = builtins::Variable::try_from(id.clone()) {
+                self.add_stmt(
+                    current_open,
+                    PrimStmt::SetBuiltin(PROCINFO, PrimExpr::LoadBuiltin(PROCINFO)),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
     fn do_condition<'c>(
         &mut self,
         cond: &'c Expr<'c, 'b, I>,
@@ -1481,10 +1825,14 @@ where
             return err!("{} statement must be inside a loop", name);
         }
         match self.f.loop_ctx.last().cloned() {
+            // Break statements unconditionally jump to the end of the loop (or switch).
+            // Continue statements jump to the beginning.
             Some((header, footer)) => {
-                // Break statements unconditionally jump to the end of the loop.
-                // Continue statements jump to the beginning.
-                let dst = if is_break { footer } else { header };
+                let dst = match (is_break, header) {
+                    (true, _) => footer,
+                    (false, Some(header)) => header,
+                    (false, None) => return err!("{} statement must be inside a loop", name),
+                };
                 self.f.cfg.add_edge(current_open, dst, Transition::null());
                 self.seal(current_open);
                 Ok(())
@@ -1495,6 +1843,118 @@ where
         }
     }
 
+    // Lower a switch statement. The subject is evaluated once. If every label is a string or a
+    // regex, a single call to MatchCases finds the first matching case; otherwise we compare
+    // against each label in turn. Either way we then branch to the start of that case's body,
+    // with each body falling through to the next.
+    fn do_switch<'c>(
+        &mut self,
+        subject: &'c Expr<'c, 'b, I>,
+        cases: &'c [(Option<&'c Expr<'c, 'b, I>>, &'c Stmt<'c, 'b, I>)],
+        current_open: NodeIx,
+    ) -> Result<NodeIx> {
+        if cases.iter().filter(|(label, _)| label.is_none()).count() > 1 {
+            return err!("duplicate default in switch");
+        }
+        let (mut open, subject_v) = self.convert_val(subject, current_open)?;
+        let footer = self.f.cfg.add_node(Default::default());
+
+        // `continue` inside a switch applies to the enclosing loop, but not to the toplevel loop.
+        let cont = match self.f.loop_ctx.last() {
+            Some((cont, _))
+                if !(self.f.loop_ctx.len() == 1 && self.f.toplevel_header.is_some()) =>
+            {
+                *cont
+            }
+            _ => None,
+        };
+        self.f.loop_ctx.push((cont, footer));
+        let mut bodies = Vec::with_capacity(cases.len());
+        for (_, body) in cases.iter() {
+            bodies.push(self.standalone_block(body)?);
+        }
+        self.f.loop_ctx.pop().unwrap();
+        for (i, (_, end)) in bodies.iter().enumerate() {
+            match bodies.get(i + 1) {
+                Some((next, _)) => self.guarded_else(*end, *next),
+                // As with the else branch of an `if`, this edge ensures the footer is reachable.
+                None => {
+                    self.f.cfg.add_edge(*end, footer, Transition::null());
+                }
+            }
+        }
+
+        let labels: SmallVec<(usize, &Expr<I>)> = cases
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (label, _))| label.map(|l| (i, l)))
+            .collect();
+        let hashable = labels.iter().all(|(_, l)| match l {
+            Expr::StrLit(s) | Expr::PatLit(s) => std::str::from_utf8(s).is_ok(),
+            _ => false,
+        });
+        let case_index = if hashable && !labels.is_empty() {
+            let spec = runtime::encode_cases(labels.iter().map(|(_, l)| match l {
+                Expr::PatLit(s) => (true, *s),
+                Expr::StrLit(s) => (false, *s),
+                _ => unreachable!(),
+            }));
+            let spec = self.arena.alloc_bytes(&spec[..]);
+            let ix = self.fresh_local();
+            self.add_stmt(
+                open,
+                PrimStmt::AsgnVar(
+                    ix,
+                    PrimExpr::CallBuiltin(
+                        builtins::Function::MatchCases,
+                        smallvec![subject_v.clone(), PrimVal::StrLit(spec)],
+                    ),
+                ),
+            )?;
+            Some(ix)
+        } else {
+            None
+        };
+        for (j, (i, label)) in labels.iter().enumerate() {
+            let (func, args) = match (case_index, label) {
+                (Some(ix), _) => (
+                    ast::Binop::EQ,
+                    smallvec![PrimVal::Var(ix), PrimVal::ILit(j as i64)],
+                ),
+                (None, Expr::PatLit(s)) => (
+                    ast::Binop::IsMatch,
+                    smallvec![subject_v.clone(), PrimVal::StrLit(s)],
+                ),
+                (None, _) => {
+                    let (next, label_v) = self.convert_val(label, open)?;
+                    open = next;
+                    (ast::Binop::EQ, smallvec![subject_v.clone(), label_v])
+                }
+            };
+            let is_case = self.fresh_local();
+            self.add_stmt(
+                open,
+                PrimStmt::AsgnVar(
+                    is_case,
+                    PrimExpr::CallBuiltin(builtins::Function::Binop(func), args),
+                ),
+            )?;
+            self.f
+                .cfg
+                .add_edge(open, bodies[*i].0, Transition::new(PrimVal::Var(is_case)));
+            let next = self.f.cfg.add_node(Default::default());
+            self.f.cfg.add_edge(open, next, Transition::null());
+            open = next;
+        }
+        let default = cases
+            .iter()
+            .position(|(label, _)| label.is_none())
+            .map(|i| bodies[i].0)
+            .unwrap_or(footer);
+        self.f.cfg.add_edge(open, default, Transition::null());
+        Ok(footer)
+    }
+
     fn set_cond(&mut self, current_open: NodeIx, cond: usize, cond_val: i64) -> Result<()> {
         let cond_ident = self.get_cond(cond);
         self.add_stmt(
@@ -1505,28 +1965,87 @@ where
 
     // Handles "next", "nextfile" statements.
     fn do_next(&mut self, current_open: NodeIx, is_next_file: bool) -> Result<()> {
-        if let Some(header) = self.f.toplevel_header {
-            if is_next_file {
-                self.add_stmt(
-                    current_open,
-                    PrimStmt::AsgnVar(
-                        Ident::unused(),
-                        PrimExpr::CallBuiltin(builtins::Function::NextFile, smallvec![]),
-                    ),
-                )?;
-            }
-            self.f
-                .cfg
-                .add_edge(current_open, header, Transition::null());
-            self.seal(current_open);
-            Ok(())
-        } else {
-            err!(
-                "Cannot use `{}` from outside of the toplevel loop! \
-                 Note that frawk does not support `next` or `nextfile` from inside functions.",
+        if self.f.toplevel_header.is_none() && !self.in_udf() {
+            return err!(
+                "Cannot use `{}` from outside of the toplevel loop!",
                 if is_next_file { "nextfile" } else { "next" }
-            )
+            );
+        }
+        if is_next_file {
+            self.add_stmt(
+                current_open,
+                PrimStmt::AsgnVar(
+                    Ident::unused(),
+                    PrimExpr::CallBuiltin(builtins::Function::NextFile, smallvec![]),
+                ),
+            )?;
+        }
+        if self.in_udf() {
+            // Signal the caller to continue the toplevel loop.
+            self.f.uses_next = true;
+            let flag = self.ctx.next_flag.unwrap();
+            self.add_stmt(
+                current_open,
+                PrimStmt::AsgnVar(flag, PrimExpr::Val(PrimVal::ILit(1))),
+            )?;
         }
+        self.exit_to_toplevel(current_open)
+    }
+
+    // Jump to the start of the toplevel loop or, from within a function, return to the caller.
+    fn exit_to_toplevel(&mut self, current_open: NodeIx) -> Result<()> {
+        let dst = if let Some(header) = self.f.toplevel_header {
+            header
+        } else {
+            self.add_stmt(
+                current_open,
+                PrimStmt::AsgnVar(self.f.ret, PrimExpr::Val(PrimVal::Var(Ident::unused()))),
+            )?;
+            self.f.exit
+        };
+        self.f.cfg.add_edge(current_open, dst, Transition::null());
+        self.seal(current_open);
+        Ok(())
+    }
+
+    // Emits a call to `f`, followed by a check for whether `f` executed `next` if any function
+    // might do so.
+    fn call_udf(
+        &mut self,
+        current_open: NodeIx,
+        f: NumTy,
+        args: SmallVec<PrimVal<'b>>,
+    ) -> Result<(NodeIx, PrimExpr<'b>)> {
+        let call = PrimExpr::CallUDF(f, args);
+        let flag = match self.ctx.next_flag {
+            Some(flag) => flag,
+            None => return Ok((current_open, call)),
+        };
+        let can_propagate = self.in_udf() || self.f.toplevel_header.is_some();
+        self.f.callees.push((f, can_propagate));
+        if !can_propagate {
+            // This is an error if `f` can execute `next`; see check_next_calls.
+            return Ok((current_open, call));
+        }
+        let res = self.to_val(call, current_open)?;
+        let next = self.f.cfg.add_node(Default::default());
+        let exit = self.f.cfg.add_node(Default::default());
+        self.f
+            .cfg
+            .add_edge(current_open, exit, Transition::new(PrimVal::Var(flag)));
+        self.f.cfg.add_edge(current_open, next, Transition::null());
+        if !self.in_udf() {
+            self.add_stmt(
+                exit,
+                PrimStmt::AsgnVar(flag, PrimExpr::Val(PrimVal::ILit(0))),
+            )?;
+        }
+        self.exit_to_toplevel(exit)?;
+        Ok((next, PrimExpr::Val(res)))
+    }
+
+    fn in_udf(&self) -> bool {
+        matches!(self.f.name, FunctionName::Named(_))
     }
 
     fn make_loop<'c>(
@@ -1545,7 +2064,7 @@ where
         // Create header and footer nodes.
         let h = self.f.cfg.add_node(Default::default());
         let f = self.f.cfg.add_node(Default::default());
-        self.f.loop_ctx.push((h, f));
+        self.f.loop_ctx.push((Some(h), f));
         if is_toplevel {
             self.f.toplevel_header = Some(h);
         }
@@ -1558,6 +2077,10 @@ where
         } else {
             self.standalone_block(body)?
         };
+        if is_toplevel {
+            // `next` cannot be used from any END blocks following the loop.
+            self.f.toplevel_header = None;
+        }
 
         // do-while loops start by running the loop body.
         // The last few edges here are added after make_loop returns to convert_stmt.
@@ -1589,6 +2112,91 @@ where
         })
     }
 
+    // Indirect calls `@f(args)` are desugared into a chain of comparisons against the names of
+    // each candidate function:
+    //   f == "name1" ? name1(args) : f == "name2" ? name2(args) : ...
+    // If `f` names none of them, the result is uninitialized. Candidates are the functions that
+    // accept at least as many arguments as are passed, and whose parameters are not used as arrays
+    // where a scalar is passed (or vice versa); otherwise type inference would fail.
+    fn indirect_call<'c>(
+        &mut self,
+        current_open: NodeIx,
+        callee: &'c Expr<'c, 'b, I>,
+        args: &'c [&'c Expr<'c, 'b, I>],
+    ) -> Result<(NodeIx, PrimExpr<'b>)> {
+        let caller_params = self
+            .indirect
+            .funcs
+            .iter()
+            .find(|(name, _)| self.f.name == FunctionName::Named(name.clone()))
+            .map(|(_, params)| params);
+        let arg_uses: SmallVec<UsedAs> = args
+            .iter()
+            .map(|arg| match arg {
+                Expr::Var(v) => match self.f.args.iter().position(|a| &a.name == v) {
+                    Some(i) => caller_params.map_or(UsedAs::Unknown, |ps| ps[i]),
+                    None if self.indirect.global_arrays.contains(v) => UsedAs::Array,
+                    None => UsedAs::Scalar,
+                },
+                Expr::Index(..) => UsedAs::Unknown,
+                _ => UsedAs::Scalar,
+            })
+            .collect();
+        let candidates: Vec<I> = self
+            .indirect
+            .funcs
+            .iter()
+            .filter(|(_, params)| {
+                params.len() >= args.len()
+                    && arg_uses.iter().zip(params.iter()).all(|uses| {
+                        !matches!(
+                            uses,
+                            (UsedAs::Scalar, UsedAs::Array) | (UsedAs::Array, UsedAs::Scalar)
+                        )
+                    })
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+        let (mut open, callee_v) = self.convert_val(callee, current_open)?;
+        let res_id = self.fresh_local();
+        self.ctx.may_rename.push(res_id);
+        let next = self.f.cfg.add_node(Default::default());
+        for name in candidates {
+            let name_lit = self.arena.alloc_bytes(name.to_string().as_bytes());
+            let is_name = self.fresh_local();
+            self.add_stmt(
+                open,
+                PrimStmt::AsgnVar(
+                    is_name,
+                    PrimExpr::CallBuiltin(
+                        builtins::Function::Binop(ast::Binop::EQ),
+                        smallvec![callee_v.clone(), PrimVal::StrLit(name_lit)],
+                    ),
+                ),
+            )?;
+            let t_start = self.f.cfg.add_node(Default::default());
+            let (t_end, t_res) = self.call(t_start, &Either::Left(name), args)?;
+            self.add_stmt(t_end, PrimStmt::AsgnVar(res_id, t_res))?;
+            self.f
+                .cfg
+                .add_edge(open, t_start, Transition::new(PrimVal::Var(is_name)));
+            self.guarded_else(t_end, next);
+            let f_start = self.f.cfg.add_node(Default::default());
+            self.f.cfg.add_edge(open, f_start, Transition::null());
+            open = f_start;
+        }
+        // No function matched the callee's name: this is a runtime error.
+        self.add_stmt(
+            open,
+            PrimStmt::AsgnVar(
+                res_id,
+                PrimExpr::CallBuiltin(builtins::Function::UndefinedFunc, smallvec![callee_v]),
+            ),
+        )?;
+        self.f.cfg.add_edge(open, next, Transition::null());
+        Ok((next, PrimExpr::Val(PrimVal::Var(res_id))))
+    }
+
     fn call<'c>(
         &mut self,
         current_open: NodeIx,
@@ -1636,7 +2244,7 @@ where
                         .entry(None)
                         .or_insert_with(Vec::new)
                         .push((current_open.index(), None));
-                    Ok((open, PrimExpr::CallUDF(*i, prim_args)))
+                    self.call_udf(open, *i, prim_args)
                 } else {
                     err!("Call to unknown function \"{}\"", fname)
                 };
@@ -1656,6 +2264,26 @@ where
                     prim_args.push(PrimVal::Var(fs));
                 }
 
+                // patsplit(string, array) => patsplit(string, array, FPAT, seps)
+                // patsplit(string, array, re) => patsplit(string, array, re, seps)
+                // where `seps` is a fresh array that is discarded.
+                if bi == builtins::Function::PatSplit {
+                    if args.len() == 2 {
+                        let fpat = self.fresh_local();
+                        self.add_stmt(
+                            current_open,
+                            PrimStmt::AsgnVar(
+                                fpat.clone(),
+                                PrimExpr::LoadBuiltin(builtins::Variable::FPAT),
+                            ),
+                        )?;
+                        prim_args.push(PrimVal::Var(fpat));
+                    }
+                    if args.len() <= 3 {
+                        prim_args.push(PrimVal::Var(self.fresh_local()));
+                    }
+                }
+
                 // join_fields(start, end) => join_{c,t}sv (if in csv/tsv output mode)
                 // join_fields(start, end) => join_fields(start, end, OFS) (otherwise)
                 if bi == builtins::Function::JoinCols && args.len() == 2 {
@@ -1684,6 +2312,79 @@ where
                     prim_args.push(PrimVal::ILit(i64::max_value()));
                 }
 
+                // gensub(re, repl, how) => gensub(re, repl, how, $0). Unlike sub and gsub, gensub
+                // returns the substituted string rather than assigning it, so we need not do
+                // anything else here.
+                if bi == builtins::Function::GenSub && args.len() == 3 {
+                    let e = &Expr::Unop(ast::Unop::Column, &Expr::ILit(0));
+                    let (next, v) = self.convert_val(e, open)?;
+                    open = next;
+                    prim_args.push(v);
+                }
+
+                // match(s, re, arr) => the variant of match that fills in arr with capture groups.
+                if bi == builtins::Function::Match && args.len() == 3 {
+                    bi = builtins::Function::MatchGroups;
+                }
+
+                // asort(src) => asort(src, src, "")
+                // asort(src, dst) => asort(src, dst, "")
+                // (similarly for asorti)
+                if let builtins::Function::Asort | builtins::Function::Asorti = bi {
+                    if args.len() == 1 {
+                        prim_args.push(prim_args[0].clone());
+                    }
+                    if args.len() <= 2 {
+                        prim_args.push(PrimVal::StrLit(b""));
+                    }
+                }
+
+                // strftime() => strftime(DEFAULT_STRFTIME_FORMAT, systime(), 0)
+                // strftime(fmt) => strftime(fmt, systime(), 0)
+                // strftime(fmt, ts) => strftime(fmt, ts, 0)
+                if bi == builtins::Function::Strftime {
+                    if args.is_empty() {
+                        prim_args.push(PrimVal::StrLit(
+                            runtime::time::DEFAULT_STRFTIME_FORMAT.as_bytes(),
+                        ));
+                    }
+                    if args.len() <= 1 {
+                        let e = &Expr::Call(Either::Right(builtins::Function::Systime), &[]);
+                        let (next, v) = self.convert_val(e, open)?;
+                        open = next;
+                        prim_args.push(v);
+                    }
+                    if args.len() <= 2 {
+                        prim_args.push(PrimVal::ILit(0));
+                    }
+                }
+
+                // fflush() => fflush(""), which flushes all open output.
+                if bi == builtins::Function::FFlush && args.is_empty() {
+                    prim_args.push(PrimVal::StrLit(b""));
+                }
+
+                // close(name) => close(name, ""), which closes both ends of a coprocess.
+                if bi == builtins::Function::Close && args.len() == 1 {
+                    prim_args.push(PrimVal::StrLit(b""));
+                }
+
+                // mktime(spec) => mktime(spec, 0)
+                if bi == builtins::Function::Mktime && args.len() == 1 {
+                    prim_args.push(PrimVal::ILit(0));
+                }
+
+                // strptime(s) => strptime(s, "", 0)
+                // strptime(s, fmt) => strptime(s, fmt, 0)
+                if bi == builtins::Function::Strptime {
+                    if args.len() == 1 {
+                        prim_args.push(PrimVal::StrLit(b""));
+                    }
+                    if args.len() <= 2 {
+                        prim_args.push(PrimVal::ILit(0));
+                    }
+                }
+
                 // srand() => the special "reseed rng" function
                 if bi == builtins::Function::Srand && args.len() == 0 {
                     bi = builtins::Function::ReseedRng;
@@ -1998,7 +2699,7 @@ where
             };
             self.ctx.max as usize
         ];
-        for id in self.ctx.hm.values() {
+        for id in self.ctx.hm.values().chain(self.ctx.next_flag.iter()) {
             if id.global && self.ctx.local_globals.get(&id.low).is_none() {
                 state[id.low as usize].global = true;
             }
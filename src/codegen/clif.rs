@@ -170,7 +170,7 @@ fn ty_to_param(ty: compile::Ty, ptr_ty: Type) -> Result<AbiParam> {
     let clif_ty = match ty {
         Null | Int => types::I64,
         Float => types::F64,
-        MapIntInt | MapIntFloat | MapIntStr | MapStrInt | MapStrFloat | MapStrStr | Str => ptr_ty,
+        map_tys!() | Str => ptr_ty,
         IterInt | IterStr => return err!("attempt to take iterator as parameter"),
         // We assume that null parameters are omitted from the argument list ahead of time
         // Null => return err!("attempt to take null as parameter"),
@@ -188,8 +188,7 @@ fn ty_to_clifty(ty: compile::Ty, ptr_ty: Type) -> Result<Type> {
         Null | Int => Ok(types::I64),
         Float => Ok(types::F64),
         Str => Ok(types::I128),
-        MapIntInt | MapIntFloat | MapIntStr => Ok(ptr_ty),
-        MapStrInt | MapStrFloat | MapStrStr => Ok(ptr_ty),
+        map_tys!() => Ok(ptr_ty),
         IterInt | IterStr => err!("taking type of an iterator"),
     }
 }
@@ -658,6 +657,18 @@ impl<'a> View<'a> {
                     MapStrInt => external!(drop_strint),
                     MapStrFloat => external!(drop_strfloat),
                     MapStrStr => external!(drop_strstr),
+                    MapIntMapIntInt => external!(drop_int_intint),
+                    MapIntMapIntFloat => external!(drop_int_intfloat),
+                    MapIntMapIntStr => external!(drop_int_intstr),
+                    MapIntMapStrInt => external!(drop_int_strint),
+                    MapIntMapStrFloat => external!(drop_int_strfloat),
+                    MapIntMapStrStr => external!(drop_int_strstr),
+                    MapStrMapIntInt => external!(drop_str_intint),
+                    MapStrMapIntFloat => external!(drop_str_intfloat),
+                    MapStrMapIntStr => external!(drop_str_intstr),
+                    MapStrMapStrInt => external!(drop_str_strint),
+                    MapStrMapStrFloat => external!(drop_str_strfloat),
+                    MapStrMapStrStr => external!(drop_str_strstr),
                     Str => external!(drop_str),
                     _ => continue,
                 };
@@ -781,7 +792,7 @@ impl<'a> View<'a> {
                 let zero64 = self.builder.ins().iconst(types::I64, 0);
                 Ok(self.builder.ins().iconcat(zero64, zero64))
             }
-            MapIntInt | MapIntFloat | MapIntStr | MapStrInt | MapStrFloat | MapStrStr => {
+            map_tys!() => {
                 let alloc_fn = match ty {
                     MapIntInt => external!(alloc_intint),
                     MapIntFloat => external!(alloc_intfloat),
@@ -789,6 +800,18 @@ impl<'a> View<'a> {
                     MapStrInt => external!(alloc_strint),
                     MapStrFloat => external!(alloc_strfloat),
                     MapStrStr => external!(alloc_strstr),
+                    MapIntMapIntInt => external!(alloc_int_intint),
+                    MapIntMapIntFloat => external!(alloc_int_intfloat),
+                    MapIntMapIntStr => external!(alloc_int_intstr),
+                    MapIntMapStrInt => external!(alloc_int_strint),
+                    MapIntMapStrFloat => external!(alloc_int_strfloat),
+                    MapIntMapStrStr => external!(alloc_int_strstr),
+                    MapStrMapIntInt => external!(alloc_str_intint),
+                    MapStrMapIntFloat => external!(alloc_str_intfloat),
+                    MapStrMapIntStr => external!(alloc_str_intstr),
+                    MapStrMapStrInt => external!(alloc_str_strint),
+                    MapStrMapStrFloat => external!(alloc_str_strfloat),
+                    MapStrMapStrStr => external!(alloc_str_strstr),
                     _ => unreachable!(),
                 };
                 Ok(self.call_external(alloc_fn, &[]))
@@ -824,7 +847,7 @@ impl<'a> View<'a> {
                     self.builder.def_var(var, addr);
                     self.store_string(slot, default_v);
                 }
-                MapIntInt | MapIntFloat | MapIntStr | MapStrInt | MapStrFloat | MapStrStr => {
+                map_tys!() => {
                     self.builder.def_var(var, default_v);
                 }
                 IterInt | IterStr => return err!("attempting to default-initialize iterator type"),
@@ -851,7 +874,7 @@ impl<'a> View<'a> {
                 let ptr_ty = self.ptr_to(cl_ty);
                 self.builder.declare_var(next_var, ptr_ty);
             }
-            MapIntInt | MapIntFloat | MapIntStr | MapStrInt | MapStrFloat | MapStrStr => {
+            map_tys!() => {
                 self.builder.declare_var(next_var, cl_ty);
             }
             IterInt | IterStr => return err!("iterators cannot be declared"),
@@ -873,8 +896,7 @@ impl<'a> View<'a> {
                 self.builder.declare_var(base, self.void_ptr_ty());
                 Ok(IterState { bytes, cur, base })
             }
-            Null | Int | Float | Str | MapIntInt | MapIntFloat | MapIntStr | MapStrInt
-            | MapStrFloat | MapStrStr => err!(
+            Null | Int | Float | Str | map_tys!() => err!(
                 "attempting to declare iterator variable for non-iterator type: {:?}",
                 ty
             ),
@@ -887,7 +909,7 @@ impl<'a> View<'a> {
     fn ref_val(&mut self, ty: compile::Ty, v: Value) {
         use compile::Ty::*;
         let func = match ty {
-            MapIntInt | MapIntFloat | MapIntStr | MapStrInt | MapStrFloat | MapStrStr => {
+            map_tys!() => {
                 external!(ref_map)
             }
             Str => external!(ref_str),
@@ -908,6 +930,18 @@ impl<'a> View<'a> {
             MapStrInt => external!(drop_strint),
             MapStrFloat => external!(drop_strfloat),
             MapStrStr => external!(drop_strstr),
+            MapIntMapIntInt => external!(drop_int_intint),
+            MapIntMapIntFloat => external!(drop_int_intfloat),
+            MapIntMapIntStr => external!(drop_int_intstr),
+            MapIntMapStrInt => external!(drop_int_strint),
+            MapIntMapStrFloat => external!(drop_int_strfloat),
+            MapIntMapStrStr => external!(drop_int_strstr),
+            MapStrMapIntInt => external!(drop_str_intint),
+            MapStrMapIntFloat => external!(drop_str_intfloat),
+            MapStrMapIntStr => external!(drop_str_intstr),
+            MapStrMapStrInt => external!(drop_str_strint),
+            MapStrMapStrFloat => external!(drop_str_strfloat),
+            MapStrMapStrStr => external!(drop_str_strstr),
             Str => external!(drop_str),
             Null | Int | Float | IterInt | IterStr => return,
         };
@@ -1134,7 +1168,7 @@ impl<'a> View<'a> {
                 self.drop_val(Str, p);
                 self.builder.ins().store(MemFlags::trusted(), v, p, 0);
             }
-            MapIntInt | MapIntFloat | MapIntStr | MapStrInt | MapStrFloat | MapStrStr => {
+            map_tys!() => {
                 if let VarKind::Global = kind {
                     // Drop the value currently in the pointer
                     let p = self.builder.use_var(var);
@@ -1175,7 +1209,7 @@ impl<'a> View<'a> {
                 let loaded = self.builder.ins().load(str_ty, MemFlags::trusted(), src, 0);
                 self.bind_val_inner((dst, Str), loaded, skip_drop)?;
             }
-            MapIntInt | MapIntFloat | MapIntStr | MapStrInt | MapStrFloat | MapStrStr => {
+            map_tys!() => {
                 self.call_external_void(external!(ref_map), &[src]);
                 self.bind_val_inner((dst, ty), src, skip_drop)?;
             }
@@ -1322,8 +1356,7 @@ impl<'a> CodeGenerator for View<'a> {
         let val = self.builder.use_var(var);
 
         match r.1 {
-            MapIntInt | MapIntFloat | MapIntStr | MapStrInt | MapStrFloat | MapStrStr | Int
-            | Float => {
+            map_tys!() | Int | Float => {
                 if is_global {
                     let ty = self.get_ty(r.1);
                     Ok(self.builder.ins().load(ty, MemFlags::trusted(), val, 0))
@@ -1497,6 +1530,18 @@ impl<'a> CodeGenerator for View<'a> {
             MapIntFloat => (external!(len_intfloat), external!(iter_intfloat)),
             MapStrInt => (external!(len_strint), external!(iter_strint)),
             MapStrStr => (external!(len_strstr), external!(iter_strstr)),
+            MapIntMapIntInt => (external!(len_int_intint), external!(iter_int_intint)),
+            MapIntMapIntFloat => (external!(len_int_intfloat), external!(iter_int_intfloat)),
+            MapIntMapIntStr => (external!(len_int_intstr), external!(iter_int_intstr)),
+            MapIntMapStrInt => (external!(len_int_strint), external!(iter_int_strint)),
+            MapIntMapStrFloat => (external!(len_int_strfloat), external!(iter_int_strfloat)),
+            MapIntMapStrStr => (external!(len_int_strstr), external!(iter_int_strstr)),
+            MapStrMapIntInt => (external!(len_str_intint), external!(iter_str_intint)),
+            MapStrMapIntFloat => (external!(len_str_intfloat), external!(iter_str_intfloat)),
+            MapStrMapIntStr => (external!(len_str_intstr), external!(iter_str_intstr)),
+            MapStrMapStrInt => (external!(len_str_strint), external!(iter_str_strint)),
+            MapStrMapStrFloat => (external!(len_str_strfloat), external!(iter_str_strfloat)),
+            MapStrMapStrStr => (external!(len_str_strstr), external!(iter_str_strstr)),
             MapStrFloat => (external!(len_strfloat), external!(iter_strfloat)),
             IterInt | IterStr | Int | Float | Str | Null => {
                 return err!("iterating over non-map type: {:?}", map.1)
@@ -1505,7 +1550,8 @@ impl<'a> CodeGenerator for View<'a> {
         let key_ty = self.get_ty(dst.1.iter()?);
         let map = self.get_val(map)?;
         let IterState { bytes, cur, base } = self.get_iter(dst)?;
-        let ptr = self.call_external(begin_fn, &[map]);
+        let rt = self.runtime_val();
+        let ptr = self.call_external(begin_fn, &[rt, map]);
         let map_len = self.call_external(len_fn, &[map]);
         let total_bytes = self.mul_by_type_size(key_ty, map_len)?;
         let zero = self.const_int(0);
@@ -7,16 +7,19 @@ use super::{Backend, FunctionAttr, Sig};
 use crate::runtime::{
     self,
     printf::{printf, FormatArg},
+    sort::{SortIndex, SortKey},
     splitter::{
         batch::{ByteReader, CSVReader, WhitespaceOffsets},
         chunk::{ChunkProducer, OffsetChunk},
+        fixed::FixedWidthReader,
         regex::RegexSplitter,
     },
-    ChainedReader, FileRead, Float, Int, IntMap, Line, LineReader, RegexCache, Str, StrMap,
+    ChainedReader, ConstRegex, FileRead, Float, Int, IntMap, Line, LineReader, RegexCache, Str,
+    StrMap,
 };
 use crate::{
     builtins::Variable,
-    common::{CancelSignal, Cleanup, FileSpec, Notification, Result},
+    common::{CancelSignal, Cleanup, FileSpec, InputSpec, Notification, Result},
     compile::Ty,
     pushdown::FieldSet,
 };
@@ -24,10 +27,10 @@ use crate::{
 use libc::c_void;
 use paste::paste;
 use rand::{self, Rng};
-use regex::bytes::Regex;
 use smallvec;
 
 use std::convert::TryFrom;
+use std::hash::Hash;
 use std::io;
 use std::mem;
 use std::slice;
@@ -98,49 +101,66 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         drop_str_slow(str_ref_ty, int_ty);
         ref_map(map_ty);
         [ReadOnly] int_to_str(int_ty) -> str_ty;
-        [ReadOnly] float_to_str(float_ty) -> str_ty;
+        float_to_str(rt_ty, float_ty) -> str_ty;
+        float_to_str_out(rt_ty, float_ty) -> str_ty;
         [ReadOnly] str_to_int(str_ref_ty) -> int_ty;
         [ReadOnly] hex_str_to_int(str_ref_ty) -> int_ty;
         [ReadOnly] str_to_float(str_ref_ty) -> float_ty;
         [ReadOnly] str_len(str_ref_ty) -> int_ty;
+        [ReadOnly] str_len_chars(str_ref_ty) -> int_ty;
         starts_with_const(str_ref_ty, rt_ty, int_ty) -> int_ty;
         concat(str_ref_ty, str_ref_ty) -> str_ty;
         [ReadOnly] match_pat(rt_ty, str_ref_ty, str_ref_ty) -> int_ty;
-        [ReadOnly] match_const_pat(str_ref_ty, rt_ty) -> int_ty;
+        [ReadOnly] match_const_pat(rt_ty, str_ref_ty, rt_ty) -> int_ty;
+        [ReadOnly] match_cases(rt_ty, str_ref_ty, str_ref_ty) -> int_ty;
         [ReadOnly] match_pat_loc(rt_ty, str_ref_ty, str_ref_ty) -> int_ty;
         [ReadOnly] match_const_pat_loc(rt_ty, str_ref_ty, rt_ty) -> int_ty;
+        match_pat_loc_strmap(rt_ty, str_ref_ty, str_ref_ty, map_ty) -> int_ty;
         [ReadOnly] substr_index(str_ref_ty, str_ref_ty) -> int_ty;
+        [ReadOnly] substr_index_chars(str_ref_ty, str_ref_ty) -> int_ty;
         subst_first(rt_ty, str_ref_ty, str_ref_ty, str_ref_ty) -> int_ty;
         subst_all(rt_ty, str_ref_ty, str_ref_ty, str_ref_ty) -> int_ty;
+        gen_subst(rt_ty, str_ref_ty, str_ref_ty, str_ref_ty, str_ref_ty) -> str_ty;
         escape_csv(str_ref_ty) -> str_ty;
         escape_tsv(str_ref_ty) -> str_ty;
         substr(str_ref_ty, int_ty, int_ty) -> str_ty;
+        substr_chars(str_ref_ty, int_ty, int_ty) -> str_ty;
         [ReadOnly] get_col(rt_ty, int_ty) -> str_ty;
         [ReadOnly] join_csv(rt_ty, int_ty, int_ty) -> str_ty;
         [ReadOnly] join_tsv(rt_ty, int_ty, int_ty) -> str_ty;
         [ReadOnly] join_cols(rt_ty, int_ty, int_ty, str_ref_ty) -> str_ty;
         [ReadOnly] to_upper_ascii(str_ref_ty) -> str_ty;
         [ReadOnly] to_lower_ascii(str_ref_ty) -> str_ty;
+        [ReadOnly] to_upper(str_ref_ty) -> str_ty;
+        [ReadOnly] to_lower(str_ref_ty) -> str_ty;
         set_col(rt_ty, int_ty, str_ref_ty);
         split_int(rt_ty, str_ref_ty, map_ty, str_ref_ty) -> int_ty;
         split_str(rt_ty, str_ref_ty, map_ty, str_ref_ty) -> int_ty;
+        pat_split(rt_ty, str_ref_ty, map_ty, str_ref_ty, map_ty) -> int_ty;
         rand_float(rt_ty) -> float_ty;
+        systime() -> int_ty;
+        [ReadOnly] strftime(str_ref_ty, int_ty, int_ty) -> str_ty;
+        mktime(str_ref_ty, int_ty) -> int_ty;
+        strptime(str_ref_ty, str_ref_ty, int_ty) -> float_ty;
         seed_rng(rt_ty, int_ty) -> int_ty;
         reseed_rng(rt_ty) -> int_ty;
 
         exit(rt_ty, int_ty);
+        undefined_func(rt_ty, str_ref_ty);
         run_system(str_ref_ty) -> int_ty;
         print_all_stdout(rt_ty, pa_args_ty, int_ty);
         print_all_file(rt_ty, pa_args_ty, int_ty, str_ref_ty, int_ty);
         sprintf_impl(rt_ty, str_ref_ty, fmt_args_ty, fmt_tys_ty, int_ty) -> str_ty;
         printf_impl_file(rt_ty, str_ref_ty, fmt_args_ty, fmt_tys_ty, int_ty, str_ref_ty, int_ty);
         printf_impl_stdout(rt_ty, str_ref_ty, fmt_args_ty, fmt_tys_ty, int_ty);
-        close_file(rt_ty, str_ref_ty);
+        close_file(rt_ty, str_ref_ty, str_ref_ty) -> int_ty;
+        fflush(rt_ty, str_ref_ty) -> int_ty;
         read_err(rt_ty, str_ref_ty, int_ty) -> int_ty;
         read_err_stdin(rt_ty) -> int_ty;
         next_line(rt_ty, str_ref_ty, int_ty) -> str_ty;
         next_line_stdin(rt_ty) -> str_ty;
         next_line_stdin_fused(rt_ty);
+        next_line_stdin_event(rt_ty) -> int_ty;
         next_file(rt_ty);
         update_used_fields(rt_ty);
         set_fi_entry(rt_ty, int_ty, int_ty);
@@ -170,18 +190,55 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         store_var_intmap(rt_ty, int_ty, map_ty);
         [ReadOnly] load_var_strmap(rt_ty, int_ty) -> map_ty;
         store_var_strmap(rt_ty, int_ty, map_ty);
+        [ReadOnly] load_var_strstrmap(rt_ty, int_ty) -> map_ty;
+        store_var_strstrmap(rt_ty, int_ty, map_ty);
 
         [ReadOnly] str_lt(str_ref_ty, str_ref_ty) -> int_ty;
         [ReadOnly] str_gt(str_ref_ty, str_ref_ty) -> int_ty;
         [ReadOnly] str_lte(str_ref_ty, str_ref_ty) -> int_ty;
         [ReadOnly] str_gte(str_ref_ty, str_ref_ty) -> int_ty;
         [ReadOnly] str_eq(str_ref_ty, str_ref_ty) -> int_ty;
+        [ReadOnly] strnum_lt(str_ref_ty, str_ref_ty) -> int_ty;
+        [ReadOnly] strnum_gt(str_ref_ty, str_ref_ty) -> int_ty;
+        [ReadOnly] strnum_lte(str_ref_ty, str_ref_ty) -> int_ty;
+        [ReadOnly] strnum_gte(str_ref_ty, str_ref_ty) -> int_ty;
+        [ReadOnly] strnum_eq(str_ref_ty, str_ref_ty) -> int_ty;
+        strnum_float_lt(rt_ty, str_ref_ty, float_ty) -> int_ty;
+        strnum_float_gt(rt_ty, str_ref_ty, float_ty) -> int_ty;
+        strnum_float_lte(rt_ty, str_ref_ty, float_ty) -> int_ty;
+        strnum_float_gte(rt_ty, str_ref_ty, float_ty) -> int_ty;
+        strnum_float_eq(rt_ty, str_ref_ty, float_ty) -> int_ty;
 
         drop_iter_int(iter_int_ty, int_ty);
         drop_iter_str(iter_str_ty, int_ty);
 
+        asort_intint(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        asort_intfloat(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        asort_intstr(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        asort_strint(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        asort_strfloat(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        asort_strstr(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        asorti_intint(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        asorti_intfloat(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        asorti_intstr(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        asorti_strint(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        asorti_strfloat(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        asorti_strstr(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        asort_intint_strkeys(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        asort_intfloat_strkeys(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        asort_intstr_strkeys(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        asort_strint_strkeys(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        asort_strfloat_strkeys(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        asort_strstr_strkeys(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        asorti_intint_strkeys(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        asorti_intfloat_strkeys(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        asorti_intstr_strkeys(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        asorti_strint_strkeys(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        asorti_strfloat_strkeys(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        asorti_strstr_strkeys(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+
         alloc_intint() -> map_ty;
-        iter_intint(map_ty) -> iter_int_ty;
+        iter_intint(rt_ty, map_ty) -> iter_int_ty;
         [ReadOnly] len_intint(map_ty) -> int_ty;
         [ReadOnly] lookup_intint(map_ty, int_ty) -> int_ty;
         [ReadOnly] contains_intint(map_ty, int_ty) -> int_ty;
@@ -193,7 +250,7 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         inc_float_intint(map_ty, int_ty, float_ty) -> int_ty;
 
         alloc_intfloat() -> map_ty;
-        iter_intfloat(map_ty) -> iter_int_ty;
+        iter_intfloat(rt_ty, map_ty) -> iter_int_ty;
         [ReadOnly] len_intfloat(map_ty) -> int_ty;
         [ReadOnly] lookup_intfloat(map_ty, int_ty) -> float_ty;
         [ReadOnly] contains_intfloat(map_ty, int_ty) -> int_ty;
@@ -205,7 +262,7 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         inc_float_intfloat(map_ty, int_ty, float_ty) -> float_ty;
 
         alloc_intstr() -> map_ty;
-        iter_intstr(map_ty) -> iter_int_ty;
+        iter_intstr(rt_ty, map_ty) -> iter_int_ty;
         [ReadOnly] len_intstr(map_ty) -> int_ty;
         [ReadOnly] lookup_intstr(map_ty, int_ty) -> str_ty;
         [ReadOnly] contains_intstr(map_ty, int_ty) -> int_ty;
@@ -217,7 +274,7 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         inc_float_intstr(map_ty, int_ty, float_ty) -> str_ty;
 
         alloc_strint() -> map_ty;
-        iter_strint(map_ty) -> iter_str_ty;
+        iter_strint(rt_ty, map_ty) -> iter_str_ty;
         [ReadOnly] len_strint(map_ty) -> int_ty;
         [ReadOnly] lookup_strint(map_ty, str_ref_ty) -> int_ty;
         [ReadOnly] contains_strint(map_ty, str_ref_ty) -> int_ty;
@@ -229,7 +286,7 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         inc_float_strint(map_ty, str_ref_ty, float_ty) -> int_ty;
 
         alloc_strfloat() -> map_ty;
-        iter_strfloat(map_ty) -> iter_str_ty;
+        iter_strfloat(rt_ty, map_ty) -> iter_str_ty;
         [ReadOnly] len_strfloat(map_ty) -> int_ty;
         [ReadOnly] lookup_strfloat(map_ty, str_ref_ty) -> float_ty;
         [ReadOnly] contains_strfloat(map_ty, str_ref_ty) -> int_ty;
@@ -241,7 +298,7 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         inc_float_strfloat(map_ty, str_ref_ty, float_ty) -> float_ty;
 
         alloc_strstr() -> map_ty;
-        iter_strstr(map_ty) -> iter_str_ty;
+        iter_strstr(rt_ty, map_ty) -> iter_str_ty;
         [ReadOnly] len_strstr(map_ty) -> int_ty;
         [ReadOnly] lookup_strstr(map_ty, str_ref_ty) -> str_ty;
         [ReadOnly] contains_strstr(map_ty, str_ref_ty) -> int_ty;
@@ -251,6 +308,117 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         drop_strstr(map_ty);
         inc_int_strstr(map_ty, str_ref_ty, int_ty) -> str_ty;
         inc_float_strstr(map_ty, str_ref_ty, float_ty) -> str_ty;
+    };
+
+    // Split to stay under the recursion limit.
+    register! {
+        alloc_int_intint() -> map_ty;
+        iter_int_intint(rt_ty, map_ty) -> iter_int_ty;
+        [ReadOnly] len_int_intint(map_ty) -> int_ty;
+        lookup_int_intint(map_ty, int_ty) -> map_ty;
+        [ReadOnly] contains_int_intint(map_ty, int_ty) -> int_ty;
+        delete_int_intint(map_ty, int_ty);
+        clear_int_intint(map_ty);
+        drop_int_intint(map_ty);
+
+        alloc_int_intfloat() -> map_ty;
+        iter_int_intfloat(rt_ty, map_ty) -> iter_int_ty;
+        [ReadOnly] len_int_intfloat(map_ty) -> int_ty;
+        lookup_int_intfloat(map_ty, int_ty) -> map_ty;
+        [ReadOnly] contains_int_intfloat(map_ty, int_ty) -> int_ty;
+        delete_int_intfloat(map_ty, int_ty);
+        clear_int_intfloat(map_ty);
+        drop_int_intfloat(map_ty);
+
+        alloc_int_intstr() -> map_ty;
+        iter_int_intstr(rt_ty, map_ty) -> iter_int_ty;
+        [ReadOnly] len_int_intstr(map_ty) -> int_ty;
+        lookup_int_intstr(map_ty, int_ty) -> map_ty;
+        [ReadOnly] contains_int_intstr(map_ty, int_ty) -> int_ty;
+        delete_int_intstr(map_ty, int_ty);
+        clear_int_intstr(map_ty);
+        drop_int_intstr(map_ty);
+
+        alloc_int_strint() -> map_ty;
+        iter_int_strint(rt_ty, map_ty) -> iter_int_ty;
+        [ReadOnly] len_int_strint(map_ty) -> int_ty;
+        lookup_int_strint(map_ty, int_ty) -> map_ty;
+        [ReadOnly] contains_int_strint(map_ty, int_ty) -> int_ty;
+        delete_int_strint(map_ty, int_ty);
+        clear_int_strint(map_ty);
+        drop_int_strint(map_ty);
+
+        alloc_int_strfloat() -> map_ty;
+        iter_int_strfloat(rt_ty, map_ty) -> iter_int_ty;
+        [ReadOnly] len_int_strfloat(map_ty) -> int_ty;
+        lookup_int_strfloat(map_ty, int_ty) -> map_ty;
+        [ReadOnly] contains_int_strfloat(map_ty, int_ty) -> int_ty;
+        delete_int_strfloat(map_ty, int_ty);
+        clear_int_strfloat(map_ty);
+        drop_int_strfloat(map_ty);
+
+        alloc_int_strstr() -> map_ty;
+        iter_int_strstr(rt_ty, map_ty) -> iter_int_ty;
+        [ReadOnly] len_int_strstr(map_ty) -> int_ty;
+        lookup_int_strstr(map_ty, int_ty) -> map_ty;
+        [ReadOnly] contains_int_strstr(map_ty, int_ty) -> int_ty;
+        delete_int_strstr(map_ty, int_ty);
+        clear_int_strstr(map_ty);
+        drop_int_strstr(map_ty);
+
+        alloc_str_intint() -> map_ty;
+        iter_str_intint(rt_ty, map_ty) -> iter_str_ty;
+        [ReadOnly] len_str_intint(map_ty) -> int_ty;
+        lookup_str_intint(map_ty, str_ref_ty) -> map_ty;
+        [ReadOnly] contains_str_intint(map_ty, str_ref_ty) -> int_ty;
+        delete_str_intint(map_ty, str_ref_ty);
+        clear_str_intint(map_ty);
+        drop_str_intint(map_ty);
+
+        alloc_str_intfloat() -> map_ty;
+        iter_str_intfloat(rt_ty, map_ty) -> iter_str_ty;
+        [ReadOnly] len_str_intfloat(map_ty) -> int_ty;
+        lookup_str_intfloat(map_ty, str_ref_ty) -> map_ty;
+        [ReadOnly] contains_str_intfloat(map_ty, str_ref_ty) -> int_ty;
+        delete_str_intfloat(map_ty, str_ref_ty);
+        clear_str_intfloat(map_ty);
+        drop_str_intfloat(map_ty);
+
+        alloc_str_intstr() -> map_ty;
+        iter_str_intstr(rt_ty, map_ty) -> iter_str_ty;
+        [ReadOnly] len_str_intstr(map_ty) -> int_ty;
+        lookup_str_intstr(map_ty, str_ref_ty) -> map_ty;
+        [ReadOnly] contains_str_intstr(map_ty, str_ref_ty) -> int_ty;
+        delete_str_intstr(map_ty, str_ref_ty);
+        clear_str_intstr(map_ty);
+        drop_str_intstr(map_ty);
+
+        alloc_str_strint() -> map_ty;
+        iter_str_strint(rt_ty, map_ty) -> iter_str_ty;
+        [ReadOnly] len_str_strint(map_ty) -> int_ty;
+        lookup_str_strint(map_ty, str_ref_ty) -> map_ty;
+        [ReadOnly] contains_str_strint(map_ty, str_ref_ty) -> int_ty;
+        delete_str_strint(map_ty, str_ref_ty);
+        clear_str_strint(map_ty);
+        drop_str_strint(map_ty);
+
+        alloc_str_strfloat() -> map_ty;
+        iter_str_strfloat(rt_ty, map_ty) -> iter_str_ty;
+        [ReadOnly] len_str_strfloat(map_ty) -> int_ty;
+        lookup_str_strfloat(map_ty, str_ref_ty) -> map_ty;
+        [ReadOnly] contains_str_strfloat(map_ty, str_ref_ty) -> int_ty;
+        delete_str_strfloat(map_ty, str_ref_ty);
+        clear_str_strfloat(map_ty);
+        drop_str_strfloat(map_ty);
+
+        alloc_str_strstr() -> map_ty;
+        iter_str_strstr(rt_ty, map_ty) -> iter_str_ty;
+        [ReadOnly] len_str_strstr(map_ty) -> int_ty;
+        lookup_str_strstr(map_ty, str_ref_ty) -> map_ty;
+        [ReadOnly] contains_str_strstr(map_ty, str_ref_ty) -> int_ty;
+        delete_str_strstr(map_ty, str_ref_ty);
+        clear_str_strstr(map_ty);
+        drop_str_strstr(map_ty);
 
         load_slot_int(rt_ty, int_ty) -> int_ty;
         load_slot_float(rt_ty, int_ty) -> float_ty;
@@ -261,6 +429,18 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         load_slot_strint(rt_ty, int_ty) -> map_ty;
         load_slot_strfloat(rt_ty, int_ty) -> map_ty;
         load_slot_strstr(rt_ty, int_ty) -> map_ty;
+        load_slot_int_intint(rt_ty, int_ty) -> map_ty;
+        load_slot_int_intfloat(rt_ty, int_ty) -> map_ty;
+        load_slot_int_intstr(rt_ty, int_ty) -> map_ty;
+        load_slot_int_strint(rt_ty, int_ty) -> map_ty;
+        load_slot_int_strfloat(rt_ty, int_ty) -> map_ty;
+        load_slot_int_strstr(rt_ty, int_ty) -> map_ty;
+        load_slot_str_intint(rt_ty, int_ty) -> map_ty;
+        load_slot_str_intfloat(rt_ty, int_ty) -> map_ty;
+        load_slot_str_intstr(rt_ty, int_ty) -> map_ty;
+        load_slot_str_strint(rt_ty, int_ty) -> map_ty;
+        load_slot_str_strfloat(rt_ty, int_ty) -> map_ty;
+        load_slot_str_strstr(rt_ty, int_ty) -> map_ty;
 
         store_slot_int(rt_ty, int_ty, int_ty);
         store_slot_float(rt_ty, int_ty, float_ty);
@@ -271,6 +451,18 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         store_slot_strint(rt_ty, int_ty, map_ty);
         store_slot_strfloat(rt_ty, int_ty, map_ty);
         store_slot_strstr(rt_ty, int_ty, map_ty);
+        store_slot_int_intint(rt_ty, int_ty, map_ty);
+        store_slot_int_intfloat(rt_ty, int_ty, map_ty);
+        store_slot_int_intstr(rt_ty, int_ty, map_ty);
+        store_slot_int_strint(rt_ty, int_ty, map_ty);
+        store_slot_int_strfloat(rt_ty, int_ty, map_ty);
+        store_slot_int_strstr(rt_ty, int_ty, map_ty);
+        store_slot_str_intint(rt_ty, int_ty, map_ty);
+        store_slot_str_intfloat(rt_ty, int_ty, map_ty);
+        store_slot_str_intstr(rt_ty, int_ty, map_ty);
+        store_slot_str_strint(rt_ty, int_ty, map_ty);
+        store_slot_str_strfloat(rt_ty, int_ty, map_ty);
+        store_slot_str_strstr(rt_ty, int_ty, map_ty);
     };
     Ok(())
 }
@@ -279,6 +471,7 @@ macro_rules! fail {
     ($rt:expr, $($es:expr),+) => {{
         #[cfg(test)]
         {
+            let _ = $rt;
             eprintln_ignore!("failure in runtime {}. Halting execution", format!($($es),*));
             panic!("failure in runtime")
         }
@@ -354,6 +547,8 @@ macro_rules! with_input {
             $crate::codegen::intrinsics::InputData::V2($p) => $body,
             $crate::codegen::intrinsics::InputData::V3($p) => $body,
             $crate::codegen::intrinsics::InputData::V4($p) => $body,
+            $crate::codegen::intrinsics::InputData::V5($p) => $body,
+            $crate::codegen::intrinsics::InputData::V6($p) => $body,
         }
     };
 }
@@ -364,6 +559,8 @@ pub(crate) enum InputData {
     V2(InputTuple<ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk<WhitespaceOffsets>>>>>),
     V3(InputTuple<ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>>>),
     V4(InputTuple<ChainedReader<RegexSplitter<Box<dyn io::Read + Send>>>>),
+    V5(InputTuple<ChainedReader<FixedWidthReader<Box<dyn io::Read + Send>>>>),
+    V6(InputTuple<ChainedReader<CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>>>>),
 }
 
 pub(crate) trait IntoRuntime {
@@ -386,13 +583,15 @@ macro_rules! impl_into_runtime {
                 named_columns: Option<Vec<&[u8]>>,
                 cancel_signal: CancelSignal,
             ) -> Runtime<'a> {
+                let mut core = crate::interp::Core::new(ff);
+                core.vars.utf8 = self.check_utf8();
                 Runtime {
                     concurrent: false,
                     input_data: InputData::$var((
                         Default::default(),
                         FileRead::new(self, used_fields.clone(), named_columns),
                     )),
-                    core: crate::interp::Core::new(ff),
+                    core,
                     cleanup: Cleanup::null(),
                     cancel_signal,
                 }
@@ -414,6 +613,14 @@ impl_into_runtime!(
 );
 impl_into_runtime!(ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>>, V3);
 impl_into_runtime!(ChainedReader<RegexSplitter<Box<dyn io::Read + Send>>>, V4);
+impl_into_runtime!(
+    ChainedReader<FixedWidthReader<Box<dyn io::Read + Send>>>,
+    V5
+);
+impl_into_runtime!(
+    ChainedReader<CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>>>,
+    V6
+);
 
 pub(crate) struct Runtime<'a> {
     pub(crate) core: crate::interp::Core<'a>,
@@ -443,6 +650,15 @@ pub(crate) unsafe extern "C" fn exit(runtime: *mut c_void, code: Int) {
     exit!(runtime, code as i32);
 }
 
+pub(crate) unsafe extern "C" fn undefined_func(runtime: *mut c_void, name: *mut U128) {
+    let name = &*(name as *mut Str);
+    fail!(
+        runtime,
+        "call to undefined function: {}",
+        name.with_bytes(|bs| String::from_utf8_lossy(bs).into_owned())
+    );
+}
+
 pub(crate) unsafe extern "C" fn run_system(cmd: *mut U128) -> Int {
     let s: &Str = &*(cmd as *mut Str);
     s.with_bytes(runtime::run_command)
@@ -466,17 +682,18 @@ pub(crate) unsafe extern "C" fn reseed_rng(runtime: *mut c_void) -> Int {
 pub(crate) unsafe extern "C" fn read_err(
     runtime: *mut c_void,
     file: *mut c_void,
-    is_file: Int,
+    spec: Int,
 ) -> Int {
     let runtime = &mut *(runtime as *mut Runtime);
+    let spec = try_abort!(runtime, InputSpec::try_from(spec));
     let res = try_abort!(
         runtime,
         with_input!(&mut runtime.input_data, |(_, read_files)| {
             let file = &*(file as *mut Str);
-            if is_file == 0 {
-                read_files.read_err_cmd(file)
-            } else {
-                read_files.read_err(file)
+            match spec {
+                InputSpec::File => read_files.read_err(file),
+                InputSpec::Cmd => read_files.read_err_cmd(file),
+                InputSpec::Coproc => read_files.read_err_coproc(file),
             }
         }),
         "unexpected error when reading error status of file:"
@@ -507,14 +724,35 @@ pub(crate) unsafe extern "C" fn next_line_stdin_fused(runtime: *mut c_void) {
     }
 }
 
+pub(crate) unsafe extern "C" fn next_line_stdin_event(runtime: *mut c_void) -> Int {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let (event, changed) = try_abort!(
+        runtime,
+        with_input!(&mut runtime.input_data, |(line, read_files)| {
+            runtime
+                .core
+                .regexes
+                .get_line_stdin_event(&runtime.core.vars.rs, read_files, line)
+        }),
+        "unexpected error when reading line from stdin:"
+    );
+    if changed {
+        runtime.reset_file_vars();
+    }
+    event
+}
+
 pub(crate) unsafe extern "C" fn next_file(runtime: *mut c_void) {
     let runtime = &mut *(runtime as *mut Runtime);
-    try_abort!(
+    let reset = try_abort!(
         runtime,
         with_input!(&mut runtime.input_data, |(_, read_files)| {
             read_files.next_file()
         })
     );
+    if reset {
+        runtime.reset_file_vars();
+    }
 }
 
 pub(crate) unsafe extern "C" fn next_line_stdin(runtime: *mut c_void) -> U128 {
@@ -538,15 +776,16 @@ pub(crate) unsafe extern "C" fn next_line_stdin(runtime: *mut c_void) -> U128 {
 pub(crate) unsafe extern "C" fn next_line(
     runtime: *mut c_void,
     file: *mut c_void,
-    is_file: Int,
+    spec: Int,
 ) -> U128 {
     let runtime = &mut *(runtime as *mut Runtime);
     let file = &*(file as *mut Str);
+    let spec = try_abort!(runtime, InputSpec::try_from(spec));
     let res = with_input!(&mut runtime.input_data, |(_, read_files)| {
         runtime
             .core
             .regexes
-            .get_line(file, &runtime.core.vars.rs, read_files, is_file != 0)
+            .get_line(file, &runtime.core.vars.rs, read_files, spec)
     });
     match res {
         Ok(res) => mem::transmute::<Str, U128>(res),
@@ -613,12 +852,36 @@ pub(crate) unsafe extern "C" fn split_int(
     res
 }
 
+pub(crate) unsafe extern "C" fn pat_split(
+    runtime: *mut c_void,
+    to_split: *mut c_void,
+    into_arr: *mut c_void,
+    pat: *mut c_void,
+    seps_arr: *mut c_void,
+) -> Int {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let into_arr = mem::transmute::<*mut c_void, IntMap<Str>>(into_arr);
+    let seps_arr = mem::transmute::<*mut c_void, IntMap<Str>>(seps_arr);
+    let to_split = &*(to_split as *mut Str);
+    let pat = &*(pat as *mut Str);
+    let res = match runtime
+        .core
+        .regexes
+        .pattern_split_intmap(pat, to_split, &into_arr, &seps_arr)
+    {
+        Ok(res) => res,
+        Err(e) => fail!(runtime, "failed to split string: {}", e),
+    };
+    mem::forget((into_arr, seps_arr, to_split, pat));
+    res
+}
+
 pub(crate) unsafe extern "C" fn get_col(runtime: *mut c_void, col: Int) -> U128 {
     let runtime = &mut *(runtime as *mut Runtime);
     let col_str = with_input!(&mut runtime.input_data, |(line, _)| {
         line.get_col(
             col,
-            &runtime.core.vars.fs,
+            &runtime.core.vars.field_split(),
             &runtime.core.vars.ofs,
             &mut runtime.core.regexes,
         )
@@ -638,7 +901,7 @@ pub(crate) unsafe extern "C" fn join_csv(runtime: *mut c_void, start: Int, end:
         with_input!(&mut runtime.input_data, |(line, _)| {
             let nf = try_abort!(
                 runtime,
-                line.nf(&runtime.core.vars.fs, &mut runtime.core.regexes),
+                line.nf(&runtime.core.vars.field_split(), &mut runtime.core.regexes),
                 "nf:"
             );
             line.join_cols(start, end, &sep, nf, |s| runtime::escape_csv(&s))
@@ -656,7 +919,7 @@ pub(crate) unsafe extern "C" fn join_tsv(runtime: *mut c_void, start: Int, end:
         with_input!(&mut runtime.input_data, |(line, _)| {
             let nf = try_abort!(
                 runtime,
-                line.nf(&runtime.core.vars.fs, &mut runtime.core.regexes),
+                line.nf(&runtime.core.vars.field_split(), &mut runtime.core.regexes),
                 "nf:"
             );
             line.join_cols(start, end, &sep, nf, |s| runtime::escape_tsv(&s))
@@ -678,7 +941,7 @@ pub(crate) unsafe extern "C" fn join_cols(
         with_input!(&mut runtime.input_data, |(line, _)| {
             let nf = try_abort!(
                 runtime,
-                line.nf(&runtime.core.vars.fs, &mut runtime.core.regexes),
+                line.nf(&runtime.core.vars.field_split(), &mut runtime.core.regexes),
                 "nf:"
             );
             line.join_cols(start, end, &*(sep as *mut Str), nf, |s| s)
@@ -698,13 +961,23 @@ pub(crate) unsafe extern "C" fn to_lower_ascii(s: *mut U128) -> U128 {
     mem::transmute::<Str, U128>(res)
 }
 
+pub(crate) unsafe extern "C" fn to_upper(s: *mut U128) -> U128 {
+    let res = (&*(s as *mut Str as *const Str)).to_upper();
+    mem::transmute::<Str, U128>(res)
+}
+
+pub(crate) unsafe extern "C" fn to_lower(s: *mut U128) -> U128 {
+    let res = (&*(s as *mut Str as *const Str)).to_lower();
+    mem::transmute::<Str, U128>(res)
+}
+
 pub(crate) unsafe extern "C" fn set_col(runtime: *mut c_void, col: Int, s: *mut c_void) {
     let runtime = &mut *(runtime as *mut Runtime);
     let s = &*(s as *mut Str);
     if let Err(e) = with_input!(&mut runtime.input_data, |(line, _)| line.set_col(
         col,
         s,
-        &runtime.core.vars.ofs,
+        &runtime.core.vars.field_split(),
         &mut runtime.core.regexes,
     )) {
         fail!(runtime, "set_col: {}", e);
@@ -718,6 +991,11 @@ pub(crate) unsafe extern "C" fn str_len(s: *mut c_void) -> usize {
     res
 }
 
+pub(crate) unsafe extern "C" fn str_len_chars(s: *mut c_void) -> usize {
+    let s = &*(s as *mut Str);
+    s.char_len()
+}
+
 pub(crate) unsafe extern "C" fn starts_with_const(
     s1: *mut c_void,
     base: *const u8,
@@ -754,9 +1032,31 @@ pub(crate) unsafe extern "C" fn match_pat(
     res as Int
 }
 
-pub(crate) unsafe extern "C" fn match_const_pat(s: *mut c_void, pat: *mut c_void) -> Int {
+pub(crate) unsafe extern "C" fn match_cases(
+    runtime: *mut c_void,
+    s: *mut c_void,
+    cases: *mut c_void,
+) -> Int {
+    let runtime = runtime as *mut Runtime;
     let s = &*(s as *mut Str);
-    let pat = &*(pat as *const Regex);
+    let cases = &*(cases as *mut Str);
+    let res = try_abort!(
+        runtime,
+        (*runtime).core.match_cases(s, cases),
+        "match_cases:"
+    );
+    mem::forget((s, cases));
+    res
+}
+
+pub(crate) unsafe extern "C" fn match_const_pat(
+    runtime: *mut c_void,
+    s: *mut c_void,
+    pat: *mut c_void,
+) -> Int {
+    let runtime = runtime as *mut Runtime;
+    let s = &*(s as *mut Str);
+    let pat = (*(pat as *const ConstRegex)).get((*runtime).core.regexes.ignore_case());
     RegexCache::regex_const_match(pat, s) as Int
 }
 
@@ -777,6 +1077,28 @@ pub(crate) unsafe extern "C" fn match_pat_loc(
     res as Int
 }
 
+pub(crate) unsafe extern "C" fn match_pat_loc_strmap(
+    runtime: *mut c_void,
+    s: *mut c_void,
+    pat: *mut c_void,
+    arr: *mut c_void,
+) -> Int {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let s = &*(s as *mut Str);
+    let pat = &*(pat as *mut Str);
+    let arr = mem::transmute::<*mut c_void, StrMap<Str>>(arr);
+    let res = try_abort!(
+        runtime,
+        runtime
+            .core
+            .regexes
+            .regex_match_loc_strmap(&mut runtime.core.vars, pat, s, &arr),
+        "match_pat_loc_strmap:"
+    );
+    mem::forget(arr);
+    res
+}
+
 pub(crate) unsafe extern "C" fn match_const_pat_loc(
     runtime: *mut c_void,
     s: *mut c_void,
@@ -784,7 +1106,7 @@ pub(crate) unsafe extern "C" fn match_const_pat_loc(
 ) -> Int {
     let runtime = runtime as *mut Runtime;
     let s = &*(s as *mut Str);
-    let pat = &*(pat as *const Regex);
+    let pat = (*(pat as *const ConstRegex)).get((*runtime).core.regexes.ignore_case());
     try_abort!(
         runtime,
         (*runtime).core.match_const_regex(s, pat),
@@ -840,10 +1162,37 @@ pub(crate) unsafe extern "C" fn subst_all(
     nsubs
 }
 
+pub(crate) unsafe extern "C" fn gen_subst(
+    runtime: *mut c_void,
+    pat: *mut U128,
+    s: *mut U128,
+    how: *mut U128,
+    in_s: *mut U128,
+) -> U128 {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let pat = &*(pat as *mut Str);
+    let s = &*(s as *mut Str);
+    let how = &*(how as *mut Str);
+    let in_s = &*(in_s as *mut Str);
+    let subbed = try_abort!(
+        runtime,
+        runtime
+            .core
+            .regexes
+            .with_regex(pat, |re| in_s.gen_subst(re, s, how))
+    );
+    mem::transmute::<Str, U128>(subbed)
+}
+
 pub(crate) unsafe extern "C" fn escape_csv(s: *mut U128) -> U128 {
     mem::transmute::<Str, U128>(runtime::escape_csv(&*(s as *mut Str)))
 }
 
+pub(crate) unsafe extern "C" fn substr_index_chars(s: *mut U128, t: *mut U128) -> Int {
+    let s = &*(s as *mut Str);
+    let t = &*(t as *mut Str);
+    runtime::string_search::index_substr_chars(/*needle*/ t, /*haystack*/ s)
+}
 pub(crate) unsafe extern "C" fn escape_tsv(s: *mut U128) -> U128 {
     mem::transmute::<Str, U128>(runtime::escape_tsv(&*(s as *mut Str)))
 }
@@ -861,6 +1210,13 @@ pub(crate) unsafe extern "C" fn substr(base: *mut U128, l: Int, r: Int) -> U128
     }
 }
 
+pub(crate) unsafe extern "C" fn substr_chars(base: *mut U128, l: Int, r: Int) -> U128 {
+    let base = &*(base as *mut Str);
+    let l = std::cmp::max(0, l - 1);
+    let r = std::cmp::max(l, l.saturating_add(r));
+    mem::transmute::<Str, U128>(base.slice_chars(l as usize, r as usize))
+}
+
 pub(crate) unsafe extern "C" fn ref_str(s: *mut c_void) {
     mem::forget((&*(s as *mut Str)).clone())
 }
@@ -884,6 +1240,52 @@ unsafe fn drop_map_generic<K, V>(m: *mut c_void) {
     mem::drop(map_ref)
 }
 
+// `into` is a map from `D` to the values (resp. keys) of `map`; `D` is `Int` unless the
+// destination array is also used with string keys.
+unsafe fn asort_generic<K, V, D>(
+    runtime: *mut c_void,
+    map: *mut c_void,
+    into: *mut c_void,
+    how: *mut U128,
+) -> Int
+where
+    K: Hash + Eq + Clone + SortKey,
+    V: Clone + SortKey,
+    D: Hash + Eq + SortIndex,
+{
+    debug_assert!(!map.is_null());
+    debug_assert!(!into.is_null());
+    let runtime = &mut *(runtime as *mut Runtime);
+    let map = mem::transmute::<*mut c_void, runtime::SharedMap<K, V>>(map);
+    let into = mem::transmute::<*mut c_void, runtime::SharedMap<D, V>>(into);
+    let how = &*(how as *mut Str);
+    let res = runtime::sort::asort(&map, &into, how);
+    mem::forget((map, into));
+    try_abort!(runtime, res, "asort:")
+}
+
+unsafe fn asorti_generic<K, V, D>(
+    runtime: *mut c_void,
+    map: *mut c_void,
+    into: *mut c_void,
+    how: *mut U128,
+) -> Int
+where
+    K: Hash + Eq + Clone + SortKey,
+    V: Clone + SortKey,
+    D: Hash + Eq + SortIndex,
+{
+    debug_assert!(!map.is_null());
+    debug_assert!(!into.is_null());
+    let runtime = &mut *(runtime as *mut Runtime);
+    let map = mem::transmute::<*mut c_void, runtime::SharedMap<K, V>>(map);
+    let into = mem::transmute::<*mut c_void, runtime::SharedMap<D, K>>(into);
+    let how = &*(how as *mut Str);
+    let res = runtime::sort::asorti(&map, &into, how);
+    mem::forget((map, into));
+    try_abort!(runtime, res, "asorti:")
+}
+
 // XXX: relying on this doing the same thing regardless of type. We probably want a custom Rc to
 // guarantee this.
 
@@ -895,8 +1297,16 @@ pub(crate) unsafe extern "C" fn int_to_str(i: Int) -> U128 {
     mem::transmute::<Str, U128>(runtime::convert::<Int, Str>(i))
 }
 
-pub(crate) unsafe extern "C" fn float_to_str(f: Float) -> U128 {
-    mem::transmute::<Str, U128>(runtime::convert::<Float, Str>(f))
+pub(crate) unsafe extern "C" fn float_to_str(rt: *mut c_void, f: Float) -> U128 {
+    let runtime = &mut *(rt as *mut Runtime);
+    let res = try_abort!(runtime, runtime.core.vars.float_to_str(f));
+    mem::transmute::<Str, U128>(res)
+}
+
+pub(crate) unsafe extern "C" fn float_to_str_out(rt: *mut c_void, f: Float) -> U128 {
+    let runtime = &mut *(rt as *mut Runtime);
+    let res = try_abort!(runtime, runtime.core.vars.float_to_str_out(f));
+    mem::transmute::<Str, U128>(res)
 }
 
 pub(crate) unsafe extern "C" fn str_to_int(s: *mut c_void) -> Int {
@@ -905,6 +1315,26 @@ pub(crate) unsafe extern "C" fn str_to_int(s: *mut c_void) -> Int {
     res
 }
 
+pub(crate) unsafe extern "C" fn systime() -> Int {
+    runtime::time::systime()
+}
+
+pub(crate) unsafe extern "C" fn strftime(fmt: *mut c_void, ts: Int, utc: Int) -> U128 {
+    let fmt = &*(fmt as *mut Str);
+    mem::transmute::<Str, U128>(runtime::time::strftime(fmt, ts, utc != 0))
+}
+
+pub(crate) unsafe extern "C" fn mktime(spec: *mut c_void, utc: Int) -> Int {
+    let spec = &*(spec as *mut Str);
+    runtime::time::mktime(spec, utc != 0)
+}
+
+pub(crate) unsafe extern "C" fn strptime(s: *mut c_void, fmt: *mut c_void, utc: Int) -> Float {
+    let s = &*(s as *mut Str);
+    let fmt = &*(fmt as *mut Str);
+    runtime::time::strptime(s, fmt, utc != 0)
+}
+
 pub(crate) unsafe extern "C" fn hex_str_to_int(s: *mut c_void) -> Int {
     let s = &*(s as *mut Str);
     let res = s.with_bytes(runtime::hextoi);
@@ -920,6 +1350,11 @@ pub(crate) unsafe extern "C" fn str_to_float(s: *mut c_void) -> Float {
 pub(crate) unsafe extern "C" fn load_var_str(rt: *mut c_void, var: usize) -> U128 {
     let runtime = &mut *(rt as *mut Runtime);
     if let Ok(var) = Variable::try_from(var) {
+        if let Variable::RT = var {
+            runtime.core.vars.rt = with_input!(&mut runtime.input_data, |(_, read_files)| {
+                read_files.record_terminator()
+            });
+        }
         let res = try_abort!(runtime, runtime.core.vars.load_str(var));
         mem::transmute::<Str, U128>(res)
     } else {
@@ -942,7 +1377,7 @@ pub(crate) unsafe extern "C" fn load_var_int(rt: *mut c_void, var: usize) -> Int
     if let Ok(var) = Variable::try_from(var) {
         if let Variable::NF = var {
             runtime.core.vars.nf = match with_input!(&mut runtime.input_data, |(line, _)| line
-                .nf(&runtime.core.vars.fs, &mut runtime.core.regexes))
+                .nf(&runtime.core.vars.field_split(), &mut runtime.core.regexes))
             {
                 Ok(nf) => nf as Int,
                 Err(e) => fail!(runtime, "nf: {}", e),
@@ -957,7 +1392,7 @@ pub(crate) unsafe extern "C" fn load_var_int(rt: *mut c_void, var: usize) -> Int
 pub(crate) unsafe extern "C" fn store_var_int(rt: *mut c_void, var: usize, i: Int) {
     let runtime = &mut *(rt as *mut Runtime);
     if let Ok(var) = Variable::try_from(var) {
-        try_abort!(runtime, runtime.core.vars.store_int(var, i));
+        try_abort!(runtime, runtime.core.store_var_int(var, i));
     } else {
         fail!(runtime, "invalid variable code={}", var)
     }
@@ -1005,6 +1440,27 @@ pub(crate) unsafe extern "C" fn store_var_strmap(rt: *mut c_void, var: usize, ma
     }
 }
 
+pub(crate) unsafe extern "C" fn load_var_strstrmap(rt: *mut c_void, var: usize) -> *mut c_void {
+    let runtime = &mut *(rt as *mut Runtime);
+    if let Ok(var) = Variable::try_from(var) {
+        let res = try_abort!(runtime, runtime.core.vars.load_strstrmap(var));
+        mem::transmute::<StrMap<Str>, *mut c_void>(res)
+    } else {
+        fail!(runtime, "invalid variable code={}", var)
+    }
+}
+
+pub(crate) unsafe extern "C" fn store_var_strstrmap(rt: *mut c_void, var: usize, map: *mut c_void) {
+    let runtime = &mut *(rt as *mut Runtime);
+    if let Ok(var) = Variable::try_from(var) {
+        let map = mem::transmute::<*mut c_void, StrMap<Str>>(map);
+        try_abort!(runtime, runtime.core.vars.store_strstrmap(var, map.clone()));
+        mem::forget(map);
+    } else {
+        fail!(runtime, "invalid variable code={}", var)
+    }
+}
+
 macro_rules! str_compare_inner {
     ($name:ident, $op:tt) => {
 
@@ -1025,6 +1481,36 @@ str_compare! {
     str_lt(<); str_gt(>); str_lte(<=); str_gte(>=); str_eq(==);
 }
 
+macro_rules! strnum_compare {
+    ($($name:ident ($pred:ident);)*) => { $(
+        pub(crate) unsafe extern "C" fn $name(s1: *mut c_void, s2: *mut c_void) -> Int {
+            let s1 = &*(s1 as *mut Str);
+            let s2 = &*(s2 as *mut Str);
+            runtime::compare_strnum(s1, s2).$pred() as Int
+        }
+    )* };
+}
+
+strnum_compare! {
+    strnum_lt(is_lt); strnum_gt(is_gt); strnum_lte(is_le); strnum_gte(is_ge); strnum_eq(is_eq);
+}
+
+macro_rules! strnum_float_compare {
+    ($($name:ident ($pred:ident);)*) => { $(
+        pub(crate) unsafe extern "C" fn $name(rt: *mut c_void, s: *mut c_void, f: Float) -> Int {
+            let runtime = &mut *(rt as *mut Runtime);
+            let s = &*(s as *mut Str);
+            let ord = try_abort!(runtime, runtime::compare_strnum_float(s, f, &runtime.core.vars));
+            ord.$pred() as Int
+        }
+    )* };
+}
+
+strnum_float_compare! {
+    strnum_float_lt(is_lt); strnum_float_gt(is_gt); strnum_float_lte(is_le);
+    strnum_float_gte(is_ge); strnum_float_eq(is_eq);
+}
+
 pub(crate) unsafe extern "C" fn drop_iter_int(iter: *mut Int, len: usize) {
     mem::drop(Box::from_raw(slice::from_raw_parts_mut(iter, len)))
 }
@@ -1159,11 +1645,27 @@ pub(crate) unsafe extern "C" fn printf_impl_stdout(
     }
 }
 
-pub(crate) unsafe extern "C" fn close_file(rt: *mut c_void, file: *mut U128) {
+pub(crate) unsafe extern "C" fn close_file(
+    rt: *mut c_void,
+    file: *mut U128,
+    how: *mut U128,
+) -> Int {
+    let rt = &mut *(rt as *mut Runtime);
+    let file = &*(file as *mut Str);
+    let how = &*(how as *mut Str);
+    let res = with_input!(&mut rt.input_data, |(_, read_files)| runtime::close(
+        read_files,
+        &mut rt.core.write_files,
+        file,
+        how
+    ));
+    try_abort!(rt, res)
+}
+
+pub(crate) unsafe extern "C" fn fflush(rt: *mut c_void, file: *mut U128) -> Int {
     let rt = &mut *(rt as *mut Runtime);
     let file = &*(file as *mut Str);
-    with_input!(&mut rt.input_data, |(_, read_files)| read_files.close(file));
-    try_abort!(rt, rt.core.write_files.close(file));
+    try_abort!(rt, rt.core.write_files.flush(file))
 }
 
 pub(crate) unsafe extern "C" fn _frawk_cos(f: Float) -> Float {
@@ -1296,10 +1798,15 @@ macro_rules! map_impl {
                 mem::transmute::<runtime::SharedMap<$k, $v>, *mut c_void>(res)
             }
 
-            pub(crate) unsafe extern "C" fn [< iter_ $ty >](map: *mut c_void) -> iter_ty!($k) {
+            pub(crate) unsafe extern "C" fn [< iter_ $ty >](
+                runtime: *mut c_void,
+                map: *mut c_void,
+            ) -> iter_ty!($k) {
                 debug_assert!(!map.is_null());
+                let runtime = &mut *(runtime as *mut Runtime);
+                let order = try_abort!(runtime, runtime.core.vars.sorted_in.clone());
                 let map = mem::transmute::<*mut c_void, runtime::SharedMap<$k, $v>>(map);
-                let iter: Vec<_> = map.to_vec();
+                let iter: Vec<_> = map.to_vec(order);
                 mem::forget(map);
                 let b = iter.into_boxed_slice();
                 Box::into_raw(b) as _
@@ -1361,6 +1868,42 @@ macro_rules! map_impl {
                 drop_map_generic::<$k, $v>(map)
             }
 
+            pub(crate) unsafe extern "C" fn [<asort_ $ty>](
+                runtime: *mut c_void,
+                map: *mut c_void,
+                into: *mut c_void,
+                how: *mut U128,
+            ) -> Int {
+                asort_generic::<$k, $v, Int>(runtime, map, into, how)
+            }
+
+            pub(crate) unsafe extern "C" fn [<asort_ $ty _strkeys>](
+                runtime: *mut c_void,
+                map: *mut c_void,
+                into: *mut c_void,
+                how: *mut U128,
+            ) -> Int {
+                asort_generic::<$k, $v, Str>(runtime, map, into, how)
+            }
+
+            pub(crate) unsafe extern "C" fn [<asorti_ $ty>](
+                runtime: *mut c_void,
+                map: *mut c_void,
+                into: *mut c_void,
+                how: *mut U128,
+            ) -> Int {
+                asorti_generic::<$k, $v, Int>(runtime, map, into, how)
+            }
+
+            pub(crate) unsafe extern "C" fn [<asorti_ $ty _strkeys>](
+                runtime: *mut c_void,
+                map: *mut c_void,
+                into: *mut c_void,
+                how: *mut U128,
+            ) -> Int {
+                asorti_generic::<$k, $v, Str>(runtime, map, into, how)
+            }
+
             pub(crate) unsafe extern "C" fn [<inc_int_ $ty>](map: *mut c_void, k: in_ty!($k), by: Int) -> out_ty!($v) {
                 debug_assert!(!map.is_null());
                 let map = mem::transmute::<*mut c_void, runtime::SharedMap<$k, $v>>(map);
@@ -1389,6 +1932,93 @@ map_impl!(strint, Str, Int);
 map_impl!(strfloat, Str, Float);
 map_impl!(strstr, Str, Str);
 
+// Arrays of arrays support the map operations that do not depend on the values being scalars.
+// Lookups insert an empty map if the key is not present, so that assignments like
+// `m[k1][k2] = v` are visible in `m`.
+macro_rules! nested_map_impl {
+    ($ty:ident, $k:tt, $ik:tt, $iv:tt) => {
+        paste! {
+            pub(crate) unsafe extern "C" fn [< alloc_ $ty >]() -> *mut c_void {
+                let res: runtime::SharedMap<$k, runtime::SharedMap<$ik, $iv>> = Default::default();
+                mem::transmute::<runtime::SharedMap<$k, runtime::SharedMap<$ik, $iv>>, *mut c_void>(res)
+            }
+
+            pub(crate) unsafe extern "C" fn [< iter_ $ty >](
+                runtime: *mut c_void,
+                map: *mut c_void,
+            ) -> iter_ty!($k) {
+                debug_assert!(!map.is_null());
+                let runtime = &mut *(runtime as *mut Runtime);
+                let order = try_abort!(runtime, runtime.core.vars.sorted_in.clone());
+                let map = mem::transmute::<*mut c_void, runtime::SharedMap<$k, runtime::SharedMap<$ik, $iv>>>(map);
+                let iter: Vec<_> = map.to_vec(order);
+                mem::forget(map);
+                let b = iter.into_boxed_slice();
+                Box::into_raw(b) as _
+            }
+
+            pub(crate) unsafe extern "C" fn [<len_ $ty>](map: *mut c_void) -> Int {
+                debug_assert!(!map.is_null());
+                let map = mem::transmute::<*mut c_void, runtime::SharedMap<$k, runtime::SharedMap<$ik, $iv>>>(map);
+                let res = map.len();
+                mem::forget(map);
+                res as Int
+            }
+
+            pub(crate) unsafe extern "C" fn [<lookup_ $ty>](map: *mut c_void, k: in_ty!($k)) -> *mut c_void {
+                debug_assert!(!map.is_null());
+                let map = mem::transmute::<*mut c_void, runtime::SharedMap<$k, runtime::SharedMap<$ik, $iv>>>(map);
+                let key = convert_in!($k, &k);
+                let res = map.get(key);
+                mem::forget(map);
+                convert_out!(Map, res)
+            }
+
+            pub(crate) unsafe extern "C" fn [<contains_ $ty>](map: *mut c_void, k: in_ty!($k)) -> Int {
+                debug_assert!(!map.is_null());
+                let map = mem::transmute::<*mut c_void, runtime::SharedMap<$k, runtime::SharedMap<$ik, $iv>>>(map);
+                let key = convert_in!($k, &k);
+                let res = map.contains(key) as Int;
+                mem::forget(map);
+                res
+            }
+
+            pub(crate) unsafe extern "C" fn [<delete_ $ty>](map: *mut c_void, k: in_ty!($k)) {
+                debug_assert!(!map.is_null());
+                let map = mem::transmute::<*mut c_void, runtime::SharedMap<$k, runtime::SharedMap<$ik, $iv>>>(map);
+                let key = convert_in!($k, &k);
+                map.delete(key);
+                mem::forget(map);
+            }
+
+            pub(crate) unsafe extern "C" fn [<clear_ $ty>](map: *mut c_void) {
+                debug_assert!(!map.is_null());
+                let map = mem::transmute::<*mut c_void, runtime::SharedMap<$k, runtime::SharedMap<$ik, $iv>>>(map);
+                map.clear();
+                mem::forget(map);
+            }
+
+            pub(crate) unsafe extern "C" fn [<drop_ $ty>](map: *mut c_void) {
+                debug_assert!(!map.is_null());
+                drop_map_generic::<$k, runtime::SharedMap<$ik, $iv>>(map)
+            }
+        }
+    };
+}
+
+nested_map_impl!(int_intint, Int, Int, Int);
+nested_map_impl!(int_intfloat, Int, Int, Float);
+nested_map_impl!(int_intstr, Int, Int, Str);
+nested_map_impl!(int_strint, Int, Str, Int);
+nested_map_impl!(int_strfloat, Int, Str, Float);
+nested_map_impl!(int_strstr, Int, Str, Str);
+nested_map_impl!(str_intint, Str, Int, Int);
+nested_map_impl!(str_intfloat, Str, Int, Float);
+nested_map_impl!(str_intstr, Str, Int, Str);
+nested_map_impl!(str_strint, Str, Str, Int);
+nested_map_impl!(str_strfloat, Str, Str, Float);
+nested_map_impl!(str_strstr, Str, Str, Str);
+
 macro_rules! slot_impl {
     ($name:ident, $ty:tt) => {
         paste! {
@@ -1416,3 +2046,15 @@ slot_impl!(intstr, Map);
 slot_impl!(strint, Map);
 slot_impl!(strfloat, Map);
 slot_impl!(strstr, Map);
+slot_impl!(int_intint, Map);
+slot_impl!(int_intfloat, Map);
+slot_impl!(int_intstr, Map);
+slot_impl!(int_strint, Map);
+slot_impl!(int_strfloat, Map);
+slot_impl!(int_strstr, Map);
+slot_impl!(str_intint, Map);
+slot_impl!(str_intfloat, Map);
+slot_impl!(str_intstr, Map);
+slot_impl!(str_strint, Map);
+slot_impl!(str_strfloat, Map);
+slot_impl!(str_strstr, Map);
@@ -49,6 +49,18 @@ impl IntrinsicMap {
             MapStrInt => Some(self.get(external!(drop_strint))),
             MapStrFloat => Some(self.get(external!(drop_strfloat))),
             MapStrStr => Some(self.get(external!(drop_strstr))),
+            MapIntMapIntInt => Some(self.get(external!(drop_int_intint))),
+            MapIntMapIntFloat => Some(self.get(external!(drop_int_intfloat))),
+            MapIntMapIntStr => Some(self.get(external!(drop_int_intstr))),
+            MapIntMapStrInt => Some(self.get(external!(drop_int_strint))),
+            MapIntMapStrFloat => Some(self.get(external!(drop_int_strfloat))),
+            MapIntMapStrStr => Some(self.get(external!(drop_int_strstr))),
+            MapStrMapIntInt => Some(self.get(external!(drop_str_intint))),
+            MapStrMapIntFloat => Some(self.get(external!(drop_str_intfloat))),
+            MapStrMapIntStr => Some(self.get(external!(drop_str_intstr))),
+            MapStrMapStrInt => Some(self.get(external!(drop_str_strint))),
+            MapStrMapStrFloat => Some(self.get(external!(drop_str_strfloat))),
+            MapStrMapStrStr => Some(self.get(external!(drop_str_strstr))),
             _ => return None,
         }
     }
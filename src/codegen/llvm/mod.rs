@@ -154,7 +154,7 @@ impl<'a> CodeGenerator for View<'a> {
                 let param = LLVMGetParam(self.f.val, *ix as libc::c_uint);
                 let new_global = to;
                 match val.1 {
-                    MapIntInt | MapIntStr | MapIntFloat | MapStrInt | MapStrStr | MapStrFloat => {
+                    map_tys!() => {
                         let prev_global = LLVMBuildLoad(self.f.builder, param, c_str!(""));
                         self.drop_val(prev_global, val.1);
                         LLVMBuildStore(self.f.builder, new_global, param);
@@ -176,7 +176,7 @@ impl<'a> CodeGenerator for View<'a> {
                 self.f.locals[&val]
             );
             match val.1 {
-                MapIntInt | MapIntStr | MapIntFloat | MapStrInt | MapStrStr | MapStrFloat => {
+                map_tys!() => {
                     // alloca only fails with an iterator or null type; but we have checked the type
                     // already.
                     let loc = self.alloca(val.1).unwrap();
@@ -486,11 +486,24 @@ impl<'a> CodeGenerator for View<'a> {
                 MapIntFloat => (intrinsic!(len_intfloat), intrinsic!(iter_intfloat)),
                 MapStrInt => (intrinsic!(len_strint), intrinsic!(iter_strint)),
                 MapStrStr => (intrinsic!(len_strstr), intrinsic!(iter_strstr)),
+                MapIntMapIntInt => (intrinsic!(len_int_intint), intrinsic!(iter_int_intint)),
+                MapIntMapIntFloat => (intrinsic!(len_int_intfloat), intrinsic!(iter_int_intfloat)),
+                MapIntMapIntStr => (intrinsic!(len_int_intstr), intrinsic!(iter_int_intstr)),
+                MapIntMapStrInt => (intrinsic!(len_int_strint), intrinsic!(iter_int_strint)),
+                MapIntMapStrFloat => (intrinsic!(len_int_strfloat), intrinsic!(iter_int_strfloat)),
+                MapIntMapStrStr => (intrinsic!(len_int_strstr), intrinsic!(iter_int_strstr)),
+                MapStrMapIntInt => (intrinsic!(len_str_intint), intrinsic!(iter_str_intint)),
+                MapStrMapIntFloat => (intrinsic!(len_str_intfloat), intrinsic!(iter_str_intfloat)),
+                MapStrMapIntStr => (intrinsic!(len_str_intstr), intrinsic!(iter_str_intstr)),
+                MapStrMapStrInt => (intrinsic!(len_str_strint), intrinsic!(iter_str_strint)),
+                MapStrMapStrFloat => (intrinsic!(len_str_strfloat), intrinsic!(iter_str_strfloat)),
+                MapStrMapStrStr => (intrinsic!(len_str_strstr), intrinsic!(iter_str_strstr)),
                 MapStrFloat => (intrinsic!(len_strfloat), intrinsic!(iter_strfloat)),
                 _ => return err!("iterating over non-map type: {:?}", map.1),
             };
 
-            let iter_ptr = self.call(begin_fn, &mut [arrv]);
+            let rt = self.runtime_val();
+            let iter_ptr = self.call(begin_fn, &mut [rt, arrv]);
             let cur_index = self.alloca(Ty::Int)?;
 
             let ty = self.tmap.get_ty(Ty::Int);
@@ -654,7 +667,7 @@ unsafe fn alloc_local(
             LLVMBuildStore(builder, v, v_loc);
             v_loc
         }
-        MapIntInt | MapIntStr | MapIntFloat | MapStrInt | MapStrStr | MapStrFloat => {
+        map_tys!() => {
             let func = match ty {
                 MapIntInt => intrinsic!(alloc_intint),
                 MapIntFloat => intrinsic!(alloc_intfloat),
@@ -662,6 +675,18 @@ unsafe fn alloc_local(
                 MapStrInt => intrinsic!(alloc_strint),
                 MapStrFloat => intrinsic!(alloc_strfloat),
                 MapStrStr => intrinsic!(alloc_strstr),
+                MapIntMapIntInt => intrinsic!(alloc_int_intint),
+                MapIntMapIntFloat => intrinsic!(alloc_int_intfloat),
+                MapIntMapIntStr => intrinsic!(alloc_int_intstr),
+                MapIntMapStrInt => intrinsic!(alloc_int_strint),
+                MapIntMapStrFloat => intrinsic!(alloc_int_strfloat),
+                MapIntMapStrStr => intrinsic!(alloc_int_strstr),
+                MapStrMapIntInt => intrinsic!(alloc_str_intint),
+                MapStrMapIntFloat => intrinsic!(alloc_str_intfloat),
+                MapStrMapIntStr => intrinsic!(alloc_str_intstr),
+                MapStrMapStrInt => intrinsic!(alloc_str_strint),
+                MapStrMapStrFloat => intrinsic!(alloc_str_strfloat),
+                MapStrMapStrStr => intrinsic!(alloc_str_strstr),
                 _ => unreachable!(),
             };
             let map_ty = tmap.get_ty(ty);
@@ -857,6 +882,18 @@ impl<'a, 'b> Generator<'a, 'b> {
         self.type_map.init(Ty::MapStrInt, make(voidptr));
         self.type_map.init(Ty::MapStrFloat, make(voidptr));
         self.type_map.init(Ty::MapStrStr, make(voidptr));
+        self.type_map.init(Ty::MapIntMapIntInt, make(voidptr));
+        self.type_map.init(Ty::MapIntMapIntFloat, make(voidptr));
+        self.type_map.init(Ty::MapIntMapIntStr, make(voidptr));
+        self.type_map.init(Ty::MapIntMapStrInt, make(voidptr));
+        self.type_map.init(Ty::MapIntMapStrFloat, make(voidptr));
+        self.type_map.init(Ty::MapIntMapStrStr, make(voidptr));
+        self.type_map.init(Ty::MapStrMapIntInt, make(voidptr));
+        self.type_map.init(Ty::MapStrMapIntFloat, make(voidptr));
+        self.type_map.init(Ty::MapStrMapIntStr, make(voidptr));
+        self.type_map.init(Ty::MapStrMapStrInt, make(voidptr));
+        self.type_map.init(Ty::MapStrMapStrFloat, make(voidptr));
+        self.type_map.init(Ty::MapStrMapStrStr, make(voidptr));
         // NB: iterators do not have types of their own, and we should never ask for their types.
         // See the IterState type and its uses for more info.
         self.type_map.init(Ty::IterInt, TypeRef::null());
@@ -1378,6 +1415,18 @@ impl<'a> View<'a> {
             Ty::MapStrInt => intrinsic!(alloc_strint),
             Ty::MapStrFloat => intrinsic!(alloc_strfloat),
             Ty::MapStrStr => intrinsic!(alloc_strstr),
+            Ty::MapIntMapIntInt => intrinsic!(alloc_int_intint),
+            Ty::MapIntMapIntFloat => intrinsic!(alloc_int_intfloat),
+            Ty::MapIntMapIntStr => intrinsic!(alloc_int_intstr),
+            Ty::MapIntMapStrInt => intrinsic!(alloc_int_strint),
+            Ty::MapIntMapStrFloat => intrinsic!(alloc_int_strfloat),
+            Ty::MapIntMapStrStr => intrinsic!(alloc_int_strstr),
+            Ty::MapStrMapIntInt => intrinsic!(alloc_str_intint),
+            Ty::MapStrMapIntFloat => intrinsic!(alloc_str_intfloat),
+            Ty::MapStrMapIntStr => intrinsic!(alloc_str_intstr),
+            Ty::MapStrMapStrInt => intrinsic!(alloc_str_strint),
+            Ty::MapStrMapStrFloat => intrinsic!(alloc_str_strfloat),
+            Ty::MapStrMapStrStr => intrinsic!(alloc_str_strstr),
         };
         let llty = self.tmap.get_ty(ty);
         let res = LLVMBuildAlloca(self.entry_builder, llty, c_str!(""));
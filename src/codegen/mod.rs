@@ -8,11 +8,9 @@ use crate::{
     common::{CancelSignal, Cleanup, FileSpec, NumTy, Result, Stage},
     compile,
     pushdown::FieldSet,
-    runtime::{self, UniqueStr},
+    runtime::{self, ConstRegex, UniqueStr},
 };
 
-use regex::bytes::Regex;
-
 use std::marker::PhantomData;
 use std::mem;
 use std::sync::Arc;
@@ -263,7 +261,7 @@ where
 /// Handles to ensure the liveness of rust objects passed by pointer into generated code.
 #[derive(Default)]
 pub(crate) struct Handles {
-    res: Vec<Arc<Regex>>,
+    res: Vec<Arc<ConstRegex>>,
     slices: Vec<Arc<[u8]>>,
 }
 
@@ -312,7 +310,7 @@ pub(crate) trait CodeGenerator: Backend {
     // const_{re,slice} take an `Arc` so that it can store a pointer to `c` and ensure references
     // to `c` will live as long as the generated code.
 
-    fn const_re(&mut self, pat: Arc<Regex>) -> Self::Val {
+    fn const_re(&mut self, pat: Arc<ConstRegex>) -> Self::Val {
         let res = self.const_ptr(&*pat);
         self.handles().res.push(pat);
         res
@@ -391,6 +389,18 @@ pub(crate) trait CodeGenerator: Backend {
             MapStrInt => intrinsic!(load_slot_strint),
             MapStrFloat => intrinsic!(load_slot_strfloat),
             MapStrStr => intrinsic!(load_slot_strstr),
+            MapIntMapIntInt => intrinsic!(load_slot_int_intint),
+            MapIntMapIntFloat => intrinsic!(load_slot_int_intfloat),
+            MapIntMapIntStr => intrinsic!(load_slot_int_intstr),
+            MapIntMapStrInt => intrinsic!(load_slot_int_strint),
+            MapIntMapStrFloat => intrinsic!(load_slot_int_strfloat),
+            MapIntMapStrStr => intrinsic!(load_slot_int_strstr),
+            MapStrMapIntInt => intrinsic!(load_slot_str_intint),
+            MapStrMapIntFloat => intrinsic!(load_slot_str_intfloat),
+            MapStrMapIntStr => intrinsic!(load_slot_str_intstr),
+            MapStrMapStrInt => intrinsic!(load_slot_str_strint),
+            MapStrMapStrFloat => intrinsic!(load_slot_str_strfloat),
+            MapStrMapStrStr => intrinsic!(load_slot_str_strstr),
             _ => unreachable!(),
         };
         let rt = self.runtime_val();
@@ -414,6 +424,18 @@ pub(crate) trait CodeGenerator: Backend {
             MapStrInt => external!(store_slot_strint),
             MapStrFloat => external!(store_slot_strfloat),
             MapStrStr => external!(store_slot_strstr),
+            MapIntMapIntInt => external!(store_slot_int_intint),
+            MapIntMapIntFloat => external!(store_slot_int_intfloat),
+            MapIntMapIntStr => external!(store_slot_int_intstr),
+            MapIntMapStrInt => external!(store_slot_int_strint),
+            MapIntMapStrFloat => external!(store_slot_int_strfloat),
+            MapIntMapStrStr => external!(store_slot_int_strstr),
+            MapStrMapIntInt => external!(store_slot_str_intint),
+            MapStrMapIntFloat => external!(store_slot_str_intfloat),
+            MapStrMapIntStr => external!(store_slot_str_intstr),
+            MapStrMapStrInt => external!(store_slot_str_strint),
+            MapStrMapStrFloat => external!(store_slot_str_strfloat),
+            MapStrMapStrStr => external!(store_slot_str_strstr),
             _ => unreachable!(),
         };
         let rt = self.runtime_val();
@@ -436,6 +458,18 @@ pub(crate) trait CodeGenerator: Backend {
             MapStrInt => intrinsic!(lookup_strint),
             MapStrFloat => intrinsic!(lookup_strfloat),
             MapStrStr => intrinsic!(lookup_strstr),
+            MapIntMapIntInt => intrinsic!(lookup_int_intint),
+            MapIntMapIntFloat => intrinsic!(lookup_int_intfloat),
+            MapIntMapIntStr => intrinsic!(lookup_int_intstr),
+            MapIntMapStrInt => intrinsic!(lookup_int_strint),
+            MapIntMapStrFloat => intrinsic!(lookup_int_strfloat),
+            MapIntMapStrStr => intrinsic!(lookup_int_strstr),
+            MapStrMapIntInt => intrinsic!(lookup_str_intint),
+            MapStrMapIntFloat => intrinsic!(lookup_str_intfloat),
+            MapStrMapIntStr => intrinsic!(lookup_str_intstr),
+            MapStrMapStrInt => intrinsic!(lookup_str_strint),
+            MapStrMapStrFloat => intrinsic!(lookup_str_strfloat),
+            MapStrMapStrStr => intrinsic!(lookup_str_strstr),
             ty => return err!("non-map type: {:?}", ty),
         };
         let mapv = self.get_val(map)?;
@@ -458,6 +492,18 @@ pub(crate) trait CodeGenerator: Backend {
             MapStrInt => external!(delete_strint),
             MapStrFloat => external!(delete_strfloat),
             MapStrStr => external!(delete_strstr),
+            MapIntMapIntInt => external!(delete_int_intint),
+            MapIntMapIntFloat => external!(delete_int_intfloat),
+            MapIntMapIntStr => external!(delete_int_intstr),
+            MapIntMapStrInt => external!(delete_int_strint),
+            MapIntMapStrFloat => external!(delete_int_strfloat),
+            MapIntMapStrStr => external!(delete_int_strstr),
+            MapStrMapIntInt => external!(delete_str_intint),
+            MapStrMapIntFloat => external!(delete_str_intfloat),
+            MapStrMapIntStr => external!(delete_str_intstr),
+            MapStrMapStrInt => external!(delete_str_strint),
+            MapStrMapStrFloat => external!(delete_str_strfloat),
+            MapStrMapStrStr => external!(delete_str_strstr),
             ty => return err!("non-map type: {:?}", ty),
         };
         let mapv = self.get_val(map)?;
@@ -478,6 +524,18 @@ pub(crate) trait CodeGenerator: Backend {
             MapStrInt => external!(clear_strint),
             MapStrFloat => external!(clear_strfloat),
             MapStrStr => external!(clear_strstr),
+            MapIntMapIntInt => external!(clear_int_intint),
+            MapIntMapIntFloat => external!(clear_int_intfloat),
+            MapIntMapIntStr => external!(clear_int_intstr),
+            MapIntMapStrInt => external!(clear_int_strint),
+            MapIntMapStrFloat => external!(clear_int_strfloat),
+            MapIntMapStrStr => external!(clear_int_strstr),
+            MapStrMapIntInt => external!(clear_str_intint),
+            MapStrMapIntFloat => external!(clear_str_intfloat),
+            MapStrMapIntStr => external!(clear_str_intstr),
+            MapStrMapStrInt => external!(clear_str_strint),
+            MapStrMapStrFloat => external!(clear_str_strfloat),
+            MapStrMapStrStr => external!(clear_str_strstr),
             ty => return err!("non-map type: {:?}", ty),
         };
         let mapv = self.get_val(map)?;
@@ -498,6 +556,18 @@ pub(crate) trait CodeGenerator: Backend {
             MapStrInt => intrinsic!(contains_strint),
             MapStrFloat => intrinsic!(contains_strfloat),
             MapStrStr => intrinsic!(contains_strstr),
+            MapIntMapIntInt => intrinsic!(contains_int_intint),
+            MapIntMapIntFloat => intrinsic!(contains_int_intfloat),
+            MapIntMapIntStr => intrinsic!(contains_int_intstr),
+            MapIntMapStrInt => intrinsic!(contains_int_strint),
+            MapIntMapStrFloat => intrinsic!(contains_int_strfloat),
+            MapIntMapStrStr => intrinsic!(contains_int_strstr),
+            MapStrMapIntInt => intrinsic!(contains_str_intint),
+            MapStrMapIntFloat => intrinsic!(contains_str_intfloat),
+            MapStrMapIntStr => intrinsic!(contains_str_intstr),
+            MapStrMapStrInt => intrinsic!(contains_str_strint),
+            MapStrMapStrFloat => intrinsic!(contains_str_strfloat),
+            MapStrMapStrStr => intrinsic!(contains_str_strstr),
             ty => return err!("non-map type: {:?}", ty),
         };
         let mapv = self.get_val(map)?;
@@ -517,6 +587,18 @@ pub(crate) trait CodeGenerator: Backend {
             MapStrInt => intrinsic!(len_strint),
             MapStrFloat => intrinsic!(len_strfloat),
             MapStrStr => intrinsic!(len_strstr),
+            MapIntMapIntInt => intrinsic!(len_int_intint),
+            MapIntMapIntFloat => intrinsic!(len_int_intfloat),
+            MapIntMapIntStr => intrinsic!(len_int_intstr),
+            MapIntMapStrInt => intrinsic!(len_int_strint),
+            MapIntMapStrFloat => intrinsic!(len_int_strfloat),
+            MapIntMapStrStr => intrinsic!(len_int_strstr),
+            MapStrMapIntInt => intrinsic!(len_str_intint),
+            MapStrMapIntFloat => intrinsic!(len_str_intfloat),
+            MapStrMapIntStr => intrinsic!(len_str_intstr),
+            MapStrMapStrInt => intrinsic!(len_str_strint),
+            MapStrMapStrFloat => intrinsic!(len_str_strfloat),
+            MapStrMapStrStr => intrinsic!(len_str_strstr),
             ty => return err!("non-map type: {:?}", ty),
         };
         let mapv = self.get_val(map)?;
@@ -525,6 +607,47 @@ pub(crate) trait CodeGenerator: Backend {
         Ok(())
     }
 
+    /// Stores the values (or keys, if `keys` is set) of `map` into `into`, ordered according to
+    /// `how`. `into` may have integer or string keys.
+    fn sort_map(&mut self, map: Ref, into: Ref, how: Ref, dst: Ref, keys: bool) -> Result<()> {
+        use compile::Ty::*;
+        let str_keys = into.1.key()? == Str;
+        let func = match (map.1, keys, str_keys) {
+            (MapIntInt, false, false) => intrinsic!(asort_intint),
+            (MapIntFloat, false, false) => intrinsic!(asort_intfloat),
+            (MapIntStr, false, false) => intrinsic!(asort_intstr),
+            (MapStrInt, false, false) => intrinsic!(asort_strint),
+            (MapStrFloat, false, false) => intrinsic!(asort_strfloat),
+            (MapStrStr, false, false) => intrinsic!(asort_strstr),
+            (MapIntInt, true, false) => intrinsic!(asorti_intint),
+            (MapIntFloat, true, false) => intrinsic!(asorti_intfloat),
+            (MapIntStr, true, false) => intrinsic!(asorti_intstr),
+            (MapStrInt, true, false) => intrinsic!(asorti_strint),
+            (MapStrFloat, true, false) => intrinsic!(asorti_strfloat),
+            (MapStrStr, true, false) => intrinsic!(asorti_strstr),
+            (MapIntInt, false, true) => intrinsic!(asort_intint_strkeys),
+            (MapIntFloat, false, true) => intrinsic!(asort_intfloat_strkeys),
+            (MapIntStr, false, true) => intrinsic!(asort_intstr_strkeys),
+            (MapStrInt, false, true) => intrinsic!(asort_strint_strkeys),
+            (MapStrFloat, false, true) => intrinsic!(asort_strfloat_strkeys),
+            (MapStrStr, false, true) => intrinsic!(asort_strstr_strkeys),
+            (MapIntInt, true, true) => intrinsic!(asorti_intint_strkeys),
+            (MapIntFloat, true, true) => intrinsic!(asorti_intfloat_strkeys),
+            (MapIntStr, true, true) => intrinsic!(asorti_intstr_strkeys),
+            (MapStrInt, true, true) => intrinsic!(asorti_strint_strkeys),
+            (MapStrFloat, true, true) => intrinsic!(asorti_strfloat_strkeys),
+            (MapStrStr, true, true) => intrinsic!(asorti_strstr_strkeys),
+            (ty, _, _) => return err!("non-map type: {:?}", ty),
+        };
+        let rt = self.runtime_val();
+        let mapv = self.get_val(map)?;
+        let intov = self.get_val(into)?;
+        let howv = self.get_val(how)?;
+        let resv = self.call_intrinsic(func, &mut [rt, mapv, intov, howv])?;
+        self.bind_val(dst, resv)?;
+        Ok(())
+    }
+
     /// Stores `val` into `map` at key `key`.
     ///
     /// Assumes that the types of the input registers match up.
@@ -582,6 +705,22 @@ pub(crate) trait CodeGenerator: Backend {
         self.bind_val(dst.reflect(), res)
     }
 
+    /// Wraps `call_intrinsic` for comparisons between a strnum and a float, which need the runtime
+    /// to convert the float using `CONVFMT` if the strnum does not look numeric.
+    fn strnum_float_cmp(
+        &mut self,
+        op: Op,
+        dst: &impl Accum,
+        l: &impl Accum,
+        r: &impl Accum,
+    ) -> Result<()> {
+        let lv = self.get_val(l.reflect())?;
+        let rv = self.get_val(r.reflect())?;
+        let rt = self.runtime_val();
+        let res = self.call_intrinsic(op, &mut [rt, lv, rv])?;
+        self.bind_val(dst.reflect(), res)
+    }
+
     /// Wraps `call_intrinsic` for [`Op`]s that have one argument and return a value.
     fn unop(&mut self, op: Op, dst: &impl Accum, x: &impl Accum) -> Result<()> {
         let xv = self.get_val(x.reflect())?;
@@ -605,7 +744,18 @@ pub(crate) trait CodeGenerator: Backend {
                 self.bind_val(fr.reflect(), fv)
             }
             IntToStr(sr, ir) => self.unop(intrinsic!(int_to_str), sr, ir),
-            FloatToStr(sr, fr) => self.unop(intrinsic!(float_to_str), sr, fr),
+            FloatToStr(sr, fr) => {
+                let fv = self.get_val(fr.reflect())?;
+                let rt = self.runtime_val();
+                let res = self.call_intrinsic(intrinsic!(float_to_str), &mut [rt, fv])?;
+                self.bind_val(sr.reflect(), res)
+            }
+            FloatToStrOut(sr, fr) => {
+                let fv = self.get_val(fr.reflect())?;
+                let rt = self.runtime_val();
+                let res = self.call_intrinsic(intrinsic!(float_to_str_out), &mut [rt, fv])?;
+                self.bind_val(sr.reflect(), res)
+            }
             StrToInt(ir, sr) => self.unop(intrinsic!(str_to_int), ir, sr),
             HexStrToInt(ir, sr) => self.unop(intrinsic!(hex_str_to_int), ir, sr),
             StrToFloat(fr, sr) => self.unop(intrinsic!(str_to_float), fr, sr),
@@ -613,6 +763,8 @@ pub(crate) trait CodeGenerator: Backend {
             IntToFloat(fr, ir) => self.unop(Op::IntToFloat, fr, ir),
             ToLowerAscii(dst, src) => self.unop(intrinsic!(to_lower_ascii), dst, src),
             ToUpperAscii(dst, src) => self.unop(intrinsic!(to_upper_ascii), dst, src),
+            ToLower(dst, src) => self.unop(intrinsic!(to_lower), dst, src),
+            ToUpper(dst, src) => self.unop(intrinsic!(to_upper), dst, src),
             AddInt(res, l, r) => self.binop(op(Arith::Add, false), res, l, r),
             AddFloat(res, l, r) => self.binop(op(Arith::Add, true), res, l, r),
             MinusInt(res, l, r) => self.binop(op(Arith::Minus, false), res, l, r),
@@ -670,6 +822,25 @@ pub(crate) trait CodeGenerator: Backend {
                 let res = self.call_intrinsic(intrinsic!(reseed_rng), &mut [rt])?;
                 self.bind_val(dst.reflect(), res)
             }
+            Systime(dst) => {
+                let res = self.call_intrinsic(intrinsic!(systime), &mut [])?;
+                self.bind_val(dst.reflect(), res)
+            }
+            Strftime(dst, fmt, ts, utc) => {
+                let fmtv = self.get_val(fmt.reflect())?;
+                let tsv = self.get_val(ts.reflect())?;
+                let utcv = self.get_val(utc.reflect())?;
+                let res = self.call_intrinsic(intrinsic!(strftime), &mut [fmtv, tsv, utcv])?;
+                self.bind_val(dst.reflect(), res)
+            }
+            Mktime(dst, spec, utc) => self.binop(intrinsic!(mktime), dst, spec, utc),
+            Strptime(dst, s, fmt, utc) => {
+                let sv = self.get_val(s.reflect())?;
+                let fmtv = self.get_val(fmt.reflect())?;
+                let utcv = self.get_val(utc.reflect())?;
+                let res = self.call_intrinsic(intrinsic!(strptime), &mut [sv, fmtv, utcv])?;
+                self.bind_val(dst.reflect(), res)
+            }
             Concat(dst, l, r) => self.binop(intrinsic!(concat), dst, l, r),
             StartsWithConst(dst, s, bs) => {
                 let s = self.get_val(s.reflect())?;
@@ -685,6 +856,15 @@ pub(crate) trait CodeGenerator: Backend {
                 let res = self.call_intrinsic(intrinsic!(match_pat_loc), &mut [rt, lv, rv])?;
                 self.bind_val(dst.reflect(), res)
             }
+            MatchGroups(dst, l, r, arr) => {
+                let lv = self.get_val(l.reflect())?;
+                let rv = self.get_val(r.reflect())?;
+                let arrv = self.get_val(arr.reflect())?;
+                let rt = self.runtime_val();
+                let res =
+                    self.call_intrinsic(intrinsic!(match_pat_loc_strmap), &mut [rt, lv, rv, arrv])?;
+                self.bind_val(dst.reflect(), res)
+            }
             IsMatch(dst, l, r) => {
                 let lv = self.get_val(l.reflect())?;
                 let rv = self.get_val(r.reflect())?;
@@ -692,6 +872,13 @@ pub(crate) trait CodeGenerator: Backend {
                 let res = self.call_intrinsic(intrinsic!(match_pat), &mut [rt, lv, rv])?;
                 self.bind_val(dst.reflect(), res)
             }
+            MatchCases(dst, s, cases) => {
+                let sv = self.get_val(s.reflect())?;
+                let cv = self.get_val(cases.reflect())?;
+                let rt = self.runtime_val();
+                let res = self.call_intrinsic(intrinsic!(match_cases), &mut [rt, sv, cv])?;
+                self.bind_val(dst.reflect(), res)
+            }
             MatchConst(res, src, pat) => {
                 let rt = self.runtime_val();
                 let srcv = self.get_val(src.reflect())?;
@@ -701,13 +888,17 @@ pub(crate) trait CodeGenerator: Backend {
                 self.bind_val(res.reflect(), resv)
             }
             IsMatchConst(res, src, pat) => {
+                let rt = self.runtime_val();
                 let srcv = self.get_val(src.reflect())?;
                 let patv = self.const_re(pat.clone());
-                let resv = self.call_intrinsic(intrinsic!(match_const_pat), &mut [srcv, patv])?;
+                let resv =
+                    self.call_intrinsic(intrinsic!(match_const_pat), &mut [rt, srcv, patv])?;
                 self.bind_val(res.reflect(), resv)
             }
             SubstrIndex(dst, s, t) => self.binop(intrinsic!(substr_index), dst, s, t),
             LenStr(dst, x) => self.unop(intrinsic!(str_len), dst, x),
+            SubstrIndexChars(dst, s, t) => self.binop(intrinsic!(substr_index_chars), dst, s, t),
+            LenStrChars(dst, x) => self.unop(intrinsic!(str_len_chars), dst, x),
             Sub(res, pat, s, in_s) => {
                 let rt = self.runtime_val();
                 let patv = self.get_val(pat.reflect())?;
@@ -726,6 +917,16 @@ pub(crate) trait CodeGenerator: Backend {
                     self.call_intrinsic(intrinsic!(subst_all), &mut [rt, patv, sv, in_sv])?;
                 self.bind_val(res.reflect(), resv)
             }
+            GenSub(res, pat, s, how, in_s) => {
+                let rt = self.runtime_val();
+                let patv = self.get_val(pat.reflect())?;
+                let sv = self.get_val(s.reflect())?;
+                let howv = self.get_val(how.reflect())?;
+                let in_sv = self.get_val(in_s.reflect())?;
+                let resv =
+                    self.call_intrinsic(intrinsic!(gen_subst), &mut [rt, patv, sv, howv, in_sv])?;
+                self.bind_val(res.reflect(), resv)
+            }
             EscapeCSV(dst, s) => self.unop(intrinsic!(escape_csv), dst, s),
             EscapeTSV(dst, s) => self.unop(intrinsic!(escape_tsv), dst, s),
             Substr(res, base, l, r) => {
@@ -735,6 +936,13 @@ pub(crate) trait CodeGenerator: Backend {
                 let resv = self.call_intrinsic(intrinsic!(substr), &mut [basev, lv, rv])?;
                 self.bind_val(res.reflect(), resv)
             }
+            SubstrChars(res, base, l, r) => {
+                let basev = self.get_val(base.reflect())?;
+                let lv = self.get_val(l.reflect())?;
+                let rv = self.get_val(r.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(substr_chars), &mut [basev, lv, rv])?;
+                self.bind_val(res.reflect(), resv)
+            }
             LTInt(res, l, r) => self.binop(cmp(Cmp::LT, false), res, l, r),
             GTInt(res, l, r) => self.binop(cmp(Cmp::GT, false), res, l, r),
             LTEInt(res, l, r) => self.binop(cmp(Cmp::LTE, false), res, l, r),
@@ -750,6 +958,26 @@ pub(crate) trait CodeGenerator: Backend {
             LTEStr(res, l, r) => self.binop(intrinsic!(str_lte), res, l, r),
             GTEStr(res, l, r) => self.binop(intrinsic!(str_gte), res, l, r),
             EQStr(res, l, r) => self.binop(intrinsic!(str_eq), res, l, r),
+            LTStrNum(res, l, r) => self.binop(intrinsic!(strnum_lt), res, l, r),
+            GTStrNum(res, l, r) => self.binop(intrinsic!(strnum_gt), res, l, r),
+            LTEStrNum(res, l, r) => self.binop(intrinsic!(strnum_lte), res, l, r),
+            GTEStrNum(res, l, r) => self.binop(intrinsic!(strnum_gte), res, l, r),
+            EQStrNum(res, l, r) => self.binop(intrinsic!(strnum_eq), res, l, r),
+            LTStrNumFloat(res, l, r) => {
+                self.strnum_float_cmp(intrinsic!(strnum_float_lt), res, l, r)
+            }
+            GTStrNumFloat(res, l, r) => {
+                self.strnum_float_cmp(intrinsic!(strnum_float_gt), res, l, r)
+            }
+            LTEStrNumFloat(res, l, r) => {
+                self.strnum_float_cmp(intrinsic!(strnum_float_lte), res, l, r)
+            }
+            GTEStrNumFloat(res, l, r) => {
+                self.strnum_float_cmp(intrinsic!(strnum_float_gte), res, l, r)
+            }
+            EQStrNumFloat(res, l, r) => {
+                self.strnum_float_cmp(intrinsic!(strnum_float_eq), res, l, r)
+            }
             SetColumn(dst, src) => {
                 let rt = self.runtime_val();
                 let srcv = self.get_val(src.reflect())?;
@@ -804,35 +1032,57 @@ pub(crate) trait CodeGenerator: Backend {
                     self.call_intrinsic(intrinsic!(split_str), &mut [rt, tsv, arrv, patv])?;
                 self.bind_val(flds.reflect(), fldsv)
             }
+            PatSplit(flds, to_split, arr, pat, seps) => {
+                let rt = self.runtime_val();
+                let tsv = self.get_val(to_split.reflect())?;
+                let arrv = self.get_val(arr.reflect())?;
+                let patv = self.get_val(pat.reflect())?;
+                let sepsv = self.get_val(seps.reflect())?;
+                let fldsv =
+                    self.call_intrinsic(intrinsic!(pat_split), &mut [rt, tsv, arrv, patv, sepsv])?;
+                self.bind_val(flds.reflect(), fldsv)
+            }
             Printf { output, fmt, args } => self.printf(output, fmt, &args[..]),
             Sprintf { dst, fmt, args } => self.sprintf(dst, fmt, &args[..]),
             PrintAll { output, args } => self.print_all(output, &args[..]),
-            Close(file) => {
+            Close(dst, file, how) => {
                 let rt = self.runtime_val();
                 let filev = self.get_val(file.reflect())?;
-                self.call_void(external!(close_file), &mut [rt, filev])?;
-                Ok(())
+                let howv = self.get_val(how.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(close_file), &mut [rt, filev, howv])?;
+                self.bind_val(dst.reflect(), resv)
             }
             RunCmd(dst, cmd) => self.unop(intrinsic!(run_system), dst, cmd),
+            FFlush(dst, file) => {
+                let rt = self.runtime_val();
+                let filev = self.get_val(file.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(fflush), &mut [rt, filev])?;
+                self.bind_val(dst.reflect(), resv)
+            }
             Exit(code) => {
                 let rt = self.runtime_val();
                 let codev = self.get_val(code.reflect())?;
                 self.call_void(external!(exit), &mut [rt, codev])?;
                 Ok(())
             }
-            ReadErr(dst, file, is_file) => {
+            UndefinedFunc(name) => {
+                let rt = self.runtime_val();
+                let namev = self.get_val(name.reflect())?;
+                self.call_void(external!(undefined_func), &mut [rt, namev])?;
+                Ok(())
+            }
+            ReadErr(dst, file, spec) => {
                 let rt = self.runtime_val();
                 let filev = self.get_val(file.reflect())?;
-                let is_filev = self.const_int(*is_file as i64);
-                let resv = self.call_intrinsic(intrinsic!(read_err), &mut [rt, filev, is_filev])?;
+                let specv = self.const_int(*spec as i64);
+                let resv = self.call_intrinsic(intrinsic!(read_err), &mut [rt, filev, specv])?;
                 self.bind_val(dst.reflect(), resv)
             }
-            NextLine(dst, file, is_file) => {
+            NextLine(dst, file, spec) => {
                 let rt = self.runtime_val();
                 let filev = self.get_val(file.reflect())?;
-                let is_filev = self.const_int(*is_file as i64);
-                let resv =
-                    self.call_intrinsic(intrinsic!(next_line), &mut [rt, filev, is_filev])?;
+                let specv = self.const_int(*spec as i64);
+                let resv = self.call_intrinsic(intrinsic!(next_line), &mut [rt, filev, specv])?;
                 self.bind_val(dst.reflect(), resv)
             }
             ReadErrStdin(dst) => {
@@ -850,6 +1100,11 @@ pub(crate) trait CodeGenerator: Backend {
                 self.call_void(external!(next_line_stdin_fused), &mut [rt])?;
                 Ok(())
             }
+            NextLineStdinEvent(dst) => {
+                let rt = self.runtime_val();
+                let resv = self.call_intrinsic(intrinsic!(next_line_stdin_event), &mut [rt])?;
+                self.bind_val(dst.reflect(), resv)
+            }
             NextFile() => {
                 let rt = self.runtime_val();
                 self.call_void(external!(next_file), &mut [rt])?;
@@ -893,6 +1148,34 @@ pub(crate) trait CodeGenerator: Backend {
             Delete { map_ty, map, key } => self.delete_map((*map, *map_ty), (*key, map_ty.key()?)),
             Clear { map_ty, map } => self.clear_map((*map, *map_ty)),
             Len { map_ty, map, dst } => self.len_map((*map, *map_ty), (*dst, compile::Ty::Int)),
+            Asort {
+                map_ty,
+                into_ty,
+                dst,
+                map,
+                into,
+                how,
+            } => self.sort_map(
+                (*map, *map_ty),
+                (*into, *into_ty),
+                how.reflect(),
+                dst.reflect(),
+                /*keys=*/ false,
+            ),
+            Asorti {
+                map_ty,
+                into_ty,
+                dst,
+                map,
+                into,
+                how,
+            } => self.sort_map(
+                (*map, *map_ty),
+                (*into, *into_ty),
+                how.reflect(),
+                dst.reflect(),
+                /*keys=*/ true,
+            ),
             Store {
                 map_ty,
                 map,
@@ -982,6 +1265,20 @@ pub(crate) trait CodeGenerator: Backend {
                 self.call_void(external!(store_var_strmap), &mut [rt, varv, srcv])?;
                 Ok(())
             }
+            LoadVarStrStrMap(dst, var) => {
+                let rt = self.runtime_val();
+                let varv = self.const_int(*var as i64);
+                let res = self.call_intrinsic(intrinsic!(load_var_strstrmap), &mut [rt, varv])?;
+                let dref = dst.reflect();
+                self.bind_val(dref, res)
+            }
+            StoreVarStrStrMap(var, src) => {
+                let rt = self.runtime_val();
+                let varv = self.const_int(*var as i64);
+                let srcv = self.get_val(src.reflect())?;
+                self.call_void(external!(store_var_strstrmap), &mut [rt, varv, srcv])?;
+                Ok(())
+            }
             LoadSlot { ty, dst, slot } => self.load_slot((*dst, *ty), *slot),
             StoreSlot { ty, src, slot } => self.store_slot((*src, *ty), *slot),
             Mov(ty, dst, src) => self.mov(*ty, *dst, *src),
@@ -183,6 +183,31 @@ impl std::fmt::Display for CompileError {
     }
 }
 
+/// Expands to a pattern matching every map type in [`crate::compile::Ty`], including arrays of
+/// arrays.
+macro_rules! map_tys {
+    () => {
+        $crate::compile::Ty::MapIntInt
+            | $crate::compile::Ty::MapIntFloat
+            | $crate::compile::Ty::MapIntStr
+            | $crate::compile::Ty::MapStrInt
+            | $crate::compile::Ty::MapStrFloat
+            | $crate::compile::Ty::MapStrStr
+            | $crate::compile::Ty::MapIntMapIntInt
+            | $crate::compile::Ty::MapIntMapIntFloat
+            | $crate::compile::Ty::MapIntMapIntStr
+            | $crate::compile::Ty::MapIntMapStrInt
+            | $crate::compile::Ty::MapIntMapStrFloat
+            | $crate::compile::Ty::MapIntMapStrStr
+            | $crate::compile::Ty::MapStrMapIntInt
+            | $crate::compile::Ty::MapStrMapIntFloat
+            | $crate::compile::Ty::MapStrMapIntStr
+            | $crate::compile::Ty::MapStrMapStrInt
+            | $crate::compile::Ty::MapStrMapStrFloat
+            | $crate::compile::Ty::MapStrMapStrStr
+    };
+}
+
 macro_rules! err {
     ($head:expr) => {
         Err($crate::common::CompileError(
@@ -388,6 +413,7 @@ pub enum FileSpec {
     Trunc = 0,
     Append = 1,
     Cmd = 2,
+    Coproc = 3,
 }
 
 #[derive(Debug)]
@@ -408,6 +434,8 @@ impl std::convert::TryFrom<i64> for FileSpec {
             Ok(FileSpec::Append)
         } else if i == FileSpec::Cmd as i64 {
             Ok(FileSpec::Cmd)
+        } else if i == FileSpec::Coproc as i64 {
+            Ok(FileSpec::Coproc)
         } else {
             Err(InvalidFileSpec)
         }
@@ -420,6 +448,30 @@ impl Default for FileSpec {
     }
 }
 
+/// The source of an explicit `getline`: a file, a command, or a coprocess.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(i64)]
+pub enum InputSpec {
+    Cmd = 0,
+    File = 1,
+    Coproc = 2,
+}
+
+impl std::convert::TryFrom<i64> for InputSpec {
+    type Error = InvalidFileSpec;
+    fn try_from(i: i64) -> std::result::Result<InputSpec, InvalidFileSpec> {
+        if i == InputSpec::Cmd as i64 {
+            Ok(InputSpec::Cmd)
+        } else if i == InputSpec::File as i64 {
+            Ok(InputSpec::File)
+        } else if i == InputSpec::Coproc as i64 {
+            Ok(InputSpec::Coproc)
+        } else {
+            Err(InvalidFileSpec)
+        }
+    }
+}
+
 pub(crate) fn traverse<T>(o: Option<Result<T>>) -> Result<Option<T>> {
     match o {
         Some(e) => Ok(Some(e?)),
@@ -5,17 +5,16 @@ use crate::codegen;
 #[cfg(feature = "llvm_backend")]
 use crate::codegen::llvm;
 use crate::common::{
-    CancelSignal, CompileError, Either, Graph, NodeIx, NumTy, Result, Stage, WorkList,
+    CancelSignal, CompileError, Either, Graph, InputSpec, NodeIx, NumTy, Result, Stage, WorkList,
 };
 use crate::cross_stage;
 use crate::input_taint::TaintedStringAnalysis;
 use crate::pushdown::{FieldSet, UsedFieldAnalysis};
-use crate::runtime::{self, Str};
+use crate::runtime::{self, ConstRegex, Str};
 use crate::string_constants::{self, StringConstantAnalysis};
 use crate::types;
 
 use hashbrown::{hash_map::Entry, HashMap, HashSet};
-use regex::bytes::Regex;
 use smallvec::smallvec;
 
 use std::collections::VecDeque;
@@ -36,9 +35,21 @@ pub(crate) enum Ty {
     MapStrInt = 6,
     MapStrFloat = 7,
     MapStrStr = 8,
-    IterInt = 9,
-    IterStr = 10,
-    Null = 11,
+    MapIntMapIntInt = 9,
+    MapIntMapIntFloat = 10,
+    MapIntMapIntStr = 11,
+    MapIntMapStrInt = 12,
+    MapIntMapStrFloat = 13,
+    MapIntMapStrStr = 14,
+    MapStrMapIntInt = 15,
+    MapStrMapIntFloat = 16,
+    MapStrMapIntStr = 17,
+    MapStrMapStrInt = 18,
+    MapStrMapStrFloat = 19,
+    MapStrMapStrStr = 20,
+    IterInt = 21,
+    IterStr = 22,
+    Null = 23,
 }
 
 pub(crate) const NUM_TYPES: usize = Ty::Null as usize + 1;
@@ -57,9 +68,21 @@ impl std::convert::TryFrom<u32> for Ty {
             6 => MapStrInt,
             7 => MapStrFloat,
             8 => MapStrStr,
-            9 => IterInt,
-            10 => IterStr,
-            11 => Null,
+            9 => MapIntMapIntInt,
+            10 => MapIntMapIntFloat,
+            11 => MapIntMapIntStr,
+            12 => MapIntMapStrInt,
+            13 => MapIntMapStrFloat,
+            14 => MapIntMapStrStr,
+            15 => MapStrMapIntInt,
+            16 => MapStrMapIntFloat,
+            17 => MapStrMapIntStr,
+            18 => MapStrMapStrInt,
+            19 => MapStrMapStrFloat,
+            20 => MapStrMapStrStr,
+            21 => IterInt,
+            22 => IterStr,
+            23 => Null,
             _ => return Err(()),
         })
     }
@@ -82,12 +105,9 @@ impl Ty {
 
     pub(crate) fn key_iter(self) -> Result<Ty> {
         use Ty::*;
-        match self {
-            MapIntInt | MapIntFloat | MapIntStr => Ok(IterInt),
-            MapStrInt | MapStrFloat | MapStrStr => Ok(IterStr),
-            Null | Int | Float | Str | IterInt | IterStr => {
-                err!("attempt to get iterator from non-map type: {:?}", self)
-            }
+        match self.key()? {
+            Int => Ok(IterInt),
+            _ => Ok(IterStr),
         }
     }
 
@@ -96,38 +116,59 @@ impl Ty {
         match self {
             IterInt => Ok(Int),
             IterStr => Ok(Str),
-            Null | Int | Float | Str | MapIntInt | MapIntFloat | MapIntStr | MapStrInt
-            | MapStrFloat | MapStrStr => {
-                err!("attempt to get element of non-iterator type: {:?}", self)
-            }
+            _ => err!("attempt to get element of non-iterator type: {:?}", self),
         }
     }
 
     pub(crate) fn is_array(self) -> bool {
-        use Ty::*;
-        match self {
-            MapIntInt | MapIntFloat | MapIntStr | MapStrInt | MapStrFloat | MapStrStr => true,
-            Null | Int | Float | Str | IterInt | IterStr => false,
-        }
+        self.key().is_ok()
+    }
+
+    /// Whether this is a map whose values are themselves maps.
+    pub(crate) fn is_nested_map(self) -> bool {
+        matches!(self.val(), Ok(v) if v.is_array())
     }
 
     pub(crate) fn key(self) -> Result<Ty> {
         use Ty::*;
         match self {
-            MapIntInt | MapIntFloat | MapIntStr => Ok(Int),
-            MapStrInt | MapStrFloat | MapStrStr => Ok(Str),
+            MapIntInt | MapIntFloat | MapIntStr | MapIntMapIntInt | MapIntMapIntFloat
+            | MapIntMapIntStr | MapIntMapStrInt | MapIntMapStrFloat | MapIntMapStrStr => Ok(Int),
+            MapStrInt | MapStrFloat | MapStrStr | MapStrMapIntInt | MapStrMapIntFloat
+            | MapStrMapIntStr | MapStrMapStrInt | MapStrMapStrFloat | MapStrMapStrStr => Ok(Str),
             Null | Int | Float | Str | IterInt | IterStr => {
                 err!("attempt to get key of non-map type: {:?}", self)
             }
         }
     }
 
+    /// The type of the map populated by asort (or asorti, if `keys` is set) with the contents
+    /// of a map of this type.
+    pub(crate) fn sorted_ty(self, keys: bool) -> Result<Ty> {
+        use Ty::*;
+        if self.is_nested_map() {
+            return err!("cannot sort an array of arrays: {:?}", self);
+        }
+        match if keys { self.key()? } else { self.val()? } {
+            Int => Ok(MapIntInt),
+            Float => Ok(MapIntFloat),
+            Str => Ok(MapIntStr),
+            ty => err!("unexpected map element type: {:?}", ty),
+        }
+    }
+
     pub(crate) fn val(self) -> Result<Ty> {
         use Ty::*;
         match self {
             MapStrInt | MapIntInt => Ok(Int),
             MapStrFloat | MapIntFloat => Ok(Float),
             MapStrStr | MapIntStr => Ok(Str),
+            MapIntMapIntInt | MapStrMapIntInt => Ok(MapIntInt),
+            MapIntMapIntFloat | MapStrMapIntFloat => Ok(MapIntFloat),
+            MapIntMapIntStr | MapStrMapIntStr => Ok(MapIntStr),
+            MapIntMapStrInt | MapStrMapStrInt => Ok(MapStrInt),
+            MapIntMapStrFloat | MapStrMapStrFloat => Ok(MapStrFloat),
+            MapIntMapStrStr | MapStrMapStrStr => Ok(MapStrStr),
             Null | Int | Float | Str | IterInt | IterStr => {
                 err!("attempt to get val of non-map type: {:?}", self)
             }
@@ -365,6 +406,9 @@ pub(crate) struct Typer<'a> {
     // For analysis passes that introspect into the set of constant string values that will
     // dynamically be assigned to a register
     string_constants: Option<StringConstantAnalysis<'a>>,
+    // Whether the program assigns IGNORECASE, in which case folded regex constants also need a
+    // case-insensitive variant.
+    assigns_ignorecase: bool,
     // Not used for bytecode generation.
     callgraph: Graph<HashSet<(NumTy, Ty)>, ()>,
 
@@ -455,6 +499,10 @@ struct View<'a, 'b> {
     // The current basic block being filled; It'll be swaped into `frame.cfg` as we translate a
     // given function cfg.
     stream: &'b mut Node<'a>,
+    // Whether string builtins operate on characters rather than bytes.
+    char_semantics: bool,
+    // Variables that may hold strnums, keyed by source function (0 for globals).
+    strnum_vars: &'b HashSet<(Ident, NumTy)>,
 }
 
 fn pop_var<'a>(instrs: &mut Vec<LL<'a>>, reg: NumTy, ty: Ty) -> Result<()> {
@@ -462,8 +510,7 @@ fn pop_var<'a>(instrs: &mut Vec<LL<'a>>, reg: NumTy, ty: Ty) -> Result<()> {
     instrs.push(match ty {
         Null => return Ok(()),
         IterInt | IterStr => return err!("invalid argument type: {:?}", ty),
-        Int | Float | Str | MapIntInt | MapIntFloat | MapIntStr | MapStrInt | MapStrFloat
-        | MapStrStr => LL::Pop(ty, reg),
+        _ => LL::Pop(ty, reg),
     });
     Ok(())
 }
@@ -472,22 +519,19 @@ fn push_var<'a>(instrs: &mut Vec<LL<'a>>, reg: NumTy, ty: Ty) -> Result<()> {
     use Ty::*;
     match ty {
         Null => Ok(()),
-        Int | Float | Str | MapIntInt | MapIntFloat | MapIntStr | MapStrInt | MapStrFloat
-        | MapStrStr => {
+        IterInt | IterStr => err!("invalid argument type: {:?}", ty),
+        _ => {
             instrs.push(LL::Push(ty, reg));
             Ok(())
         }
-        IterInt | IterStr => err!("invalid argument type: {:?}", ty),
     }
 }
 
 fn alloc_local<'a>(dst_reg: NumTy, dst_ty: Ty) -> Option<LL<'a>> {
-    use Ty::*;
-    match dst_ty {
-        MapIntInt | MapIntFloat | MapIntStr | MapStrInt | MapStrFloat | MapStrStr => {
-            Some(LL::AllocMap(dst_ty, dst_reg))
-        }
-        _ => None,
+    if dst_ty.is_array() {
+        Some(LL::AllocMap(dst_ty, dst_reg))
+    } else {
+        None
     }
 }
 
@@ -499,8 +543,7 @@ fn mov<'a>(dst_reg: u32, src_reg: u32, ty: Ty) -> Result<Option<LL<'a>>> {
     let res = match ty {
         Null => return Ok(None),
         IterInt | IterStr => return err!("attempt to move values of type {:?}", ty),
-        Int | Float | Str | MapIntInt | MapIntFloat | MapIntStr | MapStrInt | MapStrFloat
-        | MapStrStr => LL::Mov(ty, dst_reg, src_reg),
+        _ => LL::Mov(ty, dst_reg, src_reg),
     };
 
     Ok(Some(res))
@@ -725,6 +768,7 @@ impl<'a> Typer<'a> {
         if !pc.allow_arbitrary_commands {
             gen.taint_analysis = Some(Default::default());
         }
+        gen.assigns_ignorecase = pc.assigns_ignorecase();
         if pc.fold_regex_constants || pc.parse_header {
             gen.string_constants = Some(StringConstantAnalysis::from_config(
                 string_constants::Config {
@@ -733,7 +777,11 @@ impl<'a> Typer<'a> {
                 },
             ));
         }
-        let types::TypeInfo { var_tys, func_tys } = types::get_types(pc)?;
+        let types::TypeInfo {
+            var_tys,
+            func_tys,
+            strnum_vars,
+        } = types::get_types(pc)?;
         let local_globals = pc.local_globals();
         macro_rules! init_entry {
             ($v:expr, $func_id:expr, $args:expr) => {
@@ -805,6 +853,8 @@ impl<'a> Typer<'a> {
                 local_globals: &gen.local_globals,
                 func_info: &gen.func_info,
                 stream: &mut stream,
+                char_semantics: pc.char_semantics,
+                strnum_vars: &strnum_vars,
             }
             .process_function(&pc.funcs[src_func])?;
         }
@@ -863,9 +913,11 @@ impl<'a> Typer<'a> {
                     let text = std::str::from_utf8(&strs[0]).map_err(|e| {
                         CompileError(format!("regex patterns must be valid UTF-8: {}", e))
                     })?;
-                    let re = Arc::new(Regex::new(text).map_err(|err| {
-                        CompileError(format!("regex parse error during compilation: {}", err))
-                    })?);
+                    let re = Arc::new(ConstRegex::new(text, self.assigns_ignorecase).map_err(
+                        |err| {
+                            CompileError(format!("regex parse error during compilation: {}", err))
+                        },
+                    )?);
                     // TODO: finish up
                     let inst = self.frames[frame]
                         .cfg
@@ -876,7 +928,13 @@ impl<'a> Typer<'a> {
                         .unwrap();
                     let new_inst: Instr = match inst {
                         Either::Left(LL::IsMatch(dst, s, _)) => {
-                            if let Some(bs) = extract_anchored_literal(text) {
+                            // Literal prefixes are always compared case-sensitively.
+                            let literal = if self.assigns_ignorecase {
+                                None
+                            } else {
+                                extract_anchored_literal(text)
+                            };
+                            if let Some(bs) = literal {
                                 Either::Left(LL::StartsWithConst(*dst, *s, bs))
                             } else {
                                 Either::Left(LL::IsMatchConst(*dst, *s, re))
@@ -1284,24 +1342,45 @@ impl<'a, 'b> View<'a, 'b> {
             self.regs.stats.reg_of_ty(arr_val_ty)
         };
 
-        // Emit the corresponding instruction.
-        use Ty::*;
-        match arr_ty {
-            MapIntInt | MapIntFloat | MapIntStr | MapStrInt | MapStrFloat | MapStrStr => self
-                .pushl(LL::Lookup {
-                    map_ty: arr_ty,
-                    dst: load_reg,
-                    map: arr_reg,
-                    key: key_reg,
-                }),
-            Null | Int | Float | Str | IterInt | IterStr => {
-                return err!("[load_map] expected map type, found {:?}", arr_ty)
-            }
-        };
+        // Emit the corresponding instruction. `arr_ty` is a map type: `val` fails otherwise.
+        self.pushl(LL::Lookup {
+            map_ty: arr_ty,
+            dst: load_reg,
+            map: arr_reg,
+            key: key_reg,
+        });
         // Convert the result: note that if we had load_reg == dst_reg, then this is a noop.
         self.convert(dst_reg, dst_ty, load_reg, arr_val_ty)
     }
 
+    // POSIX compares a strnum (a string read from input) numerically if it looks like a number,
+    // whether it is compared to another strnum or to a number. Comparisons against other strings
+    // (and against uninitialized variables) keep their usual typing.
+    fn is_strnum_cmp(
+        &self,
+        bf: &builtins::Function,
+        args: &cfg::SmallVec<PrimVal<'a>>,
+        args_tys: &[Ty],
+    ) -> bool {
+        use crate::ast::Binop::*;
+        if !matches!(bf, builtins::Function::Binop(LT | GT | LTE | GTE | EQ)) || args.len() != 2 {
+            return false;
+        }
+        let is_strnum = |i: usize| match &args[i] {
+            PrimVal::Var(id) if args_tys[i] == Ty::Str => {
+                let func = if id.is_global(self.local_globals) {
+                    0
+                } else {
+                    self.frame.src_function
+                };
+                self.strnum_vars.contains(&(*id, func))
+            }
+            _ => false,
+        };
+        let is_num = |i: usize| matches!(args_tys[i], Ty::Int | Ty::Float);
+        (is_strnum(0) && (is_strnum(1) || is_num(1))) || (is_num(0) && is_strnum(1))
+    }
+
     fn builtin(
         &mut self,
         dst_reg: u32,
@@ -1323,7 +1402,15 @@ impl<'a, 'b> View<'a, 'b> {
 
         // Now, perform any necessary conversions if input types do not match the argument types.
         let mut conv_regs: cfg::SmallVec<_> = smallvec![UNUSED; args.len()];
-        let (conv_tys, res_ty) = bf.type_sig(&args_tys[..])?;
+        let strnum_cmp = self.is_strnum_cmp(bf, args, &args_tys[..]);
+        let (conv_tys, res_ty) = if strnum_cmp {
+            // Keep a number compared with a strnum as a Float: converting it to a string would
+            // round it using CONVFMT.
+            let conv = |ty: Ty| if ty == Ty::Str { Ty::Str } else { Ty::Float };
+            (smallvec![conv(args_tys[0]), conv(args_tys[1])], Ty::Int)
+        } else {
+            bf.type_sig(&args_tys[..])?
+        };
 
         for (areg, (aty, (creg, cty))) in args_regs.iter().cloned().zip(
             args_tys
@@ -1347,6 +1434,28 @@ impl<'a, 'b> View<'a, 'b> {
             self.regs.stats.reg_of_ty(res_ty)
         };
 
+        // Helper macro for comparisons involving strnums. A number on the left is moved to the
+        // right, reversing the comparison.
+        macro_rules! gen_strnum_op {
+            ($op:tt, $strnum:tt, $float:tt, $flipped:tt) => {
+                if res_reg != UNUSED {
+                    let (res, l, r) = (res_reg, conv_regs[0], conv_regs[1]);
+                    match (conv_tys[0], conv_tys[1]) {
+                        (Ty::Str, Ty::Str) => {
+                            self.pushl(LL::$strnum(res.into(), l.into(), r.into()))
+                        }
+                        (Ty::Str, Ty::Float) => {
+                            self.pushl(LL::$float(res.into(), l.into(), r.into()))
+                        }
+                        (Ty::Float, Ty::Str) => {
+                            self.pushl(LL::$flipped(res.into(), r.into(), l.into()))
+                        }
+                        _ => return err!("unexpected operands for {}", stringify!($op)),
+                    }
+                }
+            };
+        }
+
         // Helper macro for generating code for binary operators
         macro_rules! gen_op {
             ($op:tt, $([$ty:tt, $inst:tt]),* ) => {
@@ -1395,6 +1504,21 @@ impl<'a, 'b> View<'a, 'b> {
             Binop(Mod) => gen_op!(Mod, [Float, ModFloat], [Int, ModInt]),
             Binop(Concat) => gen_op!(Concat, [Str, Concat]),
             Binop(IsMatch) => gen_op!(IsMatch, [Str, IsMatch]),
+            Binop(LT) if strnum_cmp => {
+                gen_strnum_op!(LT, LTStrNum, LTStrNumFloat, GTStrNumFloat)
+            }
+            Binop(GT) if strnum_cmp => {
+                gen_strnum_op!(GT, GTStrNum, GTStrNumFloat, LTStrNumFloat)
+            }
+            Binop(LTE) if strnum_cmp => {
+                gen_strnum_op!(LTE, LTEStrNum, LTEStrNumFloat, GTEStrNumFloat)
+            }
+            Binop(GTE) if strnum_cmp => {
+                gen_strnum_op!(GTE, GTEStrNum, GTEStrNumFloat, LTEStrNumFloat)
+            }
+            Binop(EQ) if strnum_cmp => {
+                gen_strnum_op!(EQ, EQStrNum, EQStrNumFloat, EQStrNumFloat)
+            }
             Binop(LT) => gen_op!(LT, [Float, LTFloat], [Int, LTInt], [Str, LTStr]),
             Binop(GT) => gen_op!(GT, [Float, GTFloat], [Int, GTInt], [Str, GTStr]),
             Binop(LTE) => gen_op!(LTE, [Float, LTEFloat], [Int, LTEInt], [Str, LTEStr]),
@@ -1419,24 +1543,31 @@ impl<'a, 'b> View<'a, 'b> {
                 }
             }
             Match => gen_op!(Match, [Str, Match]),
+            SubstrIndex if self.char_semantics => gen_op!(SubstrIndex, [Str, SubstrIndexChars]),
             SubstrIndex => gen_op!(SubstrIndex, [Str, SubstrIndex]),
+            MatchCases => gen_op!(MatchCases, [Str, MatchCases]),
+            MatchGroups => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::MatchGroups(
+                    res_reg.into(),
+                    conv_regs[0].into(),
+                    conv_regs[1].into(),
+                    conv_regs[2].into(),
+                ))
+            }
             Contains => {
                 if res_reg != UNUSED {
-                    match conv_tys[0] {
-                        Ty::MapIntInt
-                        | Ty::MapIntStr
-                        | Ty::MapIntFloat
-                        | Ty::MapStrInt
-                        | Ty::MapStrStr
-                        | Ty::MapStrFloat => self.pushl(LL::Contains {
+                    if conv_tys[0].is_array() {
+                        self.pushl(LL::Contains {
                             map_ty: conv_tys[0],
                             dst: res_reg,
                             map: conv_regs[0],
                             key: conv_regs[1],
-                        }),
-                        Ty::Null | Ty::Int | Ty::Float | Ty::Str | Ty::IterInt | Ty::IterStr => {
-                            return err!("unexpected non-map type for Contains: {:?}", conv_tys[0]);
-                        }
+                        })
+                    } else {
+                        return err!("unexpected non-map type for Contains: {:?}", conv_tys[0]);
                     }
                 }
             }
@@ -1448,13 +1579,20 @@ impl<'a, 'b> View<'a, 'b> {
                 }
                 self.pushl(LL::RunCmd(res_reg.into(), conv_regs[0].into()))
             }
+            FFlush => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::FFlush(res_reg.into(), conv_regs[0].into()))
+            }
             Exit => self.pushl(LL::Exit(conv_regs[0].into())),
+            UndefinedFunc => self.pushl(LL::UndefinedFunc(conv_regs[0].into())),
             ReadErr => {
                 if res_reg != UNUSED {
                     self.pushl(LL::ReadErr(
                         res_reg.into(),
                         conv_regs[0].into(),
-                        /*is_file=*/ true,
+                        InputSpec::File,
                     ))
                 }
             }
@@ -1463,19 +1601,33 @@ impl<'a, 'b> View<'a, 'b> {
                     self.pushl(LL::ReadErr(
                         res_reg.into(),
                         conv_regs[0].into(),
-                        /*is_file=*/ false,
+                        InputSpec::Cmd,
+                    ))
+                }
+            }
+            ReadErrCoproc => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::ReadErr(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        InputSpec::Coproc,
                     ))
                 }
             }
             Nextline => self.pushl(LL::NextLine(
                 res_reg.into(),
                 conv_regs[0].into(),
-                /*is_file=*/ true,
+                InputSpec::File,
             )),
             NextlineCmd => self.pushl(LL::NextLine(
                 res_reg.into(),
                 conv_regs[0].into(),
-                /*is_file=*/ false,
+                InputSpec::Cmd,
+            )),
+            NextlineCoproc => self.pushl(LL::NextLine(
+                res_reg.into(),
+                conv_regs[0].into(),
+                InputSpec::Coproc,
             )),
             ReadErrStdin => {
                 if res_reg != UNUSED {
@@ -1484,6 +1636,12 @@ impl<'a, 'b> View<'a, 'b> {
             }
             NextlineStdin => self.pushl(LL::NextLineStdin(res_reg.into())),
             ReadLineStdinFused => self.pushl(LL::NextLineStdinFused()),
+            ReadLineStdinEvent => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::NextLineStdinEvent(res_reg.into()))
+            }
             NextFile => self.pushl(LL::NextFile()),
             Setcol => self.pushl(LL::SetColumn(conv_regs[0].into(), conv_regs[1].into())),
             Sub => {
@@ -1508,6 +1666,17 @@ impl<'a, 'b> View<'a, 'b> {
                     conv_regs[2].into(),
                 ))
             }
+            GenSub => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::GenSub(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                        conv_regs[2].into(),
+                        conv_regs[3].into(),
+                    ))
+                }
+            }
             EscapeCSV => {
                 if res_reg != UNUSED {
                     self.pushl(LL::EscapeCSV(res_reg.into(), conv_regs[0].into()))
@@ -1520,22 +1689,35 @@ impl<'a, 'b> View<'a, 'b> {
             }
             ToUpper => {
                 if res_reg != UNUSED {
-                    self.pushl(LL::ToUpperAscii(res_reg.into(), conv_regs[0].into()))
+                    self.pushl(if self.char_semantics {
+                        LL::ToUpper(res_reg.into(), conv_regs[0].into())
+                    } else {
+                        LL::ToUpperAscii(res_reg.into(), conv_regs[0].into())
+                    })
                 }
             }
             ToLower => {
                 if res_reg != UNUSED {
-                    self.pushl(LL::ToLowerAscii(res_reg.into(), conv_regs[0].into()))
+                    self.pushl(if self.char_semantics {
+                        LL::ToLower(res_reg.into(), conv_regs[0].into())
+                    } else {
+                        LL::ToLowerAscii(res_reg.into(), conv_regs[0].into())
+                    })
                 }
             }
             Substr => {
                 if res_reg != UNUSED {
-                    self.pushl(LL::Substr(
+                    let (res, base, l, r) = (
                         res_reg.into(),
                         conv_regs[0].into(),
                         conv_regs[1].into(),
                         conv_regs[2].into(),
-                    ))
+                    );
+                    self.pushl(if self.char_semantics {
+                        LL::SubstrChars(res, base, l, r)
+                    } else {
+                        LL::Substr(res, base, l, r)
+                    })
                 }
             }
             ToInt => self.convert(res_reg, Ty::Int, conv_regs[0], conv_tys[0])?,
@@ -1562,6 +1744,40 @@ impl<'a, 'b> View<'a, 'b> {
                 }
                 self.pushl(LL::ReseedRng(res_reg.into()))
             }
+            Systime => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Systime(res_reg.into()))
+                }
+            }
+            Strftime => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Strftime(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                        conv_regs[2].into(),
+                    ))
+                }
+            }
+            Mktime => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Mktime(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                    ))
+                }
+            }
+            Strptime => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Strptime(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                        conv_regs[2].into(),
+                    ))
+                }
+            }
             Split => {
                 if res_reg == UNUSED {
                     res_reg = self.regs.stats.reg_of_ty(res_ty);
@@ -1584,32 +1800,37 @@ impl<'a, 'b> View<'a, 'b> {
                     return err!("invalid input types to split: {:?}", &conv_tys[..]);
                 })
             }
+            PatSplit => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::PatSplit(
+                    res_reg.into(),
+                    conv_regs[0].into(),
+                    conv_regs[1].into(),
+                    conv_regs[2].into(),
+                    conv_regs[3].into(),
+                ))
+            }
             Length => {
                 if res_reg != UNUSED {
                     self.pushl(match conv_tys[0] {
                         Ty::Null => LL::StoreConstInt(res_reg.into(), 0),
-                        Ty::MapIntInt
-                        | Ty::MapIntStr
-                        | Ty::MapIntFloat
-                        | Ty::MapStrInt
-                        | Ty::MapStrStr
-                        | Ty::MapStrFloat => LL::Len {
+                        ty if ty.is_array() => LL::Len {
                             map_ty: conv_tys[0],
                             map: conv_regs[0],
                             dst: res_reg.into(),
                         },
+                        Ty::Str if self.char_semantics => {
+                            LL::LenStrChars(res_reg.into(), conv_regs[0].into())
+                        }
                         Ty::Str => LL::LenStr(res_reg.into(), conv_regs[0].into()),
                         _ => return err!("invalid input type for length: {:?}", &conv_tys[..]),
                     })
                 }
             }
-            Delete => match &conv_tys[0] {
-                Ty::MapIntInt
-                | Ty::MapIntStr
-                | Ty::MapIntFloat
-                | Ty::MapStrInt
-                | Ty::MapStrStr
-                | Ty::MapStrFloat => self.pushl(LL::Delete {
+            Delete => match conv_tys[0] {
+                ty if ty.is_array() => self.pushl(LL::Delete {
                     map_ty: conv_tys[0],
                     map: conv_regs[0],
                     key: conv_regs[1],
@@ -1653,6 +1874,37 @@ impl<'a, 'b> View<'a, 'b> {
                     }
                 })
             }
+            Asort | Asorti => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                let (map_ty, into_ty, map, into, how) = (
+                    conv_tys[0],
+                    conv_tys[1],
+                    conv_regs[0],
+                    conv_regs[1],
+                    conv_regs[2].into(),
+                );
+                self.pushl(if *bf == Asort {
+                    LL::Asort {
+                        map_ty,
+                        into_ty,
+                        dst: res_reg.into(),
+                        map,
+                        into,
+                        how,
+                    }
+                } else {
+                    LL::Asorti {
+                        map_ty,
+                        into_ty,
+                        dst: res_reg.into(),
+                        map,
+                        into,
+                        how,
+                    }
+                })
+            }
             Clear => {
                 if conv_tys[0].is_array() {
                     self.pushl(LL::Clear {
@@ -1664,11 +1916,14 @@ impl<'a, 'b> View<'a, 'b> {
                 }
             }
             Close => {
-                self.pushl(LL::Close(conv_regs[0].into()));
-                assert_eq!(res_ty, Ty::Str);
-                if res_reg != UNUSED {
-                    self.pushl(LL::StoreConstStr(res_reg.into(), Default::default()));
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
                 }
+                self.pushl(LL::Close(
+                    res_reg.into(),
+                    conv_regs[0].into(),
+                    conv_regs[1].into(),
+                ))
             }
             JoinCSV => {
                 if res_reg != UNUSED {
@@ -1822,18 +2077,12 @@ impl<'a, 'b> View<'a, 'b> {
                         arr_ty
                     );
                 }
-                use Ty::*;
-                match arr_ty {
-                    MapIntInt | MapIntFloat | MapIntStr | MapStrInt | MapStrFloat | MapStrStr => {
-                        self.pushl(LL::IterBegin {
-                            map_ty: arr_ty,
-                            dst: dst_reg,
-                            map: arr_reg,
-                        })
-                    }
-                    // Covered by the error check above
-                    Null | Int | Float | Str | IterInt | IterStr => unreachable!(),
-                };
+                // `arr_ty` is a map type: `key` fails otherwise.
+                self.pushl(LL::IterBegin {
+                    map_ty: arr_ty,
+                    dst: dst_reg,
+                    map: arr_reg,
+                });
             }
             PrimExpr::HasNext(pv) => {
                 let target_reg = if dst_ty == Ty::Int {
@@ -1881,6 +2130,7 @@ impl<'a, 'b> View<'a, 'b> {
                     Ty::Int => LL::LoadVarInt(target_reg.into(), *bv),
                     Ty::MapIntStr => LL::LoadVarIntMap(target_reg.into(), *bv),
                     Ty::MapStrInt => LL::LoadVarStrMap(target_reg.into(), *bv),
+                    Ty::MapStrStr => LL::LoadVarStrStrMap(target_reg.into(), *bv),
                     _ => unreachable!(),
                 });
                 self.convert(dst_reg, dst_ty, target_reg, target_ty)?
@@ -1899,24 +2149,20 @@ impl<'a, 'b> View<'a, 'b> {
                 let v_ty = a_ty.val()?;
                 let v_reg = self.regs.stats.reg_of_ty(v_ty);
                 self.expr(v_reg, v_ty, pe)?;
-                use Ty::*;
-                match a_ty {
-                    MapIntInt | MapIntFloat | MapIntStr | MapStrInt | MapStrFloat | MapStrStr => {
-                        self.pushl(LL::Store {
-                            map_ty: a_ty,
-                            map: a_reg,
-                            key: k_reg,
-                            val: v_reg,
-                        })
-                    }
-                    Null | Int | Float | Str | IterInt | IterStr => {
-                        return err!(
-                            "in stmt {:?} computed type is non-map type {:?}",
-                            stmt,
-                            a_ty
-                        )
-                    }
-                };
+                if a_ty.is_array() {
+                    self.pushl(LL::Store {
+                        map_ty: a_ty,
+                        map: a_reg,
+                        key: k_reg,
+                        val: v_reg,
+                    })
+                } else {
+                    return err!(
+                        "in stmt {:?} computed type is non-map type {:?}",
+                        stmt,
+                        a_ty
+                    );
+                }
             }
             PrimStmt::AsgnVar(id, pe) => {
                 let (dst_reg, dst_ty) = self.reg_of_ident(id);
@@ -1931,6 +2177,7 @@ impl<'a, 'b> View<'a, 'b> {
                     Str => LL::StoreVarStr(*v, reg.into()),
                     MapIntStr => LL::StoreVarIntMap(*v, reg.into()),
                     MapStrInt => LL::StoreVarStrMap(*v, reg.into()),
+                    MapStrStr => LL::StoreVarStrStrMap(*v, reg.into()),
                     Int => LL::StoreVarInt(*v, reg.into()),
                     _ => return err!("unexpected type for variable {} : {:?}", v, ty),
                 });
@@ -1947,6 +2194,13 @@ impl<'a, 'b> View<'a, 'b> {
                 let mut arg_regs = Vec::with_capacity(args.len());
                 for a in args {
                     let (a_reg, a_ty) = self.get_reg(a)?;
+                    if a_ty == Ty::Float && a_reg != UNUSED {
+                        // Floating-point arguments to print are formatted with OFMT, not CONVFMT.
+                        let s_reg = self.regs.stats.reg_of_ty(Ty::Str);
+                        self.pushl(LL::FloatToStrOut(s_reg.into(), a_reg.into()));
+                        arg_regs.push(s_reg.into());
+                        continue;
+                    }
                     arg_regs.push(self.ensure_ty(a_reg, a_ty, Ty::Str)?.into());
                 }
                 let out_reg = if let Some((out, append)) = out {
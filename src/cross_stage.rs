@@ -19,28 +19,26 @@ pub(crate) struct SlotOps {
 pub(crate) fn load_slot_instr<'a>(reg: NumTy, ty: Ty, slot: usize) -> Result<Option<LL<'a>>> {
     use Ty::*;
     match ty {
-        Int | Float | Str | MapIntInt | MapIntFloat | MapIntStr | MapStrInt | MapStrFloat
-        | MapStrStr => Ok(Some(LL::LoadSlot {
+        Null => Ok(None),
+        IterInt | IterStr => err!("unexpected slot type: {:?}", ty),
+        _ => Ok(Some(LL::LoadSlot {
             ty,
             dst: reg,
             slot: slot as _,
         })),
-        Null => Ok(None),
-        IterInt | IterStr => err!("unexpected slot type: {:?}", ty),
     }
 }
 
 pub(crate) fn store_slot_instr<'a>(reg: NumTy, ty: Ty, slot: usize) -> Result<Option<LL<'a>>> {
     use Ty::*;
     match ty {
-        Int | Float | Str | MapIntInt | MapIntFloat | MapIntStr | MapStrInt | MapStrFloat
-        | MapStrStr => Ok(Some(LL::StoreSlot {
+        Null => Ok(None),
+        IterInt | IterStr => err!("unexpected slot type: {:?}", ty),
+        _ => Ok(Some(LL::StoreSlot {
             ty,
             src: reg,
             slot: slot as _,
         })),
-        Null => Ok(None),
-        IterInt | IterStr => err!("unexpected slot type: {:?}", ty),
     }
 }
 
@@ -165,16 +165,32 @@ pub(crate) mod boilerplate {
             } => {
                 let dst_key = Key::Reg(*dst_reg, *dst_ty);
                 f(dst_key.clone(), Some(Key::Func(*func_id)));
+                if dst_ty.is_array() {
+                    // Returned maps carry their contents with them.
+                    f(Key::MapKey(*dst_reg, *dst_ty), Some(Key::Func(*func_id)));
+                    f(Key::MapVal(*dst_reg, *dst_ty), Some(Key::Func(*func_id)));
+                }
                 for (reg, ty) in args.iter().cloned() {
                     f(dst_key.clone(), Some(Key::Reg(reg, ty)));
                 }
             }
             Ret(reg, ty) => {
                 f(Key::Func(cur_fn_id), Some(Key::Reg(*reg, *ty)));
+                if ty.is_array() {
+                    f(Key::Func(cur_fn_id), Some(Key::MapKey(*reg, *ty)));
+                    f(Key::Func(cur_fn_id), Some(Key::MapVal(*reg, *ty)));
+                }
             }
             Phi(reg, ty, preds) => {
                 for (_, pred_reg) in preds.iter() {
                     f(Key::Reg(*reg, *ty), Some(Key::Reg(*pred_reg, *ty)));
+                    if ty.is_array() {
+                        // Like Mov, maps are references, so these edges go in both directions.
+                        for (dst, src) in [(*reg, *pred_reg), (*pred_reg, *reg)].iter().cloned() {
+                            f(Key::MapKey(dst, *ty), Some(Key::MapKey(src, *ty)));
+                            f(Key::MapVal(dst, *ty), Some(Key::MapVal(src, *ty)));
+                        }
+                    }
                 }
             }
             DropIter(..) => {}
@@ -190,10 +206,10 @@ pub(crate) mod boilerplate {
 
             IntToStr(dst, src) => f(dst.into(), Some(src.into())),
             IntToFloat(dst, src) => f(dst.into(), Some(src.into())),
-            FloatToStr(dst, src) => f(dst.into(), Some(src.into())),
+            FloatToStr(dst, src) | FloatToStrOut(dst, src) => f(dst.into(), Some(src.into())),
             FloatToInt(dst, src) => f(dst.into(), Some(src.into())),
             StrToFloat(dst, src) => f(dst.into(), Some(src.into())),
-            LenStr(dst, src) | StrToInt(dst, src) | HexStrToInt(dst, src) => f(dst.into(), Some(src.into())),
+            LenStr(dst, src) | LenStrChars(dst, src) | StrToInt(dst, src) | HexStrToInt(dst, src) => f(dst.into(), Some(src.into())),
 
             Mov(ty, dst, src) => if !ty.is_array() {
                 f(Key::Reg(*dst, *ty), Some(Key::Reg(*src, *ty)))
@@ -230,6 +246,21 @@ pub(crate) mod boilerplate {
                 f(Key::Rng, Some(new.into()));
             }
             ReseedRng(new) => f(Key::Rng, Some(new.into())),
+            Systime(dst) => f(dst.into(), None),
+            Strftime(dst, fmt, ts, utc) => {
+                f(dst.into(), Some(fmt.into()));
+                f(dst.into(), Some(ts.into()));
+                f(dst.into(), Some(utc.into()));
+            }
+            Mktime(dst, spec, utc) => {
+                f(dst.into(), Some(spec.into()));
+                f(dst.into(), Some(utc.into()));
+            }
+            Strptime(dst, s, fmt, utc) => {
+                f(dst.into(), Some(s.into()));
+                f(dst.into(), Some(fmt.into()));
+                f(dst.into(), Some(utc.into()));
+            }
             Concat(dst, x, y) => {
                 f(dst.into(), Some(x.into()));
                 f(dst.into(), Some(y.into()));
@@ -240,7 +271,7 @@ pub(crate) mod boilerplate {
             // user-input. That is certainly true today, but any kind of dynamic simplification or
             // inlining could change that.
             MatchConst(dst, x, _) | IsMatchConst(dst, x, _) => f(dst.into(), Some(x.into())),
-            IsMatch(dst, x, y) | Match(dst, x, y) | SubstrIndex(dst, x, y) => {
+            IsMatch(dst, x, y) | MatchCases(dst, x, y) | Match(dst, x, y) | SubstrIndex(dst, x, y) | SubstrIndexChars(dst, x, y) => {
                 f(dst.into(), Some(x.into()));
                 f(dst.into(), Some(y.into()));
             }
@@ -250,8 +281,24 @@ pub(crate) mod boilerplate {
                 f(dstin.into(), Some(x.into()));
                 f(dstin.into(), Some(y.into()));
             }
+            MatchGroups(dst, x, y, arr) => {
+                f(dst.into(), Some(x.into()));
+                f(dst.into(), Some(y.into()));
+                let (arr_reg, arr_ty) = arr.reflect();
+                // The keys of the array are the group numbers (and "start" and "length" entries),
+                // which are computed at runtime.
+                f(Key::MapKey(arr_reg, arr_ty), None);
+                f(Key::MapVal(arr_reg, arr_ty), Some(x.into()));
+                f(Key::MapVal(arr_reg, arr_ty), Some(y.into()));
+            }
+            GenSub(dst, x, y, z, w) => {
+                f(dst.into(), Some(x.into()));
+                f(dst.into(), Some(y.into()));
+                f(dst.into(), Some(z.into()));
+                f(dst.into(), Some(w.into()));
+            }
             EscapeTSV(dst, src) | EscapeCSV(dst, src) => f(dst.into(), Some(src.into())),
-            Substr(dst, x, y, z) => {
+            Substr(dst, x, y, z) | SubstrChars(dst, x, y, z) => {
                 f(dst.into(), Some(x.into()));
                 f(dst.into(), Some(y.into()));
                 f(dst.into(), Some(z.into()));
@@ -276,7 +323,20 @@ pub(crate) mod boilerplate {
             | GTStr(dst, x, y)
             | LTEStr(dst, x, y)
             | GTEStr(dst, x, y)
-            | EQStr(dst, x, y) => {
+            | EQStr(dst, x, y)
+            | LTStrNum(dst, x, y)
+            | GTStrNum(dst, x, y)
+            | LTEStrNum(dst, x, y)
+            | GTEStrNum(dst, x, y)
+            | EQStrNum(dst, x, y) => {
+                f(dst.into(), Some(x.into()));
+                f(dst.into(), Some(y.into()));
+            }
+            LTStrNumFloat(dst, x, y)
+            | GTStrNumFloat(dst, x, y)
+            | LTEStrNumFloat(dst, x, y)
+            | GTEStrNumFloat(dst, x, y)
+            | EQStrNumFloat(dst, x, y) => {
                 f(dst.into(), Some(x.into()));
                 f(dst.into(), Some(y.into()));
             }
@@ -290,12 +350,12 @@ pub(crate) mod boilerplate {
                 f(dst.into(), Some(y.into()));
                 f(dst.into(), Some(z.into()));
             }
-            ToUpperAscii(dst, src) | ToLowerAscii(dst, src) => {
+            ToUpperAscii(dst, src) | ToLowerAscii(dst, src) | ToUpper(dst, src) | ToLower(dst, src) => {
                 f(dst.into(), Some(src.into()));
             }
             ReadErr(dst, _cmd, _) => f(dst.into(), None),
             NextLine(dst, _cmd, _) => f(dst.into(), None),
-            ReadErrStdin(dst) => f(dst.into(), None),
+            ReadErrStdin(dst) | NextLineStdinEvent(dst) => f(dst.into(), None),
             NextLineStdin(dst) => f(dst.into(), None),
             SplitInt(dst1, src1, dst2, src2) => {
                 f(dst1.into(), Some(src1.into()));
@@ -311,13 +371,23 @@ pub(crate) mod boilerplate {
                 f(dst2.into(), Some(src1.into()));
                 f(dst2.into(), Some(src2.into()));
             }
+            PatSplit(dst1, src1, dst2, src2, dst3) => {
+                f(dst1.into(), Some(src1.into()));
+                f(dst1.into(), Some(src2.into()));
+                for dst in [dst2, dst3] {
+                    let (dst_reg, dst_ty) = dst.reflect();
+                    debug_assert!(dst_ty.is_array());
+                    f(Key::MapVal(dst_reg, dst_ty), Some(src1.into()));
+                    f(Key::MapVal(dst_reg, dst_ty), Some(src2.into()));
+                }
+            }
             Sprintf { dst, fmt, args } => {
                 f(dst.into(), Some(fmt.into()));
                 for (reg, ty) in args.iter() {
                     f(dst.into(), Some(Key::Reg(*reg, *ty)));
                 }
             }
-            RunCmd(dst, _) => f(dst.into(), None),
+            RunCmd(dst, _) | FFlush(dst, _) | Close(dst, _, _) => f(dst.into(), None),
             Lookup {
                 map_ty,
                 dst,
@@ -330,6 +400,16 @@ pub(crate) mod boilerplate {
                 f(Key::MapVal(*map, *map_ty), None);
                 f(Key::Reg(*dst, map_ty.val().unwrap()), Some(Key::MapVal(*map, *map_ty)))
             },
+            Asort { map_ty, into_ty, dst, map, into, how } => {
+                f(dst.into(), Some(Key::Reg(*map, *map_ty)));
+                f(Key::MapVal(*into, *into_ty), Some(Key::MapVal(*map, *map_ty)));
+                f(Key::MapVal(*into, *into_ty), Some(how.into()));
+            }
+            Asorti { map_ty, into_ty, dst, map, into, how } => {
+                f(dst.into(), Some(Key::Reg(*map, *map_ty)));
+                f(Key::MapVal(*into, *into_ty), Some(Key::MapKey(*map, *map_ty)));
+                f(Key::MapVal(*into, *into_ty), Some(how.into()));
+            }
             Len { map_ty, dst, map } => f(Key::Reg(*dst, Ty::Int), Some(Key::Reg(*map, *map_ty))),
             Store { map_ty, map, key, val } => {
                 f(Key::MapKey(*map, *map_ty), Some(Key::Reg(*key, map_ty.key().unwrap())));
@@ -371,6 +451,14 @@ pub(crate) mod boilerplate {
                 f(Key::VarKey(*v), Some(Key::MapKey(reg, ty)));
                 f(Key::VarVal(*v), Some(Key::MapVal(reg, ty)));
             },
+            StoreVarStrStrMap(v, reg) | LoadVarStrStrMap(reg, v) => {
+                let (reg, ty) = reg.reflect();
+
+                f(Key::MapKey(reg, ty), Some(Key::VarKey(*v)));
+                f(Key::MapVal(reg, ty), Some(Key::VarVal(*v)));
+                f(Key::VarKey(*v), Some(Key::MapKey(reg, ty)));
+                f(Key::VarVal(*v), Some(Key::MapVal(reg, ty)));
+            },
             StoreVarStr(v, src) => f(Key::Var(*v), Some(src.into())),
             StoreVarInt(v, src) => f(Key::Var(*v), Some(src.into())),
 
@@ -394,12 +482,12 @@ pub(crate) mod boilerplate {
             | Call(_)
             | Ret
             | Printf { .. }
-            | Close(_)
             | NextLineStdinFused()
             | NextFile()
             | SetColumn(_, _)
             | AllocMap(_, _)
-            | Exit(_) => {}
+            | Exit(_)
+            | UndefinedFunc(_) => {}
         }
     }
 }
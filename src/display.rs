@@ -57,6 +57,7 @@ impl<'a> Display for PrimStmt<'a> {
                         FileSpec::Trunc => ">",
                         FileSpec::Append => ">>",
                         FileSpec::Cmd => "|",
+                        FileSpec::Coproc => "|&",
                     };
                     write!(f, " {} {}", out, redirect)?;
                 }
@@ -77,6 +78,7 @@ impl<'a> Display for PrimStmt<'a> {
                         FileSpec::Trunc => ">",
                         FileSpec::Append => ">>",
                         FileSpec::Cmd => "|",
+                        FileSpec::Coproc => "|&",
                     };
                     write!(f, " {} {}", out, redirect)?;
                 }
@@ -156,21 +158,33 @@ impl Display for Function {
             ReadErrCmd => write!(f, "hasline(cmd)"),
             Nextline => write!(f, "nextline"),
             NextlineCmd => write!(f, "nextline(cmd)"),
+            ReadErrCoproc => write!(f, "hasline(coproc)"),
+            NextlineCoproc => write!(f, "nextline(coproc)"),
             ReadErrStdin => write!(f, "hasline(stdin)"),
             NextlineStdin => write!(f, "nextline(stdin)"),
             ReadLineStdinFused => write!(f, "stdin-fused"),
+            ReadLineStdinEvent => write!(f, "stdin-event"),
             NextFile => write!(f, "nextfile"),
             Setcol => write!(f, "$="),
             Split => write!(f, "split"),
+            PatSplit => write!(f, "patsplit"),
             Length => write!(f, "length"),
             Contains => write!(f, "contains"),
             Delete => write!(f, "delete"),
             Clear => write!(f, "clear"),
             Close => write!(f, "close"),
-            Match => write!(f, "match"),
+            Match | MatchGroups => write!(f, "match"),
             SubstrIndex => write!(f, "index"),
+            MatchCases => write!(f, "match_cases"),
             Sub => write!(f, "sub"),
             GSub => write!(f, "gsub"),
+            GenSub => write!(f, "gensub"),
+            Asort => write!(f, "asort"),
+            Asorti => write!(f, "asorti"),
+            Systime => write!(f, "systime"),
+            Strftime => write!(f, "strftime"),
+            Mktime => write!(f, "mktime"),
+            Strptime => write!(f, "strptime"),
             EscapeCSV => write!(f, "escape_csv"),
             EscapeTSV => write!(f, "escape_tsv"),
             JoinCSV => write!(f, "join_csv"),
@@ -183,12 +197,14 @@ impl Display for Function {
             Srand => write!(f, "srand"),
             ReseedRng => write!(f, "srand_reseed"),
             System => write!(f, "system"),
+            FFlush => write!(f, "fflush"),
             UpdateUsedFields => write!(f, "update_used_fields"),
             SetFI => write!(f, "set-FI"),
             ToLower => write!(f, "tolower"),
             ToUpper => write!(f, "toupper"),
             IncMap => write!(f, "inc_map"),
             Exit => write!(f, "exit"),
+            UndefinedFunc => write!(f, "undefined_func"),
         }
     }
 }
@@ -214,6 +230,15 @@ impl Display for Variable {
                 RLENGTH => "RLENGTH",
                 PID => "PID",
                 FI => "FI",
+                PROCINFO => "PROCINFO",
+                ENVIRON => "ENVIRON",
+                CONVFMT => "CONVFMT",
+                OFMT => "OFMT",
+                RT => "RT",
+                FPAT => "FPAT",
+                FIELDWIDTHS => "FIELDWIDTHS",
+                IGNORECASE => "IGNORECASE",
+                SUBSEP => "SUBSEP",
             }
         )
     }
@@ -262,6 +287,9 @@ impl Display for Binop {
 
 impl Display for lexer::Loc {
     fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(fmt, "{}, ", file)?;
+        }
         write!(fmt, "line {}, column {}", self.line + 1, self.col + 1)
     }
 }
@@ -278,6 +306,8 @@ impl<'a> Display for lexer::Tok<'a> {
         let rep = match self {
             Begin => "BEGIN",
             Prepare => "PREPARE",
+            BeginFile => "BEGINFILE",
+            EndFile => "ENDFILE",
             End => "END",
             Break => "break",
             Continue => "continue",
@@ -290,9 +320,11 @@ impl<'a> Display for lexer::Tok<'a> {
             ExitLP => "exit(",
             Print => "print",
             Printf => "printf",
-            // Separate token for a "print(" and "printf(".
+            PrintBytes => "print_bytes",
+            // Separate token for a "print(", "printf(" and "print_bytes(".
             PrintLP => "print(",
             PrintfLP => "printf(",
+            PrintBytesLP => "print_bytes(",
             While => "while",
             Do => "do",
 
@@ -308,6 +340,7 @@ impl<'a> Display for lexer::Tok<'a> {
 
             Getline => "getline",
             Pipe => "|",
+            CoPipe => "|&",
             Assign => "=",
             Add => "+",
             AddAssign => "+=",
@@ -348,11 +381,15 @@ impl<'a> Display for lexer::Tok<'a> {
             In => "in",
             Delete => "delete",
             Return => "return",
+            Switch => "switch",
+            Case => "case",
+            Default => "default",
 
             Ident(s) => return write!(fmt, "identifier({})", s),
             StrLit(s) => return write!(fmt, "{:?}", s),
             PatLit(s) => return write!(fmt, "/{}/", s),
             CallStart(s) => return write!(fmt, "{}(", s),
+            IndirectCallStart(s) => return write!(fmt, "@{}(", s),
             FunDec(s) => return write!(fmt, "function {}", s),
 
             ILit(s) | HexLit(s) | FLit(s) => return write!(fmt, "{}", s),
@@ -394,15 +394,26 @@ pub(crate) fn run_prog<'a>(
         write!(&mut instrs_buf, "\nCFG:\n").unwrap();
         ctx.dbg_print(&mut instrs_buf).unwrap();
         write!(&mut instrs_buf, "\n").unwrap();
-        let types::TypeInfo { var_tys, func_tys } = get_types(&ctx)?;
+        let types::TypeInfo {
+            var_tys, func_tys, ..
+        } = get_types(&ctx)?;
         // ident_map : Ident -> &str (but only has globals)
         // ts: Ident -> Type
         //
         // We want the types of all the entries in ts that show up in ident_map.
-        let type_map: HashMap<&'a str, compile::Ty> = var_tys
-            .iter()
-            .flat_map(|((ident, _, _), ty)| ident_map.get(&ident._base()).map(|s| (*s, ty.clone())))
-            .collect();
+        //
+        // A name can have several SSA versions, including an initial one that is never assigned
+        // and so has type Null. Prefer the type of a version that is assigned, so the result does
+        // not depend on the iteration order of var_tys.
+        let mut type_map: HashMap<&'a str, compile::Ty> = HashMap::new();
+        for ((ident, _, _), ty) in var_tys.iter() {
+            if let Some(s) = ident_map.get(&ident._base()) {
+                let cur = type_map.entry(*s).or_insert(*ty);
+                if *cur == compile::Ty::Null {
+                    *cur = *ty;
+                }
+            }
+        }
         macro_rules! with_interp {
             ($interp:ident, $body: expr) => {
                 if let Some(ifmt) = ifmt {
@@ -688,6 +699,18 @@ mod tests {
         "1 2\n24\n"
     );
 
+    test_program_parallel!(
+        parallel_nested_aggs,
+        ShardPerFile,
+        r#"{ m[$1][$2]++; }
+        END {
+            print length(m), length(m["a"]), m["a"][1], m["a"][2], m["b"][1];
+        }"#,
+        r#"a,1<<<FILE BREAK>>>a,2<<<FILE BREAK>>>b,1<<<FILE BREAK>>>a,1
+b,1"#,
+        "2 2 2 1 2\n"
+    );
+
     test_program!(
         map_default_args,
         r#"
@@ -1199,6 +1222,169 @@ print w,z;
         "232      hello 00\n\n232      hello 00 2.56 320\n\n"
     );
 
+    test_program!(
+        printf_conversions,
+        r#"BEGIN {
+        printf "%i|%u|%X|%#x|%+.2E|%G|%a|%%\n", 42.7, 7, 255, 255, 1234.5, 0.00001, 1
+        printf "[%*d][%-*s][%.*f]\n", 5, 42, 4, "ab", 2, 3.14159
+        printf "%2$s %1$s\n", "world", "hello"
+        print sprintf("%05.1f|%x", -2.25, 4096)
+        }"#,
+        "42|7|FF|0xff|+1.23E+03|1E-05|0x1p+0|%\n[   42][ab  ][3.14]\nhello world\n-02.2|1000\n"
+    );
+
+    test_program!(
+        printf_char_of_string,
+        r#"BEGIN { printf "%c|%3c|%-2c|%c\n", "hello", "xy", "z", 65 }"#,
+        "h|  x|z |A\n"
+    );
+
+    test_program!(
+        print_bytes_no_separators,
+        r#"BEGIN { OFS = "-"; ORS = "!"; print_bytes "a", 1, 2.5; print_bytes("b", "\n"); print "c" }"#,
+        "a12.5b\nc!"
+    );
+
+    test_program!(
+        paragraph_mode,
+        r#"BEGIN { RS = "" } { print NR ": " $1 "|" $NF "|" NF }"#,
+        "1: alpha|gamma|3\n2: delta|zeta|3\n",
+        @input "\n\nalpha beta\ngamma\n\n\n\ndelta\n  epsilon zeta\n\n"
+    );
+
+    test_program!(
+        record_terminator_regex,
+        r#"BEGIN { RS = ";+" } { printf "%s[%s]", $0, RT } END { print "" }"#,
+        "a[;]b[;;]c[]\n",
+        @input "a;b;;c"
+    );
+
+    test_program!(
+        record_terminator_byte,
+        r#"BEGIN { RS = ";"; FS = "," } { printf "%s[%s]", $1, RT } END { print "" }"#,
+        "a[;]c[;]d[]\n",
+        @input "a,b;c;d"
+    );
+
+    test_program!(
+        record_terminator_whitespace,
+        r#"{ printf "%s[%s]", $1, RT } END { print "" }"#,
+        "a[\n]c[]\n",
+        @input "a b\nc"
+    );
+
+    test_program!(
+        fpat_fields,
+        r#"BEGIN { FPAT = "\\[[^]]*\\]|\"[^\"]*\"|[^ ]+" } { for (i = 1; i <= NF; i++) { printf "<%s>", $i }; print "" }"#,
+        "<[12/Mar/2021:10:00]><\"GET /x\"><200>\n<a>\n",
+        @input "[12/Mar/2021:10:00] \"GET /x\" 200\na"
+    );
+
+    test_program!(
+        fpat_empty_fields,
+        r#"BEGIN { FPAT = "[^,]*"; OFS = "|" } { print NF; $2 = "X"; print }"#,
+        "3\na|X|b\n",
+        @input "a,,b"
+    );
+
+    test_program!(
+        fpat_then_fs,
+        r#"BEGIN { FPAT = "[0-9]+" } { print NF, $1; FS = "," }"#,
+        "2 12\n2 x\n",
+        @input "a12b3\nx,y"
+    );
+
+    test_program!(
+        fieldwidths_fields,
+        r#"BEGIN { FIELDWIDTHS = "2 1:3 *" } { printf "%d", NF; for (i = 1; i <= NF; i++) { printf "<%s>", $i }; print "" }"#,
+        "3<ab><def><ghij>\n1<xy>\n",
+        @input "abcdefghij\nxy\n"
+    );
+
+    test_program!(
+        fieldwidths_then_fs,
+        r#"BEGIN { FIELDWIDTHS = "1 1" } { print NF, $2; FS = ":" }"#,
+        "2 b\n2 d\n",
+        @input "abc\nc:d"
+    );
+
+    test_program!(
+        next_in_function,
+        r#"function skip_comments() { if ($0 ~ /^#/) { n++; next } }
+function check() { skip_comments(); return 1 }
+{ check(); print } END { print n }"#,
+        "a\nb\n2\n",
+        @input "# one\na\n# two\nb\n"
+    );
+
+    test_program!(
+        nextfile_in_function,
+        r#"function done() { nextfile } { if (NR == 2) { done() }; print }"#,
+        "a\n",
+        @input "a\nb\nc\n"
+    );
+
+    test_program!(
+        ignorecase_builtins,
+        r#"BEGIN { IGNORECASE = 1; s = "Hello World";
+if (s ~ /world/) { print "match" }
+print match(s, "o w"), RSTART, RLENGTH;
+n = split("aXbxc", a, "x"); print n, a[3];
+gsub(/L/, "_", s); print s;
+IGNORECASE = 0;
+print (s ~ /WORLD/), match(s, "o w") }"#,
+        "match\n5 5 3\n3 c\nHe__o Wor_d\n0 0\n"
+    );
+
+    test_program!(
+        ignorecase_separators,
+        r#"BEGIN { IGNORECASE = 1; FS = "x"; RS = "END" } { print NF, $2 }"#,
+        "2 b\n3 d\n",
+        @input "aXbendcxdxe"
+    );
+
+    test_program!(
+        ignorecase_regex_literal,
+        r#"{ print ($0 ~ /abc/i), ($0 ~ /abc/), sub(/B/i, "-"), $0 }"#,
+        "1 0 1 a-c\n",
+        @input "aBc"
+    );
+
+    test_program!(
+        patsplit_seps,
+        r#"BEGIN {
+            n = patsplit(",a,,b,", arr, "[^,]*", seps);
+            for (i = 0; i <= n; i++) { printf "%d[%s][%s]", i, arr[i], seps[i] }
+            print ""
+            print patsplit("x1y22", nums, "[0-9]+"), nums[1], nums[2]
+        }"#,
+        "0[][]1[][,]2[a][,]3[][,]4[b][,]5[][]\n2 1 22\n"
+    );
+
+    test_program!(
+        paragraph_mode_fs,
+        r#"BEGIN { RS = ""; FS = ":" } { for (i = 1; i <= NF; i++) printf "[%s]", $i; print "" }"#,
+        "[a][b][c][d]\n[e f]\n",
+        @input "a:b\nc:d\n\ne f\n"
+    );
+
+    test_program!(
+        paragraph_mode_fs_literal,
+        r#"BEGIN { RS = ""; FS = "|" } { printf "%d ", NF; FS = "." } END { print "" }"#,
+        "3 1 2 \n",
+        @input "a|b\nc\n\nd\n\ne.f\n"
+    );
+
+    test_program!(
+        default_fs_newlines,
+        r#"BEGIN {
+        $0 = "a b\nc"; print NF;
+        n = split("a b\nc", parts); print n, parts[2];
+        RS = ""; $0 = "a b\nc"; print NF;
+        }"#,
+        "2\n2 b\nc\n3\n"
+    );
+
     test_program!(
         sprintf_1,
         r#"BEGIN { x=1; y=2.5; z="hello";
@@ -1256,6 +1442,34 @@ this as well"#
         "5 5 2\n0 0 -1\n"
     );
 
+    test_program!(
+        match_capture_groups,
+        r#"BEGIN {
+        x=match("GET /index.html 200", /([A-Z]+) ([^ ]+) (x)?([0-9]+)/, m)
+        print x, RSTART, RLENGTH, length(m)
+        print m[0] "|" m[1] "|" m[2] "|" m[3] "|" m[4]
+        print m[2, "start"], m[2, "length"], ((3, "start") in m)
+        y=match("no digits", /[0-9]+/, m)
+        print y, length(m)
+        }"#,
+        "1 1 19 12\nGET /index.html 200|GET|/index.html||200\n5 11 0\n0 0\n"
+    );
+
+    test_program!(
+        match_capture_groups_subsep,
+        r#"BEGIN { SUBSEP = ":"; match("abc", /(b)(c)/, m); print m[1, "start"], m["2:length"]; }"#,
+        "2 1\n"
+    );
+
+    test_program!(
+        match_capture_groups_int_keys,
+        r#"BEGIN { m[0] = "unused"; }
+        { if (match($0, /([a-z]+)=([0-9]+)/, m)) print m[2] + 1, m[1], length(m), m[2, "start"]; }"#,
+        "43 answer 9 12\n",
+        @input "the answer=42 here",
+        @types [ m :: MapStrStr ]
+    );
+
     test_program!(degenerate_map, r#"BEGIN { print m[1]; }"#, "\n");
 
     test_program!(
@@ -1280,6 +1494,158 @@ this as well"#
         "snow banana 2\n"
     );
 
+    test_program!(
+        gensub_backrefs,
+        r#"BEGIN {
+        x="2021-03-04 2020-01-02"
+        print gensub(/([0-9]+)-([0-9]+)-([0-9]+)/, "\\3/\\2/\\1", "g", x)
+        print gensub(/([0-9]+)-([0-9]+)-([0-9]+)/, "<&>", 2, x)
+        print x
+        }"#,
+        "04/03/2021 02/01/2020\n2021-03-04 <2020-01-02>\n2021-03-04 2020-01-02\n"
+    );
+
+    test_program!(
+        gensub_column,
+        r#"{ y = gensub(/(a+)(b+)/, "\\2\\1", "G"); print y, $0; }"#,
+        "bbaa ba abba ab\n",
+        @input "abba ab"
+    );
+
+    test_program!(
+        asort_values,
+        r#"BEGIN {
+        m["x"] = 10; m["y"] = 9; m["z"] = 100;
+        n = asort(m, out)
+        for (i = 1; i <= n; i++) printf "%s ", out[i]
+        print n, length(m)
+        asort(m, out, "@val_str_desc")
+        print out[1], out[2], out[3]
+        }"#,
+        "9 10 100 3 3\n9 100 10\n",
+        @input "",
+        @types [ m :: MapStrInt, out :: MapIntInt ]
+    );
+
+    test_program!(
+        asorti_keys,
+        r#"BEGIN {
+        m[10] = "a"; m[9] = "b"; m[100] = "c";
+        n = asorti(m, out)
+        print n, out[1], out[2], out[3]
+        asorti(m, out, "@ind_num_asc")
+        print out[1], out[2], out[3]
+        asorti(m, out, "@val_str_desc")
+        print out[1], out[2], out[3]
+        }"#,
+        "3 10 100 9\n9 10 100\n100 9 10\n",
+        @input "",
+        @types [ m :: MapIntStr, out :: MapIntInt ]
+    );
+
+    test_program!(
+        asort_in_place,
+        r#"{ m[NR] = $1; }
+        END { asort(m); for (i = 1; i <= length(m); i++) print i, m[i]; }"#,
+        "1 apple\n2 banana\n3 cherry\n",
+        @input "cherry\napple\nbanana\n"
+    );
+
+    test_program!(
+        asort_string_keys_in_place,
+        r#"BEGIN { m["x"] = "b"; m["y"] = "c"; m["z"] = "a"; n = asort(m);
+        print n, m[1], m[2], m[3], ("1" in m), ("x" in m); }"#,
+        "3 a b c 1 0\n",
+        @input "",
+        @types [ m :: MapStrStr ]
+    );
+
+    test_program!(
+        sorted_in_keys,
+        r#"BEGIN {
+        for (i = 1; i <= 12; i++) m[i] = 13 - i;
+        PROCINFO["sorted_in"] = "@ind_num_asc"
+        for (k in m) printf "%s ", k
+        print ""
+        PROCINFO["sorted_in"] = "@ind_str_desc"
+        for (k in m) printf "%s ", k
+        print ""
+        PROCINFO["sorted_in"] = "@val_num_asc"
+        for (k in m) printf "%s ", k
+        print ""
+        }"#,
+        "1 2 3 4 5 6 7 8 9 10 11 12 \n9 8 7 6 5 4 3 2 12 11 10 1 \n12 11 10 9 8 7 6 5 4 3 2 1 \n",
+        @input "",
+        @types [ m :: MapIntInt ]
+    );
+
+    test_program!(
+        sorted_in_updates,
+        r#"BEGIN {
+        m["b"] = 1; m["a"] = 1; m["c"] = 1;
+        PROCINFO["sorted_in"] = "@ind_str_asc"
+        for (k in m) printf "%s ", k
+        sub(/asc/, "desc", PROCINFO["sorted_in"])
+        for (k in m) printf "%s ", k
+        delete PROCINFO
+        PROCINFO["sorted_in"] = "@ind_str"
+        PROCINFO["sorted_in"] = PROCINFO["sorted_in"] "_asc"
+        for (k in m) printf "%s ", k
+        print ""
+        }"#,
+        "a b c c b a a b c \n",
+        @input ""
+    );
+
+    test_program!(
+        sorted_in_main_loop,
+        r#"{ count[$1]++ }
+        END {
+            PROCINFO["sorted_in"] = "@val_num_desc"
+            for (w in count) print w, count[w]
+            PROCINFO["sorted_in"] = "@unsorted"
+            for (w in count) n++
+            print n
+        }"#,
+        "b 3\na 2\nc 1\n3\n",
+        @input "a\nb\nc\nb\na\nb\n"
+    );
+
+    test_program!(
+        environ_assign,
+        r#"BEGIN { ENVIRON["FRAWK_HARNESS_VAR"] = "x"; m = ENVIRON; print m["FRAWK_HARNESS_VAR"]; }"#,
+        "x\n",
+        @input "",
+        @types [ m :: MapStrStr ]
+    );
+
+    test_program!(
+        convfmt_ofmt,
+        r#"BEGIN {
+        x = 3.14159265; print x
+        CONVFMT = "%.2f"; y = x ""; print y, x
+        OFMT = "%.3f"; print x, x ""
+        a[x + 1] = "v"; a["z"] = "w"; for (k in a) if (a[k] == "v") print k
+        print 17.0 "", 2 * 0.5, CONVFMT
+        }"#,
+        "3.14159265\n3.14 3.14159265\n3.142 3.14\n4.14\n17 1 %.2f\n"
+    );
+
+    test_program!(
+        time_functions_utc,
+        r#"BEGIN {
+        ts = mktime("2024 02 29 12 34 56", 1)
+        print ts, strftime("%Y-%m-%d %H:%M:%S %a %j", ts, 1)
+        print strftime("%d/%b/%Y", mktime("2024 13 01 00 00 00", 1), 1)
+        print strptime("29/Feb/2024:04:34:56 -0800"), strptime("2024-02-29T12:34:56.5Z")
+        print strptime("2024-02-29 12:34", "%Y-%m-%d %H:%M", 1)
+        print strptime("not a timestamp"), mktime("2024 02")
+        now = systime()
+        print (now > 1700000000)
+        }"#,
+        "1709210096 2024-02-29 12:34:56 Thu 060\n01/Jan/2025\n1709210096 1709210096.5\n1709210040\n-1 -1\n1\n"
+    );
+
     test_program!(
         map_lookup_adds_key,
         r#"BEGIN { m[1]; h["hi"]; print (1 in m), (2 in m), ("hi" in h), ("hello" in h); }"#,
@@ -1368,6 +1734,144 @@ this as well"#
         "1 2 3\n"
     );
 
+    test_program!(
+        subsep_reassigned,
+        r#"function key(a, b) { return a SUBSEP b }
+        BEGIN { m[1,2] = 3; SUBSEP = ":"; m[4,5] = 6;
+                print length(m), ("1\0342" in m), ((4,5) in m), m["4:5"], key("x", "y"); }"#,
+        "2 1 1 6 x:y\n"
+    );
+
+    test_program!(
+        arrays_of_arrays,
+        r#"function total(a, k,  j, t) { for (j in a[k]) t += a[k][j]; return t; }
+        { m[$1][$2] += $3; }
+        END {
+            print length(m), length(m["x"]), total(m, "x"), total(m, "y");
+            print (2 in m["x"]), (3 in m["x"]), ("z" in m);
+            delete m["x"][1];
+            delete m["y"];
+            print length(m), length(m["x"]), m["x"][2];
+        }"#,
+        "2 2 6 4\n1 0 0\n1 1 5\n",
+        @input "x 1 1\nx 2 5\ny 1 4"
+    );
+
+    test_program!(
+        nested_iter_key_widening,
+        // The inner keys of `m` are inferred as Int before `m["x"]["b"]` widens them to Str. The
+        // type of the loop variable has to follow, or the lookups below would convert keys back
+        // to integers.
+        r#"BEGIN { m["x"][1] = 1; m["x"]["b"] = 2;
+        for (j in m["x"]) { n += (j == "b") + (j == 1); s += m["x"][j]; }
+        print n, s; }"#,
+        "2 3\n",
+        @input "",
+        @types [ m :: MapStrMapStrInt, j :: Str ]
+    );
+
+    test_program!(
+        strnum_comparisons,
+        r#"function lt(a, b) { return a < b; }
+        { n = split($0, parts); print ($1 < $2), ($1 == 10), (parts[1] >= parts[2]), lt($1, $2), ($1 < "9"); }"#,
+        "0 1 1 0 1\n1 0 0 1 0\n0 1 1 0 1\n0 1 1 0 1\n",
+        @input "10 9\nabc x\n10.0 10\n 1e1 +1e1"
+    );
+
+    test_program!(
+        strnum_float_comparisons,
+        r#"BEGIN { CONVFMT = "%.2g" } { print ($1 == 0.1234567), (0.1234567 >= $1), (0.2 > $1), ($1 < 0.5) }"#,
+        "1 1 1 1\n0 0 0 0\n",
+        @input "0.1234567\nabc"
+    );
+
+    test_program!(
+        indirect_calls,
+        r#"function sum(x) { total += x; return total; }
+        function biggest(x) { if (x > max) max = x; return max; }
+        function keys(a,  k, n) { for (k in a) n++; return n; }
+        BEGIN { ops["+"] = "sum"; ops["max"] = "biggest"; }
+        { op = ops[$1]; last = @op($2); }
+        END {
+            seen[1] = seen[2] = 1;
+            f = "keys";
+            print total, max, last, @f(seen);
+        }"#,
+        "7 5 5 2\n",
+        @input "+ 3\nmax 5\n+ 4\nmax 2"
+    );
+
+    test_program!(
+        switch_statements,
+        r#"function kind(x) {
+            switch (x) {
+            case "apple":
+                return "fruit"
+            case /^[0-9]+$/:
+                return "number"
+            case "carrot":
+            case "pea":
+                return "veg"
+            default:
+                return "other"
+            }
+        }
+        { printf "%s ", kind($1); }
+        END {
+            i = 0;
+            while (++i < 6) {
+                switch (i) {
+                case 1: printf "one "; break;
+                case 2: printf "two ";
+                case 3: printf "2or3 "; break;
+                case 4: continue;
+                default: printf "d%d ", i;
+                }
+                printf "%d ", i;
+            }
+            switch (-4) { default: print "default"; case -4: print "minus four"; }
+        }"#,
+        "fruit number veg veg other one 1 two 2or3 2 2or3 3 d5 5 minus four\n",
+        @input "apple\n123\npea\ncarrot\nrock"
+    );
+
+    test_program!(
+        switch_ignorecase,
+        r#"function kind(x) {
+            switch (x) {
+            case "Apple": return "fruit"
+            case /^pea$/: return "veg"
+            default: return "other"
+            }
+        }
+        BEGIN {
+            printf "%s %s ", kind("APPLE"), kind("PEA");
+            IGNORECASE = 1;
+            printf "%s %s %s ", kind("APPLE"), kind("apple"), kind("PEA");
+            IGNORECASE = 0;
+            print kind("apple"), kind("Apple");
+        }"#,
+        "other other fruit fruit veg other fruit\n"
+    );
+
+    test_program!(
+        fflush_builtin,
+        r#"BEGIN {
+            print "x" > "/fake/out";
+            print fflush("/fake/out"), fflush("/fake/missing"), fflush(), fflush("");
+        }"#,
+        "0 -1 0 0\n"
+    );
+
+    test_program!(
+        close_status,
+        r#"BEGIN {
+            print "x" > "/fake/out";
+            print close("/fake/out"), close("/fake/out"), close("/fake/missing");
+        }"#,
+        "0 -1 -1\n"
+    );
+
     test_program!(
         function_locals,
         r#"function p(n,  i,res) {
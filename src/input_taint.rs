@@ -43,10 +43,11 @@
 //! Users who wish to execute a script they believe is safe, but is rejected by the analysis
 //! (either because the analysis is too conservative, or because they trust user input) can opt out
 //! of taint analysis using the -A flag.
+use crate::builtins::Variable;
 use crate::bytecode::Instr;
-use crate::common::{FileSpec, NumTy};
+use crate::common::{FileSpec, InputSpec, NumTy};
 use crate::compile::HighLevel;
-use crate::dataflow::{self, JoinSemiLattice};
+use crate::dataflow::{self, JoinSemiLattice, Key};
 
 /// aka bool, with join = ||; making our own enum for explicitness.
 #[derive(Copy, Clone, Debug)]
@@ -101,30 +102,42 @@ impl TaintedStringAnalysis {
         // should read up on the potential attack surface first.
         use Instr::*;
         match inst {
-            ReadErr(dst, cmd, is_file) => {
+            ReadErr(dst, cmd, spec) => {
                 self.dfa.add_src(dst, Taint::Tainted);
-                if !*is_file {
+                if *spec != InputSpec::File {
                     self.dfa.add_query(cmd);
                 }
             }
-            NextLine(dst, cmd, is_file) => {
+            NextLine(dst, cmd, spec) => {
                 self.dfa.add_src(dst, Taint::Tainted);
-                if !*is_file {
+                if *spec != InputSpec::File {
                     self.dfa.add_query(cmd);
                 }
             }
             GetColumn(dst, _) => self.dfa.add_src(dst, Taint::Tainted),
+            // The environment is user input, as far as we are concerned.
+            LoadVarStrStrMap(_, Variable::ENVIRON) | StoreVarStrStrMap(Variable::ENVIRON, _) => {
+                self.dfa
+                    .add_src(Key::VarKey(Variable::ENVIRON), Taint::Tainted);
+                self.dfa
+                    .add_src(Key::VarVal(Variable::ENVIRON), Taint::Tainted);
+                dataflow::boilerplate::visit_ll(inst, |dst, src| {
+                    if let Some(src) = src {
+                        self.dfa.add_dep(dst, src, ())
+                    }
+                })
+            }
             ReadErrStdin(dst) => self.dfa.add_src(dst, Taint::Tainted),
             NextLineStdin(dst) => self.dfa.add_src(dst, Taint::Tainted),
             StoreConstStr(dst, _) => self.dfa.add_src(dst, Taint::Okay),
             StoreConstInt(dst, _) => self.dfa.add_src(dst, Taint::Okay),
             StoreConstFloat(dst, _) => self.dfa.add_src(dst, Taint::Okay),
             PrintAll {
-                output: Some((cmd, FileSpec::Cmd)),
+                output: Some((cmd, FileSpec::Cmd | FileSpec::Coproc)),
                 ..
             }
             | Printf {
-                output: Some((cmd, FileSpec::Cmd)),
+                output: Some((cmd, FileSpec::Cmd | FileSpec::Coproc)),
                 ..
             } => self.dfa.add_query(cmd),
             RunCmd(dst, cmd) => {
@@ -190,6 +203,13 @@ mod tests {
             BEGIN {  system(x($2, "dog")); }"#,
             r#"BEGIN { for (i=1; i<10; i++) m[i]=$i; system(m[3]); }"#,
             r#"BEGIN { for (i=1; i<10; i++) m[$i]=i; for (i in m) system(i); }"#,
+            r#"BEGIN { system("echo " ENVIRON["HOME"]); }"#,
+            r#"BEGIN { for (k in ENVIRON) print "x" | k; }"#,
+            r#"function env() { return ENVIRON; }
+            BEGIN { m = env(); system(m["SHELL"]); }"#,
+            r#"BEGIN { if (ARGC) m = ENVIRON; else m["x"] = "y"; system(m["x"]); }"#,
+            "BEGIN { print $1 |& $2; }",
+            "BEGIN { while ($1 |& getline) print; }",
         ];
 
         for p in progs.iter() {
@@ -207,6 +227,8 @@ mod tests {
             BEGIN { while(x("echo ", "hi") | getline) print; }"#,
             r#"function x(a, b) { return a b; }
             BEGIN {  system(x($2, "dog") ? "echo hello" : "echo goodbye"); }"#,
+            r#"BEGIN { print ENVIRON["HOME"]; system("echo hello"); }"#,
+            r#"BEGIN { print $1 |& "sort"; close("sort", "to"); while ("sort" |& getline) print; }"#,
         ];
         for p in progs.iter() {
             assert_analysis_accept(*p);
@@ -1,6 +1,6 @@
 use crate::builtins::Variable;
 use crate::bytecode::{Get, Instr, Label, Reg};
-use crate::common::{NumTy, Result, Stage};
+use crate::common::{InputSpec, NumTy, Result, Stage};
 use crate::compile::{self, Ty};
 use crate::pushdown::FieldSet;
 use crate::runtime::{self, Float, Int, Line, LineReader, Str, UniqueStr};
@@ -57,6 +57,20 @@ pub(crate) struct Slots {
     pub strint: Vec<HashMap<UniqueStr<'static>, Int>>,
     pub strfloat: Vec<HashMap<UniqueStr<'static>, Float>>,
     pub strstr: Vec<HashMap<UniqueStr<'static>, UniqueStr<'static>>>,
+    // Arrays of arrays, named after their key type and the type of their values.
+    pub int_intint: Vec<HashMap<Int, HashMap<Int, Int>>>,
+    pub int_intfloat: Vec<HashMap<Int, HashMap<Int, Float>>>,
+    pub int_intstr: Vec<HashMap<Int, HashMap<Int, UniqueStr<'static>>>>,
+    pub int_strint: Vec<HashMap<Int, HashMap<UniqueStr<'static>, Int>>>,
+    pub int_strfloat: Vec<HashMap<Int, HashMap<UniqueStr<'static>, Float>>>,
+    pub int_strstr: Vec<HashMap<Int, HashMap<UniqueStr<'static>, UniqueStr<'static>>>>,
+    pub str_intint: Vec<HashMap<UniqueStr<'static>, HashMap<Int, Int>>>,
+    pub str_intfloat: Vec<HashMap<UniqueStr<'static>, HashMap<Int, Float>>>,
+    pub str_intstr: Vec<HashMap<UniqueStr<'static>, HashMap<Int, UniqueStr<'static>>>>,
+    pub str_strint: Vec<HashMap<UniqueStr<'static>, HashMap<UniqueStr<'static>, Int>>>,
+    pub str_strfloat: Vec<HashMap<UniqueStr<'static>, HashMap<UniqueStr<'static>, Float>>>,
+    pub str_strstr:
+        Vec<HashMap<UniqueStr<'static>, HashMap<UniqueStr<'static>, UniqueStr<'static>>>>,
 }
 
 /// A Simple helper trait for implement aggregations for slot values and variables.
@@ -110,7 +124,9 @@ impl Slots {
             ($s1:ident, $s2:ident, $body:expr) => {
                 for_each_slot_pair!(
                     $s1, $s2, $body, int, float, strs, intint, intfloat, intstr, strint, strfloat,
-                    strstr
+                    strstr, int_intint, int_intfloat, int_intstr, int_strint, int_strfloat,
+                    int_strstr, str_intint, str_intfloat, str_intstr, str_strint, str_strfloat,
+                    str_strstr
                 );
             };
             ($s1:ident, $s2:ident, $body:expr, $($fld:tt),*) => {$({
@@ -150,6 +166,60 @@ pub fn combine_slot<T: Default>(vec: &mut Vec<T>, slot: usize, f: impl FnOnce(T)
     vec.push(res)
 }
 
+/// Conversions between the contents of arrays of arrays and their `Send` counterparts in `Slots`.
+trait SlotValue<'a> {
+    type Slot: Default;
+    fn to_slot(&self) -> Self::Slot;
+    fn from_slot(s: Self::Slot) -> Self;
+}
+
+impl<'a> SlotValue<'a> for Int {
+    type Slot = Int;
+    fn to_slot(&self) -> Int {
+        *self
+    }
+    fn from_slot(i: Int) -> Int {
+        i
+    }
+}
+
+impl<'a> SlotValue<'a> for Float {
+    type Slot = Float;
+    fn to_slot(&self) -> Float {
+        *self
+    }
+    fn from_slot(f: Float) -> Float {
+        f
+    }
+}
+
+impl<'a> SlotValue<'a> for Str<'a> {
+    type Slot = UniqueStr<'static>;
+    fn to_slot(&self) -> UniqueStr<'static> {
+        self.clone().unmoor().into()
+    }
+    fn from_slot(s: UniqueStr<'static>) -> Str<'a> {
+        s.into_str().upcast()
+    }
+}
+
+impl<'a, K, V> SlotValue<'a> for runtime::SharedMap<K, V>
+where
+    K: SlotValue<'a> + std::hash::Hash + Eq,
+    K::Slot: std::hash::Hash + Eq,
+    V: SlotValue<'a>,
+{
+    type Slot = HashMap<K::Slot, V::Slot>;
+    fn to_slot(&self) -> Self::Slot {
+        self.iter(|i| i.map(|(k, v)| (k.to_slot(), v.to_slot())).collect())
+    }
+    fn from_slot(m: Self::Slot) -> Self {
+        m.into_iter()
+            .map(|(k, v)| (K::from_slot(k), V::from_slot(v)))
+            .collect()
+    }
+}
+
 impl<'a> Core<'a> {
     pub fn shuttle(&self, pid: Int) -> impl FnOnce() -> Core<'a> + Send {
         use crate::builtins::Variables;
@@ -158,10 +228,22 @@ impl<'a> Core<'a> {
         let fs: UniqueStr<'a> = self.vars.fs.clone().into();
         let ofs: UniqueStr<'a> = self.vars.ofs.clone().into();
         let rs: UniqueStr<'a> = self.vars.rs.clone().into();
+        let field_sep: UniqueStr<'a> = self.vars.field_sep.clone().into();
         let ors: UniqueStr<'a> = self.vars.ors.clone().into();
         let filename: UniqueStr<'a> = self.vars.filename.clone().into();
         let argv = self.vars.argv.shuttle();
         let fi = self.vars.fi.shuttle();
+        let procinfo = self.vars.procinfo.shuttle();
+        let sorted_in = self.vars.sorted_in.clone();
+        let environ = self.vars.environ.as_ref().map(|m| m.shuttle());
+        let convfmt: Option<UniqueStr<'a>> = self.vars.convfmt.clone().map(Into::into);
+        let ofmt: Option<UniqueStr<'a>> = self.vars.ofmt.clone().map(Into::into);
+        let fpat: UniqueStr<'a> = self.vars.fpat.clone().into();
+        let fieldwidths: UniqueStr<'a> = self.vars.fieldwidths.clone().into();
+        let field_mode = self.vars.field_mode.clone();
+        let utf8 = self.vars.utf8;
+        let ignorecase = self.vars.ignorecase;
+        let subsep: UniqueStr<'a> = self.vars.subsep.clone().into();
         let slots = self.slots.clone();
         move || {
             let vars = Variables {
@@ -169,6 +251,7 @@ impl<'a> Core<'a> {
                 ofs: ofs.into_str(),
                 ors: ors.into_str(),
                 rs: rs.into_str(),
+                field_sep: field_sep.into_str(),
                 filename: filename.into_str(),
                 pid,
                 nf: 0,
@@ -179,10 +262,24 @@ impl<'a> Core<'a> {
                 argc: 0,
                 argv: argv.into(),
                 fi: fi.into(),
+                procinfo: procinfo.into(),
+                sorted_in,
+                environ: environ.map(Into::into),
+                convfmt: convfmt.map(UniqueStr::into_str),
+                ofmt: ofmt.map(UniqueStr::into_str),
+                rt: Default::default(),
+                fpat: fpat.into_str(),
+                fieldwidths: fieldwidths.into_str(),
+                field_mode,
+                utf8,
+                ignorecase,
+                subsep: subsep.into_str(),
             };
+            let mut regexes = runtime::RegexCache::default();
+            regexes.set_ignore_case(ignorecase != 0);
             Core {
                 vars,
-                regexes: Default::default(),
+                regexes,
                 write_files: fw,
                 rng: rand::rngs::StdRng::seed_from_u64(seed),
                 current_seed: seed,
@@ -226,6 +323,14 @@ impl<'a> Core<'a> {
         self.reseed(rand::thread_rng().gen::<u64>())
     }
 
+    pub fn store_var_int(&mut self, var: Variable, i: Int) -> Result<()> {
+        self.vars.store_int(var, i)?;
+        if let Variable::IGNORECASE = var {
+            self.regexes.set_ignore_case(i != 0);
+        }
+        Ok(())
+    }
+
     pub fn match_regex(&mut self, s: &Str<'a>, pat: &Str<'a>) -> Result<Int> {
         self.regexes.regex_match_loc(&mut self.vars, pat, s)
     }
@@ -238,6 +343,10 @@ impl<'a> Core<'a> {
         self.regexes.is_regex_match(pat, s)
     }
 
+    pub fn match_cases(&mut self, s: &Str<'a>, cases: &Str<'a>) -> Result<Int> {
+        self.regexes.match_cases(cases, s)
+    }
+
     pub fn load_int(&mut self, slot: usize) -> Int {
         self.slots.int[slot]
     }
@@ -334,6 +443,127 @@ impl<'a> Core<'a> {
             }),
         )
     }
+    pub fn load_int_intint(&mut self, slot: usize) -> runtime::IntMap<runtime::IntMap<Int>> {
+        SlotValue::from_slot(mem::take(&mut self.slots.int_intint[slot]))
+    }
+    pub fn store_int_intint(&mut self, slot: usize, m: runtime::IntMap<runtime::IntMap<Int>>) {
+        set_slot(&mut self.slots.int_intint, slot, m.to_slot())
+    }
+    pub fn load_int_intfloat(&mut self, slot: usize) -> runtime::IntMap<runtime::IntMap<Float>> {
+        SlotValue::from_slot(mem::take(&mut self.slots.int_intfloat[slot]))
+    }
+    pub fn store_int_intfloat(&mut self, slot: usize, m: runtime::IntMap<runtime::IntMap<Float>>) {
+        set_slot(&mut self.slots.int_intfloat, slot, m.to_slot())
+    }
+    pub fn load_int_intstr(&mut self, slot: usize) -> runtime::IntMap<runtime::IntMap<Str<'a>>> {
+        SlotValue::from_slot(mem::take(&mut self.slots.int_intstr[slot]))
+    }
+    pub fn store_int_intstr(&mut self, slot: usize, m: runtime::IntMap<runtime::IntMap<Str<'a>>>) {
+        set_slot(&mut self.slots.int_intstr, slot, m.to_slot())
+    }
+    pub fn load_int_strint(&mut self, slot: usize) -> runtime::IntMap<runtime::StrMap<'a, Int>> {
+        SlotValue::from_slot(mem::take(&mut self.slots.int_strint[slot]))
+    }
+    pub fn store_int_strint(&mut self, slot: usize, m: runtime::IntMap<runtime::StrMap<'a, Int>>) {
+        set_slot(&mut self.slots.int_strint, slot, m.to_slot())
+    }
+    pub fn load_int_strfloat(
+        &mut self,
+        slot: usize,
+    ) -> runtime::IntMap<runtime::StrMap<'a, Float>> {
+        SlotValue::from_slot(mem::take(&mut self.slots.int_strfloat[slot]))
+    }
+    pub fn store_int_strfloat(
+        &mut self,
+        slot: usize,
+        m: runtime::IntMap<runtime::StrMap<'a, Float>>,
+    ) {
+        set_slot(&mut self.slots.int_strfloat, slot, m.to_slot())
+    }
+    pub fn load_int_strstr(
+        &mut self,
+        slot: usize,
+    ) -> runtime::IntMap<runtime::StrMap<'a, Str<'a>>> {
+        SlotValue::from_slot(mem::take(&mut self.slots.int_strstr[slot]))
+    }
+    pub fn store_int_strstr(
+        &mut self,
+        slot: usize,
+        m: runtime::IntMap<runtime::StrMap<'a, Str<'a>>>,
+    ) {
+        set_slot(&mut self.slots.int_strstr, slot, m.to_slot())
+    }
+    pub fn load_str_intint(&mut self, slot: usize) -> runtime::StrMap<'a, runtime::IntMap<Int>> {
+        SlotValue::from_slot(mem::take(&mut self.slots.str_intint[slot]))
+    }
+    pub fn store_str_intint(&mut self, slot: usize, m: runtime::StrMap<'a, runtime::IntMap<Int>>) {
+        set_slot(&mut self.slots.str_intint, slot, m.to_slot())
+    }
+    pub fn load_str_intfloat(
+        &mut self,
+        slot: usize,
+    ) -> runtime::StrMap<'a, runtime::IntMap<Float>> {
+        SlotValue::from_slot(mem::take(&mut self.slots.str_intfloat[slot]))
+    }
+    pub fn store_str_intfloat(
+        &mut self,
+        slot: usize,
+        m: runtime::StrMap<'a, runtime::IntMap<Float>>,
+    ) {
+        set_slot(&mut self.slots.str_intfloat, slot, m.to_slot())
+    }
+    pub fn load_str_intstr(
+        &mut self,
+        slot: usize,
+    ) -> runtime::StrMap<'a, runtime::IntMap<Str<'a>>> {
+        SlotValue::from_slot(mem::take(&mut self.slots.str_intstr[slot]))
+    }
+    pub fn store_str_intstr(
+        &mut self,
+        slot: usize,
+        m: runtime::StrMap<'a, runtime::IntMap<Str<'a>>>,
+    ) {
+        set_slot(&mut self.slots.str_intstr, slot, m.to_slot())
+    }
+    pub fn load_str_strint(
+        &mut self,
+        slot: usize,
+    ) -> runtime::StrMap<'a, runtime::StrMap<'a, Int>> {
+        SlotValue::from_slot(mem::take(&mut self.slots.str_strint[slot]))
+    }
+    pub fn store_str_strint(
+        &mut self,
+        slot: usize,
+        m: runtime::StrMap<'a, runtime::StrMap<'a, Int>>,
+    ) {
+        set_slot(&mut self.slots.str_strint, slot, m.to_slot())
+    }
+    pub fn load_str_strfloat(
+        &mut self,
+        slot: usize,
+    ) -> runtime::StrMap<'a, runtime::StrMap<'a, Float>> {
+        SlotValue::from_slot(mem::take(&mut self.slots.str_strfloat[slot]))
+    }
+    pub fn store_str_strfloat(
+        &mut self,
+        slot: usize,
+        m: runtime::StrMap<'a, runtime::StrMap<'a, Float>>,
+    ) {
+        set_slot(&mut self.slots.str_strfloat, slot, m.to_slot())
+    }
+    pub fn load_str_strstr(
+        &mut self,
+        slot: usize,
+    ) -> runtime::StrMap<'a, runtime::StrMap<'a, Str<'a>>> {
+        SlotValue::from_slot(mem::take(&mut self.slots.str_strstr[slot]))
+    }
+    pub fn store_str_strstr(
+        &mut self,
+        slot: usize,
+        m: runtime::StrMap<'a, runtime::StrMap<'a, Str<'a>>>,
+    ) {
+        set_slot(&mut self.slots.str_strstr, slot, m.to_slot())
+    }
 }
 
 macro_rules! map_regs {
@@ -353,6 +583,114 @@ macro_rules! map_regs {
             $body
         )
     }};
+    ($map_ty:expr, $map_reg:ident, $key_reg:ident, $val_reg:ident, $iter_reg:ident, $body:expr) => {{
+        let map_ty = $map_ty;
+        match map_ty {
+            Ty::MapIntMapIntInt => {
+                let $map_reg: Reg<runtime::IntMap<runtime::IntMap<Int>>> = $map_reg.into();
+                let $key_reg: Reg<Int> = $key_reg.into();
+                let $val_reg: Reg<runtime::IntMap<Int>> = $val_reg.into();
+                let $iter_reg: Reg<runtime::Iter<Int>> = $iter_reg.into();
+                $body
+            }
+            Ty::MapIntMapIntFloat => {
+                let $map_reg: Reg<runtime::IntMap<runtime::IntMap<Float>>> = $map_reg.into();
+                let $key_reg: Reg<Int> = $key_reg.into();
+                let $val_reg: Reg<runtime::IntMap<Float>> = $val_reg.into();
+                let $iter_reg: Reg<runtime::Iter<Int>> = $iter_reg.into();
+                $body
+            }
+            Ty::MapIntMapIntStr => {
+                let $map_reg: Reg<runtime::IntMap<runtime::IntMap<Str<'a>>>> = $map_reg.into();
+                let $key_reg: Reg<Int> = $key_reg.into();
+                let $val_reg: Reg<runtime::IntMap<Str<'a>>> = $val_reg.into();
+                let $iter_reg: Reg<runtime::Iter<Int>> = $iter_reg.into();
+                $body
+            }
+            Ty::MapIntMapStrInt => {
+                let $map_reg: Reg<runtime::IntMap<runtime::StrMap<'a, Int>>> = $map_reg.into();
+                let $key_reg: Reg<Int> = $key_reg.into();
+                let $val_reg: Reg<runtime::StrMap<'a, Int>> = $val_reg.into();
+                let $iter_reg: Reg<runtime::Iter<Int>> = $iter_reg.into();
+                $body
+            }
+            Ty::MapIntMapStrFloat => {
+                let $map_reg: Reg<runtime::IntMap<runtime::StrMap<'a, Float>>> = $map_reg.into();
+                let $key_reg: Reg<Int> = $key_reg.into();
+                let $val_reg: Reg<runtime::StrMap<'a, Float>> = $val_reg.into();
+                let $iter_reg: Reg<runtime::Iter<Int>> = $iter_reg.into();
+                $body
+            }
+            Ty::MapIntMapStrStr => {
+                let $map_reg: Reg<runtime::IntMap<runtime::StrMap<'a, Str<'a>>>> = $map_reg.into();
+                let $key_reg: Reg<Int> = $key_reg.into();
+                let $val_reg: Reg<runtime::StrMap<'a, Str<'a>>> = $val_reg.into();
+                let $iter_reg: Reg<runtime::Iter<Int>> = $iter_reg.into();
+                $body
+            }
+            Ty::MapStrMapIntInt => {
+                let $map_reg: Reg<runtime::StrMap<'a, runtime::IntMap<Int>>> = $map_reg.into();
+                let $key_reg: Reg<Str<'a>> = $key_reg.into();
+                let $val_reg: Reg<runtime::IntMap<Int>> = $val_reg.into();
+                let $iter_reg: Reg<runtime::Iter<Str<'a>>> = $iter_reg.into();
+                $body
+            }
+            Ty::MapStrMapIntFloat => {
+                let $map_reg: Reg<runtime::StrMap<'a, runtime::IntMap<Float>>> = $map_reg.into();
+                let $key_reg: Reg<Str<'a>> = $key_reg.into();
+                let $val_reg: Reg<runtime::IntMap<Float>> = $val_reg.into();
+                let $iter_reg: Reg<runtime::Iter<Str<'a>>> = $iter_reg.into();
+                $body
+            }
+            Ty::MapStrMapIntStr => {
+                let $map_reg: Reg<runtime::StrMap<'a, runtime::IntMap<Str<'a>>>> = $map_reg.into();
+                let $key_reg: Reg<Str<'a>> = $key_reg.into();
+                let $val_reg: Reg<runtime::IntMap<Str<'a>>> = $val_reg.into();
+                let $iter_reg: Reg<runtime::Iter<Str<'a>>> = $iter_reg.into();
+                $body
+            }
+            Ty::MapStrMapStrInt => {
+                let $map_reg: Reg<runtime::StrMap<'a, runtime::StrMap<'a, Int>>> = $map_reg.into();
+                let $key_reg: Reg<Str<'a>> = $key_reg.into();
+                let $val_reg: Reg<runtime::StrMap<'a, Int>> = $val_reg.into();
+                let $iter_reg: Reg<runtime::Iter<Str<'a>>> = $iter_reg.into();
+                $body
+            }
+            Ty::MapStrMapStrFloat => {
+                let $map_reg: Reg<runtime::StrMap<'a, runtime::StrMap<'a, Float>>> =
+                    $map_reg.into();
+                let $key_reg: Reg<Str<'a>> = $key_reg.into();
+                let $val_reg: Reg<runtime::StrMap<'a, Float>> = $val_reg.into();
+                let $iter_reg: Reg<runtime::Iter<Str<'a>>> = $iter_reg.into();
+                $body
+            }
+            Ty::MapStrMapStrStr => {
+                let $map_reg: Reg<runtime::StrMap<'a, runtime::StrMap<'a, Str<'a>>>> =
+                    $map_reg.into();
+                let $key_reg: Reg<Str<'a>> = $key_reg.into();
+                let $val_reg: Reg<runtime::StrMap<'a, Str<'a>>> = $val_reg.into();
+                let $iter_reg: Reg<runtime::Iter<Str<'a>>> = $iter_reg.into();
+                $body
+            }
+            _ => flat_map_regs!(map_ty, $map_reg, $key_reg, $val_reg, $iter_reg, $body),
+        }
+    }};
+}
+
+// Like map_regs, but only for maps with scalar values. This is used for operations that do not
+// apply to arrays of arrays, like incrementing values or sorting.
+macro_rules! flat_map_regs {
+    ($map_ty:expr, $map_reg:ident, $key_reg:ident, $val_reg:ident, $body:expr) => {{
+        let _placeholder_iter = 0u32;
+        flat_map_regs!(
+            $map_ty,
+            $map_reg,
+            $key_reg,
+            $val_reg,
+            _placeholder_iter,
+            $body
+        )
+    }};
     ($map_ty:expr, $map_reg:ident, $key_reg:ident, $val_reg:ident, $iter_reg:ident, $body:expr) => {{
         let map_ty = $map_ty;
         match map_ty {
@@ -398,7 +736,7 @@ macro_rules! map_regs {
                 let $iter_reg: Reg<runtime::Iter<Str<'a>>> = $iter_reg.into();
                 $body
             }
-            Ty::Null | Ty::Int | Ty::Float | Ty::Str | Ty::IterInt | Ty::IterStr => panic!(
+            _ => panic!(
                 "attempting to perform map operations on non-map type: {:?}",
                 map_ty
             ),
@@ -432,10 +770,82 @@ pub(crate) struct Interp<'a, LR: LineReader = ClassicReader> {
     pub(crate) maps_str_int: Storage<runtime::StrMap<'a, Int>>,
     pub(crate) maps_str_str: Storage<runtime::StrMap<'a, Str<'a>>>,
 
+    pub(crate) nested: NestedMaps<'a>,
+
     pub(crate) iters_int: Storage<runtime::Iter<Int>>,
     pub(crate) iters_str: Storage<runtime::Iter<Str<'a>>>,
 }
 
+/// Registers for arrays of arrays, named after their key type and the type of their values.
+pub(crate) struct NestedMaps<'a> {
+    pub(crate) int_intint: Storage<runtime::IntMap<runtime::IntMap<Int>>>,
+    pub(crate) int_intfloat: Storage<runtime::IntMap<runtime::IntMap<Float>>>,
+    pub(crate) int_intstr: Storage<runtime::IntMap<runtime::IntMap<Str<'a>>>>,
+    pub(crate) int_strint: Storage<runtime::IntMap<runtime::StrMap<'a, Int>>>,
+    pub(crate) int_strfloat: Storage<runtime::IntMap<runtime::StrMap<'a, Float>>>,
+    pub(crate) int_strstr: Storage<runtime::IntMap<runtime::StrMap<'a, Str<'a>>>>,
+    pub(crate) str_intint: Storage<runtime::StrMap<'a, runtime::IntMap<Int>>>,
+    pub(crate) str_intfloat: Storage<runtime::StrMap<'a, runtime::IntMap<Float>>>,
+    pub(crate) str_intstr: Storage<runtime::StrMap<'a, runtime::IntMap<Str<'a>>>>,
+    pub(crate) str_strint: Storage<runtime::StrMap<'a, runtime::StrMap<'a, Int>>>,
+    pub(crate) str_strfloat: Storage<runtime::StrMap<'a, runtime::StrMap<'a, Float>>>,
+    pub(crate) str_strstr: Storage<runtime::StrMap<'a, runtime::StrMap<'a, Str<'a>>>>,
+}
+
+impl<'a> NestedMaps<'a> {
+    fn new(regs: impl Fn(Ty) -> usize) -> NestedMaps<'a> {
+        NestedMaps {
+            int_intint: default_of(regs(Ty::MapIntMapIntInt)),
+            int_intfloat: default_of(regs(Ty::MapIntMapIntFloat)),
+            int_intstr: default_of(regs(Ty::MapIntMapIntStr)),
+            int_strint: default_of(regs(Ty::MapIntMapStrInt)),
+            int_strfloat: default_of(regs(Ty::MapIntMapStrFloat)),
+            int_strstr: default_of(regs(Ty::MapIntMapStrStr)),
+            str_intint: default_of(regs(Ty::MapStrMapIntInt)),
+            str_intfloat: default_of(regs(Ty::MapStrMapIntFloat)),
+            str_intstr: default_of(regs(Ty::MapStrMapIntStr)),
+            str_strint: default_of(regs(Ty::MapStrMapStrInt)),
+            str_strfloat: default_of(regs(Ty::MapStrMapStrFloat)),
+            str_strstr: default_of(regs(Ty::MapStrMapStrStr)),
+        }
+    }
+
+    /// The number of registers of each type, indexed by type.
+    fn sizes(&self) -> [usize; compile::NUM_TYPES] {
+        let mut res = [0; compile::NUM_TYPES];
+        res[Ty::MapIntMapIntInt as usize] = self.int_intint.regs.len();
+        res[Ty::MapIntMapIntFloat as usize] = self.int_intfloat.regs.len();
+        res[Ty::MapIntMapIntStr as usize] = self.int_intstr.regs.len();
+        res[Ty::MapIntMapStrInt as usize] = self.int_strint.regs.len();
+        res[Ty::MapIntMapStrFloat as usize] = self.int_strfloat.regs.len();
+        res[Ty::MapIntMapStrStr as usize] = self.int_strstr.regs.len();
+        res[Ty::MapStrMapIntInt as usize] = self.str_intint.regs.len();
+        res[Ty::MapStrMapIntFloat as usize] = self.str_intfloat.regs.len();
+        res[Ty::MapStrMapIntStr as usize] = self.str_intstr.regs.len();
+        res[Ty::MapStrMapStrInt as usize] = self.str_strint.regs.len();
+        res[Ty::MapStrMapStrFloat as usize] = self.str_strfloat.regs.len();
+        res[Ty::MapStrMapStrStr as usize] = self.str_strstr.regs.len();
+        res
+    }
+
+    #[cfg(test)]
+    #[cfg(feature = "unstable")]
+    fn reset(&mut self) {
+        self.int_intint.reset();
+        self.int_intfloat.reset();
+        self.int_intstr.reset();
+        self.int_strint.reset();
+        self.int_strfloat.reset();
+        self.int_strstr.reset();
+        self.str_intint.reset();
+        self.str_intfloat.reset();
+        self.str_intstr.reset();
+        self.str_strint.reset();
+        self.str_strfloat.reset();
+        self.str_strstr.reset();
+    }
+}
+
 fn default_of<T: Default>(n: usize) -> Storage<T> {
     let mut regs = Vec::new();
     regs.resize_with(n, Default::default);
@@ -457,6 +867,8 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
         named_columns: Option<Vec<&[u8]>>,
     ) -> Self {
         use compile::Ty::*;
+        let mut core = Core::new(ff);
+        core.vars.utf8 = stdin.check_utf8();
         Interp {
             main_func,
             num_workers,
@@ -465,7 +877,7 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
             floats: default_of(regs(Float)),
             ints: default_of(regs(Int)),
             strs: default_of(regs(Str)),
-            core: Core::new(ff),
+            core,
 
             line: Default::default(),
             read_files: runtime::FileRead::new(stdin, used_fields.clone(), named_columns),
@@ -478,6 +890,8 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
             maps_str_int: default_of(regs(MapStrInt)),
             maps_str_str: default_of(regs(MapStrStr)),
 
+            nested: NestedMaps::new(&regs),
+
             iters_int: default_of(regs(IterInt)),
             iters_str: default_of(regs(IterStr)),
         }
@@ -555,6 +969,7 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
             let maps_str_int_size = self.maps_str_int.regs.len();
             let maps_str_float_size = self.maps_str_float.regs.len();
             let maps_str_str_size = self.maps_str_str.regs.len();
+            let nested_sizes = self.nested.sizes();
             let iters_int_size = self.iters_int.regs.len();
             let iters_str_size = self.iters_str.regs.len();
             for (i, handle) in handles.into_iter().enumerate() {
@@ -581,6 +996,7 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                             maps_str_int: default_of(maps_str_int_size),
                             maps_str_float: default_of(maps_str_float_size),
                             maps_str_str: default_of(maps_str_str_size),
+                            nested: NestedMaps::new(|ty| nested_sizes[ty as usize]),
                             iters_int: default_of(iters_int_size),
                             iters_str: default_of(iters_str_size),
                         };
@@ -670,7 +1086,12 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         *self.get_mut(sr) = s;
                     }
                     FloatToStr(sr, fr) => {
-                        let s = runtime::convert::<_, Str>(*self.get(*fr));
+                        let s = self.core.vars.float_to_str(*self.get(*fr))?;
+                        let sr = *sr;
+                        *self.get_mut(sr) = s;
+                    }
+                    FloatToStrOut(sr, fr) => {
+                        let s = self.core.vars.float_to_str_out(*self.get(*fr))?;
                         let sr = *sr;
                         *self.get_mut(sr) = s;
                     }
@@ -813,6 +1234,30 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                     ReseedRng(res) => {
                         *index_mut(&mut self.ints, res) = self.core.reseed_random() as Int;
                     }
+                    Systime(res) => {
+                        *index_mut(&mut self.ints, res) = runtime::time::systime();
+                    }
+                    Strftime(res, fmt, ts, utc) => {
+                        let s = runtime::time::strftime(
+                            index(&self.strs, fmt),
+                            *index(&self.ints, ts),
+                            *index(&self.ints, utc) != 0,
+                        );
+                        *index_mut(&mut self.strs, res) = s;
+                    }
+                    Mktime(res, spec, utc) => {
+                        *index_mut(&mut self.ints, res) = runtime::time::mktime(
+                            index(&self.strs, spec),
+                            *index(&self.ints, utc) != 0,
+                        );
+                    }
+                    Strptime(res, s, fmt, utc) => {
+                        *index_mut(&mut self.floats, res) = runtime::time::strptime(
+                            index(&self.strs, s),
+                            index(&self.strs, fmt),
+                            *index(&self.ints, utc) != 0,
+                        );
+                    }
                     StartsWithConst(res, s, bs) => {
                         let s_bytes = unsafe { &*index(&self.strs, s).get_bytes() };
                         *index_mut(&mut self.ints, res) =
@@ -829,20 +1274,37 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                             .core
                             .match_regex(index(&self.strs, l), index(&self.strs, r))?;
                     }
+                    MatchGroups(res, l, r, arr) => {
+                        let arr = index(&self.maps_str_str, arr);
+                        *index_mut(&mut self.ints, res) =
+                            self.core.regexes.regex_match_loc_strmap(
+                                &mut self.core.vars,
+                                index(&self.strs, r),
+                                index(&self.strs, l),
+                                arr,
+                            )?;
+                    }
                     IsMatch(res, l, r) => {
                         *index_mut(&mut self.ints, res) = self
                             .core
                             .is_match_regex(index(&self.strs, l), index(&self.strs, r))?
                             as Int;
                     }
+                    MatchCases(res, s, cases) => {
+                        *index_mut(&mut self.ints, res) = self
+                            .core
+                            .match_cases(index(&self.strs, s), index(&self.strs, cases))?;
+                    }
                     MatchConst(res, x, pat) => {
+                        let pat = pat.get(self.core.regexes.ignore_case());
                         *index_mut(&mut self.ints, res) =
-                            runtime::RegexCache::regex_const_match(&*pat, index(&self.strs, x))
+                            runtime::RegexCache::regex_const_match(pat, index(&self.strs, x))
                                 as Int;
                     }
                     IsMatchConst(res, x, pat) => {
+                        let pat = pat.get(self.core.regexes.ignore_case());
                         *index_mut(&mut self.ints, res) =
-                            self.core.match_const_regex(index(&self.strs, x), &*pat)?;
+                            self.core.match_const_regex(index(&self.strs, x), pat)?;
                     }
                     SubstrIndex(res, s, t) => {
                         let res = *res;
@@ -858,6 +1320,17 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let len = self.get(s).len();
                         *self.get_mut(res) = len as Int;
                     }
+                    LenStrChars(res, s) => {
+                        let res = *res;
+                        let len = index(&self.strs, s).char_len();
+                        *self.get_mut(res) = len as Int;
+                    }
+                    SubstrIndexChars(res, s, t) => {
+                        let res = *res;
+                        let s = index(&self.strs, s);
+                        let t = index(&self.strs, t);
+                        *self.get_mut(res) = runtime::string_search::index_substr_chars(t, s);
+                    }
                     Sub(res, pat, s, in_s) => {
                         let (subbed, new) = {
                             let pat = index(&self.strs, pat);
@@ -882,6 +1355,18 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         *index_mut(&mut self.strs, in_s) = subbed;
                         *index_mut(&mut self.ints, res) = subs_made;
                     }
+                    GenSub(res, pat, s, how, in_s) => {
+                        let subbed = {
+                            let pat = index(&self.strs, pat);
+                            let s = index(&self.strs, s);
+                            let how = index(&self.strs, how);
+                            let in_s = index(&self.strs, in_s);
+                            self.core
+                                .regexes
+                                .with_regex(pat, |re| in_s.gen_subst(re, s, how))?
+                        };
+                        *index_mut(&mut self.strs, res) = subbed;
+                    }
                     EscapeCSV(res, s) => {
                         *index_mut(&mut self.strs, res) = {
                             let s = index(&self.strs, s);
@@ -906,6 +1391,12 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                             base.slice(l as usize, r)
                         };
                     }
+                    SubstrChars(res, base, l, r) => {
+                        let base = index(&self.strs, base);
+                        let l = cmp::max(0, -1 + *index(&self.ints, l));
+                        let r = cmp::max(l, l.saturating_add(*index(&self.ints, r)));
+                        *index_mut(&mut self.strs, res) = base.slice_chars(l as usize, r as usize);
+                    }
                     LTFloat(res, l, r) => {
                         let res = *res;
                         let l = *self.get(*l);
@@ -996,25 +1487,96 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let r = self.get(*r);
                         *self.get_mut(res) = (l == r) as Int;
                     }
+                    LTStrNum(res, l, r) => {
+                        let res = *res;
+                        let l = self.get(*l);
+                        let r = self.get(*r);
+                        *self.get_mut(res) = runtime::compare_strnum(l, r).is_lt() as Int;
+                    }
+                    GTStrNum(res, l, r) => {
+                        let res = *res;
+                        let l = self.get(*l);
+                        let r = self.get(*r);
+                        *self.get_mut(res) = runtime::compare_strnum(l, r).is_gt() as Int;
+                    }
+                    LTEStrNum(res, l, r) => {
+                        let res = *res;
+                        let l = self.get(*l);
+                        let r = self.get(*r);
+                        *self.get_mut(res) = runtime::compare_strnum(l, r).is_le() as Int;
+                    }
+                    GTEStrNum(res, l, r) => {
+                        let res = *res;
+                        let l = self.get(*l);
+                        let r = self.get(*r);
+                        *self.get_mut(res) = runtime::compare_strnum(l, r).is_ge() as Int;
+                    }
+                    EQStrNum(res, l, r) => {
+                        let res = *res;
+                        let l = self.get(*l);
+                        let r = self.get(*r);
+                        *self.get_mut(res) = runtime::compare_strnum(l, r).is_eq() as Int;
+                    }
+                    LTStrNumFloat(res, l, r) => {
+                        let res = *res;
+                        let l = self.get(*l);
+                        let r = *self.get(*r);
+                        let ord = runtime::compare_strnum_float(l, r, &self.core.vars)?;
+                        *self.get_mut(res) = ord.is_lt() as Int;
+                    }
+                    GTStrNumFloat(res, l, r) => {
+                        let res = *res;
+                        let l = self.get(*l);
+                        let r = *self.get(*r);
+                        let ord = runtime::compare_strnum_float(l, r, &self.core.vars)?;
+                        *self.get_mut(res) = ord.is_gt() as Int;
+                    }
+                    LTEStrNumFloat(res, l, r) => {
+                        let res = *res;
+                        let l = self.get(*l);
+                        let r = *self.get(*r);
+                        let ord = runtime::compare_strnum_float(l, r, &self.core.vars)?;
+                        *self.get_mut(res) = ord.is_le() as Int;
+                    }
+                    GTEStrNumFloat(res, l, r) => {
+                        let res = *res;
+                        let l = self.get(*l);
+                        let r = *self.get(*r);
+                        let ord = runtime::compare_strnum_float(l, r, &self.core.vars)?;
+                        *self.get_mut(res) = ord.is_ge() as Int;
+                    }
+                    EQStrNumFloat(res, l, r) => {
+                        let res = *res;
+                        let l = self.get(*l);
+                        let r = *self.get(*r);
+                        let ord = runtime::compare_strnum_float(l, r, &self.core.vars)?;
+                        *self.get_mut(res) = ord.is_eq() as Int;
+                    }
                     SetColumn(dst, src) => {
                         let col = *self.get(*dst);
                         let v = index(&self.strs, src);
-                        self.line
-                            .set_col(col, v, &self.core.vars.ofs, &mut self.core.regexes)?;
+                        self.line.set_col(
+                            col,
+                            v,
+                            &self.core.vars.field_split(),
+                            &mut self.core.regexes,
+                        )?;
                     }
                     GetColumn(dst, src) => {
                         let col = *self.get(*src);
                         let dst = *dst;
                         let res = self.line.get_col(
                             col,
-                            &self.core.vars.fs,
+                            &self.core.vars.field_split(),
                             &self.core.vars.ofs,
                             &mut self.core.regexes,
                         )?;
                         *self.get_mut(dst) = res;
                     }
                     JoinCSV(dst, start, end) => {
-                        let nf = self.line.nf(&self.core.vars.fs, &mut self.core.regexes)?;
+                        let nf = self
+                            .line
+                            .nf(&self.core.vars.field_split(), &mut self.core.regexes)?;
                         *index_mut(&mut self.strs, dst) = {
                             let start = *index(&self.ints, start);
                             let end = *index(&self.ints, end);
@@ -1024,7 +1586,9 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         };
                     }
                     JoinTSV(dst, start, end) => {
-                        let nf = self.line.nf(&self.core.vars.fs, &mut self.core.regexes)?;
+                        let nf = self
+                            .line
+                            .nf(&self.core.vars.field_split(), &mut self.core.regexes)?;
                         *index_mut(&mut self.strs, dst) = {
                             let start = *index(&self.ints, start);
                             let end = *index(&self.ints, end);
@@ -1034,7 +1598,9 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         };
                     }
                     JoinColumns(dst, start, end, sep) => {
-                        let nf = self.line.nf(&self.core.vars.fs, &mut self.core.regexes)?;
+                        let nf = self
+                            .line
+                            .nf(&self.core.vars.field_split(), &mut self.core.regexes)?;
                         *index_mut(&mut self.strs, dst) = {
                             let sep = index(&self.strs, sep);
                             let start = *index(&self.ints, start);
@@ -1050,6 +1616,14 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let res = index(&self.strs, src).to_lower_ascii();
                         *index_mut(&mut self.strs, dst) = res;
                     }
+                    ToUpper(dst, src) => {
+                        let res = index(&self.strs, src).to_upper();
+                        *index_mut(&mut self.strs, dst) = res;
+                    }
+                    ToLower(dst, src) => {
+                        let res = index(&self.strs, src).to_lower();
+                        *index_mut(&mut self.strs, dst) = res;
+                    }
                     SplitInt(flds, to_split, arr, pat) => {
                         // Index manually here to defeat the borrow checker.
                         let to_split = index(&self.strs, to_split);
@@ -1074,6 +1648,18 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let flds = *flds;
                         *self.get_mut(flds) = res;
                     }
+                    PatSplit(flds, to_split, arr, pat, seps) => {
+                        let to_split = index(&self.strs, to_split);
+                        let arr = index(&self.maps_int_str, arr);
+                        let pat = index(&self.strs, pat);
+                        let seps = index(&self.maps_int_str, seps);
+                        let res = self
+                            .core
+                            .regexes
+                            .pattern_split_intmap(pat, to_split, arr, seps)?;
+                        let flds = *flds;
+                        *self.get_mut(flds) = res;
+                    }
                     Sprintf { dst, fmt, args } => {
                         debug_assert_eq!(scratch.len(), 0);
                         for a in args.iter() {
@@ -1129,19 +1715,32 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         }
                         scratch.clear();
                     }
-                    Close(file) => {
-                        let file = index(&self.strs, file);
-                        // NB this may create an unused entry in write_files. It would not be
-                        // terribly difficult to optimize the close path to include an existence
-                        // check first.
-                        self.core.write_files.close(file)?;
-                        self.read_files.close(file);
+                    Close(dst, file, how) => {
+                        let status = runtime::close(
+                            &mut self.read_files,
+                            &mut self.core.write_files,
+                            index(&self.strs, file),
+                            index(&self.strs, how),
+                        )?;
+                        *index_mut(&mut self.ints, dst) = status;
                     }
                     RunCmd(dst, cmd) => {
                         *index_mut(&mut self.ints, dst) =
                             index(&self.strs, cmd).with_bytes(runtime::run_command);
                     }
+                    FFlush(dst, file) => {
+                        let res = self.core.write_files.flush(index(&self.strs, file))?;
+                        *index_mut(&mut self.ints, dst) = res;
+                    }
                     Exit(code) => return Ok(*index(&self.ints, code) as i32),
+                    UndefinedFunc(name) => {
+                        return index(&self.strs, name).with_bytes(|bs| {
+                            err!(
+                                "call to undefined function: {}",
+                                String::from_utf8_lossy(bs)
+                            )
+                        })
+                    }
                     Lookup {
                         map_ty,
                         dst,
@@ -1156,6 +1755,22 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                     } => self.contains(*map_ty, *dst, *map, *key),
                     Delete { map_ty, map, key } => self.delete(*map_ty, *map, *key),
                     Clear { map_ty, map } => self.clear(*map_ty, *map),
+                    Asort {
+                        map_ty,
+                        into_ty,
+                        dst,
+                        map,
+                        into,
+                        how,
+                    } => self.asort(*map_ty, *into_ty, *dst, *map, *into, *how)?,
+                    Asorti {
+                        map_ty,
+                        into_ty,
+                        dst,
+                        map,
+                        into,
+                        how,
+                    } => self.asorti(*map_ty, *into_ty, *dst, *map, *into, *how)?,
                     Len { map_ty, map, dst } => self.len(*map_ty, *map, *dst),
                     Store {
                         map_ty,
@@ -1178,6 +1793,9 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         dst,
                     } => self.inc_map_float(*map_ty, *map, *key, *by, *dst),
                     LoadVarStr(dst, var) => {
+                        if let RT = *var {
+                            self.core.vars.rt = self.read_files.record_terminator().upcast();
+                        }
                         let s = self.core.vars.load_str(*var)?;
                         let dst = *dst;
                         *self.get_mut(dst) = s;
@@ -1191,8 +1809,10 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         // If someone explicitly sets NF to a different value, this means we will
                         // ignore it. I think that is fine.
                         if let NF = *var {
-                            self.core.vars.nf =
-                                self.line.nf(&self.core.vars.fs, &mut self.core.regexes)? as Int;
+                            self.core.vars.nf = self
+                                .line
+                                .nf(&self.core.vars.field_split(), &mut self.core.regexes)?
+                                as Int;
                         }
                         let i = self.core.vars.load_int(*var)?;
                         let dst = *dst;
@@ -1201,7 +1821,7 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                     StoreVarInt(var, src) => {
                         let src = *src;
                         let s = *self.get(src);
-                        self.core.vars.store_int(*var, s)?;
+                        self.core.store_var_int(*var, s)?;
                     }
                     LoadVarIntMap(dst, var) => {
                         let arr = self.core.vars.load_intmap(*var)?;
@@ -1223,8 +1843,18 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let s = self.get(src).clone();
                         self.core.vars.store_strmap(*var, s)?;
                     }
+                    LoadVarStrStrMap(dst, var) => {
+                        let arr = self.core.vars.load_strstrmap(*var)?;
+                        let dst = *dst;
+                        *self.get_mut(dst) = arr;
+                    }
+                    StoreVarStrStrMap(var, src) => {
+                        let src = *src;
+                        let s = self.get(src).clone();
+                        self.core.vars.store_strstrmap(*var, s)?;
+                    }
 
-                    IterBegin { map_ty, map, dst } => self.iter_begin(*map_ty, *map, *dst),
+                    IterBegin { map_ty, map, dst } => self.iter_begin(*map_ty, *map, *dst)?,
                     IterHasNext { iter_ty, dst, iter } => self.iter_has_next(*iter_ty, *dst, *iter),
                     IterGetNext { iter_ty, dst, iter } => self.iter_get_next(*iter_ty, *dst, *iter),
 
@@ -1234,24 +1864,24 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                     AllocMap(ty, reg) => self.alloc_map(*ty, *reg),
 
                     // TODO add error logging for these errors perhaps?
-                    ReadErr(dst, file, is_file) => {
+                    ReadErr(dst, file, spec) => {
                         let dst = *dst;
                         let file = index(&self.strs, file);
-                        let res = if *is_file {
-                            self.read_files.read_err(file)?
-                        } else {
-                            self.read_files.read_err_cmd(file)?
+                        let res = match spec {
+                            InputSpec::File => self.read_files.read_err(file)?,
+                            InputSpec::Cmd => self.read_files.read_err_cmd(file)?,
+                            InputSpec::Coproc => self.read_files.read_err_coproc(file)?,
                         };
                         *self.get_mut(dst) = res;
                     }
-                    NextLine(dst, file, is_file) => {
+                    NextLine(dst, file, spec) => {
                         let dst = *dst;
                         let file = index(&self.strs, file);
                         match self.core.regexes.get_line(
                             file,
                             &self.core.vars.rs,
                             &mut self.read_files,
-                            *is_file,
+                            *spec,
                         ) {
                             Ok(l) => *self.get_mut(dst) = l,
                             Err(_) => *self.get_mut(dst) = "".into(),
@@ -1283,9 +1913,22 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                             self.reset_file_vars()
                         }
                     }
+                    NextLineStdinEvent(dst) => {
+                        let dst = *dst;
+                        let (event, changed) = self.core.regexes.get_line_stdin_event(
+                            &self.core.vars.rs,
+                            &mut self.read_files,
+                            &mut self.line,
+                        )?;
+                        if changed {
+                            self.reset_file_vars()
+                        }
+                        *self.get_mut(dst) = event;
+                    }
                     NextFile() => {
-                        self.read_files.next_file()?;
-                        self.reset_file_vars();
+                        if self.read_files.next_file()? {
+                            self.reset_file_vars();
+                        }
                     }
                     UpdateUsedFields() => {
                         let fi = &self.core.vars.fi;
@@ -1296,7 +1939,7 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let val = *index(&self.ints, val);
                         let col = self.line.get_col(
                             key,
-                            &self.core.vars.fs,
+                            &self.core.vars.field_split(),
                             &self.core.vars.ofs,
                             &mut self.core.regexes,
                         )?;
@@ -1371,7 +2014,11 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                 let src = index(&self.maps_str_str, &src.into()).clone();
                 *index_mut(&mut self.maps_str_str, &dst.into()) = src;
             }
-            Ty::Null | Ty::IterInt | Ty::IterStr => {
+            ty if ty.is_nested_map() => map_regs!(ty, src, {
+                let src = self.get(src).clone();
+                *self.get_mut(Reg::from(dst)) = src;
+            }),
+            _ => {
                 panic!("invalid type for move operation: {:?}", ty)
             }
         }
@@ -1410,7 +2057,7 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
         });
     }
     fn inc_map_int(&mut self, map_ty: Ty, map: NumTy, key: NumTy, by: Reg<Int>, dst: NumTy) {
-        map_regs!(map_ty, map, key, dst, {
+        flat_map_regs!(map_ty, map, key, dst, {
             let k = self.get(key);
             let m = self.get(map);
             let by = self.get(by).clone();
@@ -1419,7 +2066,7 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
         })
     }
     fn inc_map_float(&mut self, map_ty: Ty, map: NumTy, key: NumTy, by: Reg<Float>, dst: NumTy) {
-        map_regs!(map_ty, map, key, dst, {
+        flat_map_regs!(map_ty, map, key, dst, {
             let k = self.get(key);
             let m = self.get(map);
             let by = self.get(by).clone();
@@ -1431,13 +2078,66 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
         let len = map_regs!(map_ty, map, self.get(map).len() as Int);
         *index_mut(&mut self.ints, &dst.into()) = len;
     }
-    fn iter_begin(&mut self, map_ty: Ty, map: NumTy, dst: NumTy) {
+    fn asort(
+        &mut self,
+        map_ty: Ty,
+        into_ty: Ty,
+        dst: Reg<Int>,
+        map: NumTy,
+        into: NumTy,
+        how: Reg<Str<'a>>,
+    ) -> Result<()> {
+        // map_regs gives `into` the type of a register holding a value of `map`; we convert it to
+        // a register holding a map of those values, keyed by integers or strings.
+        let _k = 0u32;
+        let str_keys = into_ty.key()? == Ty::Str;
+        let res = flat_map_regs!(map_ty, map, _k, into, {
+            let how = self.get(how);
+            if str_keys {
+                let into: Reg<runtime::StrMap<'a, _>> = Reg::from(into.index() as NumTy);
+                runtime::sort::asort(self.get(map), self.get(into), how)?
+            } else {
+                let into: Reg<runtime::IntMap<_>> = Reg::from(into.index() as NumTy);
+                runtime::sort::asort(self.get(map), self.get(into), how)?
+            }
+        });
+        *self.get_mut(dst) = res;
+        Ok(())
+    }
+    fn asorti(
+        &mut self,
+        map_ty: Ty,
+        into_ty: Ty,
+        dst: Reg<Int>,
+        map: NumTy,
+        into: NumTy,
+        how: Reg<Str<'a>>,
+    ) -> Result<()> {
+        // As in `asort`, but `into` holds the keys of `map`.
+        let _v = 0u32;
+        let str_keys = into_ty.key()? == Ty::Str;
+        let res = flat_map_regs!(map_ty, map, into, _v, {
+            let how = self.get(how);
+            if str_keys {
+                let into: Reg<runtime::StrMap<'a, _>> = Reg::from(into.index() as NumTy);
+                runtime::sort::asorti(self.get(map), self.get(into), how)?
+            } else {
+                let into: Reg<runtime::IntMap<_>> = Reg::from(into.index() as NumTy);
+                runtime::sort::asorti(self.get(map), self.get(into), how)?
+            }
+        });
+        *self.get_mut(dst) = res;
+        Ok(())
+    }
+    fn iter_begin(&mut self, map_ty: Ty, map: NumTy, dst: NumTy) -> Result<()> {
         let _k = 0u32;
         let _v = 0u32;
+        let order = self.core.vars.sorted_in.clone()?;
         map_regs!(map_ty, map, _k, _v, dst, {
-            let iter = self.get(map).to_iter();
+            let iter = self.get(map).to_iter(order);
             *self.get_mut(dst) = iter;
-        })
+        });
+        Ok(())
     }
     fn iter_has_next(&mut self, iter_ty: Ty, dst: NumTy, iter: NumTy) {
         match iter_ty {
@@ -1468,8 +2168,8 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
     fn load_slot(&mut self, ty: Ty, dst: NumTy, slot: Int) {
         let slot = slot as usize;
         macro_rules! do_load {
-            ($load_meth:tt, $reg_fld:tt) => {
-                *index_mut(&mut self.$reg_fld, &dst.into()) = self.core.$load_meth(slot)
+            ($load_meth:tt, $($reg_fld:ident).+) => {
+                *index_mut(&mut self.$($reg_fld).+, &dst.into()) = self.core.$load_meth(slot)
             };
         }
         match ty {
@@ -1482,6 +2182,18 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
             Ty::MapStrInt => do_load!(load_strint, maps_str_int),
             Ty::MapStrFloat => do_load!(load_strfloat, maps_str_float),
             Ty::MapStrStr => do_load!(load_strstr, maps_str_str),
+            Ty::MapIntMapIntInt => do_load!(load_int_intint, nested.int_intint),
+            Ty::MapIntMapIntFloat => do_load!(load_int_intfloat, nested.int_intfloat),
+            Ty::MapIntMapIntStr => do_load!(load_int_intstr, nested.int_intstr),
+            Ty::MapIntMapStrInt => do_load!(load_int_strint, nested.int_strint),
+            Ty::MapIntMapStrFloat => do_load!(load_int_strfloat, nested.int_strfloat),
+            Ty::MapIntMapStrStr => do_load!(load_int_strstr, nested.int_strstr),
+            Ty::MapStrMapIntInt => do_load!(load_str_intint, nested.str_intint),
+            Ty::MapStrMapIntFloat => do_load!(load_str_intfloat, nested.str_intfloat),
+            Ty::MapStrMapIntStr => do_load!(load_str_intstr, nested.str_intstr),
+            Ty::MapStrMapStrInt => do_load!(load_str_strint, nested.str_strint),
+            Ty::MapStrMapStrFloat => do_load!(load_str_strfloat, nested.str_strfloat),
+            Ty::MapStrMapStrStr => do_load!(load_str_strstr, nested.str_strstr),
             Ty::Null | Ty::IterInt | Ty::IterStr => {
                 panic!("unexpected operand type to slot operation: {:?}", ty)
             }
@@ -1490,9 +2202,9 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
     fn store_slot(&mut self, ty: Ty, src: NumTy, slot: Int) {
         let slot = slot as usize;
         macro_rules! do_store {
-            ($store_meth:tt, $reg_fld:tt) => {
+            ($store_meth:tt, $($reg_fld:ident).+) => {
                 self.core
-                    .$store_meth(slot, index(&self.$reg_fld, &src.into()).clone())
+                    .$store_meth(slot, index(&self.$($reg_fld).+, &src.into()).clone())
             };
         }
         match ty {
@@ -1505,6 +2217,18 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
             Ty::MapStrInt => do_store!(store_strint, maps_str_int),
             Ty::MapStrFloat => do_store!(store_strfloat, maps_str_float),
             Ty::MapStrStr => do_store!(store_strstr, maps_str_str),
+            Ty::MapIntMapIntInt => do_store!(store_int_intint, nested.int_intint),
+            Ty::MapIntMapIntFloat => do_store!(store_int_intfloat, nested.int_intfloat),
+            Ty::MapIntMapIntStr => do_store!(store_int_intstr, nested.int_intstr),
+            Ty::MapIntMapStrInt => do_store!(store_int_strint, nested.int_strint),
+            Ty::MapIntMapStrFloat => do_store!(store_int_strfloat, nested.int_strfloat),
+            Ty::MapIntMapStrStr => do_store!(store_int_strstr, nested.int_strstr),
+            Ty::MapStrMapIntInt => do_store!(store_str_intint, nested.str_intint),
+            Ty::MapStrMapIntFloat => do_store!(store_str_intfloat, nested.str_intfloat),
+            Ty::MapStrMapIntStr => do_store!(store_str_intstr, nested.str_intstr),
+            Ty::MapStrMapStrInt => do_store!(store_str_strint, nested.str_strint),
+            Ty::MapStrMapStrFloat => do_store!(store_str_strfloat, nested.str_strfloat),
+            Ty::MapStrMapStrStr => do_store!(store_str_strstr, nested.str_strstr),
             Ty::Null | Ty::IterInt | Ty::IterStr => panic!("unsupported slot type: {:?}", ty),
         }
     }
@@ -1519,6 +2243,18 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
             Ty::MapStrInt => push(&mut self.maps_str_int, &src.into()),
             Ty::MapStrFloat => push(&mut self.maps_str_float, &src.into()),
             Ty::MapStrStr => push(&mut self.maps_str_str, &src.into()),
+            Ty::MapIntMapIntInt => push(&mut self.nested.int_intint, &src.into()),
+            Ty::MapIntMapIntFloat => push(&mut self.nested.int_intfloat, &src.into()),
+            Ty::MapIntMapIntStr => push(&mut self.nested.int_intstr, &src.into()),
+            Ty::MapIntMapStrInt => push(&mut self.nested.int_strint, &src.into()),
+            Ty::MapIntMapStrFloat => push(&mut self.nested.int_strfloat, &src.into()),
+            Ty::MapIntMapStrStr => push(&mut self.nested.int_strstr, &src.into()),
+            Ty::MapStrMapIntInt => push(&mut self.nested.str_intint, &src.into()),
+            Ty::MapStrMapIntFloat => push(&mut self.nested.str_intfloat, &src.into()),
+            Ty::MapStrMapIntStr => push(&mut self.nested.str_intstr, &src.into()),
+            Ty::MapStrMapStrInt => push(&mut self.nested.str_strint, &src.into()),
+            Ty::MapStrMapStrFloat => push(&mut self.nested.str_strfloat, &src.into()),
+            Ty::MapStrMapStrStr => push(&mut self.nested.str_strstr, &src.into()),
             Ty::Null | Ty::IterInt | Ty::IterStr => {
                 panic!("unsupported register type for push operation: {:?}", ty)
             }
@@ -1545,7 +2281,55 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                 *index_mut(&mut self.maps_str_float, &dst.into()) = pop(&mut self.maps_str_float)
             }
             Ty::MapStrStr => {
-                *index_mut(&mut self.maps_str_float, &dst.into()) = pop(&mut self.maps_str_float)
+                *index_mut(&mut self.maps_str_str, &dst.into()) = pop(&mut self.maps_str_str)
+            }
+            Ty::MapIntMapIntInt => {
+                *index_mut(&mut self.nested.int_intint, &dst.into()) =
+                    pop(&mut self.nested.int_intint)
+            }
+            Ty::MapIntMapIntFloat => {
+                *index_mut(&mut self.nested.int_intfloat, &dst.into()) =
+                    pop(&mut self.nested.int_intfloat)
+            }
+            Ty::MapIntMapIntStr => {
+                *index_mut(&mut self.nested.int_intstr, &dst.into()) =
+                    pop(&mut self.nested.int_intstr)
+            }
+            Ty::MapIntMapStrInt => {
+                *index_mut(&mut self.nested.int_strint, &dst.into()) =
+                    pop(&mut self.nested.int_strint)
+            }
+            Ty::MapIntMapStrFloat => {
+                *index_mut(&mut self.nested.int_strfloat, &dst.into()) =
+                    pop(&mut self.nested.int_strfloat)
+            }
+            Ty::MapIntMapStrStr => {
+                *index_mut(&mut self.nested.int_strstr, &dst.into()) =
+                    pop(&mut self.nested.int_strstr)
+            }
+            Ty::MapStrMapIntInt => {
+                *index_mut(&mut self.nested.str_intint, &dst.into()) =
+                    pop(&mut self.nested.str_intint)
+            }
+            Ty::MapStrMapIntFloat => {
+                *index_mut(&mut self.nested.str_intfloat, &dst.into()) =
+                    pop(&mut self.nested.str_intfloat)
+            }
+            Ty::MapStrMapIntStr => {
+                *index_mut(&mut self.nested.str_intstr, &dst.into()) =
+                    pop(&mut self.nested.str_intstr)
+            }
+            Ty::MapStrMapStrInt => {
+                *index_mut(&mut self.nested.str_strint, &dst.into()) =
+                    pop(&mut self.nested.str_strint)
+            }
+            Ty::MapStrMapStrFloat => {
+                *index_mut(&mut self.nested.str_strfloat, &dst.into()) =
+                    pop(&mut self.nested.str_strfloat)
+            }
+            Ty::MapStrMapStrStr => {
+                *index_mut(&mut self.nested.str_strstr, &dst.into()) =
+                    pop(&mut self.nested.str_strstr)
             }
             Ty::Null | Ty::IterInt | Ty::IterStr => {
                 panic!("unsupported register type for pop operation: {:?}", ty)
@@ -1623,6 +2407,7 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
         self.maps_str_int.reset();
         self.maps_str_float.reset();
         self.maps_str_str.reset();
+        self.nested.reset();
         self.iters_int.reset();
         self.iters_str.reset();
     }
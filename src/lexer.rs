@@ -2,7 +2,11 @@
 //!
 //! This lexer is fairly rudamentary. It ought not be too slow, but it also has not been optimized
 //! very aggressively. Various edge cases still do not work.
-use hashbrown::HashMap;
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use hashbrown::{HashMap, HashSet};
 use lazy_static::lazy_static;
 use regex::Regex;
 use unicode_xid::UnicodeXID;
@@ -14,6 +18,8 @@ pub struct Loc {
     pub line: usize,
     pub col: usize,
     offset: usize,
+    // The program file containing this location, if the program was read from a file.
+    pub file: Option<Rc<str>>,
 }
 
 pub type Spanned<T> = (Loc, T, Loc);
@@ -22,6 +28,8 @@ pub type Spanned<T> = (Loc, T, Loc);
 pub enum Tok<'a> {
     Begin,
     Prepare,
+    BeginFile,
+    EndFile,
     End,
     Break,
     Continue,
@@ -32,13 +40,18 @@ pub enum Tok<'a> {
     Else,
     Print,
     Printf,
-    // Separate token for a "print(" and "printf(".
+    PrintBytes,
+    // Separate token for a "print(", "printf(" and "print_bytes(".
     PrintLP,
     PrintfLP,
+    PrintBytesLP,
     Exit,
     ExitLP,
     While,
     Do,
+    Switch,
+    Case,
+    Default,
 
     // { }
     LBrace,
@@ -82,6 +95,7 @@ pub enum Tok<'a> {
     QUESTION,
     COLON,
     Pipe,
+    CoPipe, // |&
 
     Append, // >>
 
@@ -97,6 +111,8 @@ pub enum Tok<'a> {
     StrLit(&'a str),
     PatLit(&'a str),
     CallStart(&'a str),
+    // `@name(`, the start of an indirect call through the variable `name`.
+    IndirectCallStart(&'a str),
     FunDec(&'a str),
 
     ILit(&'a str),
@@ -135,6 +151,7 @@ lazy_static! {
     static ref WS_SEMI_NL_RB: Regex = Regex::new(r"^[\s;\n}]").unwrap();
     static ref WS_SEMI_RPAREN: Regex = Regex::new(r"^[\s;)]").unwrap();
     static ref WS_PAREN: Regex = Regex::new(r"^[\s()]").unwrap();
+    static ref WS_COLON: Regex = Regex::new(r"^[\s:]").unwrap();
 }
 
 keyword_map!(
@@ -142,6 +159,8 @@ keyword_map!(
     [b"PREPARE", Tok::Prepare],
     [b"BEGIN", Tok::Begin, WS_BRACE.clone()],
     [b"END", Tok::End, WS_BRACE.clone()],
+    [b"BEGINFILE", Tok::BeginFile, WS_BRACE.clone()],
+    [b"ENDFILE", Tok::EndFile, WS_BRACE.clone()],
     [b"break", Tok::Break, WS_SEMI.clone()],
     [b"continue", Tok::Continue, WS_SEMI.clone()],
     [b"next", Tok::Next],
@@ -153,10 +172,15 @@ keyword_map!(
     [b"printf", Tok::Printf, WS_SEMI_NL.clone()],
     [b"print(", Tok::PrintLP],
     [b"printf(", Tok::PrintfLP],
+    [b"print_bytes", Tok::PrintBytes, WS_SEMI_NL_RB.clone()],
+    [b"print_bytes(", Tok::PrintBytesLP],
     [b"exit(", Tok::ExitLP],
     [b"exit", Tok::Exit],
     [b"while", Tok::While, WS_PAREN.clone()],
     [b"do", Tok::Do, WS_BRACE.clone()],
+    [b"switch", Tok::Switch, WS_PAREN.clone()],
+    [b"case", Tok::Case, WS.clone()],
+    [b"default", Tok::Default, WS_COLON.clone()],
     [b"{", Tok::LBrace],
     [b"}", Tok::RBrace],
     [b"[", Tok::LBrack],
@@ -165,6 +189,7 @@ keyword_map!(
     [b")", Tok::RParen],
     [b"getline", Tok::Getline, WS_SEMI_RPAREN.clone()],
     [b"|", Tok::Pipe],
+    [b"|&", Tok::CoPipe],
     [b"=", Tok::Assign],
     [b"+", Tok::Add],
     [b"+=", Tok::AddAssign],
@@ -224,6 +249,61 @@ pub struct Tokenizer<'a> {
     cur: usize,
     prev_tok: Option<Tok<'a>>,
     lines: Vec<usize>,
+    file: Option<Rc<str>>,
+    // Where to pick up once we reach the end of `text`: the rest of a file containing an
+    // `@include`, or program files following this one. The next source to read is at the end.
+    pending: Vec<Source<'a>>,
+    // None if `@include` is not supported; see `Tokenizer::with_includes`.
+    includes: Option<Includes<'a>>,
+}
+
+/// A program file (or program text given on the command line), and how far into it we are.
+struct Source<'a> {
+    text: &'a str,
+    cur: usize,
+    lines: Vec<usize>,
+    file: Option<Rc<str>>,
+}
+
+impl<'a> Source<'a> {
+    fn new(file: Option<Rc<str>>, text: &'a str) -> Source<'a> {
+        Source {
+            // A hack to get around some programs failing to parse due to a trailing newline
+            text: text.trim_end_matches('\n'),
+            cur: 0,
+            lines: text
+                .as_bytes()
+                .iter()
+                .enumerate()
+                .flat_map(|(i, b)| if *b == b'\n' { Some(i) } else { None }.into_iter())
+                .collect(),
+            file,
+        }
+    }
+}
+
+/// The state needed to resolve `@include` directives.
+struct Includes<'a> {
+    arena: &'a Arena,
+    // Directories to search for included files, after the directory of the including file.
+    search_path: Vec<PathBuf>,
+    // The canonical paths of all program files read so far; each file is only included once.
+    seen: HashSet<PathBuf>,
+    buf: Vec<u8>,
+}
+
+impl<'a> Includes<'a> {
+    /// Find the file named by an `@include` directive in a file in `dir`.
+    fn resolve(&self, name: &str, dir: &Path) -> Option<PathBuf> {
+        let name = Path::new(name);
+        if name.is_absolute() {
+            return Some(name.into());
+        }
+        std::iter::once(dir)
+            .chain(self.search_path.iter().map(PathBuf::as_path))
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_file())
+    }
 }
 
 pub fn is_ident(s: &str) -> bool {
@@ -359,9 +439,13 @@ pub(crate) fn parse_regex_literal<'a>(lit: &str, arena: &'a Arena, buf: &mut Vec
     // NB: Awk escaping rules are a subset of Rust's regex escape rule syntax, but if we applied
     // Awk's rewrites here, we might create a pattern that is invalid UTF-8, which will cause a
     // failure when we try and compile the regular expression.
+    //
+    // A literal followed by an `i` suffix (e.g. /abc/i) is lexed as "abc/i"; it matches
+    // case-insensitively.
     buf.clear();
     let mut is_escape = false;
-    for c in lit.chars() {
+    let mut ignore_case = false;
+    for (ix, c) in lit.char_indices() {
         if is_escape {
             match c {
                 '/' => buf.push(b'/'),
@@ -378,6 +462,7 @@ pub(crate) fn parse_regex_literal<'a>(lit: &str, arena: &'a Arena, buf: &mut Vec
                     continue;
                 }
                 '/' => {
+                    ignore_case = &lit[ix + 1..] == "i";
                     break;
                 }
                 c => {
@@ -386,6 +471,9 @@ pub(crate) fn parse_regex_literal<'a>(lit: &str, arena: &'a Arena, buf: &mut Vec
             }
         }
     }
+    if ignore_case {
+        buf.splice(0..0, b"(?i)".iter().cloned());
+    }
     arena.alloc_bytes(&buf[..])
 }
 
@@ -452,6 +540,17 @@ impl<'a> Tokenizer<'a> {
         }
     }
 
+    fn indirect_call(&self) -> Option<(Tok<'a>, usize)> {
+        lazy_static! {
+            static ref INDIRECT_PATTERN: Regex =
+                Regex::new(r"^@([a-zA-Z_][a-zA-Z_0-9]*)\(").unwrap();
+        }
+        let captures = INDIRECT_PATTERN.captures(&self.text[self.cur..])?;
+        let full = captures.get(0)?.as_str();
+        let name = captures.get(1)?.as_str();
+        Some((Tok::IndirectCallStart(name), full.len()))
+    }
+
     fn ident(&mut self, id_start: usize) -> (&'a str, usize) {
         debug_assert!(is_id_start(self.text[id_start..].chars().next().unwrap()));
         let ix = self.text[self.cur..]
@@ -484,7 +583,7 @@ impl<'a> Tokenizer<'a> {
             Some(end) => Ok((&self.text[self.cur..self.cur + end], self.cur + end + 1)),
             None => Err(Error {
                 location: self.index_to_loc(self.cur),
-                desc: error_msg,
+                desc: error_msg.into(),
             }),
         }
     }
@@ -493,6 +592,14 @@ impl<'a> Tokenizer<'a> {
         self.literal('/', "incomplete regex literal")
     }
 
+    // Whether a regex literal ending just before `end` has an `i` suffix: an `i` that does not
+    // begin an identifier.
+    fn ignore_case_suffix(&self, end: usize) -> bool {
+        let mut rest = self.text[end..].chars();
+        rest.next() == Some('i')
+            && !matches!(rest.next(), Some(c) if c.is_alphanumeric() || c == '_')
+    }
+
     fn string_lit(&mut self) -> Result<(&'a str, usize /* new start */), Error> {
         self.literal('"', "incomplete string literal")
     }
@@ -509,11 +616,11 @@ impl<'a> Tokenizer<'a> {
     }
 
     fn consume_ws(&mut self) {
-        let mut res = 0;
+        // If the rest of the text is whitespace, consume all of it.
+        let mut res = self.text.len() - self.cur;
         let mut iter = self.text[self.cur..].char_indices();
         'outer: while let Some((ix, c)) = iter.next() {
             loop {
-                res = ix;
                 if c == '\\' {
                     // look ahead for a newline and hence a line continuation
                     if let Some((_, next_c)) = iter.next() {
@@ -521,10 +628,12 @@ impl<'a> Tokenizer<'a> {
                             // count this as whitespace
                             continue 'outer;
                         }
+                        res = ix;
                         break 'outer;
                     }
                 }
                 if c == '\n' || !c.is_whitespace() {
+                    res = ix;
                     break 'outer;
                 }
 
@@ -560,50 +669,159 @@ impl<'a> Tokenizer<'a> {
 #[derive(Debug)]
 pub struct Error {
     pub location: Loc,
-    pub desc: &'static str,
+    pub desc: Cow<'static, str>,
 }
 
 impl From<&'static str> for Error {
     fn from(s: &'static str) -> Error {
         Error {
             location: Default::default(),
-            desc: s,
+            desc: s.into(),
         }
     }
 }
 
 impl<'a> Tokenizer<'a> {
     pub fn new(text: &'a str) -> Tokenizer<'a> {
+        let src = Source::new(None, text);
         Tokenizer {
-            // A hack to get around some programs failing to parse due to a trailing newline
-            text: text.trim_end_matches('\n'),
-            cur: 0,
+            text: src.text,
+            cur: src.cur,
             prev_tok: None,
-            lines: text
-                .as_bytes()
-                .iter()
-                .enumerate()
-                .flat_map(|(i, b)| if *b == b'\n' { Some(i) } else { None }.into_iter())
-                .collect(),
+            lines: src.lines,
+            file: src.file,
+            pending: Vec::new(),
+            includes: None,
+        }
+    }
+
+    /// Tokenize a program made up of `sources` in order, each of which is the name of the file it
+    /// was read from (if any) and its contents. `@include "name"` directives read the named file
+    /// from the directory of the file containing the directive (or the current directory), or
+    /// failing that from a directory in `search_path`. Each file is only read once.
+    pub fn with_includes(
+        arena: &'a Arena,
+        search_path: Vec<PathBuf>,
+        sources: &[(Option<&str>, &'a str)],
+    ) -> Tokenizer<'a> {
+        let mut tok = Tokenizer::new("");
+        let mut includes = Includes {
+            arena,
+            search_path,
+            seen: Default::default(),
+            buf: Vec::new(),
+        };
+        for (file, text) in sources.iter().rev() {
+            if let Some(path) = file.and_then(|f| std::fs::canonicalize(f).ok()) {
+                includes.seen.insert(path);
+            }
+            tok.pending.push(Source::new(file.map(Rc::from), text));
+        }
+        tok.includes = Some(includes);
+        if let Some(first) = tok.pending.pop() {
+            tok.switch_to(first);
+        }
+        tok
+    }
+
+    /// Start tokenizing `src`, returning the source we were tokenizing before.
+    fn switch_to(&mut self, src: Source<'a>) -> Source<'a> {
+        Source {
+            text: std::mem::replace(&mut self.text, src.text),
+            cur: std::mem::replace(&mut self.cur, src.cur),
+            lines: std::mem::replace(&mut self.lines, src.lines),
+            file: std::mem::replace(&mut self.file, src.file),
+        }
+    }
+
+    /// Handle an `@include` directive starting at `ix`, if there is one: switch to tokenizing the
+    /// included file, unless it has been read already.
+    fn include(&mut self, ix: usize) -> Option<Result<(), Error>> {
+        lazy_static! {
+            static ref INCLUDE_PATTERN: Regex = Regex::new(r#"^@include\b[ \t]*"#).unwrap();
+        }
+        let start = INCLUDE_PATTERN.find(&self.text[self.cur..])?.end();
+        let rest = &self.text[self.cur + start..];
+        if rest.starts_with('(') {
+            // An indirect call to a function named `include`.
+            return None;
+        }
+        if !rest.starts_with('"') {
+            return Some(Err(Error {
+                location: self.index_to_loc(ix),
+                desc: "@include expects a quoted file name".into(),
+            }));
+        }
+        self.cur += start + 1;
+        Some(self.include_inner(ix))
+    }
+
+    fn include_inner(&mut self, ix: usize) -> Result<(), Error> {
+        let (lit, new_start) = self.string_lit()?;
+        self.cur = new_start;
+        let location = self.index_to_loc(ix);
+        let fail = |desc: String| {
+            Err(Error {
+                location: location.clone(),
+                desc: desc.into(),
+            })
+        };
+        let includes = match &mut self.includes {
+            Some(includes) => includes,
+            None => return fail("@include is only supported in programs run by frawk".into()),
+        };
+        let arena = includes.arena;
+        let name = match std::str::from_utf8(parse_string_literal(lit, arena, &mut includes.buf)) {
+            Ok(name) => name,
+            Err(e) => return fail(format!("invalid UTF-8 in @include: {}", e)),
+        };
+        let dir = self
+            .file
+            .as_deref()
+            .and_then(|f| Path::new(f).parent())
+            .unwrap_or_else(|| Path::new(""));
+        let path = match includes.resolve(name, dir) {
+            Some(path) => path,
+            None => return fail(format!("could not find @include file {:?}", name)),
+        };
+        let canonical = match std::fs::canonicalize(&path) {
+            Ok(canonical) => canonical,
+            Err(e) => return fail(format!("could not read {}: {}", path.display(), e)),
+        };
+        if !includes.seen.insert(canonical) {
+            return Ok(());
         }
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => arena.alloc_str(text.as_str()),
+            Err(e) => return fail(format!("could not read {}: {}", path.display(), e)),
+        };
+        let file = Rc::from(path.to_string_lossy());
+        let outer = self.switch_to(Source::new(Some(file), text));
+        self.pending.push(outer);
+        Ok(())
     }
+
     fn index_to_loc(&self, ix: usize) -> Loc {
         let offset = ix;
+        let file = self.file.clone();
         match self.lines.binary_search(&ix) {
             Ok(0) | Err(0) => Loc {
                 line: 0,
                 col: ix,
                 offset,
+                file,
             },
             Ok(line) => Loc {
                 line: line - 1,
                 col: ix - self.lines[line - 1] - 1,
                 offset,
+                file,
             },
             Err(line) => Loc {
                 line,
                 col: ix - self.lines[line - 1] - 1,
                 offset,
+                file,
             },
         }
     }
@@ -624,6 +842,16 @@ impl<'a> Iterator for Tokenizer<'a> {
             };
         }
         self.advance();
+        while self.cur == self.text.len() && self.prev_tok == Some(Tok::Newline) {
+            // We are done with this file; move on to the rest of the program, if there is any.
+            match self.pending.pop() {
+                Some(src) => {
+                    self.switch_to(src);
+                    self.advance();
+                }
+                None => break,
+            }
+        }
         let span = if let Some((ix, c)) = self.text[self.cur..].char_indices().next() {
             let ix = self.cur + ix;
             match c {
@@ -635,10 +863,35 @@ impl<'a> Iterator for Tokenizer<'a> {
                 }
                 '/' if self.potential_re() => {
                     self.cur += 1;
-                    let (re, new_start) = try_tok!(self.regex_lit());
+                    let (mut re, mut new_start) = try_tok!(self.regex_lit());
+                    if self.ignore_case_suffix(new_start) {
+                        // Keep the suffix as part of the literal; see parse_regex_literal.
+                        new_start += 1;
+                        re = &self.text[self.cur..new_start];
+                    }
                     self.cur = new_start;
                     self.spanned(ix, new_start, Tok::PatLit(re))
                 }
+                '@' => {
+                    if let Some(res) = self.include(ix) {
+                        try_tok!(res);
+                        // Separate the included file from whatever precedes the directive.
+                        let span = self.spanned(self.cur, self.cur, Tok::Newline);
+                        self.prev_tok = Some(Tok::Newline);
+                        return Some(Ok(span));
+                    }
+                    let (tok, len) = match self.indirect_call() {
+                        Some(res) => res,
+                        None => {
+                            return Some(Err(Error {
+                                location: self.index_to_loc(ix),
+                                desc: "unexpected character '@'".into(),
+                            }))
+                        }
+                    };
+                    self.cur += len;
+                    self.spanned(ix, self.cur, tok)
+                }
                 c => {
                     if let Some((tok, len)) = self.fundec() {
                         self.cur += len;
@@ -694,6 +947,7 @@ and the third"#;
                 line: 0,
                 col: 4,
                 offset: 4,
+                file: None,
             }
         );
         assert_eq!(
@@ -702,6 +956,7 @@ and the third"#;
                 line: 0,
                 col: 22,
                 offset: 22,
+                file: None,
             }
         );
         assert_eq!(
@@ -710,6 +965,7 @@ and the third"#;
                 line: 1,
                 col: 0,
                 offset: 23,
+                file: None,
             }
         );
         let tok2 = Tokenizer::new("\nhello");
@@ -718,7 +974,8 @@ and the third"#;
             Loc {
                 line: 0,
                 col: 0,
-                offset: 0
+                offset: 0,
+                file: None,
             },
         );
         assert_eq!(
@@ -726,7 +983,8 @@ and the third"#;
             Loc {
                 line: 1,
                 col: 0,
-                offset: 1
+                offset: 1,
+                file: None,
             },
         );
         assert_eq!(
@@ -734,7 +992,8 @@ and the third"#;
             Loc {
                 line: 1,
                 col: 1,
-                offset: 2
+                offset: 2,
+                file: None,
             },
         );
     }
@@ -776,10 +1035,38 @@ and the third"#;
         );
     }
 
+    #[test]
+    fn calls() {
+        let toks: Vec<_> = Tokenizer::new(r#"f(x) + @g(y, z) @ h"#).take(10).collect();
+        use Tok::*;
+        assert_eq!(
+            toks[..9]
+                .iter()
+                .map(|x| x.as_ref().unwrap().1.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                CallStart("f"),
+                Ident("x"),
+                RParen,
+                Add,
+                IndirectCallStart("g"),
+                Ident("y"),
+                Comma,
+                Ident("z"),
+                RParen,
+            ]
+        );
+        // An `@` that does not start an indirect call is an error, rather than the end of input.
+        let err = toks[9].as_ref().unwrap_err();
+        assert_eq!(err.desc, "unexpected character '@'");
+        assert_eq!(err.location.col, 16);
+    }
+
     #[test]
     fn literals() {
-        let toks =
-            lex_str(r#" x="\"hi\tthere\n"; b   =/hows it \/going/; x="重庆辣子鸡"; c= 1 / 3.5 "#);
+        let toks = lex_str(
+            r#" x="\"hi\tthere\n"; b   =/hows it \/going/; x="重庆辣子鸡"; c= 1 / 3.5; d = /a/i; e = /a/ix "#,
+        );
         use Tok::*;
         let s1 = "\\\"hi\\tthere\\n";
         let s2 = "hows it \\/going";
@@ -803,12 +1090,24 @@ and the third"#;
                 ILit("1"),
                 Div,
                 FLit("3.5"),
+                Semi,
+                Ident("d"),
+                Assign,
+                PatLit("a/i"),
+                Semi,
+                Ident("e"),
+                Assign,
+                PatLit("a"),
+                Ident("ix"),
+                Newline,
             ],
         );
         let mut buf = Vec::new();
         let a = Arena::default();
         assert_eq!(parse_string_literal(s1, &a, &mut buf), b"\"hi\tthere\n");
         assert_eq!(parse_regex_literal(s2, &a, &mut buf), b"hows it /going");
+        assert_eq!(parse_regex_literal(r#"ab\/i/i"#, &a, &mut buf), b"(?i)ab/i");
+        assert_eq!(parse_regex_literal(r#"ab\/i"#, &a, &mut buf), b"ab/i");
         assert_eq!(
             parse_string_literal(r#"are you there \77\x3f"#, &a, &mut buf),
             b"are you there ??"
@@ -822,4 +1121,37 @@ and the third"#;
             b"are you there ?\\xh"
         );
     }
+
+    #[test]
+    fn multiple_sources() {
+        let a = Arena::default();
+        let toks: Vec<_> = Tokenizer::with_includes(
+            &a,
+            Vec::new(),
+            &[(Some("a.awk"), "BEGIN\n"), (Some("b.awk"), "\n  x")],
+        )
+        .map(|x| x.ok().unwrap())
+        .collect();
+        let summary: Vec<_> = toks
+            .iter()
+            .map(|(l, tok, _)| (tok.clone(), l.file.as_deref(), l.line, l.col))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Tok::Begin, Some("a.awk"), 0, 0),
+                (Tok::Newline, Some("a.awk"), 0, 5),
+                (Tok::Newline, Some("b.awk"), 0, 0),
+                (Tok::Ident("x"), Some("b.awk"), 1, 2),
+                (Tok::Newline, Some("b.awk"), 1, 3),
+            ]
+        );
+        let err = Tokenizer::new(r#"@include "lib.awk""#)
+            .find_map(|x| x.err())
+            .unwrap();
+        assert_eq!(
+            err.desc,
+            "@include is only supported in programs run by frawk"
+        );
+    }
 }
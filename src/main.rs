@@ -40,6 +40,7 @@ use common::{CancelSignal, ExecutionStrategy, Stage};
 use runtime::{
     splitter::{
         batch::{ByteReader, CSVReader, InputFormat},
+        fixed::{FieldWidths, FixedWidthReader},
         regex::RegexSplitter,
     },
     ChainedReader, LineReader, CHUNK_SIZE,
@@ -48,6 +49,7 @@ use std::fs::File;
 use std::io::{self, BufReader, Write};
 use std::iter::once;
 use std::mem;
+use std::path::PathBuf;
 
 #[cfg(feature = "use_jemalloc")]
 #[global_allocator]
@@ -65,8 +67,11 @@ struct PreludeScalars {
     arbitrary_shell: bool,
     fold_regexes: bool,
     parse_header: bool,
+    char_semantics: bool,
+    strnum_compare: bool,
     escaper: Escaper,
     stage: Stage<()>,
+    shard_per_record: bool,
 }
 
 struct RawPrelude {
@@ -176,19 +181,37 @@ fn get_prelude<'a>(a: &'a Arena, raw: &RawPrelude) -> Prelude<'a> {
     }
 }
 
+// A piece of the program text, along with the file it was read from if it was passed with -f.
+type ProgramSource = (Option<String>, String);
+
+// The directories to search for files named by `@include`, from the AWKPATH environment variable.
+fn awk_path() -> Vec<PathBuf> {
+    std::env::var_os("AWKPATH")
+        .map(|paths| std::env::split_paths(&paths).collect())
+        .unwrap_or_default()
+}
+
 fn get_context<'a>(
-    prog: &str,
+    prog: &[ProgramSource],
     a: &'a Arena,
     mut prelude: Prelude<'a>,
 ) -> cfg::ProgramContext<'a, &'a str> {
-    let prog = a.alloc_str(prog);
-    let lexer = lexer::Tokenizer::new(prog);
+    let sources: Vec<_> = prog
+        .iter()
+        .map(|(file, text)| (file.as_deref(), a.alloc_str(text)))
+        .collect();
+    let lexer = lexer::Tokenizer::with_includes(a, awk_path(), &sources[..]);
     let mut buf = Vec::new();
     let parser = parsing::syntax::ProgParser::new();
     let mut prog = ast::Prog::from_stage(a, prelude.scalars.stage.clone());
     prog.argv = mem::take(&mut prelude.argv);
     let stmt = match parser.parse(a, &mut buf, &mut prog, lexer) {
         Ok(()) => {
+            if prelude.scalars.shard_per_record
+                && (!prog.beginfile.is_empty() || !prog.endfile.is_empty())
+            {
+                fail!("BEGINFILE and ENDFILE are not supported with -pr; try -pf instead");
+            }
             prog.field_sep = prelude.field_sep;
             prog.prelude_vardecs = prelude.var_decs;
             prog.output_sep = prelude.output_sep;
@@ -204,6 +227,8 @@ fn get_context<'a>(
         Ok(mut ctx) => {
             ctx.allow_arbitrary_commands = prelude.scalars.arbitrary_shell;
             ctx.fold_regex_constants = prelude.scalars.fold_regexes;
+            ctx.char_semantics = prelude.scalars.char_semantics;
+            ctx.strnum_compare = prelude.scalars.strnum_compare;
             ctx
         }
         Err(e) => fail!("failed to create program context: {}", e),
@@ -256,7 +281,7 @@ cfg_if::cfg_if! {
             }
         }
 
-        fn dump_llvm(prog: &str, cfg: codegen::Config, raw: &RawPrelude) -> String {
+        fn dump_llvm(prog: &[ProgramSource], cfg: codegen::Config, raw: &RawPrelude) -> String {
             let a = Arena::default();
             let mut ctx = get_context(prog, &a, get_prelude(&a, raw));
             match compile::dump_llvm(&mut ctx, cfg) {
@@ -270,7 +295,7 @@ cfg_if::cfg_if! {
 
 const DEFAULT_OPT_LEVEL: i32 = 3;
 
-fn dump_bytecode(prog: &str, raw: &RawPrelude) -> String {
+fn dump_bytecode(prog: &[ProgramSource], raw: &RawPrelude) -> String {
     use std::io::Cursor;
     let a = Arena::default();
     let mut ctx = get_context(prog, &a, get_prelude(&a, raw));
@@ -285,7 +310,7 @@ fn dump_bytecode(prog: &str, raw: &RawPrelude) -> String {
             ExecutionStrategy::Serial,
             Default::default(),
         )),
-        runtime::writers::default_factory(),
+        runtime::writers::default_factory(/*line_buffered=*/ false),
         /*num_workers=*/ 1,
     ) {
         Ok(ctx) => ctx,
@@ -319,7 +344,10 @@ fn main() {
              .about("the optimization level for the program. Positive levels determine the optimization level for LLVM. Level -1 forces bytecode interpretation")
              .possible_values(&["0", "1", "2", "3"]))
         .arg("--out-file=[FILE] 'the output file used in place of standard input'")
-        .arg("--utf8 'validate all input as UTF-8, returning an error if it is invalid'")
+        .arg("--line-buffered 'flush standard output after every line, rather than only when standard output is a terminal'")
+        .arg("--utf8 'validate all input as UTF-8, returning an error if it is invalid. Implies --chars'")
+        .arg("--chars 'length, substr, index, toupper and tolower operate on UTF-8 characters rather than bytes'")
+        .arg("--no-strnum 'compare input-derived strings using only their inferred types, skipping the runtime check for numeric-looking strings. Faster, but comparisons may differ from other awks'")
         .arg("--dump-cfg 'print untyped SSA form for input program'")
         .arg("--dump-bytecode 'print bytecode for input program'")
         .arg(Arg::new("parse-header")
@@ -330,8 +358,8 @@ fn main() {
         .arg(Arg::new("input-format")
              .long("input-format")
              .short('i')
-             .possible_values(&["csv", "tsv"])
-             .about("Input is split according to the rules of (csv|tsv). $0 contains the unescaped line. Assigning to columns does nothing."))
+             .takes_value(true)
+             .about("Input is split according to the rules of (csv|tsv). $0 contains the unescaped line. Assigning to columns does nothing. fixed:<widths> (e.g. fixed:5,2:10,*) splits each line into fixed-width fields, as with FIELDWIDTHS."))
         .arg(Arg::new("var")
              .short('v')
              .multiple_occurrences(true)
@@ -382,9 +410,19 @@ fn main() {
         }
     }
     let matches = app.get_matches();
+    let check_utf8 = matches.is_present("utf8");
+    let mut fixed_widths = None;
     let ifmt = match matches.value_of("input-format") {
         Some("csv") => Some(InputFormat::CSV),
         Some("tsv") => Some(InputFormat::TSV),
+        Some(x) if x.starts_with("fixed:") => {
+            let spec = &x["fixed:".len()..];
+            match FieldWidths::parse(spec.as_bytes(), check_utf8) {
+                Ok(widths) => fixed_widths = Some((spec, widths)),
+                Err(e) => fail!("invalid input format: {}", e),
+            }
+            None
+        }
         Some(x) => fail!("invalid input format: {}", x),
         None => None,
     };
@@ -407,6 +445,16 @@ fn main() {
     } else {
         CHUNK_SIZE
     };
+    let line_buffered = matches.is_present("line-buffered");
+    // With --line-buffered, records are processed as soon as they are read, rather than once a
+    // full chunk of input is available, so that output keeps up with interactive input.
+    let regex_splitter = |r: Box<dyn io::Read + Send>, name: String| {
+        if line_buffered {
+            RegexSplitter::new_interactive(r, chunk_size, name, check_utf8)
+        } else {
+            RegexSplitter::new(r, chunk_size, name, check_utf8)
+        }
+    };
     let num_workers = match matches.value_of("jobs") {
         Some(s) => match s.parse::<usize>() {
             Ok(u) => u,
@@ -428,26 +476,23 @@ fn main() {
         .values_of("input-files")
         .map(|x| x.map(String::from).collect())
         .unwrap_or_else(Vec::new);
-    let program_string = {
+    let program_sources: Vec<ProgramSource> = {
         if let Some(pfiles) = matches.values_of("program-file") {
             // We specified a file on the command line, so the "program" will be
             // interpreted as another input file.
             if let Some(p) = matches.value_of("program") {
                 input_files.insert(0, p.into());
             }
-            let mut prog = String::new();
+            let mut prog = Vec::new();
             for pfile in pfiles {
                 match std::fs::read_to_string(pfile) {
-                    Ok(p) => {
-                        prog.push_str(p.as_str());
-                        prog.push_str("\n");
-                    }
+                    Ok(p) => prog.push((Some(String::from(pfile)), p)),
                     Err(e) => fail!("failed to read program from {}: {}", pfile, e),
                 }
             }
             prog
         } else if let Some(p) = matches.value_of("program") {
-            String::from(p)
+            vec![(None, String::from(p))]
         } else {
             fail!("must specify program at command line, or in a file via -f");
         }
@@ -463,6 +508,8 @@ fn main() {
     };
     let arbitrary_shell = matches.is_present("arbitrary-shell");
     let parse_header = matches.is_present("parse-header");
+    let char_semantics = check_utf8 || matches.is_present("chars");
+    let strnum_compare = !matches.is_present("no-strnum");
 
     let opt_level: i32 = match matches.value_of("opt-level") {
         Some("3") => 3,
@@ -475,17 +522,29 @@ fn main() {
     };
     let raw = RawPrelude {
         field_sep: matches.value_of("field-separator").map(String::from),
-        var_decs: matches
-            .values_of("var")
-            .map(|x| x.map(String::from).collect())
-            .unwrap_or_else(Vec::new),
+        // Fixed-width input also sets FIELDWIDTHS, so that records assigned to $0 are split
+        // the same way.
+        var_decs: fixed_widths
+            .iter()
+            .map(|(spec, _)| format!("FIELDWIDTHS={}", spec))
+            .chain(
+                matches
+                    .values_of("var")
+                    .into_iter()
+                    .flatten()
+                    .map(String::from),
+            )
+            .collect(),
         output_sep,
         scalars: PreludeScalars {
             escaper,
             arbitrary_shell,
             fold_regexes: opt_level >= 3,
             stage: exec_strategy.stage(),
+            shard_per_record: matches!(exec_strategy, ExecutionStrategy::ShardPerRecord),
             parse_header,
+            char_semantics,
+            strnum_compare,
         },
         output_record_sep,
         argv,
@@ -503,7 +562,7 @@ fn main() {
                 let _ = write!(
                     std::io::stdout(),
                     "{}",
-                    dump_llvm(program_string.as_str(), config, &raw),
+                    dump_llvm(&program_sources[..], config, &raw),
                 );
             }
         } else {
@@ -515,27 +574,42 @@ fn main() {
         let _ = write!(
             std::io::stdout(),
             "{}",
-            dump_bytecode(program_string.as_str(), &raw),
+            dump_bytecode(&program_sources[..], &raw),
         );
     }
     if opt_dump_cfg {
         let a = Arena::default();
-        let ctx = get_context(program_string.as_str(), &a, get_prelude(&a, &raw));
+        let ctx = get_context(&program_sources[..], &a, get_prelude(&a, &raw));
         let mut stdout = std::io::stdout();
         let _ = ctx.dbg_print(&mut stdout);
     }
     if skip_output {
         return;
     }
-    let check_utf8 = matches.is_present("utf8");
     let signal = CancelSignal::default();
 
     // This horrid macro is here because all of the different ways of reading input are different
     // types, making functions hard to write. Still, there must be something to be done to clean
     // this up here.
     macro_rules! with_inp {
-        ($analysis:expr, $inp:ident, $body:expr) => {{
-            if input_files.len() == 0 {
+        ($analysis:expr, $file_events:expr, $inp:ident, $body:expr) => {{
+            if let Some((_, widths)) = fixed_widths {
+                if input_files.len() == 0 {
+                    let _reader: Box<dyn io::Read + Send> = Box::new(io::stdin());
+                    let $inp = chained(FixedWidthReader::new(
+                        _reader, widths, chunk_size, "-", check_utf8,
+                    ));
+                    $body
+                } else {
+                    let iter = input_files.iter().cloned().map(|file| {
+                        let reader: Box<dyn io::Read + Send> =
+                            Box::new(open_file_read(file.as_str()));
+                        FixedWidthReader::new(reader, widths.clone(), chunk_size, file, check_utf8)
+                    });
+                    let $inp = ChainedReader::new(iter);
+                    $body
+                }
+            } else if input_files.len() == 0 {
                 let _reader: Box<dyn io::Read + Send> = Box::new(io::stdin());
                 match (ifmt, $analysis) {
                     (Some(ifmt), _) => {
@@ -581,14 +655,12 @@ fn main() {
                                 $body
                             }
                         } else {
-                            let $inp =
-                                chained(RegexSplitter::new(_reader, chunk_size, "-", check_utf8));
+                            let $inp = chained(regex_splitter(_reader, String::from("-")));
                             $body
                         }
                     }
                     (None, cfg::SepAssign::Unsure) => {
-                        let $inp =
-                            chained(RegexSplitter::new(_reader, chunk_size, "-", check_utf8));
+                        let $inp = chained(regex_splitter(_reader, String::from("-")));
                         $body
                     }
                 }
@@ -598,15 +670,31 @@ fn main() {
                     .cloned()
                     .map(|file| (open_file_read(file.as_str()), file))
                     .collect();
-                let $inp = CSVReader::new(
-                    file_handles.into_iter(),
-                    ifmt,
-                    chunk_size,
-                    check_utf8,
-                    exec_strategy,
-                    signal.clone(),
-                );
-                $body
+                if $file_events {
+                    // BEGINFILE and ENDFILE need a reader that stops at the end of each file.
+                    let iter = file_handles.into_iter().map(|handle| {
+                        CSVReader::new(
+                            once(handle),
+                            ifmt,
+                            chunk_size,
+                            check_utf8,
+                            exec_strategy,
+                            signal.clone(),
+                        )
+                    });
+                    let $inp = ChainedReader::new(iter);
+                    $body
+                } else {
+                    let $inp = CSVReader::new(
+                        file_handles.into_iter(),
+                        ifmt,
+                        chunk_size,
+                        check_utf8,
+                        exec_strategy,
+                        signal.clone(),
+                    );
+                    $body
+                }
             } else {
                 match $analysis {
                     cfg::SepAssign::Potential {
@@ -646,7 +734,7 @@ fn main() {
                             let iter = input_files.iter().cloned().map(|file| {
                                 let reader: Box<dyn io::Read + Send> =
                                     Box::new(open_file_read(file.as_str()));
-                                RegexSplitter::new(reader, chunk_size, file, check_utf8)
+                                regex_splitter(reader, file)
                             });
                             let $inp = ChainedReader::new(iter);
                             $body
@@ -656,7 +744,7 @@ fn main() {
                         let iter = input_files.iter().cloned().map(|file| {
                             let reader: Box<dyn io::Read + Send> =
                                 Box::new(open_file_read(file.as_str()));
-                            RegexSplitter::new(reader, chunk_size, file, check_utf8)
+                            regex_splitter(reader, file)
                         });
                         let $inp = ChainedReader::new(iter);
                         $body
@@ -667,20 +755,27 @@ fn main() {
     }
 
     let a = Arena::default();
-    let ctx = get_context(program_string.as_str(), &a, get_prelude(&a, &raw));
-    let analysis_result = ctx.analyze_sep_assignments();
+    let ctx = get_context(&program_sources[..], &a, get_prelude(&a, &raw));
+    // The batched readers wait for a full chunk of input, so --line-buffered uses the regex
+    // splitter.
+    let analysis_result = if line_buffered {
+        cfg::SepAssign::Unsure
+    } else {
+        ctx.analyze_sep_assignments()
+    };
+    let file_events = ctx.file_events;
     let out_file = matches.value_of("out-file");
     macro_rules! with_io {
         (|$inp:ident, $out:ident| $body:expr) => {
             match out_file {
                 Some(oup) => {
-                    let $out = runtime::writers::factory_from_file(oup)
+                    let $out = runtime::writers::factory_from_file(oup, line_buffered)
                         .unwrap_or_else(|e| fail!("failed to open {}: {}", oup, e));
-                    with_inp!(analysis_result, $inp, $body);
+                    with_inp!(analysis_result, file_events, $inp, $body);
                 }
                 None => {
-                    let $out = runtime::writers::default_factory();
-                    with_inp!(analysis_result, $inp, $body);
+                    let $out = runtime::writers::default_factory(line_buffered);
+                    with_inp!(analysis_result, file_events, $inp, $body);
                 }
             }
         };
@@ -1,8 +1,91 @@
-use std::io;
-use std::process::{ChildStdin, ChildStdout, Command, Stdio};
+use std::io::{self, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, ExitStatus, Stdio};
+use std::sync::Mutex;
+
+use hashbrown::HashMap;
+use lazy_static::lazy_static;
 
 use crate::runtime::Int;
 
+lazy_static! {
+    // Coprocesses (started with `|&`) are shared by the thread writing to them and the one reading
+    // from them. Whichever side asks for a coprocess first starts it, and leaves the other end
+    // here for the other side to claim.
+    static ref COPROCESSES: Mutex<HashMap<Box<[u8]>, Coprocess>> = Default::default();
+}
+
+#[derive(Default)]
+struct Coprocess {
+    // The ends of the coprocess that have not been claimed yet.
+    stdin: Option<ChildStdin>,
+    stdout: Option<ChildStdout>,
+    // Set once the corresponding end has been closed with `close`.
+    stdin_closed: bool,
+    stdout_closed: bool,
+    child: Option<Child>,
+}
+
+/// The standard input of a command that frawk is writing to.
+///
+/// For commands started with `|`, this also owns the child process, so closing it can wait for
+/// the command to exit. Coprocesses are instead waited on by `close_coprocess`, once both of their
+/// ends are closed.
+pub struct CommandInput {
+    stdin: ChildStdin,
+    child: Option<Child>,
+}
+
+impl CommandInput {
+    /// Close the command's standard input, returning its exit status if this is a `|` command.
+    pub fn close(self) -> io::Result<Option<Int>> {
+        let CommandInput { stdin, child } = self;
+        drop(stdin);
+        match child {
+            Some(mut child) => Ok(Some(exit_code(child.wait()?))),
+            None => Ok(None),
+        }
+    }
+}
+
+impl Write for CommandInput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stdin.write(buf)
+    }
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.stdin.write_all(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdin.flush()
+    }
+}
+
+/// The output of a command that frawk is reading from (`cmd | getline`).
+pub struct CommandOutput {
+    pub stdout: ChildStdout,
+    pub child: Child,
+}
+
+/// The value `close` returns for a command that has exited: its exit code, or 256 plus the
+/// signal that killed it (following gawk).
+fn exit_code(status: ExitStatus) -> Int {
+    if let Some(code) = status.code() {
+        return Int::from(code);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(sig) = status.signal() {
+            return 256 + Int::from(sig);
+        }
+    }
+    -1
+}
+
+/// Wait for `child` to exit, once its output has been closed.
+pub fn wait_command(mut child: Child) -> io::Result<Int> {
+    Ok(exit_code(child.wait()?))
+}
+
 fn prepare_command(bs: &[u8]) -> io::Result<Command> {
     let prog = match std::str::from_utf8(bs) {
         Ok(s) => s,
@@ -33,14 +116,86 @@ pub fn run_command(bs: &[u8]) -> Int {
     }
 }
 
-pub fn command_for_write(bs: &[u8]) -> io::Result<ChildStdin> {
+pub fn command_for_write(bs: &[u8]) -> io::Result<CommandInput> {
     let mut cmd = prepare_command(bs)?;
     let mut child = cmd.stdin(Stdio::piped()).stdout(Stdio::inherit()).spawn()?;
-    Ok(child.stdin.take().unwrap())
+    Ok(CommandInput {
+        stdin: child.stdin.take().unwrap(),
+        child: Some(child),
+    })
 }
 
-pub fn command_for_read(bs: &[u8]) -> io::Result<ChildStdout> {
+pub fn command_for_read(bs: &[u8]) -> io::Result<CommandOutput> {
     let mut cmd = prepare_command(bs)?;
     let mut child = cmd.stdin(Stdio::inherit()).stdout(Stdio::piped()).spawn()?;
-    Ok(child.stdout.take().unwrap())
+    Ok(CommandOutput {
+        stdout: child.stdout.take().unwrap(),
+        child,
+    })
+}
+
+fn claim_coprocess<T>(
+    bs: &[u8],
+    mut take: impl FnMut(&mut Coprocess) -> Option<T>,
+) -> io::Result<T> {
+    let mut coprocs = COPROCESSES.lock().unwrap();
+    if let Some(coproc) = coprocs.get_mut(bs) {
+        // This end has already been claimed and closed, but the other end is still open. Like
+        // gawk, we do not restart the coprocess until it has been closed completely.
+        return take(coproc).ok_or_else(|| {
+            io::Error::other("attempt to reopen a closed end of a coprocess that is still running")
+        });
+    }
+    let mut child = prepare_command(bs)?
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let mut coproc = Coprocess {
+        stdin: child.stdin.take(),
+        stdout: child.stdout.take(),
+        child: Some(child),
+        ..Default::default()
+    };
+    let end = take(&mut coproc).unwrap();
+    coprocs.insert(bs.into(), coproc);
+    Ok(end)
+}
+
+pub fn coprocess_for_write(bs: &[u8]) -> io::Result<CommandInput> {
+    let stdin = claim_coprocess(bs, |c| c.stdin.take())?;
+    Ok(CommandInput { stdin, child: None })
+}
+
+pub fn coprocess_for_read(bs: &[u8]) -> io::Result<ChildStdout> {
+    claim_coprocess(bs, |c| c.stdout.take())
+}
+
+/// Record that the input (`to`) and/or output (`from`) of the coprocess `bs` have been closed.
+/// Once both ends are closed, the coprocess is waited on and its exit status is returned.
+/// Returns `None` if the coprocess is still running, or if none is running under that name.
+pub fn close_coprocess(bs: &[u8], to: bool, from: bool) -> io::Result<Option<Int>> {
+    let child = {
+        let mut coprocs = COPROCESSES.lock().unwrap();
+        let coproc = match coprocs.get_mut(bs) {
+            Some(coproc) => coproc,
+            None => return Ok(None),
+        };
+        if to {
+            coproc.stdin = None;
+            coproc.stdin_closed = true;
+        }
+        if from {
+            coproc.stdout = None;
+            coproc.stdout_closed = true;
+        }
+        if !(coproc.stdin_closed && coproc.stdout_closed) {
+            return Ok(None);
+        }
+        // Release the lock before waiting, so other coprocesses can start in the meantime.
+        coprocs.remove(bs).and_then(|c| c.child)
+    };
+    match child {
+        Some(child) => wait_command(child).map(Some),
+        None => Ok(None),
+    }
 }
@@ -75,6 +75,27 @@ pub fn strtod(bs: &[u8]) -> f64 {
     }
 }
 
+/// Parse `bs` as a number if the whole string looks like one, in the sense of POSIX's "numeric
+/// string": optional leading and trailing blanks around a decimal integer or floating-point
+/// number. Hexadecimal constants, "inf" and "nan" are not considered numeric.
+pub fn parse_numeric(bs: &[u8]) -> Option<f64> {
+    let start = bs.iter().position(|b| !b.is_ascii_whitespace())?;
+    let end = bs.iter().rposition(|b| !b.is_ascii_whitespace())? + 1;
+    let bs = &bs[start..end];
+    let digits = match bs[0] {
+        b'+' | b'-' => &bs[1..],
+        _ => bs,
+    };
+    match digits.first() {
+        Some(b'0'..=b'9') | Some(b'.') => {}
+        _ => return None,
+    }
+    match fast_float::parse_partial(bs) {
+        Ok((f, len)) if len == bs.len() => Some(f),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -94,4 +115,18 @@ mod tests {
         assert_eq!(strtod(imax.as_bytes()), i64::max_value() as f64);
         assert_eq!(strtod(imin.as_bytes()), i64::min_value() as f64);
     }
+
+    #[test]
+    fn numeric_strings() {
+        assert_eq!(parse_numeric(b"10"), Some(10.0));
+        assert_eq!(parse_numeric(b"  -1.5e3 \t"), Some(-1500.0));
+        assert_eq!(parse_numeric(b"+.5"), Some(0.5));
+        assert_eq!(parse_numeric(b"10abc"), None);
+        assert_eq!(parse_numeric(b"abc"), None);
+        assert_eq!(parse_numeric(b""), None);
+        assert_eq!(parse_numeric(b"   "), None);
+        assert_eq!(parse_numeric(b"-"), None);
+        assert_eq!(parse_numeric(b"inf"), None);
+        assert_eq!(parse_numeric(b"0x1A"), None);
+    }
 }
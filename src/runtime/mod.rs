@@ -1,22 +1,24 @@
-use crate::common::{FileSpec, Result};
+use crate::common::{FileSpec, InputSpec, Result};
 use hashbrown::HashMap;
-use regex::bytes::Regex;
+use regex::bytes::{Regex, RegexBuilder, RegexSet, RegexSetBuilder};
 use std::cell::{Cell, RefCell};
 use std::fs::File;
 use std::hash::Hash;
 use std::io;
 use std::iter::FromIterator;
 use std::mem;
-use std::process::ChildStdout;
+use std::process::{Child, ChildStdout};
 use std::rc::Rc;
 use std::str;
 
 mod command;
 pub mod float_parse;
 pub mod printf;
+pub(crate) mod sort;
 pub mod splitter;
 pub mod str_impl;
 pub mod string_search;
+pub mod time;
 pub mod utf8;
 pub mod writers;
 
@@ -26,25 +28,191 @@ use splitter::regex::RegexSplitter;
 // TODO: remove the pub use for Variables here.
 pub(crate) use crate::builtins::Variables;
 pub use command::run_command;
-pub(crate) use float_parse::{hextoi, strtod, strtoi};
+pub(crate) use float_parse::{hextoi, parse_numeric, strtod, strtoi};
 pub(crate) use printf::FormatArg;
 pub use splitter::{
     batch::{escape_csv, escape_tsv},
-    ChainedReader, Line, LineReader,
+    ChainedReader, FieldSplit, Line, LineReader,
 };
 pub use str_impl::{Str, UniqueStr};
 
 #[derive(Default)]
-pub struct RegexCache(Registry<Regex>);
+pub struct RegexCache {
+    cache: Registry<Regex>,
+    // Compiled case labels for `switch` statements, keyed by their encoding.
+    cases: Registry<CaseTable>,
+    // Set when IGNORECASE is nonzero.
+    ignore_case: bool,
+}
+
+/// Encode the labels of a `switch` statement as a single string that can be passed to
+/// [`RegexCache::match_cases`]. Each label is either a regex or a string to compare against
+/// exactly, and is written as a tag byte (`r` or `s`), its length in decimal, a `:`, and then the
+/// label itself.
+pub(crate) fn encode_cases<'a>(
+    cases: impl Iterator<Item = (bool /* is_regex */, &'a [u8])>,
+) -> Vec<u8> {
+    let mut res = Vec::new();
+    for (is_regex, label) in cases {
+        res.push(if is_regex { b'r' } else { b's' });
+        res.extend(label.len().to_string().as_bytes());
+        res.push(b':');
+        res.extend(label);
+    }
+    res
+}
+
+// The labels of a `switch` statement: string labels are looked up in a hash table, and all
+// regex labels are matched at once with a RegexSet.
+struct CaseTable {
+    // The first case with a given string label. Labels are lowercased if `ignore_case` is set.
+    strs: HashMap<Vec<u8>, Int>,
+    ignore_case: bool,
+    regexes: RegexSet,
+    // The case index for each pattern in `regexes`.
+    regex_cases: Vec<Int>,
+}
+
+impl CaseTable {
+    fn new(spec: &str, ignore_case: bool) -> Result<CaseTable> {
+        let mut strs = HashMap::new();
+        let mut pats = Vec::new();
+        let mut regex_cases = Vec::new();
+        let mut rest = spec;
+        let mut case: Int = 0;
+        while !rest.is_empty() {
+            let tag = rest.as_bytes()[0];
+            let colon = match rest.find(':') {
+                Some(i) => i,
+                None => return err!("malformed case labels: {:?}", spec),
+            };
+            let len: usize = match rest[1..colon].parse() {
+                Ok(len) if colon + 1 + len <= rest.len() => len,
+                _ => return err!("malformed case labels: {:?}", spec),
+            };
+            let label = &rest[colon + 1..colon + 1 + len];
+            match tag {
+                b'r' => {
+                    pats.push(label);
+                    regex_cases.push(case);
+                }
+                b's' => {
+                    let label = if ignore_case {
+                        label.to_lowercase()
+                    } else {
+                        label.to_string()
+                    };
+                    strs.entry(label.into_bytes()).or_insert(case);
+                }
+                _ => return err!("malformed case labels: {:?}", spec),
+            }
+            rest = &rest[colon + 1 + len..];
+            case += 1;
+        }
+        let regexes = match RegexSetBuilder::new(pats)
+            .case_insensitive(ignore_case)
+            .build()
+        {
+            Ok(r) => r,
+            Err(e) => return err!("{}", e),
+        };
+        Ok(CaseTable {
+            strs,
+            ignore_case,
+            regexes,
+            regex_cases,
+        })
+    }
+
+    fn lookup(&self, s: &Str) -> Int {
+        let by_str = if self.ignore_case {
+            s.to_lower().with_bytes(|bs| self.strs.get(bs).cloned())
+        } else {
+            s.with_bytes(|bs| self.strs.get(bs).cloned())
+        };
+        let by_regex = if self.regex_cases.is_empty() {
+            None
+        } else {
+            // Patterns are added in case order, so the first match is the earliest case.
+            s.with_bytes(|bs| {
+                self.regexes
+                    .matches(bs)
+                    .iter()
+                    .next()
+                    .map(|i| self.regex_cases[i])
+            })
+        };
+        match (by_str, by_regex) {
+            (Some(x), Some(y)) => x.min(y),
+            (Some(x), None) | (None, Some(x)) => x,
+            (None, None) => -1,
+        }
+    }
+}
+
+/// A regex constant compiled ahead of time. Programs that assign IGNORECASE also get a
+/// case-insensitive copy of the pattern, which is used while IGNORECASE is nonzero.
+pub(crate) struct ConstRegex {
+    re: Regex,
+    re_ignore_case: Option<Regex>,
+}
+
+impl std::fmt::Debug for ConstRegex {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.re.fmt(f)
+    }
+}
+
+impl ConstRegex {
+    pub(crate) fn new(
+        pat: &str,
+        with_ignore_case: bool,
+    ) -> std::result::Result<Self, regex::Error> {
+        let re = Regex::new(pat)?;
+        let re_ignore_case = if with_ignore_case {
+            Some(RegexBuilder::new(pat).case_insensitive(true).build()?)
+        } else {
+            None
+        };
+        Ok(ConstRegex { re, re_ignore_case })
+    }
+
+    /// The compiled pattern to use given the current value of IGNORECASE.
+    pub(crate) fn get(&self, ignore_case: bool) -> &Regex {
+        match &self.re_ignore_case {
+            Some(re) if ignore_case => re,
+            _ => &self.re,
+        }
+    }
+}
 
 impl RegexCache {
+    /// Compile subsequent patterns case-insensitively if `ignore_case` is set. Patterns compiled
+    /// under the previous setting are discarded if it changes.
+    pub(crate) fn set_ignore_case(&mut self, ignore_case: bool) {
+        if self.ignore_case != ignore_case {
+            self.ignore_case = ignore_case;
+            self.cache = Default::default();
+            self.cases = Default::default();
+        }
+    }
+
+    pub(crate) fn ignore_case(&self) -> bool {
+        self.ignore_case
+    }
+
+    fn compile(s: &str, ignore_case: bool) -> Result<Regex> {
+        match RegexBuilder::new(s).case_insensitive(ignore_case).build() {
+            Ok(r) => Ok(r),
+            Err(e) => err!("{}", e),
+        }
+    }
+
     pub(crate) fn with_regex<T>(&mut self, pat: &Str, mut f: impl FnMut(&Regex) -> T) -> Result<T> {
-        self.0.get(
+        let ignore_case = self.ignore_case;
+        self.cache.get(
             pat,
-            |s| match Regex::new(s) {
-                Ok(r) => Ok(r),
-                Err(e) => err!("{}", e),
-            },
+            |s| Self::compile(s, ignore_case),
             // eta-expansion required to get this compiling..
             |x| f(x),
         )
@@ -54,35 +222,46 @@ impl RegexCache {
         pat: &Str,
         mut f: impl FnMut(&Regex) -> Result<T>,
     ) -> Result<T> {
-        self.0.get_fallible(
+        let ignore_case = self.ignore_case;
+        self.cache.get_fallible(
             pat,
-            |s| match Regex::new(s) {
-                Ok(r) => Ok(r),
-                Err(e) => err!("{}", e),
-            },
+            |s| Self::compile(s, ignore_case),
             // eta-expansion required to get this compiling..
             |x| f(x),
         )
     }
 
+    /// The index of the first case matching `s` among those encoded in `cases` (see
+    /// [`encode_cases`]), or -1 if none match.
+    pub(crate) fn match_cases(&mut self, cases: &Str, s: &Str) -> Result<Int> {
+        let ignore_case = self.ignore_case;
+        self.cases.get(
+            cases,
+            |spec| CaseTable::new(spec, ignore_case),
+            |table| table.lookup(s),
+        )
+    }
+
     pub(crate) fn get_line<'a, LR: LineReader>(
         &mut self,
         file: &Str<'a>,
         pat: &Str<'a>,
         reg: &mut FileRead<LR>,
-        is_file: bool,
+        spec: InputSpec,
     ) -> Result<Str<'a>> {
-        Ok(if is_file {
-            reg.with_file(file, |reader| {
-                self.with_regex(pat, |re| reader.read_line_regex(re))
-            })?
-        } else {
-            reg.with_cmd(file, |reader| {
-                self.with_regex(pat, |re| reader.read_line_regex(re))
-            })?
-        }
-        .clone()
-        .upcast())
+        let (line, terminator) = match spec {
+            InputSpec::File => reg.with_file(file, |reader| {
+                Ok((reader.read_record(pat, self)?, reader.record_terminator()))
+            })?,
+            InputSpec::Cmd => reg.with_cmd(file, |reader| {
+                Ok((reader.read_record(pat, self)?, reader.record_terminator()))
+            })?,
+            InputSpec::Coproc => reg.with_coproc(file, |reader| {
+                Ok((reader.read_record(pat, self)?, reader.record_terminator()))
+            })?,
+        };
+        reg.last_terminator = Some(terminator);
+        Ok(line.upcast())
     }
 
     // This only gets used if getline is invoked explicitly without an input file argument.
@@ -91,10 +270,15 @@ impl RegexCache {
         pat: &Str<'a>,
         reg: &mut FileRead<LR>,
     ) -> Result<(/* file changed */ bool, Str<'a>)> {
+        reg.last_terminator = None;
         let (changed, mut line) = reg.stdin.read_line(pat, self)?;
         // NB both of these `pat`s are "wrong" but we are fine because they are only used
         // when the column is nonzero, or someone has overwritten a nonzero column.
-        Ok((changed, line.get_col(0, pat, pat, self)?.clone().upcast()))
+        let split = FieldSplit::Sep(pat.clone());
+        Ok((
+            changed,
+            line.get_col(0, &split, pat, self)?.clone().upcast(),
+        ))
     }
     pub(crate) fn get_line_stdin_reuse<'a, LR: LineReader>(
         &mut self,
@@ -102,8 +286,59 @@ impl RegexCache {
         reg: &mut FileRead<LR>,
         old_line: &mut LR::Line,
     ) -> Result</*file changed */ bool> {
+        reg.last_terminator = None;
         reg.stdin.read_line_reuse(pat, self, old_line)
     }
+
+    /// Read the next record from the main input into `line`, unless an input file starts or ends
+    /// first. Returns RECORD_EVENT, BEGINFILE_EVENT or ENDFILE_EVENT (or the read state, if the
+    /// input is exhausted or there was an error), along with whether FILENAME and FNR should be
+    /// reset.
+    ///
+    /// Records are read without moving on to the next file. ENDFILE is reported when the current
+    /// file runs out, and BEGINFILE when `next_file` switches to a new one, so both run for empty
+    /// files too. ENDFILE still sees the last record of its file, and BEGINFILE sees the new
+    /// FILENAME but no record from it.
+    pub(crate) fn get_line_stdin_event<'a, LR: LineReader>(
+        &mut self,
+        pat: &Str<'a>,
+        reg: &mut FileRead<LR>,
+        line: &mut LR::Line,
+    ) -> Result<(Int, /* file changed */ bool)> {
+        reg.last_terminator = None;
+        match reg.file_event {
+            FileEvent::Between => {
+                reg.file_event = FileEvent::Begin;
+                return Ok((BEGINFILE_EVENT, true));
+            }
+            FileEvent::Skipped => {
+                reg.file_event = FileEvent::End;
+                return Ok((ENDFILE_EVENT, false));
+            }
+            FileEvent::End => {
+                if !reg.stdin.next_file()? {
+                    return Ok((reg.stdin.read_state(), false));
+                }
+                reg.file_event = FileEvent::Begin;
+                return Ok((BEGINFILE_EVENT, true));
+            }
+            FileEvent::Begin | FileEvent::InFile => {}
+        }
+        reg.stdin
+            .read_line_in_file(pat, self, &mut reg.pending_line)?;
+        match reg.stdin.read_state() {
+            1 => {
+                mem::swap(line, &mut reg.pending_line);
+                reg.file_event = FileEvent::InFile;
+                Ok((RECORD_EVENT, false))
+            }
+            0 => {
+                reg.file_event = FileEvent::End;
+                Ok((ENDFILE_EVENT, false))
+            }
+            state => Ok((state, false)),
+        }
+    }
     fn split_internal<'a>(
         &mut self,
         pat: &Str,
@@ -112,20 +347,7 @@ impl RegexCache {
         mut push: impl FnMut(Str<'a>),
     ) -> Result<()> {
         if pat == &Str::from(" ") {
-            self.with_regex(&Str::from(r#"[ \t]+"#), |re| {
-                s.split(
-                    re,
-                    |s, is_empty| {
-                        if !is_empty {
-                            push(s);
-                            1
-                        } else {
-                            0
-                        }
-                    },
-                    used_fields,
-                )
-            })
+            self.split_whitespace(r#"[ \t]+"#, s, used_fields, push)
         } else {
             self.with_regex(pat, |re| {
                 s.split(
@@ -139,14 +361,87 @@ impl RegexCache {
             })
         }
     }
-    pub(crate) fn split_regex<'a>(
+    // Split `s` on runs of `ws`, dropping the empty fields at either end.
+    fn split_whitespace<'a>(
         &mut self,
-        pat: &Str,
+        ws: &str,
+        s: &Str<'a>,
+        used_fields: &FieldSet,
+        mut push: impl FnMut(Str<'a>),
+    ) -> Result<()> {
+        self.with_regex(&Str::from(ws), |re| {
+            s.split(
+                re,
+                |s, is_empty| {
+                    if !is_empty {
+                        push(s);
+                        1
+                    } else {
+                        0
+                    }
+                },
+                used_fields,
+            )
+        })
+    }
+    pub(crate) fn split_fields<'a>(
+        &mut self,
+        split: &FieldSplit,
         s: &Str<'a>,
         used_fields: &FieldSet,
         v: &mut Vec<Str<'a>>,
     ) -> Result<()> {
-        self.split_internal(pat, s, used_fields, |s| v.push(s))
+        match split {
+            FieldSplit::Sep(pat) => self.split_internal(pat, s, used_fields, |s| v.push(s)),
+            FieldSplit::Whitespace => {
+                self.split_whitespace(r#"[ \t\n]+"#, s, used_fields, |s| v.push(s))
+            }
+            FieldSplit::Pat(pat) => self.with_regex(pat, |re| {
+                s.split_pattern(re, |_, field| v.push(field), used_fields);
+            }),
+            FieldSplit::Widths(widths) => {
+                widths.split(s, used_fields, |field| v.push(field));
+                Ok(())
+            }
+        }
+    }
+
+    /// Implements `patsplit`: the successive matches of `pat` in `s` are stored in `m`, and the
+    /// text around them is stored in `seps`. As in gawk, `seps[i]` holds the text between
+    /// `m[i]` and `m[i+1]`, while `seps[0]` and `seps[n]` hold any leading or trailing text.
+    /// Returns the number of fields.
+    pub(crate) fn pattern_split_intmap<'a>(
+        &mut self,
+        pat: &Str<'a>,
+        s: &Str<'a>,
+        m: &IntMap<Str<'a>>,
+        seps: &IntMap<Str<'a>>,
+    ) -> Result<Int> {
+        if Rc::ptr_eq(&m.0, &seps.0) {
+            return err!("patsplit: cannot use the same array for the fields and separators");
+        }
+        let mut i = 0i64;
+        let mut m_b = m.0.borrow_mut();
+        let mut seps_b = seps.0.borrow_mut();
+        m_b.clear();
+        seps_b.clear();
+        self.with_regex(pat, |re| {
+            let trailing = s.split_pattern(
+                re,
+                |sep, field| {
+                    if i > 0 || !sep.is_empty() {
+                        seps_b.insert(i, sep);
+                    }
+                    i += 1;
+                    m_b.insert(i, field);
+                },
+                &FieldSet::all(),
+            );
+            if !trailing.is_empty() {
+                seps_b.insert(i, trailing);
+            }
+            i
+        })
     }
 
     pub(crate) fn split_regex_intmap<'a>(
@@ -193,6 +488,68 @@ impl RegexCache {
         vars.store_int(Variable::RLENGTH, len)?;
         Ok(start)
     }
+    /// Like `regex_const_match_loc`, but also passes the (1-indexed) location and contents of
+    /// the entire match (group 0) and each participating capture group to `f`.
+    fn regex_const_match_groups<'a>(
+        vars: &mut Variables,
+        re: &Regex,
+        s: &Str<'a>,
+        mut f: impl FnMut(Int, Int, Int, Str<'a>),
+    ) -> Result<Int> {
+        use crate::builtins::Variable;
+        use smallvec::SmallVec;
+        let groups: SmallVec<[(usize, usize, usize); 4]> =
+            s.with_bytes(|bs| match re.captures(bs) {
+                Some(caps) => caps
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, m)| m.map(|m| (i, m.start(), m.end())))
+                    .collect(),
+                None => Default::default(),
+            });
+        let (start, len) = match groups.first() {
+            Some((_, start, end)) => (*start as Int + 1, (end - start) as Int),
+            None => (0, -1),
+        };
+        for (i, start, end) in groups.into_iter() {
+            f(
+                i as Int,
+                start as Int + 1,
+                (end - start) as Int,
+                s.slice(start, end),
+            );
+        }
+        vars.store_int(Variable::RSTART, start)?;
+        vars.store_int(Variable::RLENGTH, len)?;
+        Ok(start)
+    }
+
+    /// The 3-argument form of `match`, where capture groups are stored in a string-keyed map.
+    /// Like gawk, we also store the start and length of each group at keys `n SUBSEP "start"`
+    /// and `n SUBSEP "length"`.
+    pub(crate) fn regex_match_loc_strmap<'a>(
+        &mut self,
+        vars: &mut Variables<'a>,
+        pat: &Str<'a>,
+        s: &Str<'a>,
+        m: &StrMap<'a, Str<'a>>,
+    ) -> Result<Int> {
+        let mut m_b = m.0.borrow_mut();
+        m_b.clear();
+        let subsep = vars.subsep.clone();
+        self.with_regex_fallible(pat, |re| {
+            Self::regex_const_match_groups(vars, re, s, |i, start, len, text| {
+                let key = Str::concat(convert::<Int, Str>(i), subsep.clone());
+                m_b.insert(
+                    Str::concat(key.clone(), "start".into()),
+                    convert::<Int, Str>(start),
+                );
+                m_b.insert(Str::concat(key, "length".into()), convert::<Int, Str>(len));
+                m_b.insert(convert::<Int, Str>(i), text);
+            })
+        })
+    }
+
     pub(crate) fn regex_match_loc(
         &mut self,
         vars: &mut Variables,
@@ -216,7 +573,7 @@ pub(crate) struct FileWrite(writers::Registry);
 
 impl Default for FileWrite {
     fn default() -> FileWrite {
-        FileWrite::new(writers::default_factory())
+        FileWrite::new(writers::default_factory(/*line_buffered=*/ false))
     }
 }
 
@@ -224,9 +581,23 @@ impl FileWrite {
     pub(crate) fn flush_stdout(&mut self) -> Result<()> {
         self.0.get_file(None)?.flush()
     }
-    pub(crate) fn close(&mut self, path: &Str) -> Result<()> {
+    /// Close the named output file, command or coprocess. Returns the exit status of a command,
+    /// or -1 if nothing by that name is open for writing.
+    pub(crate) fn close(&mut self, path: &Str) -> Result<Int> {
         self.0.close(path)
     }
+    /// Flush the named output file, command or coprocess; or all open output if `path` is empty.
+    /// Returns -1 if nothing by that name has been written to.
+    pub(crate) fn flush(&mut self, path: &Str) -> Result<Int> {
+        if path.is_empty() {
+            self.0.flush_all()?;
+            Ok(0)
+        } else if self.0.flush(path)? {
+            Ok(0)
+        } else {
+            Ok(-1)
+        }
+    }
     pub(crate) fn new(ff: impl writers::FileFactory) -> FileWrite {
         FileWrite(writers::Registry::from_factory(ff))
     }
@@ -274,15 +645,93 @@ pub const CHUNK_SIZE: usize = 8 << 10;
 #[derive(Default)]
 pub(crate) struct Inputs {
     files: Registry<RegexSplitter<File>>,
-    commands: Registry<RegexSplitter<ChildStdout>>,
+    // Commands are stored alongside their process, so that closing them can wait for them to exit.
+    commands: Registry<(RegexSplitter<ChildStdout>, Child)>,
+    coprocs: Registry<RegexSplitter<ChildStdout>>,
+}
+
+/// Which ends of a file, command or coprocess `close(name, how)` closes: `how` is "to" for the end
+/// we write to, "from" for the end we read from, and empty for both.
+pub(crate) fn close_ends(how: &Str) -> Result<(/*read=*/ bool, /*write=*/ bool)> {
+    how.with_bytes(|bs| {
+        if bs.is_empty() {
+            Ok((true, true))
+        } else if bs.eq_ignore_ascii_case(b"to") {
+            Ok((false, true))
+        } else if bs.eq_ignore_ascii_case(b"from") {
+            Ok((true, false))
+        } else {
+            err!(
+                "second argument to close must be \"to\" or \"from\", got {:?}",
+                String::from_utf8_lossy(bs)
+            )
+        }
+    })
+}
+
+/// Implements `close(name, how)`. Returns the exit status of a command or coprocess, 0 for a file,
+/// or -1 if nothing named `name` was open.
+pub(crate) fn close<LR: LineReader>(
+    read_files: &mut FileRead<LR>,
+    write_files: &mut FileWrite,
+    name: &Str,
+    how: &Str,
+) -> Result<Int> {
+    let (read, write) = close_ends(how)?;
+    let mut status = -1;
+    // NB this may create an unused entry in write_files. It would not be terribly difficult to
+    // optimize the close path to include an existence check first.
+    if write {
+        status = write_files.close(name)?;
+    }
+    if read {
+        let read_status = read_files.close(name)?;
+        if status == -1 {
+            status = read_status;
+        }
+    }
+    // If this closes the last open end of a coprocess by this name, wait for it to exit.
+    match name.with_bytes(|bs| command::close_coprocess(bs, write, read)) {
+        Ok(Some(coproc_status)) => status = coproc_status,
+        Ok(None) => {}
+        Err(e) => return err!("failed to wait for coprocess: {}", e),
+    }
+    Ok(status)
+}
+
+/// The values returned by [`RegexCache::get_line_stdin_event`] when it reads a record, or when
+/// an input file starts or ends.
+pub(crate) const RECORD_EVENT: Int = 1;
+pub(crate) const BEGINFILE_EVENT: Int = 2;
+pub(crate) const ENDFILE_EVENT: Int = 3;
+
+// Where the main loop is relative to the boundaries between input files, for BEGINFILE and ENDFILE.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum FileEvent {
+    // Before the first file. Programs without BEGINFILE or ENDFILE always stay here.
+    Between,
+    // BEGINFILE was reported; no record of the file has been read yet.
+    Begin,
+    // Reading records from a file.
+    InFile,
+    // `nextfile` skipped the rest of the file; its ENDFILE is reported next.
+    Skipped,
+    // ENDFILE was reported (or skipped); the next call moves on to the next file.
+    End,
 }
 
-pub(crate) struct FileRead<LR = RegexSplitter<Box<dyn io::Read + Send>>> {
+pub(crate) struct FileRead<LR: LineReader = RegexSplitter<Box<dyn io::Read + Send>>> {
     pub(crate) inputs: Inputs,
     stdin: LR,
+    file_event: FileEvent,
+    // Records are read here first, so that ENDFILE still sees the last record of its file.
+    pending_line: LR::Line,
     named_columns: Option<Vec<Str<'static>>>,
     used_fields: FieldSet,
     backup_used_fields: FieldSet,
+    // The terminator of the last record read by `getline` from a file or command. `None` if the
+    // last record was read from the main input.
+    last_terminator: Option<Str<'static>>,
 }
 
 impl<LR: LineReader> FileRead<LR> {
@@ -300,7 +749,10 @@ impl<LR: LineReader> FileRead<LR> {
                             named_columns: None,
                             used_fields: fields.clone(),
                             backup_used_fields: fields.clone(),
+                            last_terminator: None,
                             stdin,
+                            file_event: FileEvent::Between,
+                            pending_line: Default::default(),
                         })
                     } else {
                         None
@@ -310,8 +762,25 @@ impl<LR: LineReader> FileRead<LR> {
             .collect()
     }
 
-    pub(crate) fn close(&mut self, path: &Str) {
-        self.inputs.files.remove(path);
+    /// Close any file, command or coprocess we are reading from named `path`. Returns the exit
+    /// status of a command, 0 for a file or coprocess, or -1 if nothing by that name was open.
+    pub(crate) fn close(&mut self, path: &Str) -> Result<Int> {
+        let mut status = -1;
+        if self.inputs.files.remove(path).is_some() {
+            status = 0;
+        }
+        if self.inputs.coprocs.remove(path).is_some() {
+            status = 0;
+        }
+        if let Some((reader, child)) = self.inputs.commands.remove(path) {
+            // Close our end of the pipe first, in case the command is still writing to it.
+            mem::drop(reader);
+            status = match command::wait_command(child) {
+                Ok(s) => s,
+                Err(e) => return err!("failed to wait for command: {}", e),
+            };
+        }
+        Ok(status)
     }
 
     pub(crate) fn new(
@@ -333,6 +802,9 @@ impl<LR: LineReader> FileRead<LR> {
             stdin,
             used_fields,
             backup_used_fields,
+            last_terminator: None,
+            file_event: FileEvent::Between,
+            pending_line: Default::default(),
             named_columns: named_columns
                 .map(|cs| cs.into_iter().map(|s| Str::from(s).unmoor()).collect()),
         };
@@ -376,6 +848,14 @@ impl<LR: LineReader> FileRead<LR> {
         self.stdin.filename()
     }
 
+    /// The value of `RT`: the separator that terminated the most recently read record.
+    pub(crate) fn record_terminator(&self) -> Str<'static> {
+        match &self.last_terminator {
+            Some(t) => t.clone(),
+            None => self.stdin.record_terminator(),
+        }
+    }
+
     pub(crate) fn read_err_stdin<'a>(&mut self) -> Int {
         self.stdin.read_state()
     }
@@ -386,28 +866,67 @@ impl<LR: LineReader> FileRead<LR> {
     pub(crate) fn read_err_cmd<'a>(&mut self, cmd: &Str<'a>) -> Result<Int> {
         self.with_cmd(cmd, |reader| Ok(reader.read_state()))
     }
+    pub(crate) fn read_err_coproc<'a>(&mut self, cmd: &Str<'a>) -> Result<Int> {
+        self.with_coproc(cmd, |reader| Ok(reader.read_state()))
+    }
 
-    pub(crate) fn next_file(&mut self) -> Result<()> {
-        let _ = self.stdin.next_file()?;
-        Ok(())
+    /// Skip the rest of the current input file. Returns whether FILENAME and FNR should be reset
+    /// now, rather than when BEGINFILE for the next file is reported.
+    pub(crate) fn next_file(&mut self) -> Result<bool> {
+        match self.file_event {
+            FileEvent::Between => {
+                let _ = self.stdin.next_file()?;
+                Ok(true)
+            }
+            // Skipping a file from BEGINFILE skips its ENDFILE as well.
+            FileEvent::Begin => {
+                self.file_event = FileEvent::End;
+                Ok(false)
+            }
+            FileEvent::InFile => {
+                self.file_event = FileEvent::Skipped;
+                Ok(false)
+            }
+            // The file has already ended.
+            FileEvent::Skipped | FileEvent::End => Ok(false),
+        }
     }
 
     fn with_cmd<'a, R>(
         &mut self,
         cmd: &Str<'a>,
-        f: impl FnMut(&mut RegexSplitter<ChildStdout>) -> Result<R>,
+        mut f: impl FnMut(&mut RegexSplitter<ChildStdout>) -> Result<R>,
     ) -> Result<R> {
         let check_utf8 = self.stdin.check_utf8();
         self.inputs.commands.get_fallible(
             cmd,
             |s| match command::command_for_read(s.as_bytes()) {
-                Ok(r) => Ok(RegexSplitter::new(
+                Ok(r) => Ok((
+                    RegexSplitter::new(r.stdout, CHUNK_SIZE, cmd.clone().unmoor(), check_utf8),
+                    r.child,
+                )),
+                Err(e) => err!("failed to crate command for reading: {}", e),
+            },
+            |(reader, _)| f(reader),
+        )
+    }
+
+    fn with_coproc<'a, R>(
+        &mut self,
+        cmd: &Str<'a>,
+        f: impl FnMut(&mut RegexSplitter<ChildStdout>) -> Result<R>,
+    ) -> Result<R> {
+        let check_utf8 = self.stdin.check_utf8();
+        self.inputs.coprocs.get_fallible(
+            cmd,
+            |s| match command::coprocess_for_read(s.as_bytes()) {
+                Ok(r) => Ok(RegexSplitter::new_interactive(
                     r,
                     CHUNK_SIZE,
                     cmd.clone().unmoor(),
                     check_utf8,
                 )),
-                Err(e) => err!("failed to crate command for reading: {}", e),
+                Err(e) => err!("failed to start coprocess: {}", e),
             },
             f,
         )
@@ -453,8 +972,8 @@ impl<T> Default for Registry<T> {
 }
 
 impl<T> Registry<T> {
-    fn remove(&mut self, s: &Str) {
-        self.cached.remove(&s.clone().unmoor());
+    fn remove(&mut self, s: &Str) -> Option<T> {
+        self.cached.remove(&s.clone().unmoor())
     }
     fn get<R>(
         &mut self,
@@ -540,6 +1059,32 @@ impl<'b, 'a> Convert<&'b Str<'a>, Int> for _Carrier {
     }
 }
 
+/// Compare two strings that may be strnums (see `types::strnum_vars`): numerically if both look
+/// like numbers, and as strings otherwise.
+pub(crate) fn compare_strnum(l: &Str, r: &Str) -> std::cmp::Ordering {
+    l.with_bytes(|l| {
+        r.with_bytes(|r| match (parse_numeric(l), parse_numeric(r)) {
+            (Some(x), Some(y)) => x.partial_cmp(&y).unwrap_or(std::cmp::Ordering::Equal),
+            _ => l.cmp(r),
+        })
+    })
+}
+
+/// Compare a string that may be a strnum with a number. If `l` looks like a number, the two are
+/// compared as floats, without a round-trip of `r` through `CONVFMT`. Otherwise `r` is converted
+/// to a string and the comparison is a string comparison.
+pub(crate) fn compare_strnum_float(
+    l: &Str,
+    r: Float,
+    vars: &Variables,
+) -> Result<std::cmp::Ordering> {
+    if let Some(x) = l.with_bytes(parse_numeric) {
+        return Ok(x.partial_cmp(&r).unwrap_or(std::cmp::Ordering::Equal));
+    }
+    let r = vars.float_to_str(r)?;
+    Ok(l.with_bytes(|l| r.with_bytes(|r| l.cmp(r))))
+}
+
 pub(crate) trait Inc {
     fn inc_int(&mut self, by: Int);
     fn inc_float(&mut self, by: Float);
@@ -662,6 +1207,16 @@ impl<'a> From<Shuttle<HashMap<UniqueStr<'a>, Int>>> for StrMap<'a, Int> {
     }
 }
 
+impl<'a> From<Shuttle<HashMap<UniqueStr<'a>, UniqueStr<'a>>>> for StrMap<'a, Str<'a>> {
+    fn from(sh: Shuttle<HashMap<UniqueStr<'a>, UniqueStr<'a>>>) -> Self {
+        SharedMap(Rc::new(RefCell::new(
+            sh.0.into_iter()
+                .map(|(x, y)| (x.into_str(), y.into_str()))
+                .collect(),
+        )))
+    }
+}
+
 impl<K, V> SharedMap<K, V> {
     fn borrow_mut(&self) -> impl std::ops::DerefMut<Target = HashMap<K, V>> + '_ {
         // Unlike the full std::collections APIs, we are careful not to hand out any references
@@ -730,12 +1285,32 @@ impl<'a> StrMap<'a, Int> {
     }
 }
 
-impl<K: Hash + Eq + Clone, V> SharedMap<K, V> {
-    pub(crate) fn to_iter(&self) -> Iter<K> {
-        self.0.borrow().keys().cloned().collect()
+impl<'a> StrMap<'a, Str<'a>> {
+    pub(crate) fn shuttle(&self) -> Shuttle<HashMap<UniqueStr<'a>, UniqueStr<'a>>> {
+        Shuttle(
+            self.0
+                .borrow()
+                .iter()
+                .map(|(x, y)| (UniqueStr::from(x.clone()), UniqueStr::from(y.clone())))
+                .collect(),
+        )
     }
-    pub(crate) fn to_vec(&self) -> Vec<K> {
-        self.0.borrow().keys().cloned().collect()
+}
+
+impl<K: Hash + Eq + Clone + sort::SortKey, V: Clone + sort::SortKey> SharedMap<K, V> {
+    /// The keys of the map, in the order given by `order` if it is set and in an unspecified
+    /// order otherwise.
+    pub(crate) fn to_iter(&self, order: Option<sort::SortOrder>) -> Iter<K> {
+        self.to_vec(order).into_iter().collect()
+    }
+    pub(crate) fn to_vec(&self, order: Option<sort::SortOrder>) -> Vec<K> {
+        match order {
+            Some(order) => sort::sorted_entries(self, order)
+                .into_iter()
+                .map(|(k, _)| k)
+                .collect(),
+            None => self.0.borrow().keys().cloned().collect(),
+        }
     }
 }
 
@@ -1,16 +1,15 @@
 //! This module implements much of printf in awk.
 //!
-//! We lean heavily on ryu and the std::fmt machinery; as such, most of the work is parsing
-//! awk-style format strings and translating them to individual calls to write!.
+//! We lean heavily on the std::fmt machinery; as such, most of the work is parsing awk-style
+//! format strings and translating them to individual calls to write!. Format strings follow C's
+//! printf: directives have the form `%[n$][flags][width][.precision][length]conv`, where width and
+//! precision may be given as `*` (or `*m$`) to read them from the argument list.
 //!
-//! TODO: Originally, frawk enforced that all Strs contained valid UTF-8. We have since allowed
-//! strings to contain arbitrary byte sequences, but this module will eagerly replace invalid UTF8
-//! byte sequences with REPLACEMENT CHARACTER using String's from_utf8_lossy function. This means
-//! that users hoping to output raw bytes using `printf` (as may be necessary, given that print
-//! appends a newline) may find some bytes replaced inadvertently. We could solve this by adding a
-//! new print function that does not append a newline.
+//! As in the "C" locale, strings are treated as sequences of bytes: `%s` copies its argument
+//! through unchanged (it need not be valid UTF-8), and widths and precisions are measured in
+//! bytes.
 use crate::common::Result;
-use crate::runtime::{convert, strtoi, Float, Int, Str};
+use crate::runtime::{convert, str_impl::DynamicBuf, utf8, Float, Int, Str};
 
 use std::convert::TryFrom;
 use std::fmt;
@@ -38,13 +37,6 @@ impl Write for StackWriter {
     }
 }
 
-struct DisplayBytes<'a>(&'a [u8]);
-impl<'a> fmt::Display for DisplayBytes<'a> {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Display::fmt(&*std::string::String::from_utf8_lossy(self.0), fmt)
-    }
-}
-
 #[derive(Clone, Debug)]
 pub(crate) enum FormatArg<'a> {
     S(Str<'a>),
@@ -110,130 +102,161 @@ impl<'a> FormatArg<'a> {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
-struct FormatSpec {
-    // leading '-' ? -- left justification.
+#[derive(Copy, Clone, Debug, Default)]
+struct Flags {
+    // '-': left-justify the output within the field width.
     minus: bool,
-    // number to the left of '.', if any
-    leading_zeros: bool,
-    // padding
-    lnum: usize,
-    // maximum string width, or floating point precision.
-    rnum: usize,
-    // format specifier: e.g. c, d, s, x.
-    spec: u8,
-}
-
-impl Default for FormatSpec {
-    fn default() -> FormatSpec {
-        FormatSpec {
-            minus: false,
-            leading_zeros: false,
-            lnum: 0,
-            rnum: usize::max_value(),
-            spec: b'z', /* invalid */
-        }
+    // '+': always print a sign for signed conversions.
+    plus: bool,
+    // ' ': print a space where a '+' sign would go.
+    space: bool,
+    // '#': use the "alternate form" of a conversion, e.g. a "0x" prefix for `%x`.
+    alt: bool,
+    // '0': pad with leading zeros rather than spaces.
+    zero: bool,
+}
+
+/// A field width or precision.
+#[derive(Copy, Clone, Debug)]
+enum Count {
+    Lit(usize),
+    // `*`: read the count from the next argument.
+    Next,
+    // `*m$`: read the count from the given (0-indexed) argument.
+    Arg(usize),
+}
+
+/// A single conversion specification, of the form `%[n$][flags][width][.precision][length]conv`.
+#[derive(Copy, Clone, Debug)]
+struct Directive {
+    // The (0-indexed) argument to format for `%n$` directives, or None to use the next argument.
+    pos: Option<usize>,
+    flags: Flags,
+    width: Option<Count>,
+    prec: Option<Count>,
+    conv: u8,
+}
+
+type ParseResult<T> = std::result::Result<T, String>;
+
+fn is_conv(c: u8) -> bool {
+    matches!(
+        c,
+        b'd' | b'i'
+            | b'o'
+            | b'u'
+            | b'x'
+            | b'X'
+            | b'e'
+            | b'E'
+            | b'f'
+            | b'F'
+            | b'g'
+            | b'G'
+            | b'a'
+            | b'A'
+            | b'c'
+            | b's'
+            | b'%'
+    )
+}
+
+fn parse_num(spec: &[u8], ix: &mut usize) -> ParseResult<Option<usize>> {
+    let start = *ix;
+    while matches!(spec.get(*ix), Some(b'0'..=b'9')) {
+        *ix += 1;
+    }
+    if start == *ix {
+        return Ok(None);
+    }
+    let digits = str::from_utf8(&spec[start..*ix]).unwrap();
+    match digits.parse::<usize>() {
+        Ok(n) => Ok(Some(n)),
+        Err(_) => Err(format!("{} is too large", digits)),
     }
 }
 
-fn is_spec(c: u8) -> bool {
-    match c {
-        b'f' | b'c' | b'd' | b'e' | b'g' | b'o' | b's' | b'x' => true,
-        _ => false,
-    }
-}
-
-fn process_spec(mut w: impl Write, fspec: &mut FormatSpec, arg: &FormatArg) -> Result<()> {
-    macro_rules! match_for_spec {
-        ($s:expr, $arg:expr) => {
-            match (
-                fspec.minus,
-                fspec.leading_zeros,
-                fspec.lnum,
-                fspec.rnum == usize::max_value(),
-            ) {
-                (true, true, lnum, true) => write!(w, concat!("{:0<l$", $s, "}"), $arg, l = lnum),
-                (true, false, lnum, true) => write!(w, concat!("{:<l$", $s, "}"), $arg, l = lnum),
-                (true, true, lnum, false) => write!(
-                    w,
-                    concat!("{:0<l$.r$", $s, "}"),
-                    $arg,
-                    l = lnum,
-                    r = fspec.rnum
-                ),
-                (true, false, lnum, false) => write!(
-                    w,
-                    concat!("{:<l$.r$", $s, "}"),
-                    $arg,
-                    l = lnum,
-                    r = fspec.rnum
-                ),
-                (false, true, lnum, true) => write!(w, concat!("{:0>l$", $s, "}"), $arg, l = lnum),
-                (false, false, lnum, true) => write!(w, concat!("{:>l$", $s, "}"), $arg, l = lnum),
-                (false, true, lnum, false) => write!(
-                    w,
-                    concat!("{:0>l$.r$", $s, "}"),
-                    $arg,
-                    l = lnum,
-                    r = fspec.rnum
-                ),
-                (false, false, lnum, false) => write!(
-                    w,
-                    concat!("{:>l$.r$", $s, "}"),
-                    $arg,
-                    l = lnum,
-                    r = fspec.rnum
-                ),
-            }
-        };
+fn arg_index(n: usize) -> ParseResult<usize> {
+    if n == 0 {
+        Err("argument positions start at 1".into())
+    } else {
+        Ok(n - 1)
     }
-    let res = match fspec.spec {
-        b'f' => {
-            if !fspec.leading_zeros && fspec.lnum == 0 && fspec.rnum == usize::max_value() {
-                // Fast path: use Ryu, which today is more efficient than the standard library.
-                // NB Ryu prints some things a bit differently than most awk implementations.
-                // `write!(w, "{}", arg.to_float())` is a bit closer.
-                let mut buf = ryu::Buffer::new();
-                write!(w, "{}", buf.format(arg.to_float()))
-            } else {
-                match_for_spec!("", arg.to_float())
-            }
+}
+
+fn parse_count(spec: &[u8], ix: &mut usize) -> ParseResult<Option<Count>> {
+    if spec.get(*ix) != Some(&b'*') {
+        return Ok(parse_num(spec, ix)?.map(Count::Lit));
+    }
+    *ix += 1;
+    match parse_num(spec, ix)? {
+        Some(n) if spec.get(*ix) == Some(&b'$') => {
+            *ix += 1;
+            Ok(Some(Count::Arg(arg_index(n)?)))
         }
-        b'e' => match_for_spec!("e", arg.to_float()),
-        b'g' => {
-            let mut buf = StackWriter::default();
-            // %g means "pick the shorter of standard and scientific notation". We do the obvious
-            // thing of computing both and writing out the smaller one.
-            fspec.spec = b'f';
-            process_spec(&mut buf, fspec, arg)?;
-            let l1 = buf.len();
-            fspec.spec = b'e';
-            process_spec(&mut buf, fspec, arg)?;
-            let l2 = buf.len() - l1;
-            let bytes = if l1 < l2 {
-                &buf.0[0..l1]
-            } else {
-                &buf.0[l1..(l1 + l2)]
-            };
-            return write_bytes(&mut w, bytes);
+        Some(_) => Err("expected '$' after the argument position in '*'".into()),
+        None => Ok(Some(Count::Next)),
+    }
+}
+
+/// Parse the directive following a '%' character, where `start` is the index just past the '%'.
+/// On success, returns the directive along with the index of the first byte following it.
+fn parse_directive(spec: &[u8], start: usize) -> ParseResult<(Directive, usize)> {
+    let mut ix = start;
+    let mut pos = None;
+    if matches!(spec.get(ix), Some(b'1'..=b'9')) {
+        let mut end = ix;
+        let n = parse_num(spec, &mut end)?;
+        if spec.get(end) == Some(&b'$') {
+            pos = Some(arg_index(n.unwrap())?);
+            ix = end + 1;
         }
-        b'd' => match_for_spec!("", arg.to_int()),
-        b'o' => match_for_spec!("o", arg.to_int()),
-        b'x' => match_for_spec!("x", arg.to_int()),
-        b'c' => {
-            // First, see if we have something ascii/UTF8 here
-            match char::try_from(arg.to_int() as u32) {
-                Ok(ch) => match_for_spec!("", ch),
-                // TODO: Unclear what we should do here, write out the raw bytes? write out the
-                // character code? Awk may just write the raw bytes out, but it's hard to say
-                // (different behavior across implementations)
-                _ => match_for_spec!("", "?"),
-            }
+    }
+    let mut flags = Flags::default();
+    loop {
+        match spec.get(ix) {
+            Some(b'-') => flags.minus = true,
+            Some(b'+') => flags.plus = true,
+            Some(b' ') => flags.space = true,
+            Some(b'#') => flags.alt = true,
+            Some(b'0') => flags.zero = true,
+            // Thousands grouping: a no-op in the "C" locale.
+            Some(b'\'') => {}
+            _ => break,
         }
-        b's' => arg.with_bytes(|bs| match_for_spec!("", DisplayBytes(bs))),
-        x => return err!("unsupported format specifier: {}", x),
+        ix += 1;
+    }
+    let width = parse_count(spec, &mut ix)?;
+    let prec = if spec.get(ix) == Some(&b'.') {
+        ix += 1;
+        Some(parse_count(spec, &mut ix)?.unwrap_or(Count::Lit(0)))
+    } else {
+        None
     };
-    wrap_result(res)
+    // Length modifiers do not mean anything for awk values; we accept and ignore them.
+    while matches!(
+        spec.get(ix),
+        Some(b'h' | b'l' | b'L' | b'q' | b'j' | b'z' | b't')
+    ) {
+        ix += 1;
+    }
+    match spec.get(ix) {
+        Some(c) if is_conv(*c) => {
+            let d = Directive {
+                pos,
+                flags,
+                width,
+                prec,
+                conv: *c,
+            };
+            Ok((d, ix + 1))
+        }
+        Some(c) => Err(format!(
+            "unsupported conversion specifier '{}'",
+            std::ascii::escape_default(*c)
+        )),
+        None => Err("incomplete format specifier".into()),
+    }
 }
 
 fn wrap_result<T>(r: std::result::Result<T, impl fmt::Display>) -> Result<()> {
@@ -244,157 +267,431 @@ fn wrap_result<T>(r: std::result::Result<T, impl fmt::Display>) -> Result<()> {
 }
 
 fn write_bytes(mut w: impl Write, bs: &[u8]) -> Result<()> {
-    wrap_result(w.write(bs))
-}
-
-pub(crate) fn printf(mut w: impl Write, spec: &[u8], mut args: &[FormatArg]) -> Result<()> {
-    #[derive(Copy, Clone)]
-    enum State {
-        // Byte index of start of string
-        Raw(usize),
-        // Byte index of percent sign
-        Format(usize),
-    }
-
-    use State::*;
-    let mut iter = spec.iter().cloned().enumerate();
-    macro_rules! next_state {
-        ($e:expr) => {
-            match $e {
-                Some((_, b'%')) => Format(0),
-                Some(_) => Raw(0),
-                None => return Ok(()),
+    wrap_result(w.write_all(bs))
+}
+
+fn write_repeated(mut w: impl Write, b: u8, mut n: usize) -> Result<()> {
+    let chunk = [b; 32];
+    while n > 0 {
+        let len = std::cmp::min(n, chunk.len());
+        write_bytes(&mut w, &chunk[..len])?;
+        n -= len;
+    }
+    Ok(())
+}
+
+/// Write `prefix` (e.g. a sign or "0x") followed by `body`, padded out to `width` bytes. If `zero`
+/// is set then padding is inserted as zeros between the prefix and the body.
+fn write_padded(
+    mut w: impl Write,
+    prefix: &[u8],
+    body: &[u8],
+    width: usize,
+    minus: bool,
+    zero: bool,
+) -> Result<()> {
+    let padding = width.saturating_sub(prefix.len() + body.len());
+    if minus {
+        write_bytes(&mut w, prefix)?;
+        write_bytes(&mut w, body)?;
+        write_repeated(&mut w, b' ', padding)
+    } else if zero {
+        write_bytes(&mut w, prefix)?;
+        write_repeated(&mut w, b'0', padding)?;
+        write_bytes(&mut w, body)
+    } else {
+        write_repeated(&mut w, b' ', padding)?;
+        write_bytes(&mut w, prefix)?;
+        write_bytes(&mut w, body)
+    }
+}
+
+fn sign_prefix(negative: bool, flags: Flags) -> &'static [u8] {
+    if negative {
+        b"-"
+    } else if flags.plus {
+        b"+"
+    } else if flags.space {
+        b" "
+    } else {
+        b""
+    }
+}
+
+fn format_int_arg(
+    w: impl Write,
+    conv: u8,
+    flags: Flags,
+    width: usize,
+    prec: Option<usize>,
+    arg: &FormatArg,
+) -> Result<()> {
+    if let FormatArg::F(f) = arg {
+        // Like gawk, fall back to floating-point formatting for values that do not fit in an Int.
+        if !f.is_finite() || f.abs() >= 9223372036854775808.0 {
+            return format_float_arg(w, b'f', flags, width, Some(0), f.trunc());
+        }
+    }
+    let i = arg.to_int();
+    let mut prefix: &[u8] = b"";
+    let mut body = StackWriter::default();
+    let res = match conv {
+        b'd' | b'i' => {
+            prefix = sign_prefix(i < 0, flags);
+            write!(body, "{}", i.unsigned_abs())
+        }
+        b'u' => write!(body, "{}", i as u64),
+        b'o' => write!(body, "{:o}", i as u64),
+        b'x' => write!(body, "{:x}", i as u64),
+        b'X' => write!(body, "{:X}", i as u64),
+        _ => unreachable!(),
+    };
+    wrap_result(res)?;
+    if let Some(p) = prec {
+        if p == 0 && i == 0 {
+            body.0.clear();
+        }
+        if body.len() < p {
+            let zeros = p - body.len();
+            body.0.insert_many(0, (0..zeros).map(|_| b'0'));
+        }
+    }
+    match conv {
+        b'o' if flags.alt && body.0.first() != Some(&b'0') => body.0.insert(0, b'0'),
+        b'x' if flags.alt && i != 0 => prefix = b"0x",
+        b'X' if flags.alt && i != 0 => prefix = b"0X",
+        _ => {}
+    }
+    let zero = flags.zero && !flags.minus && prec.is_none();
+    write_padded(w, prefix, &body.0[..], width, flags.minus, zero)
+}
+
+/// Write `x` in the style of `%e`, with `prec` digits after the decimal point.
+fn write_exp(buf: &mut StackWriter, x: Float, prec: usize, alt: bool, upper: bool) -> Result<()> {
+    let start = buf.len();
+    wrap_result(write!(buf, "{:.*e}", prec, x))?;
+    // Rust writes exponents like "e4" or "e-7"; C always includes a sign and at least two
+    // digits.
+    let e_ix = start + buf.0[start..].iter().position(|b| *b == b'e').unwrap();
+    let exp: i32 = str::from_utf8(&buf.0[e_ix + 1..]).unwrap().parse().unwrap();
+    buf.0.truncate(e_ix);
+    if alt && prec == 0 {
+        buf.0.push(b'.');
+    }
+    let e = if upper { 'E' } else { 'e' };
+    let sign = if exp < 0 { '-' } else { '+' };
+    wrap_result(write!(buf, "{}{}{:02}", e, sign, exp.abs()))
+}
+
+/// Write `x` in the style of `%g`, using `prec` significant digits.
+fn write_general(
+    buf: &mut StackWriter,
+    x: Float,
+    prec: usize,
+    alt: bool,
+    upper: bool,
+) -> Result<()> {
+    let prec = std::cmp::max(prec, 1);
+    // %g uses scientific notation if the exponent (after rounding to `prec` digits) is less than
+    // -4 or at least `prec`.
+    let exp = if x == 0.0 {
+        0
+    } else {
+        let mut tmp = StackWriter::default();
+        write_exp(&mut tmp, x, prec - 1, false, false)?;
+        let e_ix = tmp.0.iter().position(|b| *b == b'e').unwrap();
+        str::from_utf8(&tmp.0[e_ix + 1..])
+            .unwrap()
+            .parse::<i64>()
+            .unwrap()
+    };
+    let start = buf.len();
+    if exp < -4 || exp >= prec as i64 {
+        write_exp(buf, x, prec - 1, alt, upper)?;
+    } else {
+        wrap_result(write!(buf, "{:.*}", (prec as i64 - 1 - exp) as usize, x))?;
+        if alt && !buf.0[start..].contains(&b'.') {
+            buf.0.push(b'.');
+        }
+    }
+    if alt {
+        return Ok(());
+    }
+    // Without '#', trailing zeros are removed from the fractional part.
+    let mant_end = buf.0[start..]
+        .iter()
+        .position(|b| *b == b'e' || *b == b'E')
+        .map(|ix| start + ix)
+        .unwrap_or_else(|| buf.len());
+    if !buf.0[start..mant_end].contains(&b'.') {
+        return Ok(());
+    }
+    let mut end = mant_end;
+    while buf.0[end - 1] == b'0' {
+        end -= 1;
+    }
+    if buf.0[end - 1] == b'.' {
+        end -= 1;
+    }
+    buf.0.drain(end..mant_end);
+    Ok(())
+}
+
+/// Write `x` in the style of `%a`: a hexadecimal mantissa and a binary exponent. If `prec` is
+/// None then as many digits as are needed to represent `x` exactly are written.
+fn write_hex_float(
+    buf: &mut StackWriter,
+    x: Float,
+    prec: Option<usize>,
+    alt: bool,
+    upper: bool,
+) -> Result<()> {
+    const MANT_DIGITS: usize = 13;
+    let bits = x.to_bits();
+    let biased_exp = ((bits >> 52) & 0x7ff) as i64;
+    let mut mant = bits & ((1 << 52) - 1);
+    let (mut lead, exp) = if x == 0.0 {
+        (0u64, 0)
+    } else if biased_exp == 0 {
+        // subnormal
+        (0, -1022)
+    } else {
+        (1, biased_exp - 1023)
+    };
+    let (digits, trailing_zeros) = match prec {
+        None => {
+            let mut digits = MANT_DIGITS;
+            while digits > 0 && mant & 0xf == 0 {
+                mant >>= 4;
+                digits -= 1;
+            }
+            (digits, 0)
+        }
+        Some(p) if p < MANT_DIGITS => {
+            // Round (half to even) to p hex digits.
+            let shift = 4 * (MANT_DIGITS - p) as u32;
+            let rem = mant & ((1 << shift) - 1);
+            let half = 1 << (shift - 1);
+            mant >>= shift;
+            if rem > half || (rem == half && mant & 1 == 1) {
+                mant += 1;
+                if mant >> (4 * p) != 0 {
+                    mant &= (1 << (4 * p)) - 1;
+                    lead += 1;
+                }
             }
+            (p, 0)
+        }
+        Some(p) => (MANT_DIGITS, p - MANT_DIGITS),
+    };
+    wrap_result(write!(buf, "{}", lead))?;
+    if digits > 0 || trailing_zeros > 0 || alt {
+        buf.0.push(b'.');
+    }
+    if digits > 0 {
+        let res = if upper {
+            write!(buf, "{:0w$X}", mant, w = digits)
+        } else {
+            write!(buf, "{:0w$x}", mant, w = digits)
         };
+        wrap_result(res)?;
+    }
+    write_repeated(&mut *buf, b'0', trailing_zeros)?;
+    let p = if upper { 'P' } else { 'p' };
+    wrap_result(write!(buf, "{}{:+}", p, exp))
+}
+
+fn format_float_arg(
+    w: impl Write,
+    conv: u8,
+    flags: Flags,
+    width: usize,
+    prec: Option<usize>,
+    f: Float,
+) -> Result<()> {
+    let upper = conv.is_ascii_uppercase();
+    let mut prefix = SmallVec::<u8>::new();
+    prefix.extend_from_slice(sign_prefix(f.is_sign_negative(), flags));
+    let x = f.abs();
+    let mut body = StackWriter::default();
+    if !x.is_finite() {
+        let s: &[u8] = match (x.is_nan(), upper) {
+            (true, false) => b"nan",
+            (true, true) => b"NAN",
+            (false, false) => b"inf",
+            (false, true) => b"INF",
+        };
+        return write_padded(w, &prefix[..], s, width, flags.minus, false);
+    }
+    match conv.to_ascii_lowercase() {
+        b'f' => {
+            let prec = prec.unwrap_or(6);
+            wrap_result(write!(body, "{:.*}", prec, x))?;
+            if flags.alt && prec == 0 {
+                body.0.push(b'.');
+            }
+        }
+        b'e' => write_exp(&mut body, x, prec.unwrap_or(6), flags.alt, upper)?,
+        b'g' => write_general(&mut body, x, prec.unwrap_or(6), flags.alt, upper)?,
+        b'a' => {
+            prefix.extend_from_slice(if upper { b"0X" } else { b"0x" });
+            write_hex_float(&mut body, x, prec, flags.alt, upper)?;
+        }
+        _ => unreachable!(),
+    }
+    let zero = flags.zero && !flags.minus;
+    write_padded(w, &prefix[..], &body.0[..], width, flags.minus, zero)
+}
+
+fn format_directive(
+    w: impl Write,
+    d: &Directive,
+    args: &[FormatArg],
+    next: &mut usize,
+) -> Result<()> {
+    if d.conv == b'%' {
+        return write_bytes(w, b"%");
     }
-    let mut state = next_state!(iter.next());
     let default = FormatArg::S(Default::default());
-    let mut next_arg = || {
-        if args.len() == 0 {
-            &default
-        } else {
-            let res = &args[0];
-            args = &args[1..];
-            res
+    // Missing arguments are treated as empty strings.
+    let mut get = |pos: Option<usize>| {
+        let ix = pos.unwrap_or_else(|| {
+            *next += 1;
+            *next - 1
+        });
+        args.get(ix).cloned().unwrap_or_else(|| default.clone())
+    };
+    let mut flags = d.flags;
+    let mut count = |c: Option<Count>| match c {
+        None => None,
+        Some(Count::Lit(n)) => Some(n as Int),
+        Some(Count::Next) => Some(get(None).to_int()),
+        Some(Count::Arg(ix)) => Some(get(Some(ix)).to_int()),
+    };
+    // As in C, a negative width is treated as a '-' flag, and a negative precision is ignored.
+    let width = match count(d.width) {
+        Some(w) if w < 0 => {
+            flags.minus = true;
+            w.unsigned_abs() as usize
         }
+        Some(w) => w as usize,
+        None => 0,
     };
-    let mut buf = SmallVec::new();
-    'outer: loop {
-        match state {
-            Raw(start) => {
-                while let Some((ix, ch)) = iter.next() {
-                    if ch == b'%' {
-                        write_bytes(&mut w, &spec[start..ix])?;
-                        state = Format(ix);
-                        continue 'outer;
-                    }
-                }
-                write_bytes(&mut w, &spec[start..])?;
-                break 'outer;
-            }
-            Format(start) => {
-                let mut fs = FormatSpec::default();
-                #[derive(Copy, Clone)]
-                enum Stage {
-                    Begin,
-                    Lnum,
-                    Rnum,
-                }
-                use Stage::*;
-                let mut stage = Begin;
-                let mut next = iter.next();
-                // AWK is, as usual, rather permissive when it comes to invalid format specifiers:
-                // If something is formatted incorrectly, it is simply treated like a normal
-                // string. We implement by checking for error conditions and `break`ing out of the
-                // inner loop, which will change state to Raw(start).
-                while let Some((ix, ch)) = next {
-                    if !ch.is_ascii() {
-                        // We cast characters to bytes in what follows.
-                        break;
-                    }
-                    match (ch, stage) {
-                        (b'%', Begin) => {
-                            fs.spec = b'%';
-                            process_spec(&mut w, &mut fs, next_arg())?;
-                            state = Raw(ix + 1);
-                            continue 'outer;
-                        }
-                        (ch, _) if is_spec(ch) => {
-                            fs.spec = ch as u8;
-                            process_spec(&mut w, &mut fs, next_arg())?;
-                            state = Raw(ix + 1);
-                            continue 'outer;
-                        }
-                        (b'-', Begin) => {
-                            stage = Lnum;
-                            fs.minus = true;
-                        }
-                        (b'-', _) | (b'%', _) => break,
-                        (ch, Lnum) | (ch, Begin) => {
-                            if fs.lnum != 0 {
-                                break;
-                            }
-                            buf.clear();
-                            if ch == b'0' {
-                                fs.leading_zeros = true;
-                            } else if ch == b'.' {
-                                stage = Rnum;
-                                continue;
-                            } else {
-                                buf.push(ch);
-                            };
-                            next = None;
-                            while let Some((ix, ch)) = iter.next() {
-                                if !matches!(ch, b'0'..=b'9') {
-                                    next = Some((ix, ch));
-                                    break;
-                                }
-                                buf.push(ch);
-                            }
-                            let num = strtoi(&buf[..]);
-                            if num < 0 {
-                                break;
-                            }
-                            fs.lnum = num as usize;
-                            stage = Rnum;
-                            continue;
-                        }
-                        (ch, Rnum) => {
-                            if fs.rnum != usize::max_value() {
-                                break;
-                            }
-                            if ch != b'.' {
-                                break;
-                            }
-                            buf.clear();
-                            next = None;
-                            while let Some((ix, ch)) = iter.next() {
-                                if !matches!(ch, b'0'..=b'9') {
-                                    next = Some((ix, ch));
-                                    break;
-                                }
-                                buf.push(ch);
-                            }
-                            let num = strtoi(&buf[..]);
-                            if num < 0 {
-                                break;
-                            }
-                            fs.rnum = num as usize;
-                            continue;
-                        }
+    let prec = match count(d.prec) {
+        Some(p) if p >= 0 => Some(p as usize),
+        _ => None,
+    };
+    let arg = get(d.pos);
+    match d.conv {
+        b'd' | b'i' | b'o' | b'u' | b'x' | b'X' => {
+            format_int_arg(w, d.conv, flags, width, prec, &arg)
+        }
+        b'e' | b'E' | b'f' | b'F' | b'g' | b'G' | b'a' | b'A' => {
+            format_float_arg(w, d.conv, flags, width, prec, arg.to_float())
+        }
+        b'c' => {
+            let zero = flags.zero && !flags.minus;
+            if let FormatArg::S(s) = &arg {
+                // Strings print their first character: a full UTF-8 sequence if the string
+                // starts with one, and the first byte otherwise.
+                return s.with_bytes(|bs| {
+                    let len = if bs.is_empty() {
+                        0
+                    } else {
+                        utf8::char_width(bs)
                     };
-                    next = iter.next();
+                    write_padded(w, b"", &bs[..len], width, flags.minus, zero)
+                });
+            }
+            // Values that fit in a byte are written out as that byte. Larger values are encoded
+            // as UTF-8 if they are valid code points, and truncated to a byte otherwise.
+            let i = arg.to_int();
+            let mut buf = [0u8; 4];
+            let bs: &[u8] = match char::try_from(i as u32) {
+                Ok(ch) if i > 0xff => ch.encode_utf8(&mut buf).as_bytes(),
+                _ => {
+                    buf[0] = i as u8;
+                    &buf[..1]
                 }
-                // We do not have a complete format specifier, and we have exhausted the string.
-                // Just print it out.
-                state = Raw(start);
-                continue 'outer;
+            };
+            write_padded(w, b"", bs, width, flags.minus, zero)
+        }
+        b's' => arg.with_bytes(|bs| {
+            let bs = match prec {
+                Some(p) if p < bs.len() => &bs[..p],
+                _ => bs,
+            };
+            let zero = flags.zero && !flags.minus;
+            write_padded(w, b"", bs, width, flags.minus, zero)
+        }),
+        _ => unreachable!(),
+    }
+}
+
+pub(crate) fn printf(mut w: impl Write, spec: &[u8], args: &[FormatArg]) -> Result<()> {
+    // The next argument to consume, for directives that do not specify an argument position.
+    let mut next = 0;
+    // The start of the literal text that has not yet been written out.
+    let mut start = 0;
+    let mut ix = 0;
+    while let Some(off) = memchr::memchr(b'%', &spec[ix..]) {
+        let pct = ix + off;
+        match parse_directive(spec, pct + 1) {
+            Ok((d, end)) => {
+                write_bytes(&mut w, &spec[start..pct])?;
+                format_directive(&mut w, &d, args, &mut next)?;
+                start = end;
+                ix = end;
+            }
+            // AWK is, as usual, rather permissive when it comes to invalid format specifiers: if
+            // something is formatted incorrectly, it is simply treated like a normal string.
+            // Constant format strings are checked ahead of time with `validate`.
+            Err(_) => ix = pct + 1,
+        }
+    }
+    write_bytes(&mut w, &spec[start..])
+}
+
+/// Check that all of the directives in the format string `spec` are well-formed, returning an
+/// error describing the first one that is not.
+pub(crate) fn validate(spec: &[u8]) -> Result<()> {
+    let mut ix = 0;
+    while let Some(off) = memchr::memchr(b'%', &spec[ix..]) {
+        let pct = ix + off;
+        match parse_directive(spec, pct + 1) {
+            Ok((_, end)) => ix = end,
+            Err(msg) => {
+                return err!(
+                    "invalid format string {:?} at byte {}: {}",
+                    String::from_utf8_lossy(spec),
+                    pct,
+                    msg
+                )
             }
         }
     }
     Ok(())
 }
 
+/// The default value of `CONVFMT` and `OFMT`.
+pub(crate) const DEFAULT_NUM_FMT: &str = "%.6g";
+
+/// Convert `f` to a string in the manner of `CONVFMT` or `OFMT`: integral values are printed as
+/// integers, and other values are formatted according to `fmt`. If `fmt` is `None` then the
+/// variable has not been assigned and we use the standard (ryu-based) conversion.
+pub(crate) fn format_float<'a>(f: Float, fmt: Option<&Str>) -> Result<Str<'a>> {
+    let fmt = match fmt {
+        Some(fmt) => fmt,
+        None => return Ok(convert::<_, Str>(f)),
+    };
+    if f.fract() == 0.0 && f.abs() < (1u64 << 53) as Float {
+        return Ok(convert::<_, Str>(f as Int));
+    }
+    let mut buf = DynamicBuf::new(0);
+    fmt.with_bytes(|bs| printf(&mut buf, bs, &[FormatArg::F(f)]))?;
+    Ok(unsafe { buf.into_str() })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -425,11 +722,11 @@ mod tests {
         let s = str::from_utf8(&v[..]).unwrap();
         assert_eq!(
             s,
-            "Hi there, to my 2 friends 1.0 percent of the time: 1.25369e23!"
+            "Hi there, to my 2 friends 1.000000 percent of the time: 1.25369e+23!"
         );
 
         let s2 = sprintf!(b"%e %d ~~ %s", 12535, 3, "hi");
-        assert_eq!(s2.as_str(), "1.2535e4 3 ~~ hi");
+        assert_eq!(s2.as_str(), "1.253500e+04 3 ~~ hi");
     }
 
     #[test]
@@ -447,4 +744,120 @@ mod tests {
         let s2 = sprintf!(b"%.2f", 2.375);
         assert_eq!(s2.as_str(), "2.38");
     }
+
+    #[test]
+    fn raw_bytes() {
+        let mut v = Vec::<u8>::new();
+        let w = Cursor::new(&mut v);
+        let bs: &[u8] = b"\xff\xfeab";
+        printf(w, b"[%s][%-5.3s][%c]", &[bs.into(), bs.into(), 233.into()]).unwrap();
+        assert_eq!(&v[..], b"[\xff\xfeab][\xff\xfea  ][\xe9]");
+    }
+
+    #[test]
+    fn char_of_string() {
+        let s1 = sprintf!(b"%c|%3c", "hello", "xy");
+        assert_eq!(s1.as_str(), "h|  x");
+        let s2 = sprintf!(b"[%-3c][%c][%c]", "\u{e9}t\u{e9}", "", 65);
+        // Widths are measured in bytes, and 'é' is two of them.
+        assert_eq!(s2.as_str(), "[\u{e9} ][][A]");
+    }
+
+    #[test]
+    fn int_conversions() {
+        let s1 = sprintf!(b"%i %u %x %X %#x %#o %o", 42, -1, 255, 255, 255, 8, 0);
+        assert_eq!(s1.as_str(), "42 18446744073709551615 ff FF 0xff 010 0");
+        let s2 = sprintf!(
+            b"[%+d] [% d] [%+5d] [%-5d] [%05d] [%.3d] [%8.3d] [%.0d]",
+            3,
+            3,
+            -3,
+            3,
+            -3,
+            7,
+            7,
+            0
+        );
+        assert_eq!(
+            s2.as_str(),
+            "[+3] [ 3] [   -3] [3    ] [-0003] [007] [     007] []"
+        );
+        let s3 = sprintf!(b"%d %d", 1e20, 2.9);
+        assert_eq!(s3.as_str(), "100000000000000000000 2");
+    }
+
+    #[test]
+    fn float_conversions() {
+        let s1 = sprintf!(b"%e %E %.2e %.0e %#.0e", 1234.5, 0.000123, -5.0, 15.0, 3.0);
+        assert_eq!(
+            s1.as_str(),
+            "1.234500e+03 1.230000E-04 -5.00e+00 2e+01 3.e+00"
+        );
+        let s2 = sprintf!(
+            b"%g %g %g %G %.3g %#g %g",
+            100000,
+            1000000,
+            0.0001,
+            1e-5,
+            1.23456,
+            1.5,
+            0
+        );
+        assert_eq!(s2.as_str(), "100000 1e+06 0.0001 1E-05 1.23 1.50000 0");
+        let s3 = sprintf!(b"%f %F %.1f %+08.2f %#.0f", 1.5, 2, 0.25, -1.5, 2);
+        assert_eq!(s3.as_str(), "1.500000 2.000000 0.2 -0001.50 2.");
+        let s4 = sprintf!(b"%a %A %.1a %a %a", 1, 255.5, 1.96875, 0, -0.1);
+        assert_eq!(
+            s4.as_str(),
+            "0x1p+0 0X1.FFP+7 0x2.0p+0 0x0p+0 -0x1.999999999999ap-4"
+        );
+        let s5 = sprintf!(b"%f %5.1F %e", f64::INFINITY, f64::NAN, -f64::INFINITY);
+        assert_eq!(s5.as_str(), "inf   NAN -inf");
+    }
+
+    #[test]
+    fn star_and_positional() {
+        let s1 = sprintf!(
+            b"[%*d] [%-*d] [%.*f] [%*.*s]",
+            4,
+            1,
+            3,
+            2,
+            2,
+            1.23456,
+            5,
+            2,
+            "abc"
+        );
+        assert_eq!(s1.as_str(), "[   1] [2  ] [1.23] [   ab]");
+        let s2 = sprintf!(b"[%*d]", -4, 1);
+        assert_eq!(s2.as_str(), "[1   ]");
+        let s3 = sprintf!(b"%2$s %1$s %2$s", "a", "b");
+        assert_eq!(s3.as_str(), "b a b");
+        let s4 = sprintf!(b"[%1$*2$d]", 7, 3);
+        assert_eq!(s4.as_str(), "[  7]");
+        let s5 = sprintf!(b"100%% %c%c %lld %5%", 72, 105, 5);
+        assert_eq!(s5.as_str(), "100% Hi 5 %");
+    }
+
+    #[test]
+    fn validate_formats() {
+        assert!(validate(b"%d %5.2f %-*s %2$s %%").is_ok());
+        assert!(validate(b"%q").is_err());
+        assert!(validate(b"abc %").is_err());
+        assert!(validate(b"%0$d").is_err());
+        assert!(validate(b"%*3d").is_err());
+        // Malformed directives are printed verbatim at runtime.
+        assert_eq!(sprintf!(b"%q %d %", 1).as_str(), "%q 1 %");
+    }
+
+    #[test]
+    fn format_float_fmt() {
+        let fmt: Str = "%.3f".into();
+        let fmt = Some(&fmt);
+        assert_eq!(format_float(0.5, None).unwrap(), Str::from("0.5"));
+        assert_eq!(format_float(0.5, fmt).unwrap(), Str::from("0.500"));
+        assert_eq!(format_float(-12.0, fmt).unwrap(), Str::from("-12"));
+        assert_eq!(format_float(1e300, fmt).unwrap().len(), 305);
+    }
 }
@@ -0,0 +1,257 @@
+//! Support for ordering the contents of maps, used by the `asort` and `asorti` builtins.
+//!
+//! Orderings are described using the same strings as gawk's `PROCINFO["sorted_in"]` (e.g.
+//! `"@ind_str_asc"` or `"@val_num_desc"`). Because frawk's maps are statically typed, the `type`
+//! orderings that gawk provides are equivalent to comparing elements by their natural ordering:
+//! numerically for numbers and lexicographically for strings.
+use crate::common::Result;
+use crate::runtime::{convert, Float, Int, SharedMap, Str};
+
+use std::cmp::Ordering;
+use std::hash::Hash;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Comparison {
+    Natural,
+    Num,
+    Str,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct SortOrder {
+    pub by_val: bool,
+    pub cmp: Comparison,
+    pub desc: bool,
+}
+
+impl SortOrder {
+    /// Ascending order by key, or by value if `by_val` is set.
+    pub(crate) fn natural(by_val: bool) -> SortOrder {
+        SortOrder {
+            by_val,
+            cmp: Comparison::Natural,
+            desc: false,
+        }
+    }
+
+    /// Parse an ordering of the form `@{ind,val}_{num,str,type}_{asc,desc}`. `@unsorted` and the
+    /// empty string yield `None`.
+    pub(crate) fn parse(s: &[u8]) -> Result<Option<SortOrder>> {
+        if s.is_empty() || s == b"@unsorted" {
+            return Ok(None);
+        }
+        let parts: Vec<&[u8]> = s.split(|b| *b == b'_').collect();
+        let by_val = match parts.first().cloned() {
+            Some(b"@ind") => false,
+            Some(b"@val") => true,
+            _ => return err!("invalid sort order: {:?}", String::from_utf8_lossy(s)),
+        };
+        let cmp = match parts.get(1).cloned() {
+            Some(b"num") => Comparison::Num,
+            Some(b"str") => Comparison::Str,
+            Some(b"type") => Comparison::Natural,
+            _ => return err!("invalid sort order: {:?}", String::from_utf8_lossy(s)),
+        };
+        let desc = match parts.get(2).cloned() {
+            Some(b"asc") => false,
+            Some(b"desc") => true,
+            _ => return err!("invalid sort order: {:?}", String::from_utf8_lossy(s)),
+        };
+        if parts.len() != 3 {
+            return err!("invalid sort order: {:?}", String::from_utf8_lossy(s));
+        }
+        Ok(Some(SortOrder { by_val, cmp, desc }))
+    }
+}
+
+pub(crate) trait SortKey {
+    fn natural_cmp(&self, other: &Self) -> Ordering;
+    fn num_cmp(&self, other: &Self) -> Ordering;
+    fn str_cmp(&self, other: &Self) -> Ordering;
+    fn compare(&self, other: &Self, cmp: Comparison) -> Ordering {
+        match cmp {
+            Comparison::Natural => self.natural_cmp(other),
+            Comparison::Num => self.num_cmp(other),
+            Comparison::Str => self.str_cmp(other),
+        }
+    }
+}
+
+fn cmp_floats(x: Float, y: Float) -> Ordering {
+    x.partial_cmp(&y).unwrap_or(Ordering::Equal)
+}
+
+fn cmp_bytes(x: &Str, y: &Str) -> Ordering {
+    x.with_bytes(|x| y.with_bytes(|y| x.cmp(y)))
+}
+
+impl SortKey for Int {
+    fn natural_cmp(&self, other: &Int) -> Ordering {
+        self.cmp(other)
+    }
+    fn num_cmp(&self, other: &Int) -> Ordering {
+        self.cmp(other)
+    }
+    fn str_cmp(&self, other: &Int) -> Ordering {
+        cmp_bytes(&convert::<Int, Str>(*self), &convert::<Int, Str>(*other))
+    }
+}
+
+impl SortKey for Float {
+    fn natural_cmp(&self, other: &Float) -> Ordering {
+        cmp_floats(*self, *other)
+    }
+    fn num_cmp(&self, other: &Float) -> Ordering {
+        cmp_floats(*self, *other)
+    }
+    fn str_cmp(&self, other: &Float) -> Ordering {
+        cmp_bytes(
+            &convert::<Float, Str>(*self),
+            &convert::<Float, Str>(*other),
+        )
+    }
+}
+
+impl<'a> SortKey for Str<'a> {
+    fn natural_cmp(&self, other: &Str<'a>) -> Ordering {
+        cmp_bytes(self, other)
+    }
+    fn num_cmp(&self, other: &Str<'a>) -> Ordering {
+        cmp_floats(convert::<&Str, Float>(self), convert::<&Str, Float>(other))
+    }
+    fn str_cmp(&self, other: &Str<'a>) -> Ordering {
+        cmp_bytes(self, other)
+    }
+}
+
+// Arrays of arrays order their subarrays by size when iterating in value order.
+impl<K: Hash + Eq, V> SortKey for SharedMap<K, V> {
+    fn natural_cmp(&self, other: &Self) -> Ordering {
+        self.len().cmp(&other.len())
+    }
+    fn num_cmp(&self, other: &Self) -> Ordering {
+        self.natural_cmp(other)
+    }
+    fn str_cmp(&self, other: &Self) -> Ordering {
+        self.natural_cmp(other)
+    }
+}
+
+/// Returns the entries of `m` in the order given by `order`. Ties are broken by the natural
+/// ordering of the keys, so the result is deterministic.
+pub(crate) fn sorted_entries<K, V>(m: &SharedMap<K, V>, order: SortOrder) -> Vec<(K, V)>
+where
+    K: Hash + Eq + Clone + SortKey,
+    V: Clone + SortKey,
+{
+    let mut entries: Vec<(K, V)> =
+        m.0.borrow()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+    entries.sort_by(|(k1, v1), (k2, v2)| {
+        let primary = if order.by_val {
+            v1.compare(v2, order.cmp)
+        } else {
+            k1.compare(k2, order.cmp)
+        };
+        let res = primary.then_with(|| k1.natural_cmp(k2));
+        if order.desc {
+            res.reverse()
+        } else {
+            res
+        }
+    });
+    entries
+}
+
+fn parse_how(how: &Str, default: SortOrder) -> Result<SortOrder> {
+    Ok(how.with_bytes(SortOrder::parse)?.unwrap_or(default))
+}
+
+/// The keys of a map populated by `asort` or `asorti`. String-keyed destinations receive the
+/// string forms of the indices, which is what lets a string-keyed array be sorted in place.
+pub(crate) trait SortIndex {
+    fn from_index(i: Int) -> Self;
+}
+
+impl SortIndex for Int {
+    fn from_index(i: Int) -> Int {
+        i
+    }
+}
+
+impl<'a> SortIndex for Str<'a> {
+    fn from_index(i: Int) -> Str<'a> {
+        convert::<Int, Str>(i)
+    }
+}
+
+fn fill<D: Hash + Eq + SortIndex, T>(dst: &SharedMap<D, T>, elts: impl Iterator<Item = T>) -> Int {
+    let mut dst = dst.0.borrow_mut();
+    dst.clear();
+    for (i, t) in elts.enumerate() {
+        dst.insert(D::from_index(i as Int + 1), t);
+    }
+    dst.len() as Int
+}
+
+/// Implements `asort(src, dst, how)`: store the values of `src` in `dst`, indexed from 1 in the
+/// order specified by `how` (by default, the natural order of the values). `src` and `dst` may be
+/// the same map.
+pub(crate) fn asort<K, V, D>(src: &SharedMap<K, V>, dst: &SharedMap<D, V>, how: &Str) -> Result<Int>
+where
+    K: Hash + Eq + Clone + SortKey,
+    D: Hash + Eq + SortIndex,
+    V: Clone + SortKey,
+{
+    let entries = sorted_entries(src, parse_how(how, SortOrder::natural(/*by_val=*/ true))?);
+    Ok(fill(dst, entries.into_iter().map(|(_, v)| v)))
+}
+
+/// Implements `asorti(src, dst, how)`: like `asort`, but stores the keys of `src` rather than the
+/// values. By default, keys are compared as strings.
+pub(crate) fn asorti<K, V, D>(
+    src: &SharedMap<K, V>,
+    dst: &SharedMap<D, K>,
+    how: &Str,
+) -> Result<Int>
+where
+    K: Hash + Eq + Clone + SortKey,
+    D: Hash + Eq + SortIndex,
+    V: Clone + SortKey,
+{
+    let default = SortOrder {
+        by_val: false,
+        cmp: Comparison::Str,
+        desc: false,
+    };
+    let entries = sorted_entries(src, parse_how(how, default)?);
+    Ok(fill(dst, entries.into_iter().map(|(k, _)| k)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_orders() {
+        assert_eq!(SortOrder::parse(b"").unwrap(), None);
+        assert_eq!(SortOrder::parse(b"@unsorted").unwrap(), None);
+        assert_eq!(
+            SortOrder::parse(b"@val_num_desc").unwrap(),
+            Some(SortOrder {
+                by_val: true,
+                cmp: Comparison::Num,
+                desc: true
+            })
+        );
+        assert_eq!(
+            SortOrder::parse(b"@ind_type_asc").unwrap(),
+            Some(SortOrder::natural(false))
+        );
+        assert!(SortOrder::parse(b"@ind_num").is_err());
+        assert!(SortOrder::parse(b"@key_num_asc").is_err());
+        assert!(SortOrder::parse(b"@ind_num_asc_x").is_err());
+    }
+}
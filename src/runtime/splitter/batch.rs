@@ -114,6 +114,10 @@ impl LineReader for CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
             ReaderState::OK as i64
         }
     }
+    fn record_terminator(&self) -> Str<'static> {
+        // CSV and TSV inputs ignore RS.
+        Str::default()
+    }
     fn next_file(&mut self) -> Result<bool> {
         self.cur_chunk.off.clear();
         self.cur_buf = UniqueBuf::new(0).into_buf();
@@ -329,14 +333,14 @@ impl<'a> super::Line<'a> for Line {
             .join(self.fields[start..end].iter().cloned().map(trans))
             .upcast())
     }
-    fn nf(&mut self, _pat: &Str, _rc: &mut super::RegexCache) -> Result<usize> {
+    fn nf(&mut self, _split: &super::FieldSplit, _rc: &mut super::RegexCache) -> Result<usize> {
         Ok(self.fields.len())
     }
 
     fn get_col(
         &mut self,
         col: super::Int,
-        _pat: &Str,
+        _split: &super::FieldSplit,
         _ofs: &Str,
         _rc: &mut super::RegexCache,
     ) -> Result<Str<'a>> {
@@ -359,7 +363,7 @@ impl<'a> super::Line<'a> for Line {
         &mut self,
         _col: super::Int,
         _s: &Str<'a>,
-        _pat: &Str,
+        _split: &super::FieldSplit,
         _rc: &mut super::RegexCache,
     ) -> Result<()> {
         Ok(())
@@ -1388,6 +1392,8 @@ pub struct ByteReader<P: ChunkProducer> {
     // Progress in the current buffer.
     progress: usize,
     record_sep: u8,
+    // Whether the last record was followed by record_sep, rather than the end of the input.
+    terminated: bool,
 
     last_len: usize,
     check_utf8: bool,
@@ -1477,6 +1483,7 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
             buf_len: 0,
             progress: 0,
             record_sep,
+            terminated: false,
             used_fields: FieldSet::all(),
             last_len: usize::max_value(),
             check_utf8,
@@ -1568,7 +1575,8 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk<WhitespaceOffsets>>>>
             cur_buf: UniqueBuf::new(0).into_buf(),
             buf_len: 0,
             progress: 0,
-            record_sep: 0, // unused
+            record_sep: b'\n',
+            terminated: false,
             used_fields: FieldSet::all(),
             last_len: usize::max_value(),
             check_utf8,
@@ -1604,6 +1612,7 @@ where
                 buf_len: 0,
                 progress: 0,
                 record_sep,
+                terminated: false,
                 last_len: usize::max_value(),
                 used_fields,
                 check_utf8,
@@ -1642,6 +1651,14 @@ where
         }
     }
 
+    fn record_terminator(&self) -> Str<'static> {
+        if self.terminated {
+            self.cur_buf.slice_to_str(self.progress - 1, self.progress)
+        } else {
+            Str::default()
+        }
+    }
+
     fn next_file(&mut self) -> Result<bool> {
         self.cur_chunk = C::default();
         self.cur_buf = UniqueBuf::new(0).into_buf();
@@ -1716,13 +1733,19 @@ where
         if is_eof && br.progress == br.buf_len {
             *line = Str::default();
             br.last_len = 0;
+            br.terminated = false;
             debug_assert!(!changed);
             return Ok(false);
         }
     }
+    let line_start = br.progress;
     let (next_line, consumed) = unsafe { br.consume_line(fields) };
     *line = next_line;
     br.last_len = consumed;
+    // The final record in the input need not be followed by a separator.
+    let end = line_start + consumed;
+    br.terminated =
+        consumed > 0 && end <= br.buf_len && br.cur_buf.as_bytes()[end - 1] == br.record_sep;
     Ok(changed)
 }
 
@@ -0,0 +1,229 @@
+//! Splitting of fixed-width input, where each field occupies a given number of columns.
+//!
+//! This backs both the `FIELDWIDTHS` variable and the `-i fixed:...` input format. The format
+//! follows gawk: a list of widths, each of which may be preceded by a number of columns to skip
+//! (`2:5` skips two columns and then reads a field of width five), optionally followed by a `*`
+//! field holding the rest of the record.
+use std::io::Read;
+use std::str;
+
+use crate::common::Result;
+use crate::pushdown::FieldSet;
+use crate::runtime::{str_impl::Str, utf8, RegexCache};
+
+use super::{regex::RegexSplitter, DefaultLine, LineReader};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldWidths {
+    // (columns to skip, field width) pairs, in order.
+    widths: Vec<(usize, usize)>,
+    // Whether a final field holds whatever is left of the record.
+    rest: bool,
+    // Whether widths count UTF-8 characters rather than bytes.
+    chars: bool,
+}
+
+impl FieldWidths {
+    /// Parse a list of field widths separated by whitespace or commas.
+    pub fn parse(spec: &[u8], chars: bool) -> Result<FieldWidths> {
+        let spec = match str::from_utf8(spec) {
+            Ok(s) => s,
+            Err(_) => return err!("invalid field widths: not valid UTF-8"),
+        };
+        let mut widths = Vec::new();
+        let mut rest = false;
+        let mut items = spec
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|s| !s.is_empty())
+            .peekable();
+        while let Some(item) = items.next() {
+            if item == "*" {
+                if items.peek().is_some() {
+                    return err!("invalid field widths {:?}: `*` must come last", spec);
+                }
+                rest = true;
+                break;
+            }
+            let parse_num = |s: &str| match s.parse::<usize>() {
+                Ok(n) => Ok(n),
+                Err(_) => err!("invalid field width {:?} in {:?}", item, spec),
+            };
+            let (skip, width) = match item.find(':') {
+                Some(ix) => (parse_num(&item[..ix])?, parse_num(&item[ix + 1..])?),
+                None => (0, parse_num(item)?),
+            };
+            widths.push((skip, width));
+        }
+        if widths.is_empty() && !rest {
+            return err!("empty field widths");
+        }
+        Ok(FieldWidths {
+            widths,
+            rest,
+            chars,
+        })
+    }
+
+    // The offset `n` bytes or characters past `pos` in `bs`, clamped to the length of `bs`.
+    fn advance(&self, bs: &[u8], pos: usize, n: usize) -> usize {
+        if !self.chars {
+            return std::cmp::min(pos.saturating_add(n), bs.len());
+        }
+        if pos >= bs.len() {
+            return bs.len();
+        }
+        match utf8::char_offsets(&bs[pos..]).nth(n) {
+            Some(off) => pos + off,
+            None => bs.len(),
+        }
+    }
+
+    /// Pass each field of `s` to `push`. Fields that start past the end of `s` are omitted, and
+    /// fields not present in `used_fields` are passed as empty strings without being sliced out of
+    /// `s`.
+    pub fn split<'a>(&self, s: &Str<'a>, used_fields: &FieldSet, mut push: impl FnMut(Str<'a>)) {
+        s.with_bytes(|bs| {
+            let mut pos = 0;
+            let mut cur_field = 1;
+            let mut emit = |start: usize, end: usize| {
+                push(if used_fields.get(cur_field) {
+                    s.slice(start, end)
+                } else {
+                    Str::default()
+                });
+                cur_field += 1;
+            };
+            for (skip, width) in self.widths.iter().cloned() {
+                let start = self.advance(bs, pos, skip);
+                if start >= bs.len() {
+                    return;
+                }
+                pos = self.advance(bs, start, width);
+                emit(start, pos);
+            }
+            if self.rest && pos < bs.len() {
+                emit(pos, bs.len());
+            }
+        })
+    }
+}
+
+/// Reads records like `RegexSplitter`, splitting each one into fixed-width fields as it is read.
+pub struct FixedWidthReader<R> {
+    inner: RegexSplitter<R>,
+    widths: FieldWidths,
+}
+
+impl<R: Read> FixedWidthReader<R> {
+    pub fn new(
+        r: R,
+        widths: FieldWidths,
+        chunk_size: usize,
+        name: impl Into<Str<'static>>,
+        check_utf8: bool,
+    ) -> Self {
+        FixedWidthReader {
+            inner: RegexSplitter::new(r, chunk_size, name, check_utf8),
+            widths,
+        }
+    }
+
+    fn split(&self, line: &mut DefaultLine) {
+        let DefaultLine {
+            line,
+            used_fields,
+            fields,
+            ..
+        } = line;
+        self.widths.split(line, used_fields, |f| fields.push(f));
+    }
+}
+
+impl<R: Read> LineReader for FixedWidthReader<R> {
+    type Line = DefaultLine;
+    fn filename(&self) -> Str<'static> {
+        self.inner.filename()
+    }
+    fn check_utf8(&self) -> bool {
+        self.inner.check_utf8()
+    }
+    fn read_line_reuse<'a, 'b: 'a>(
+        &'b mut self,
+        pat: &Str,
+        rc: &mut RegexCache,
+        old: &'a mut Self::Line,
+    ) -> Result<bool> {
+        let changed = self.inner.read_line_reuse(pat, rc, old)?;
+        self.split(old);
+        Ok(changed)
+    }
+    fn read_line(&mut self, pat: &Str, rc: &mut RegexCache) -> Result<(bool, Self::Line)> {
+        let (changed, mut line) = self.inner.read_line(pat, rc)?;
+        self.split(&mut line);
+        Ok((changed, line))
+    }
+    fn read_state(&self) -> i64 {
+        self.inner.read_state()
+    }
+    fn record_terminator(&self) -> Str<'static> {
+        self.inner.record_terminator()
+    }
+    fn next_file(&mut self) -> Result<bool> {
+        self.inner.next_file()
+    }
+    fn set_used_fields(&mut self, used_fields: &FieldSet) {
+        self.inner.set_used_fields(used_fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split_all(spec: &str, chars: bool, s: &str) -> Vec<Vec<u8>> {
+        let widths = FieldWidths::parse(spec.as_bytes(), chars).unwrap();
+        let mut res = Vec::new();
+        widths.split(&Str::from(s), &FieldSet::all(), |f| {
+            res.push(f.with_bytes(|bs| bs.to_vec()))
+        });
+        res
+    }
+
+    #[test]
+    fn fixed_width_split() {
+        assert_eq!(
+            split_all("3 2", false, "abcdefg"),
+            vec![b"abc".to_vec(), b"de".to_vec()]
+        );
+        assert_eq!(
+            split_all("3,2,*", false, "abcdefg"),
+            vec![b"abc".to_vec(), b"de".to_vec(), b"fg".to_vec()]
+        );
+        assert_eq!(
+            split_all("1:2 2:1", false, "abcdefg"),
+            vec![b"bc".to_vec(), b"f".to_vec()]
+        );
+        assert_eq!(
+            split_all("3 3 3", false, "abcd"),
+            vec![b"abc".to_vec(), b"d".to_vec()]
+        );
+        assert_eq!(split_all("2 *", false, "ab"), vec![b"ab".to_vec()]);
+        assert_eq!(split_all("2 *", false, ""), Vec::<Vec<u8>>::new());
+        assert_eq!(
+            split_all("2 1", false, "héllo"),
+            vec![b"h\xc3".to_vec(), b"\xa9".to_vec()]
+        );
+        assert_eq!(
+            split_all("2 1", true, "héllo"),
+            vec!["hé".as_bytes().to_vec(), b"l".to_vec()]
+        );
+    }
+
+    #[test]
+    fn fixed_width_parse_errors() {
+        assert!(FieldWidths::parse(b"", false).is_err());
+        assert!(FieldWidths::parse(b"* 2", false).is_err());
+        assert!(FieldWidths::parse(b"2 x", false).is_err());
+        assert!(FieldWidths::parse(b"2:", false).is_err());
+    }
+}
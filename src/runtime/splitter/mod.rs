@@ -7,6 +7,7 @@
 // TODO: add padding to the linereader trait
 pub mod batch;
 pub mod chunk;
+pub mod fixed;
 pub mod regex;
 
 use super::str_impl::{Buf, Str, UniqueBuf};
@@ -16,10 +17,30 @@ use crate::common::Result;
 use crate::pushdown::FieldSet;
 
 use std::io::{ErrorKind, Read};
+use std::sync::Arc;
 
 // We have several implementations of "read and split a line"; they are governed by the LineReader
 // and Line traits.
 
+/// How a record is divided into fields.
+///
+/// This is passed to each `Line` method rather than selecting a separate `Line` type: a
+/// reader's line type is fixed when the program is compiled, but which of `FS`, `FPAT` and
+/// `FIELDWIDTHS` is in effect depends on which was assigned last, and can change between
+/// records.
+#[derive(Clone, Debug)]
+pub enum FieldSplit<'a> {
+    /// Fields are separated by matches of a pattern (i.e. `FS`).
+    Sep(Str<'a>),
+    /// Fields are separated by runs of blanks and newlines, ignoring leading and trailing
+    /// whitespace. This is what the default `FS` means in paragraph mode (when `RS` is empty).
+    Whitespace,
+    /// Fields are the successive matches of a pattern (i.e. `FPAT`).
+    Pat(Str<'a>),
+    /// Fields have fixed widths (i.e. `FIELDWIDTHS`).
+    Widths(Arc<fixed::FieldWidths>),
+}
+
 pub trait Line<'a>: Default {
     fn join_cols<F>(
         &mut self,
@@ -31,9 +52,21 @@ pub trait Line<'a>: Default {
     ) -> Result<Str<'a>>
     where
         F: FnMut(Str<'static>) -> Str<'static>;
-    fn nf(&mut self, pat: &Str, rc: &mut RegexCache) -> Result<usize>;
-    fn get_col(&mut self, col: Int, pat: &Str, ofs: &Str, rc: &mut RegexCache) -> Result<Str<'a>>;
-    fn set_col(&mut self, col: Int, s: &Str<'a>, pat: &Str, rc: &mut RegexCache) -> Result<()>;
+    fn nf(&mut self, split: &FieldSplit, rc: &mut RegexCache) -> Result<usize>;
+    fn get_col(
+        &mut self,
+        col: Int,
+        split: &FieldSplit,
+        ofs: &Str,
+        rc: &mut RegexCache,
+    ) -> Result<Str<'a>>;
+    fn set_col(
+        &mut self,
+        col: Int,
+        s: &Str<'a>,
+        split: &FieldSplit,
+        rc: &mut RegexCache,
+    ) -> Result<()>;
 }
 
 pub trait LineReader: Sized {
@@ -61,7 +94,22 @@ pub trait LineReader: Sized {
         std::mem::swap(old, &mut new);
         Ok(changed)
     }
+    // Like read_line_reuse, but stop at the end of the current file rather than moving on to the
+    // next one: read_state then reports EOF until next_file is called. Only readers chaining
+    // several files need to override this.
+    fn read_line_in_file<'a, 'b: 'a>(
+        &'b mut self,
+        pat: &Str,
+        rc: &mut RegexCache,
+        old: &'a mut Self::Line,
+    ) -> Result<()> {
+        self.read_line_reuse(pat, rc, old)?;
+        Ok(())
+    }
     fn read_state(&self) -> i64;
+    // The text matched by the record separator at the end of the most recently read record, or
+    // the empty string if the record ended at the end of the input. This is the value of RT.
+    fn record_terminator(&self) -> Str<'static>;
     fn next_file(&mut self) -> Result<bool>;
     fn set_used_fields(&mut self, used_fields: &FieldSet);
     // Whether or not this LineReader is configured to check for valid UTF-8. This is used to
@@ -112,9 +160,9 @@ impl Default for DefaultLine {
 }
 
 impl DefaultLine {
-    fn split_if_needed(&mut self, pat: &Str, rc: &mut RegexCache) -> Result<()> {
-        if self.fields.len() == 0 {
-            rc.split_regex(pat, &self.line, &self.used_fields, &mut self.fields)?;
+    fn split_if_needed(&mut self, split: &FieldSplit, rc: &mut RegexCache) -> Result<()> {
+        if self.fields.is_empty() {
+            rc.split_fields(split, &self.line, &self.used_fields, &mut self.fields)?;
         }
         Ok(())
     }
@@ -142,11 +190,17 @@ impl<'a> Line<'a> for DefaultLine {
             .join(self.fields[start..end].iter().cloned().map(trans))
             .upcast())
     }
-    fn nf(&mut self, pat: &Str, rc: &mut RegexCache) -> Result<usize> {
-        self.split_if_needed(pat, rc)?;
+    fn nf(&mut self, split: &FieldSplit, rc: &mut RegexCache) -> Result<usize> {
+        self.split_if_needed(split, rc)?;
         Ok(self.fields.len())
     }
-    fn get_col(&mut self, col: Int, pat: &Str, ofs: &Str, rc: &mut RegexCache) -> Result<Str<'a>> {
+    fn get_col(
+        &mut self,
+        col: Int,
+        split: &FieldSplit,
+        ofs: &Str,
+        rc: &mut RegexCache,
+    ) -> Result<Str<'a>> {
         if col < 0 {
             return err!("attempt to access field {}; field must be nonnegative", col);
         }
@@ -163,7 +217,7 @@ impl<'a> Line<'a> for DefaultLine {
                 // this doesn't happen again for a while.
                 let old_set = std::mem::replace(&mut self.used_fields, FieldSet::all());
                 let mut new_vec = Vec::with_capacity(self.fields.len());
-                rc.split_regex(pat, &self.line, &self.used_fields, &mut new_vec)?;
+                rc.split_fields(split, &self.line, &self.used_fields, &mut new_vec)?;
 
                 for (i, field) in self.fields.iter().enumerate().rev() {
                     if i >= new_vec.len() {
@@ -180,7 +234,7 @@ impl<'a> Line<'a> for DefaultLine {
             self.diverged = false;
             res
         } else {
-            self.split_if_needed(pat, rc)?;
+            self.split_if_needed(split, rc)?;
             self.fields
                 .get((col - 1) as usize)
                 .cloned()
@@ -188,7 +242,13 @@ impl<'a> Line<'a> for DefaultLine {
         };
         Ok(res.upcast())
     }
-    fn set_col(&mut self, col: Int, s: &Str<'a>, pat: &Str, rc: &mut RegexCache) -> Result<()> {
+    fn set_col(
+        &mut self,
+        col: Int,
+        s: &Str<'a>,
+        split: &FieldSplit,
+        rc: &mut RegexCache,
+    ) -> Result<()> {
         if col == 0 {
             self.line = s.clone().unmoor();
             self.fields.clear();
@@ -197,7 +257,7 @@ impl<'a> Line<'a> for DefaultLine {
         if col < 0 {
             return err!("attempt to access field {}; field must be nonnegative", col);
         }
-        self.split_if_needed(pat, rc)?;
+        self.split_if_needed(split, rc)?;
         let col = col as usize - 1;
         if col >= self.fields.len() {
             self.fields.resize_with(col + 1, Str::default);
@@ -256,26 +316,51 @@ where
             }
         };
         let changed = cur.read_line_reuse(pat, rc, old)?;
-        if cur.read_state() == 0 /* EOF */ && self.next_file()? {
-            self.read_line_reuse(pat, rc, old)?;
+        // At the end of a file, move on to the next one (if any).
+        if cur.read_state() == 0 {
+            if self.next_file()? {
+                self.read_line_reuse(pat, rc, old)?;
+            } else {
+                *old = Default::default();
+            }
             Ok(true)
         } else {
             Ok(changed)
         }
     }
+    fn read_line_in_file<'a, 'b: 'a>(
+        &'b mut self,
+        pat: &Str,
+        rc: &mut RegexCache,
+        old: &'a mut Self::Line,
+    ) -> Result<()> {
+        match self.0.last_mut() {
+            Some(cur) => {
+                cur.read_line_reuse(pat, rc, old)?;
+            }
+            None => *old = Default::default(),
+        }
+        Ok(())
+    }
     fn read_state(&self) -> i64 {
         match self.0.last() {
             Some(cur) => cur.read_state(),
             None => 0, /* EOF */
         }
     }
+    fn record_terminator(&self) -> Str<'static> {
+        self.0
+            .last()
+            .map(LineReader::record_terminator)
+            .unwrap_or_else(Str::default)
+    }
     fn next_file(&mut self) -> Result<bool> {
         Ok(match self.0.last_mut() {
             Some(e) => {
                 if !e.next_file()? {
                     self.0.pop();
                 }
-                true
+                self.0.len() != 0
             }
             None => false,
         })
@@ -323,6 +408,10 @@ struct Reader<R> {
 
     // Validate input as UTF-8
     check_utf8: bool,
+
+    // Return whatever data is available rather than waiting for a full chunk. Only a read of zero
+    // bytes signals EOF. Used for coprocesses, which may wait on us before writing more.
+    interactive: bool,
 }
 
 fn read_to_slice(r: &mut impl Read, mut buf: &mut [u8]) -> Result<usize> {
@@ -348,6 +437,20 @@ fn read_to_slice(r: &mut impl Read, mut buf: &mut [u8]) -> Result<usize> {
     Ok(read)
 }
 
+// Like read_to_slice, but returns after the first successful read.
+fn read_some(r: &mut impl Read, buf: &mut [u8]) -> Result<usize> {
+    loop {
+        match r.read(buf) {
+            Ok(n) => return Ok(n),
+            Err(e) => match e.kind() {
+                ErrorKind::Interrupted => continue,
+                ErrorKind::UnexpectedEof => return Ok(0),
+                _ => return err!("read error {}", e),
+            },
+        }
+    }
+}
+
 impl<R: Read> Reader<R> {
     pub(crate) fn new(r: R, chunk_size: usize, padding: usize, check_utf8: bool) -> Self {
         let res = Reader {
@@ -361,6 +464,7 @@ impl<R: Read> Reader<R> {
             state: ReaderState::OK,
             last_len: 0,
             check_utf8,
+            interactive: false,
         };
         res
     }
@@ -437,9 +541,14 @@ impl<R: Read> Reader<R> {
             );
         }
         let mut bytes = &mut data.as_mut_bytes()[..self.chunk_size];
-        let bytes_read = plen + read_to_slice(&mut self.inner, &mut bytes[plen..])?;
+        let bytes_read = plen
+            + if self.interactive {
+                read_some(&mut self.inner, &mut bytes[plen..])?
+            } else {
+                read_to_slice(&mut self.inner, &mut bytes[plen..])?
+            };
         if bytes_read != self.chunk_size {
-            done = true;
+            done = !self.interactive || bytes_read == plen;
             bytes = &mut bytes[..bytes_read];
         }
         let mut ulen = bytes.len();
@@ -3,11 +3,18 @@ use std::io::Read;
 
 use crate::common::Result;
 use crate::pushdown::FieldSet;
-use crate::runtime::Str;
+use crate::runtime::{RegexCache, Str};
+use lazy_static::lazy_static;
 use regex::bytes::Regex;
 
 use super::{DefaultLine, LineReader, Reader, ReaderState};
 
+lazy_static! {
+    // Separates records in "paragraph mode" (when RS is empty). Runs of trailing newlines at the
+    // end of the input are consumed along with the last record.
+    static ref PARAGRAPH_SEP: Regex = Regex::new(r"\n\n+|\n+\z").unwrap();
+}
+
 // TODO: this can probably just be "Splitter"
 pub struct RegexSplitter<R> {
     reader: Reader<R>,
@@ -15,6 +22,8 @@ pub struct RegexSplitter<R> {
     used_fields: FieldSet,
     // Used to trigger updating FILENAME on the first read.
     start: bool,
+    // The separator matched at the end of the last record.
+    terminator: Str<'static>,
 }
 
 impl<R: Read> LineReader for RegexSplitter<R> {
@@ -42,26 +51,27 @@ impl<R: Read> LineReader for RegexSplitter<R> {
         self.start = false;
         old.diverged = false;
         old.fields.clear();
-        rc.with_regex(pat, |re| {
-            old.line = self.read_line_regex(re);
-        })?;
+        old.line = self.read_record(pat, rc)?;
         Ok(/* file changed */ start)
     }
 
     fn read_line(&mut self, pat: &Str, rc: &mut super::RegexCache) -> Result<(bool, Self::Line)> {
         let start = self.start;
         self.start = false;
-        let line = rc.with_regex(pat, |re| DefaultLine {
-            line: self.read_line_regex(re),
+        let line = DefaultLine {
+            line: self.read_record(pat, rc)?,
             fields: Default::default(),
             used_fields: self.used_fields.clone(),
             diverged: false,
-        })?;
+        };
         Ok((/* file changed */ start, line))
     }
     fn read_state(&self) -> i64 {
         self.reader.read_state()
     }
+    fn record_terminator(&self) -> Str<'static> {
+        self.terminator.clone()
+    }
     fn next_file(&mut self) -> Result<bool> {
         // There is just one file. Set EOF.
         self.reader.force_eof();
@@ -79,7 +89,68 @@ impl<R: Read> RegexSplitter<R> {
             name: name.into(),
             used_fields: FieldSet::all(),
             start: true,
+            terminator: Str::default(),
+        }
+    }
+
+    /// A splitter that yields records as soon as they are available, rather than waiting to fill
+    /// a full chunk of input. A separator at the end of the available input is assumed to be
+    /// complete.
+    pub fn new_interactive(
+        r: R,
+        chunk_size: usize,
+        name: impl Into<Str<'static>>,
+        check_utf8: bool,
+    ) -> Self {
+        let mut res = Self::new(r, chunk_size, name, check_utf8);
+        res.reader.interactive = true;
+        res
+    }
+
+    /// Read the next record separated by `pat`, the current value of `RS`. An empty `pat` selects
+    /// "paragraph mode," where records are separated by one or more blank lines.
+    pub fn read_record(&mut self, pat: &Str, rc: &mut RegexCache) -> Result<Str<'static>> {
+        if pat.is_empty() {
+            Ok(self.read_paragraph())
+        } else {
+            rc.with_regex(pat, |re| self.read_line_regex(re))
+        }
+    }
+
+    pub fn read_paragraph(&mut self) -> Str<'static> {
+        let (res, consumed) = self.read_paragraph_inner();
+        self.reader.last_len = consumed;
+        res
+    }
+
+    fn read_paragraph_inner(&mut self) -> (Str<'static>, usize) {
+        // Newlines at the start of the input do not delimit an (empty) record; skip past them
+        // before searching for the separator.
+        let mut skipped = 0;
+        loop {
+            if self.reader.is_eof() {
+                // Input consisting only of newlines contains no records.
+                self.terminator = Str::default();
+                return (Str::default(), 0);
+            }
+            let s = &self.reader.buf.as_bytes()[self.reader.start..self.reader.end];
+            let newlines = s.iter().take_while(|b| **b == b'\n').count();
+            self.reader.start += newlines;
+            skipped += newlines;
+            if self.reader.start < self.reader.end {
+                break;
+            }
+            match self.reader.reset() {
+                Ok(_) => continue,
+                Err(_) => {
+                    self.reader.state = ReaderState::ERROR;
+                    self.terminator = Str::default();
+                    return (Str::default(), 0);
+                }
+            }
         }
+        let (res, consumed) = self.read_line_inner(&PARAGRAPH_SEP);
+        (res, skipped + consumed)
     }
 
     pub fn read_line_regex(&mut self, pat: &Regex) -> Str<'static> {
@@ -90,6 +161,9 @@ impl<R: Read> RegexSplitter<R> {
     }
 
     fn read_line_inner(&mut self, pat: &Regex) -> (Str<'static>, usize) {
+        // Records that are not followed by a separator (i.e. at the end of the input) have an
+        // empty terminator. The branches below that find a separator overwrite this.
+        self.terminator = Str::default();
         if self.reader.is_eof() {
             return (Str::default(), 0);
         }
@@ -100,12 +174,18 @@ impl<R: Read> RegexSplitter<R> {
             // the start and end pointers.
             match pat.find(s).map(|m| (m.start(), m.end())) {
                 // We need this check in case the regex matches across a chunk boundary.
-                Some((start, end)) if end + self.reader.start < self.reader.end => {
+                Some((start, end))
+                    if end + self.reader.start < self.reader.end || self.reader.interactive =>
+                {
                     // Valid offsets guaranteed by correctness of regex `find`.
                     let res = self
                         .reader
                         .buf
                         .slice_to_str(self.reader.start, self.reader.start + start);
+                    self.terminator = self
+                        .reader
+                        .buf
+                        .slice_to_str(self.reader.start + start, self.reader.start + end);
                     self.reader.start += end;
                     return (res, end);
                 }
@@ -155,6 +235,10 @@ impl<R: Read> RegexSplitter<R> {
                                 .reader
                                 .buf
                                 .slice_to_str(self.reader.start, self.reader.start + start);
+                            self.terminator = self
+                                .reader
+                                .buf
+                                .slice_to_str(self.reader.start + start, self.reader.start + end);
                             self.reader.start += end;
                             (res, end)
                         }
@@ -226,6 +310,33 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_paragraph_split() {
+        // Use long runs of newlines and a small chunk size so that separators straddle chunk
+        // boundaries.
+        let mut bs = String::from("\n\n\n");
+        let mut expected = Vec::new();
+        for i in 0..200 {
+            let para = format!("para {}\nline two", i);
+            bs.push_str(&para);
+            bs.extend((0..(i % 7) + 2).map(|_| '\n'));
+            expected.push(para);
+        }
+        let c = Cursor::new(bs);
+        let mut rdr =
+            RegexSplitter::new(c, /*chunk_size=*/ 64, "", /*check_utf8=*/ false);
+        let mut paras = Vec::new();
+        loop {
+            let para = rdr.read_paragraph().upcast();
+            if rdr.read_state() != ReaderState::OK as i64 {
+                break;
+            }
+            paras.push(para);
+        }
+        let expected: Vec<Str> = expected.iter().map(|s| s.as_str().into()).collect();
+        assert_eq!(paras, expected);
+    }
+
     #[test]
     fn test_line_split() {
         let chunk_size = 1 << 9;
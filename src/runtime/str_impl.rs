@@ -7,7 +7,7 @@
 ///
 /// TODO explain more about what is going on here.
 use crate::pushdown::FieldSet;
-use crate::runtime::{Float, Int};
+use crate::runtime::{utf8, Float, Int};
 
 use regex::bytes::Regex;
 use smallvec::SmallVec;
@@ -457,6 +457,53 @@ impl<'a> Str<'a> {
         });
     }
 
+    /// Like `split`, but fields are the successive non-overlapping matches of `pat` (as with
+    /// `FPAT`) rather than the text between them. `push` is passed the text preceding each field
+    /// along with the field itself; the text after the last field is returned.
+    pub fn split_pattern(
+        &self,
+        pat: &Regex,
+        mut push: impl FnMut(Str<'a>, Str<'a>),
+        used_fields: &FieldSet,
+    ) -> Str<'a> {
+        if self.is_empty() {
+            return Str::default();
+        }
+        self.with_bytes(|s| {
+            let mut pos = 0;
+            let mut prev = 0;
+            let mut cur_field = 1;
+            // An empty match directly after a non-empty one does not start a new field.
+            let mut last_nonempty_end = None;
+            while pos <= s.len() {
+                let m = match pat.find_at(s, pos) {
+                    Some(m) => m,
+                    None => break,
+                };
+                let is_empty = m.start() == m.end();
+                if is_empty && last_nonempty_end == Some(m.start()) {
+                    pos = m.start() + 1;
+                    continue;
+                }
+                let field = if used_fields.get(cur_field) {
+                    self.slice(m.start(), m.end())
+                } else {
+                    Str::default()
+                };
+                push(self.slice(prev, m.start()), field);
+                cur_field += 1;
+                prev = m.end();
+                if is_empty {
+                    pos = m.end() + 1;
+                } else {
+                    pos = m.end();
+                    last_nonempty_end = Some(m.end());
+                }
+            }
+            self.slice(prev, s.len())
+        })
+    }
+
     pub fn join_slice<'other, 'b>(&self, inps: &[Str<'other>]) -> Str<'b> {
         // We've noticed that performance of `join_slice` is very sensitive to the number of
         // `realloc` calls that happen when pushing onto DynamicBufHeap, so we spend the extra time
@@ -511,6 +558,32 @@ impl<'a> Str<'a> {
         })
     }
 
+    // The Unicode-aware variants fall back to the ASCII ones for strings that are not valid UTF-8.
+
+    pub fn to_lower<'b>(&self) -> Str<'b> {
+        self.with_bytes(|bs| {
+            if utf8::is_ascii(bs) {
+                return self.to_lower_ascii();
+            }
+            match str::from_utf8(bs) {
+                Ok(s) => Str::from(s.to_lowercase()),
+                Err(_) => self.to_lower_ascii(),
+            }
+        })
+    }
+
+    pub fn to_upper<'b>(&self) -> Str<'b> {
+        self.with_bytes(|bs| {
+            if utf8::is_ascii(bs) {
+                return self.to_upper_ascii();
+            }
+            match str::from_utf8(bs) {
+                Ok(s) => Str::from(s.to_uppercase()),
+                Err(_) => self.to_upper_ascii(),
+            }
+        })
+    }
+
     fn map_bytes<'b>(&self, mut f: impl FnMut(u8) -> u8) -> Str<'b> {
         self.with_bytes(|bs| {
             if bs.len() <= MAX_INLINE_SIZE {
@@ -567,10 +640,55 @@ impl<'a> Str<'a> {
         })
     }
 
+    /// Substitution with the semantics of gawk's `gensub`. `how` is either a string starting
+    /// with "g" or "G" (replace all matches), or a number indicating which match to replace.
+    /// Unlike `subst_first` and `subst_all`, `subst` can refer to capture groups via `\1`
+    /// through `\9` (`\0` and `&` both refer to the entire match).
+    pub fn gen_subst(&self, pat: &Regex, subst: &Str<'a>, how: &Str<'a>) -> Str<'a> {
+        let nth = how.with_bytes(|h| match h.first() {
+            Some(b'g') | Some(b'G') => None,
+            // gawk treats nonpositive values as 1.
+            _ => Some(std::cmp::max(crate::runtime::strtoi(h), 1) as usize),
+        });
+        self.with_bytes(|s| {
+            subst.with_bytes(|subst| {
+                let mut buf = DynamicBuf::new(0);
+                let mut prev = 0;
+                let mut replaced = false;
+                for (i, caps) in pat.captures_iter(s).enumerate() {
+                    if let Some(n) = nth {
+                        if i + 1 < n {
+                            continue;
+                        }
+                        if i + 1 > n {
+                            break;
+                        }
+                    }
+                    let m = caps.get(0).unwrap();
+                    buf.write_all(&s[prev..m.start()]).unwrap();
+                    process_match_groups(&caps, subst, &mut buf).unwrap();
+                    prev = m.end();
+                    replaced = true;
+                }
+                if !replaced {
+                    self.clone()
+                } else {
+                    buf.write_all(&s[prev..s.len()]).unwrap();
+                    unsafe { buf.into_str() }
+                }
+            })
+        })
+    }
+
     pub fn len(&self) -> usize {
         unsafe { self.rep_mut() }.len()
     }
 
+    /// The number of characters in the string, treating it as UTF-8.
+    pub fn char_len(&self) -> usize {
+        self.with_bytes(utf8::char_count)
+    }
+
     pub fn concat(left: Str<'a>, right: Str<'a>) -> Str<'a> {
         if left.is_empty() {
             mem::forget(left);
@@ -720,6 +838,25 @@ impl<'a> Str<'a> {
         unsafe { self.slice_internal(from, to) }
     }
 
+    /// Like `slice`, but `from` and `to` count characters rather than bytes. Offsets past the end
+    /// of the string are clamped to its length.
+    pub fn slice_chars(&self, from: usize, to: usize) -> Str<'a> {
+        let (start, end) = self.with_bytes(|bs| {
+            if utf8::is_ascii(bs) {
+                return (std::cmp::min(from, bs.len()), std::cmp::min(to, bs.len()));
+            }
+            let mut offsets = utf8::char_offsets(bs).chain(std::iter::once(bs.len()));
+            let start = offsets.nth(from).unwrap_or(bs.len());
+            let end = if to > from {
+                offsets.nth(to - from - 1).unwrap_or(bs.len())
+            } else {
+                start
+            };
+            (start, end)
+        });
+        self.slice(start, end)
+    }
+
     // Why is [with_bytes] safe and [force] unsafe? Let's go case-by-case for the state of `self`
     // EMPTY:  no data is passed into `f`.
     // BOXED:  The function signature ensures that no string references can "escape" `f`, and `self`
@@ -1323,6 +1460,50 @@ fn process_match(matched: &[u8], subst: &[u8], w: &mut impl Write) -> io::Result
     Ok(())
 }
 
+/// Helper function for `gen_subst`: handles '&' along with the `\0`-`\9` backreferences.
+fn process_match_groups(
+    caps: &regex::bytes::Captures,
+    subst: &[u8],
+    w: &mut impl Write,
+) -> io::Result<()> {
+    if memchr::memchr2(b'&', b'\\', subst).is_none() {
+        w.write_all(subst)?;
+        return Ok(());
+    }
+    let mut i = 0;
+    while i < subst.len() {
+        match subst[i] {
+            b'&' => {
+                w.write_all(caps.get(0).unwrap().as_bytes())?;
+            }
+            b'\\' if i + 1 < subst.len() => {
+                i += 1;
+                match subst[i] {
+                    c @ b'0'..=b'9' => {
+                        if let Some(m) = caps.get((c - b'0') as usize) {
+                            w.write_all(m.as_bytes())?;
+                        }
+                    }
+                    b'&' => {
+                        w.write_all(&[b'&'])?;
+                    }
+                    b'\\' => {
+                        w.write_all(&[b'\\'])?;
+                    }
+                    c => {
+                        w.write_all(&[b'\\', c])?;
+                    }
+                }
+            }
+            c => {
+                w.write_all(&[c])?;
+            }
+        }
+        i += 1;
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1418,6 +1599,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn char_slicing() {
+        let s: Str = "añb東京c".into();
+        assert_eq!(s.char_len(), 6);
+        assert_eq!(s.slice_chars(1, 3), Str::from("ñb"));
+        assert_eq!(s.slice_chars(3, 100), Str::from("東京c"));
+        assert_eq!(s.slice_chars(6, 8), Str::default());
+        assert_eq!(s.slice_chars(2, 2), Str::default());
+        let ascii: Str = "abcdef".into();
+        assert_eq!(ascii.slice_chars(4, 10), Str::from("ef"));
+        // Bytes that are not part of a valid character count individually.
+        let invalid = Str::from(&b"a\xa9b\xe6\x9dc"[..]);
+        assert_eq!(invalid.char_len(), 6);
+        assert_eq!(invalid.slice_chars(1, 2), Str::from(&b"\xa9"[..]));
+        assert_eq!(invalid.slice_chars(3, 5), Str::from(&b"\xe6\x9d"[..]));
+    }
+
     #[test]
     fn dynamic_string() {
         let mut d = DynamicBuf::new(0);
@@ -1479,6 +1677,22 @@ And this is the second part"#
         s6.with_bytes(|bs| assert_eq!(bs, b"hz&hbhc"));
         assert!(subbed);
     }
+
+    #[test]
+    fn gen_subst_groups() {
+        let s1: Str = "2021-03-04 and 2020-01-02".into();
+        let re = Regex::new(r"(\d+)-(\d+)-(\d+)").unwrap();
+        let repl: Str = r"\3/\2/\1".into();
+        let s2 = s1.gen_subst(&re, &repl, &"g".into());
+        s2.with_bytes(|bs| assert_eq!(bs, b"04/03/2021 and 02/01/2020"));
+        let s3 = s1.gen_subst(&re, &repl, &"2".into());
+        s3.with_bytes(|bs| assert_eq!(bs, b"2021-03-04 and 02/01/2020"));
+        let s4 = s1.gen_subst(&re, &repl, &"3".into());
+        assert_eq!(s1, s4);
+        let repl2: Str = r"<&|\0|\&|\\>".into();
+        let s5 = s1.gen_subst(&re, &repl2, &"1".into());
+        s5.with_bytes(|bs| assert_eq!(bs, b"<2021-03-04|2021-03-04|&|\\> and 2020-01-02"));
+    }
 }
 
 #[cfg(all(feature = "unstable", test))]
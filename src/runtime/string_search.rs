@@ -1,7 +1,7 @@
 //! Implementation of substring searches.
 //!
 //! This is a tiny wrapper on top of `memmem::find` from the `memchr` crate.
-use super::{Int, Str};
+use super::{utf8, Int, Str};
 use memchr::memmem;
 
 // 1-indexed, 0 on failure
@@ -11,3 +11,13 @@ pub fn index_substr<'a, 'b>(needle: &Str<'a>, haystack: &Str<'a>) -> Int {
         .map(|x| x as Int + 1)
         .unwrap_or(0)
 }
+
+/// Like `index_substr`, but the returned position counts characters rather than bytes.
+pub fn index_substr_chars<'a>(needle: &Str<'a>, haystack: &Str<'a>) -> Int {
+    needle
+        .with_bytes(|n| {
+            haystack.with_bytes(|h| memmem::find(h, n).map(|x| utf8::char_count(&h[..x])))
+        })
+        .map(|x| x as Int + 1)
+        .unwrap_or(0)
+}
@@ -0,0 +1,684 @@
+//! Support for the time-related builtins: `systime`, `strftime`, `mktime` and `strptime`.
+//!
+//! Calendar arithmetic for UTC is done directly, while conversions to and from local time go
+//! through libc's `localtime_r` and `mktime` so that they honor the `TZ` environment variable in
+//! the same way as other tools on the system. Formatting and parsing always use the "C" locale.
+use crate::runtime::{str_impl::DynamicBuf, Float, Int, Str};
+
+use std::convert::TryInto;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The format used by `strftime` when none is provided; this matches gawk's default.
+pub const DEFAULT_STRFTIME_FORMAT: &str = "%a %b %e %H:%M:%S %Z %Y";
+
+const DAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+// Formats tried in order by `strptime` when no format is given.
+const COMMON_FORMATS: &[&[u8]] = &[
+    b"%Y-%m-%dT%H:%M:%S %z",
+    b"%Y-%m-%dT%H:%M:%S",
+    b"%Y-%m-%d %H:%M:%S %z",
+    b"%Y-%m-%d %H:%M:%S",
+    b"%Y/%m/%d %H:%M:%S",
+    b"%Y-%m-%d",
+    b"%d/%b/%Y:%H:%M:%S %z",
+    b"%a, %d %b %Y %H:%M:%S %z",
+    b"%a %b %e %H:%M:%S %Z %Y",
+    b"%a %b %e %H:%M:%S %Y",
+];
+
+/// A broken-down time. Unlike `struct tm`, months and days of the year are 1-indexed and years
+/// are not offset from 1900.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Tm {
+    year: i64,
+    mon: i64,
+    mday: i64,
+    hour: i64,
+    min: i64,
+    sec: i64,
+    // 0 is Sunday
+    wday: i64,
+    yday: i64,
+    gmtoff: i64,
+    zone: String,
+}
+
+// Days since 1970-01-01 of the given (proleptic Gregorian) date. Months outside of [1, 12] are
+// normalized, and days are counted linearly from the first of the month. Returns `None` if the
+// result does not fit in an i64.
+fn days_from_civil(y: i64, m: i64, d: i64) -> Option<i64> {
+    let m0 = m.checked_sub(1)?;
+    let y = y.checked_add(m0.div_euclid(12))?;
+    let m = m0.rem_euclid(12) + 1;
+    let y = if m <= 2 { y.checked_sub(1)? } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = (m + 9) % 12;
+    let doy = (153 * mp + 2) / 5;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era.checked_mul(146097)?
+        .checked_add(doe - 719468)?
+        .checked_add(d.checked_sub(1)?)
+}
+
+fn is_leap(y: i64) -> bool {
+    (y % 4 == 0 && y % 100 != 0) || y % 400 == 0
+}
+
+fn utc_tm(ts: i64) -> Tm {
+    let days = ts.div_euclid(86400);
+    let secs = ts.rem_euclid(86400);
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let mday = doy - (153 * mp + 2) / 5 + 1;
+    let mon = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if mon <= 2 { 1 } else { 0 };
+    Tm {
+        year,
+        mon,
+        mday,
+        hour: secs / 3600,
+        min: secs / 60 % 60,
+        sec: secs % 60,
+        wday: (days + 4).rem_euclid(7),
+        // Any year reachable from an i64 timestamp is well within range here.
+        yday: days - days_from_civil(year, 1, 1).unwrap() + 1,
+        gmtoff: 0,
+        zone: "UTC".into(),
+    }
+}
+
+// Seconds since the epoch of `tm` in UTC, or `None` if that does not fit in an i64.
+fn utc_secs(tm: &Tm) -> Option<i64> {
+    days_from_civil(tm.year, tm.mon, tm.mday)?
+        .checked_mul(86400)?
+        .checked_add(tm.hour.checked_mul(3600)?)?
+        .checked_add(tm.min.checked_mul(60)?)?
+        .checked_add(tm.sec)
+}
+
+// Returns `None` if any field does not fit in the corresponding field of `struct tm`.
+#[cfg(unix)]
+fn to_libc(tm: &Tm, isdst: i64) -> Option<libc::tm> {
+    let mut res: libc::tm = unsafe { std::mem::zeroed() };
+    res.tm_year = tm.year.checked_sub(1900)?.try_into().ok()?;
+    res.tm_mon = tm.mon.checked_sub(1)?.try_into().ok()?;
+    res.tm_mday = tm.mday.try_into().ok()?;
+    res.tm_hour = tm.hour.try_into().ok()?;
+    res.tm_min = tm.min.try_into().ok()?;
+    res.tm_sec = tm.sec.try_into().ok()?;
+    res.tm_isdst = isdst.try_into().ok()?;
+    Some(res)
+}
+
+#[cfg(unix)]
+fn local_tm(ts: i64) -> Tm {
+    let t = ts as libc::time_t;
+    let mut res: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&t, &mut res) }.is_null() {
+        return utc_tm(ts);
+    }
+    let zone = if res.tm_zone.is_null() {
+        String::new()
+    } else {
+        unsafe { std::ffi::CStr::from_ptr(res.tm_zone) }
+            .to_string_lossy()
+            .into_owned()
+    };
+    Tm {
+        year: res.tm_year as i64 + 1900,
+        mon: res.tm_mon as i64 + 1,
+        mday: res.tm_mday as i64,
+        hour: res.tm_hour as i64,
+        min: res.tm_min as i64,
+        sec: res.tm_sec as i64,
+        wday: res.tm_wday as i64,
+        yday: res.tm_yday as i64 + 1,
+        gmtoff: res.tm_gmtoff as i64,
+        zone,
+    }
+}
+
+#[cfg(unix)]
+fn local_secs(tm: &Tm, isdst: i64) -> Option<i64> {
+    let mut tm = to_libc(tm, isdst)?;
+    // -1 is also the second before the epoch. mktime only fills in the weekday when it
+    // succeeds, so use that to tell the two apart.
+    tm.tm_wday = -1;
+    let t = unsafe { libc::mktime(&mut tm) };
+    if t == -1 && tm.tm_wday == -1 {
+        None
+    } else {
+        Some(t)
+    }
+}
+
+// Without libc's timezone support, local time is UTC.
+#[cfg(not(unix))]
+fn local_tm(ts: i64) -> Tm {
+    utc_tm(ts)
+}
+
+#[cfg(not(unix))]
+fn local_secs(tm: &Tm, _isdst: i64) -> Option<i64> {
+    utc_secs(tm)
+}
+
+/// The current time, in seconds since the epoch.
+pub fn systime() -> Int {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as Int,
+        Err(e) => -(e.duration().as_secs() as Int),
+    }
+}
+
+// The ISO 8601 week-based year and week number.
+fn iso_week(tm: &Tm) -> (i64, i64) {
+    fn weeks_in_year(y: i64) -> i64 {
+        let p = |y: i64| (y + y.div_euclid(4) - y.div_euclid(100) + y.div_euclid(400)) % 7;
+        if p(y) == 4 || p(y - 1) == 3 {
+            53
+        } else {
+            52
+        }
+    }
+    let iso_wday = (tm.wday + 6) % 7 + 1;
+    let week = (tm.yday - iso_wday + 10) / 7;
+    if week < 1 {
+        (tm.year - 1, weeks_in_year(tm.year - 1))
+    } else if week > weeks_in_year(tm.year) {
+        (tm.year + 1, 1)
+    } else {
+        (tm.year, week)
+    }
+}
+
+fn format_tm(fmt: &[u8], tm: &Tm, ts: i64, w: &mut impl Write) -> std::io::Result<()> {
+    let mut i = 0;
+    while i < fmt.len() {
+        if fmt[i] != b'%' || i + 1 == fmt.len() {
+            w.write_all(&fmt[i..i + 1])?;
+            i += 1;
+            continue;
+        }
+        let hour12 = if tm.hour % 12 == 0 { 12 } else { tm.hour % 12 };
+        match fmt[i + 1] {
+            b'a' => write!(w, "{}", &DAYS[tm.wday as usize][..3])?,
+            b'A' => write!(w, "{}", DAYS[tm.wday as usize])?,
+            b'b' | b'h' => write!(w, "{}", &MONTHS[tm.mon as usize - 1][..3])?,
+            b'B' => write!(w, "{}", MONTHS[tm.mon as usize - 1])?,
+            b'c' => format_tm(b"%a %b %e %H:%M:%S %Y", tm, ts, w)?,
+            b'C' => write!(w, "{:02}", tm.year.div_euclid(100))?,
+            b'd' => write!(w, "{:02}", tm.mday)?,
+            b'D' => format_tm(b"%m/%d/%y", tm, ts, w)?,
+            b'e' => write!(w, "{:2}", tm.mday)?,
+            b'F' => format_tm(b"%Y-%m-%d", tm, ts, w)?,
+            b'g' => write!(w, "{:02}", iso_week(tm).0.rem_euclid(100))?,
+            b'G' => write!(w, "{}", iso_week(tm).0)?,
+            b'H' => write!(w, "{:02}", tm.hour)?,
+            b'I' => write!(w, "{:02}", hour12)?,
+            b'j' => write!(w, "{:03}", tm.yday)?,
+            b'k' => write!(w, "{:2}", tm.hour)?,
+            b'l' => write!(w, "{:2}", hour12)?,
+            b'm' => write!(w, "{:02}", tm.mon)?,
+            b'M' => write!(w, "{:02}", tm.min)?,
+            b'n' => w.write_all(b"\n")?,
+            b'p' => w.write_all(if tm.hour < 12 { b"AM" } else { b"PM" })?,
+            b'r' => format_tm(b"%I:%M:%S %p", tm, ts, w)?,
+            b'R' => format_tm(b"%H:%M", tm, ts, w)?,
+            b's' => write!(w, "{}", ts)?,
+            b'S' => write!(w, "{:02}", tm.sec)?,
+            b't' => w.write_all(b"\t")?,
+            b'T' => format_tm(b"%H:%M:%S", tm, ts, w)?,
+            b'u' => write!(w, "{}", (tm.wday + 6) % 7 + 1)?,
+            b'U' => write!(w, "{:02}", (tm.yday + 6 - tm.wday) / 7)?,
+            b'V' => write!(w, "{:02}", iso_week(tm).1)?,
+            b'w' => write!(w, "{}", tm.wday)?,
+            b'W' => write!(w, "{:02}", (tm.yday + 6 - (tm.wday + 6) % 7) / 7)?,
+            b'x' => format_tm(b"%m/%d/%y", tm, ts, w)?,
+            b'X' => format_tm(b"%H:%M:%S", tm, ts, w)?,
+            b'y' => write!(w, "{:02}", tm.year.rem_euclid(100))?,
+            b'Y' => write!(w, "{}", tm.year)?,
+            b'z' => {
+                let off = tm.gmtoff.abs() / 60;
+                let sign = if tm.gmtoff < 0 { '-' } else { '+' };
+                write!(w, "{}{:02}{:02}", sign, off / 60, off % 60)?
+            }
+            b'Z' => w.write_all(tm.zone.as_bytes())?,
+            b'%' => w.write_all(b"%")?,
+            // Unknown conversions are printed as-is.
+            _ => w.write_all(&fmt[i..i + 2])?,
+        }
+        i += 2;
+    }
+    Ok(())
+}
+
+/// Implements `strftime(fmt, ts, utc)`: format the timestamp `ts` (in seconds since the epoch)
+/// according to `fmt`, in UTC if `utc` is set and in local time otherwise.
+pub fn strftime<'a>(fmt: &Str<'a>, ts: Int, utc: bool) -> Str<'a> {
+    let tm = if utc { utc_tm(ts) } else { local_tm(ts) };
+    let mut buf = DynamicBuf::new(0);
+    fmt.with_bytes(|fmt| format_tm(fmt, &tm, ts, &mut buf))
+        .unwrap();
+    unsafe { buf.into_str() }
+}
+
+/// Implements `mktime(spec, utc)`: convert a string of the form `"YYYY MM DD HH MM SS [DST]"` into
+/// seconds since the epoch. Values outside of their normal ranges are normalized, so (e.g.) a
+/// month of 13 is January of the following year. Returns -1 if `spec` is malformed or the result
+/// is out of range.
+pub fn mktime(spec: &Str, utc: bool) -> Int {
+    spec.with_bytes(|bs| {
+        let mut parts = Vec::with_capacity(7);
+        for word in bs
+            .split(|b| b.is_ascii_whitespace())
+            .filter(|w| !w.is_empty())
+        {
+            match std::str::from_utf8(word)
+                .ok()
+                .and_then(|s| s.parse::<i64>().ok())
+            {
+                Some(i) => parts.push(i),
+                None => return -1,
+            }
+        }
+        if parts.len() < 6 || parts.len() > 7 {
+            return -1;
+        }
+        let tm = Tm {
+            year: parts[0],
+            mon: parts[1],
+            mday: parts[2],
+            hour: parts[3],
+            min: parts[4],
+            sec: parts[5],
+            ..Default::default()
+        };
+        let secs = if utc {
+            utc_secs(&tm)
+        } else {
+            local_secs(&tm, parts.get(6).cloned().unwrap_or(-1))
+        };
+        secs.unwrap_or(-1)
+    })
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    tm: Tm,
+    frac: Float,
+    // The UTC offset in seconds, if the input specified one
+    offset: Option<i64>,
+    // The seconds since the epoch, if parsed directly via %s
+    epoch: Option<Float>,
+    pm: Option<bool>,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_space(&mut self) {
+        while let Some(b) = self.s.first() {
+            if !b.is_ascii_whitespace() {
+                break;
+            }
+            self.s = &self.s[1..];
+        }
+    }
+
+    fn num(&mut self, max_digits: usize) -> Option<i64> {
+        self.skip_space();
+        let digits = self
+            .s
+            .iter()
+            .take(max_digits)
+            .take_while(|b| b.is_ascii_digit())
+            .count();
+        if digits == 0 {
+            return None;
+        }
+        let res = self.s[..digits]
+            .iter()
+            .fold(0, |acc, b| acc * 10 + (b - b'0') as i64);
+        self.s = &self.s[digits..];
+        Some(res)
+    }
+
+    fn fraction(&mut self) -> Option<Float> {
+        let digits = self.s.iter().take_while(|b| b.is_ascii_digit()).count();
+        if digits == 0 {
+            return None;
+        }
+        let res = std::str::from_utf8(&self.s[..digits]).ok()?;
+        let res = format!("0.{}", res).parse::<Float>().ok()?;
+        self.s = &self.s[digits..];
+        Some(res)
+    }
+
+    // Matches a (case-insensitive) full or abbreviated name from `names`, returning its index.
+    fn name(&mut self, names: &[&str]) -> Option<i64> {
+        self.skip_space();
+        for (i, name) in names.iter().enumerate() {
+            for cand in &[name.as_bytes(), &name.as_bytes()[..3]] {
+                if self.s.len() >= cand.len() && self.s[..cand.len()].eq_ignore_ascii_case(cand) {
+                    self.s = &self.s[cand.len()..];
+                    return Some(i as i64);
+                }
+            }
+        }
+        None
+    }
+
+    fn zone_offset(&mut self) -> Option<i64> {
+        self.skip_space();
+        match self.s.first()? {
+            b'Z' | b'z' => {
+                self.s = &self.s[1..];
+                Some(0)
+            }
+            sign @ (b'+' | b'-') => {
+                let neg = *sign == b'-';
+                self.s = &self.s[1..];
+                let hours = self.num(2)?;
+                if let Some(b':') = self.s.first() {
+                    self.s = &self.s[1..];
+                }
+                let mins = self.num(2).unwrap_or(0);
+                let off = hours * 3600 + mins * 60;
+                Some(if neg { -off } else { off })
+            }
+            _ => None,
+        }
+    }
+
+    fn parse(&mut self, fmt: &[u8]) -> Option<()> {
+        let mut i = 0;
+        while i < fmt.len() {
+            let c = fmt[i];
+            if c.is_ascii_whitespace() {
+                self.skip_space();
+                i += 1;
+                continue;
+            }
+            if c != b'%' || i + 1 == fmt.len() {
+                if self.s.first() != Some(&c) {
+                    return None;
+                }
+                self.s = &self.s[1..];
+                i += 1;
+                continue;
+            }
+            match fmt[i + 1] {
+                b'Y' => self.tm.year = self.num(4)?,
+                b'y' => {
+                    let y = self.num(2)?;
+                    self.tm.year = if y < 69 { 2000 + y } else { 1900 + y };
+                }
+                b'C' => self.tm.year = self.num(2)? * 100 + self.tm.year.rem_euclid(100),
+                b'm' => self.tm.mon = self.num(2)?,
+                b'd' | b'e' => self.tm.mday = self.num(2)?,
+                b'j' => {
+                    // Day of the year: start from January 1st and count forward.
+                    self.tm.mon = 1;
+                    self.tm.mday = self.num(3)?;
+                }
+                b'H' | b'k' => self.tm.hour = self.num(2)?,
+                b'I' | b'l' => self.tm.hour = self.num(2)? % 12,
+                b'M' => self.tm.min = self.num(2)?,
+                b'S' => {
+                    self.tm.sec = self.num(2)?;
+                    if let Some(b'.') = self.s.first() {
+                        self.s = &self.s[1..];
+                        self.frac = self.fraction()?;
+                    }
+                }
+                b'f' => self.frac = self.fraction()?,
+                b'b' | b'B' | b'h' => self.tm.mon = self.name(&MONTHS)? + 1,
+                b'a' | b'A' => {
+                    self.name(&DAYS)?;
+                }
+                b'p' => {
+                    self.skip_space();
+                    let pm = match self.s.get(..2) {
+                        Some(p) if p.eq_ignore_ascii_case(b"am") => false,
+                        Some(p) if p.eq_ignore_ascii_case(b"pm") => true,
+                        _ => return None,
+                    };
+                    self.s = &self.s[2..];
+                    self.pm = Some(pm);
+                }
+                b'z' => self.offset = Some(self.zone_offset()?),
+                b'Z' => {
+                    self.skip_space();
+                    let len = self
+                        .s
+                        .iter()
+                        .take_while(|b| b.is_ascii_alphabetic())
+                        .count();
+                    if len == 0 {
+                        return None;
+                    }
+                    let zone = &self.s[..len];
+                    if zone.eq_ignore_ascii_case(b"UTC")
+                        || zone.eq_ignore_ascii_case(b"GMT")
+                        || zone.eq_ignore_ascii_case(b"Z")
+                    {
+                        self.offset = Some(0);
+                    }
+                    self.s = &self.s[len..];
+                }
+                b's' => {
+                    self.skip_space();
+                    let neg = self.s.first() == Some(&b'-');
+                    if neg {
+                        self.s = &self.s[1..];
+                    }
+                    let mut secs = self.num(18)? as Float;
+                    if let Some(b'.') = self.s.first() {
+                        self.s = &self.s[1..];
+                        secs += self.fraction()?;
+                    }
+                    self.epoch = Some(if neg { -secs } else { secs });
+                }
+                b'T' => self.parse(b"%H:%M:%S")?,
+                b'D' => self.parse(b"%m/%d/%y")?,
+                b'F' => self.parse(b"%Y-%m-%d")?,
+                b'R' => self.parse(b"%H:%M")?,
+                b'r' => self.parse(b"%I:%M:%S %p")?,
+                b'n' | b't' => self.skip_space(),
+                b'%' => {
+                    if self.s.first() != Some(&b'%') {
+                        return None;
+                    }
+                    self.s = &self.s[1..];
+                }
+                _ => return None,
+            }
+            i += 2;
+        }
+        Some(())
+    }
+}
+
+fn parse_time(s: &[u8], fmt: &[u8], utc: bool) -> Option<Float> {
+    let mut p = Parser {
+        s,
+        tm: Tm {
+            year: 1970,
+            mon: 1,
+            mday: 1,
+            ..Default::default()
+        },
+        frac: 0.0,
+        offset: None,
+        epoch: None,
+        pm: None,
+    };
+    p.parse(fmt)?;
+    p.skip_space();
+    if !p.s.is_empty() {
+        return None;
+    }
+    if let Some(epoch) = p.epoch {
+        return Some(epoch);
+    }
+    if p.pm == Some(true) {
+        p.tm.hour += 12;
+    }
+    let tm = &p.tm;
+    if tm.mon < 1
+        || tm.mon > 12
+        || tm.mday < 1
+        || tm.mday > 366
+        || tm.hour > 23
+        || tm.min > 59
+        || tm.sec > 60
+    {
+        return None;
+    }
+    if tm.mday > 31 && !(tm.mon == 1 && tm.mday <= if is_leap(tm.year) { 366 } else { 365 }) {
+        return None;
+    }
+    let secs = match p.offset {
+        Some(off) => utc_secs(tm)?.checked_sub(off)?,
+        None if utc => utc_secs(tm)?,
+        None => local_secs(tm, -1)?,
+    };
+    Some(secs as Float + p.frac)
+}
+
+/// Implements `strptime(s, fmt, utc)`: parse the timestamp `s` according to the format `fmt`,
+/// returning the number of seconds since the epoch (including any fractional seconds). If `fmt`
+/// is empty, a number of common log timestamp formats are tried in turn. Timestamps without an
+/// explicit UTC offset are interpreted as UTC if `utc` is set, and as local time otherwise.
+/// Returns -1 if `s` could not be parsed.
+pub fn strptime(s: &Str, fmt: &Str, utc: bool) -> Float {
+    s.with_bytes(|s| {
+        fmt.with_bytes(|fmt| {
+            let res = if fmt.is_empty() {
+                COMMON_FORMATS
+                    .iter()
+                    .find_map(|fmt| parse_time(s, fmt, utc))
+            } else {
+                parse_time(s, fmt, utc)
+            };
+            res.unwrap_or(-1.0)
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fmt_utc(fmt: &str, ts: Int) -> String {
+        strftime(&Str::from(fmt), ts, /*utc=*/ true).to_string()
+    }
+
+    fn parse_utc(s: &str, fmt: &str) -> Float {
+        strptime(&Str::from(s), &Str::from(fmt), /*utc=*/ true)
+    }
+
+    #[test]
+    fn civil_round_trip() {
+        for ts in &[-86401, -1, 0, 951782400, 1609459199, 1709210096, 4102444800] {
+            assert_eq!(utc_secs(&utc_tm(*ts)), Some(*ts));
+        }
+        let tm = utc_tm(951782400);
+        assert_eq!(
+            (tm.year, tm.mon, tm.mday, tm.yday, tm.wday),
+            (2000, 2, 29, 60, 2)
+        );
+    }
+
+    #[test]
+    fn format_utc() {
+        assert_eq!(
+            fmt_utc(DEFAULT_STRFTIME_FORMAT, 1709210096),
+            "Thu Feb 29 12:34:56 UTC 2024"
+        );
+        assert_eq!(
+            fmt_utc("%F %T %j %u %w %p %I %s %%", 1709210096),
+            "2024-02-29 12:34:56 060 4 4 PM 12 1709210096 %"
+        );
+        // 2021-01-03 is a Sunday belonging to the last ISO week of 2020.
+        assert_eq!(fmt_utc("%G-W%V %U %W", 1609675200), "2020-W53 01 00");
+        assert_eq!(fmt_utc("%Q", 0), "%Q");
+    }
+
+    #[test]
+    fn mktime_utc() {
+        let mk = |s: &str| mktime(&Str::from(s), /*utc=*/ true);
+        assert_eq!(mk("1970 01 01 00 00 00"), 0);
+        assert_eq!(mk("2024 02 29 12 34 56"), 1709210096);
+        assert_eq!(mk("2023 14 01 00 00 00"), mk("2024 02 01 00 00 00"));
+        assert_eq!(mk("2024 02 29"), -1);
+        assert_eq!(mk("2024 02 29 12 34 fifty"), -1);
+    }
+
+    #[test]
+    fn mktime_out_of_range() {
+        for &utc in &[true, false] {
+            let mk = |s: &str| mktime(&Str::from(s), utc);
+            assert_eq!(mk("999999999999999999 1 1 0 0 0"), -1);
+            assert_eq!(mk("2024 -9223372036854775808 1 0 0 0"), -1);
+            assert_eq!(mk("2024 1 1 0 0 9223372036854775807"), -1);
+        }
+    }
+
+    #[test]
+    fn local_secs_before_epoch() {
+        // The second before the epoch is a valid result, not an error.
+        for &ts in &[-1, 0, 1709210096] {
+            assert_eq!(local_secs(&local_tm(ts), -1), Some(ts));
+        }
+    }
+
+    #[test]
+    fn parse_formats() {
+        assert_eq!(parse_utc("2024-02-29T12:34:56Z", ""), 1709210096.0);
+        assert_eq!(parse_utc("2024-02-29T14:34:56+02:00", ""), 1709210096.0);
+        assert_eq!(parse_utc("2024-02-29 12:34:56.25", ""), 1709210096.25);
+        assert_eq!(parse_utc("29/Feb/2024:04:34:56 -0800", ""), 1709210096.0);
+        assert_eq!(
+            parse_utc("Thu, 29 Feb 2024 12:34:56 +0000", ""),
+            1709210096.0
+        );
+        assert_eq!(parse_utc("Thu Feb 29 12:34:56 UTC 2024", ""), 1709210096.0);
+        assert_eq!(parse_utc("02/29/24 12:34:56 PM", "%D %r"), 1709210096.0);
+        assert_eq!(parse_utc("02/29/24 12:34:56 AM", "%D %r"), 1709166896.0);
+        assert_eq!(parse_utc("1709210096.5", "%s"), 1709210096.5);
+        assert_eq!(parse_utc("2024-060", "%Y-%j"), 1709164800.0);
+        assert_eq!(parse_utc("2024-02-29 trailing", "%Y-%m-%d"), -1.0);
+        assert_eq!(parse_utc("2024-13-01", "%Y-%m-%d"), -1.0);
+        assert_eq!(parse_utc("not a time", ""), -1.0);
+    }
+}
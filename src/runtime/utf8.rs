@@ -24,6 +24,55 @@ pub fn is_char_boundary(b: u8) -> bool {
     (b as i8) >= -0x40
 }
 
+/// The length in bytes of the character at the start of `bs`, or 1 if `bs` does not begin with a
+/// valid UTF-8 sequence.
+pub(crate) fn char_width(bs: &[u8]) -> usize {
+    let width = match bs[0] {
+        0xC2..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF4 => 4,
+        _ => return 1,
+    };
+    match bs.get(..width) {
+        Some(c) if str::from_utf8(c).is_ok() => width,
+        _ => 1,
+    }
+}
+
+/// The offsets at which each character in `bs` begins. Any bytes that are not part of a valid
+/// character are treated as characters of their own.
+pub(crate) fn char_offsets(bs: &[u8]) -> impl Iterator<Item = usize> + '_ {
+    let mut i = 0;
+    std::iter::from_fn(move || {
+        if i >= bs.len() {
+            return None;
+        }
+        let start = i;
+        i += char_width(&bs[i..]);
+        Some(start)
+    })
+}
+
+/// The number of characters in `bs`, assuming it is UTF-8. Any bytes that are not part of a valid
+/// character are counted individually.
+pub(crate) fn char_count(bs: &[u8]) -> usize {
+    if is_ascii(bs) {
+        bs.len()
+    } else {
+        char_offsets(bs).count()
+    }
+}
+
+pub(crate) fn is_ascii(bs: &[u8]) -> bool {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("sse2") {
+            return unsafe { x86::validate_ascii(bs) };
+        }
+    }
+    bs.is_ascii()
+}
+
 #[cfg(test)]
 fn parse_utf8_clipped(bs: &[u8]) -> Option<&str> {
     validate_utf8_clipped(bs).map(|off| unsafe { str::from_utf8_unchecked(&bs[..off]) })
@@ -37,7 +37,6 @@
 
 use std::collections::VecDeque;
 use std::io::{self, Write};
-use std::process::ChildStdin;
 use std::sync::{
     atomic::{AtomicBool, AtomicUsize, Ordering},
     Arc, Mutex,
@@ -57,7 +56,10 @@ use crossbeam_channel::{bounded, Receiver, Sender};
 use hashbrown::HashMap;
 
 use crate::common::{CompileError, FileSpec, Notification, Result};
-use crate::runtime::{command::command_for_write, Str};
+use crate::runtime::{
+    command::{command_for_write, coprocess_for_write, CommandInput},
+    Int, Str,
+};
 
 /// The maximum number of pending requests in the per-file channels.
 const IO_CHAN_SIZE: usize = 8;
@@ -74,13 +76,21 @@ const BUFFER_SIZE: usize = 64 << 10;
 pub trait FileFactory: Clone + 'static + Send + Sync {
     type Output: io::Write;
     type Stdout: io::Write;
-    // TODO: make ChildStdin an associated type, to permit better testing
-    fn cmd(&self, cmd: &[u8]) -> io::Result<ChildStdin> {
+    // TODO: make CommandInput an associated type, to permit better testing
+    fn cmd(&self, cmd: &[u8]) -> io::Result<CommandInput> {
         command_for_write(cmd)
     }
+    fn coproc(&self, cmd: &[u8]) -> io::Result<CommandInput> {
+        coprocess_for_write(cmd)
+    }
     fn build(&self, path: &str, spec: FileSpec) -> io::Result<Self::Output>;
     // TODO maybe we shold support this returning an error.
     fn stdout(&self) -> Self::Stdout;
+    /// Whether to flush standard output after every line written to it, rather than batching
+    /// writes.
+    fn line_buffer_stdout(&self) -> bool {
+        grep_cli::is_tty_stdout()
+    }
 }
 
 impl<W: io::Write, T: Fn(&str, FileSpec) -> io::Result<W> + Clone + 'static + Send + Sync>
@@ -107,16 +117,38 @@ fn open_file(path: &str, spec: FileSpec) -> io::Result<FileWriter> {
     Ok(file)
 }
 
-pub fn default_factory() -> impl FileFactory {
-    open_file
+/// The factory for writing to standard output. If `line_buffered` is set, each line written to
+/// standard output is flushed immediately, as it is already when standard output is a terminal.
+pub fn default_factory(line_buffered: bool) -> impl FileFactory {
+    #[derive(Clone)]
+    struct DefaultFactory {
+        line_buffered: bool,
+    }
+    impl FileFactory for DefaultFactory {
+        type Output = FileWriter;
+        type Stdout = grep_cli::StandardStream;
+        fn build(&self, path: &str, spec: FileSpec) -> io::Result<Self::Output> {
+            open_file(path, spec)
+        }
+        fn stdout(&self) -> Self::Stdout {
+            grep_cli::stdout(termcolor::ColorChoice::Auto)
+        }
+        fn line_buffer_stdout(&self) -> bool {
+            self.line_buffered || grep_cli::is_tty_stdout()
+        }
+    }
+    DefaultFactory { line_buffered }
 }
 
-pub fn factory_from_file(fname: &str) -> io::Result<impl FileFactory> {
+pub fn factory_from_file(fname: &str, line_buffered: bool) -> io::Result<impl FileFactory> {
     // Do a test open+truncate of the file.
     let _file = open_file(fname, FileSpec::Trunc)?;
 
     #[derive(Clone)]
-    struct FileStdout(String);
+    struct FileStdout {
+        path: String,
+        line_buffered: bool,
+    }
     impl FileFactory for FileStdout {
         type Output = FileWriter;
         type Stdout = FileWriter;
@@ -124,27 +156,53 @@ pub fn factory_from_file(fname: &str) -> io::Result<impl FileFactory> {
             open_file(path, spec)
         }
         fn stdout(&self) -> Self::Stdout {
-            open_file(self.0.as_str(), FileSpec::Append).expect("failed to open stdout")
+            open_file(self.path.as_str(), FileSpec::Append).expect("failed to open stdout")
+        }
+        fn line_buffer_stdout(&self) -> bool {
+            self.line_buffered
         }
     }
-    Ok(FileStdout(fname.into()))
+    Ok(FileStdout {
+        path: fname.into(),
+        line_buffered,
+    })
 }
 
-fn build_handle<W: io::Write, F: Fn(FileSpec) -> io::Result<W> + Send + 'static>(
-    f: F,
-    is_stdout: bool,
+/// Spawn a writer thread, which opens its output with `open` and closes it with `close`.
+fn build_handle<W: io::Write>(
+    open: impl Fn(FileSpec) -> io::Result<W> + Send + 'static,
+    close: impl Fn(W) -> io::Result<Int> + Send + 'static,
+    line_buffer: bool,
 ) -> RawHandle {
     let (sender, receiver) = bounded(IO_CHAN_SIZE);
     let error = Arc::new(Mutex::new(None));
+    let exit_status = Arc::new(Mutex::new(None));
     let receiver_error = error.clone();
-    std::thread::spawn(move || receive_thread(receiver, receiver_error, f));
+    let receiver_status = exit_status.clone();
+    std::thread::spawn(move || {
+        receive_thread(receiver, receiver_error, receiver_status, open, close)
+    });
     RawHandle {
         error,
+        exit_status,
         sender,
-        line_buffer: is_stdout && grep_cli::is_tty_stdout(),
+        line_buffer,
     }
 }
 
+/// Closing a file (or standard output) always "succeeds" as far as `close` is concerned: errors
+/// writing to the file are reported before this point.
+fn close_writer<W: io::Write>(w: W) -> io::Result<Int> {
+    drop(w);
+    Ok(0)
+}
+
+/// Closing a command's input waits for it to exit, yielding its exit status. Coprocesses are
+/// waited on separately, once their output has been closed as well.
+fn close_command(w: CommandInput) -> io::Result<Int> {
+    Ok(w.close()?.unwrap_or(0))
+}
+
 /// Registry is a thread-local handle on all files we have ever interacted with.
 ///
 /// Note that handles are never removed, even after a file is closed. The single thread continues
@@ -153,6 +211,7 @@ pub struct Registry {
     global: Arc<dyn Root>,
     files: HashMap<Str<'static>, FileHandle>,
     cmds: HashMap<Str<'static>, FileHandle>,
+    coprocs: HashMap<Str<'static>, FileHandle>,
     stdout: FileHandle,
 }
 
@@ -164,6 +223,7 @@ impl Registry {
             global: Arc::new(root_impl),
             files: Default::default(),
             cmds: Default::default(),
+            coprocs: Default::default(),
             stdout,
         }
     }
@@ -180,25 +240,57 @@ impl Registry {
         };
         match fspec {
             FileSpec::Cmd => self.get_cmd(name),
+            FileSpec::Coproc => self.get_coproc(name),
             FileSpec::Trunc | FileSpec::Append => self.get_file(Some(name)),
         }
     }
 
-    pub fn close<'a>(&mut self, path_or_cmd: &Str<'a>) -> Result<()> {
+    /// Close the file, command or coprocess named `path_or_cmd`, returning the exit status of a
+    /// command, 0 for a file or coprocess, and -1 if nothing by that name was open.
+    pub fn close<'a>(&mut self, path_or_cmd: &Str<'a>) -> Result<Int> {
         // TODO: implement a newtype for heterogeneous lookup. We shouldn't have to do the clone or
         // the unmoor here, but we need to because we cannot implement Borrow<Str<'a>> for
         // Borrow<Str<'static>> (conflicts with the blanket impl for Borrow).
         if let Some(fh) = self.files.get_mut(&path_or_cmd.clone().unmoor()) {
-            fh.close()?;
-            return Ok(());
+            return fh.close();
         }
         if let Some(ch) = self.cmds.get_mut(&path_or_cmd.clone().unmoor()) {
-            ch.close()?;
-            return Ok(());
+            return ch.close();
+        }
+        if let Some(ch) = self.coprocs.get_mut(&path_or_cmd.clone().unmoor()) {
+            return ch.close();
         }
         path_or_cmd.with_bytes(|bs| self.global.close(bs))
     }
 
+    /// Flush any files, commands or coprocesses named `path_or_cmd`, returning whether there were
+    /// any.
+    pub fn flush<'a>(&mut self, path_or_cmd: &Str<'a>) -> Result<bool> {
+        let key = path_or_cmd.clone().unmoor();
+        let mut found = false;
+        for handles in [&mut self.files, &mut self.cmds, &mut self.coprocs] {
+            if let Some(fh) = handles.get_mut(&key) {
+                fh.flush()?;
+                found = true;
+            }
+        }
+        Ok(found)
+    }
+
+    /// Flush standard output along with every file, command and coprocess written to so far.
+    pub fn flush_all(&mut self) -> Result<()> {
+        self.stdout.flush()?;
+        for (_, fh) in self
+            .files
+            .iter_mut()
+            .chain(self.cmds.iter_mut())
+            .chain(self.coprocs.iter_mut())
+        {
+            fh.flush()?;
+        }
+        Ok(())
+    }
+
     pub fn get_cmd<'a>(&mut self, cmd: &Str<'a>) -> Result<&mut FileHandle> {
         use hashbrown::hash_map::Entry;
         // borrowed by with_bytes closure.
@@ -211,6 +303,18 @@ impl Registry {
         }
     }
 
+    pub fn get_coproc<'a>(&mut self, cmd: &Str<'a>) -> Result<&mut FileHandle> {
+        use hashbrown::hash_map::Entry;
+        // borrowed by with_bytes closure.
+        let global = &self.global;
+        match self.coprocs.entry(cmd.clone().unmoor()) {
+            Entry::Occupied(o) => Ok(o.into_mut()),
+            Entry::Vacant(v) => {
+                Ok(v.insert(cmd.with_bytes(|bs| global.get_coprocess(bs)).into_handle()))
+            }
+        }
+    }
+
     pub fn get_file<'a>(&mut self, name: Option<&Str<'a>>) -> Result<&mut FileHandle> {
         match name {
             Some(path) => {
@@ -234,7 +338,12 @@ impl Registry {
 
     pub fn destroy_and_flush_all_files(&mut self) -> Result<()> {
         let mut last_error = Ok(());
-        for (_, mut fh) in self.files.drain().chain(self.cmds.drain()) {
+        for (_, mut fh) in self
+            .files
+            .drain()
+            .chain(self.cmds.drain())
+            .chain(self.coprocs.drain())
+        {
             let res = fh.flush();
             if res.is_err() {
                 last_error = res;
@@ -250,6 +359,7 @@ impl Clone for Registry {
             global: self.global.clone(),
             files: Default::default(),
             cmds: Default::default(),
+            coprocs: Default::default(),
             stdout: self.stdout.raw().into_handle(),
         }
     }
@@ -259,15 +369,17 @@ impl Clone for Registry {
 // receiver threads, while still avoiding an extra type parameter all the way up the stack.
 trait Root: 'static + Send + Sync {
     fn get_command(&self, cmd: &[u8]) -> RawHandle;
+    fn get_coprocess(&self, cmd: &[u8]) -> RawHandle;
     fn get_handle(&self, fname: &str) -> RawHandle;
     fn get_stdout(&self) -> RawHandle;
-    // closes a file or command with name `fname`.
-    fn close(&self, fname: &[u8]) -> Result<()>;
+    // closes a file or command with name `fname`, returning its status as in Registry::close.
+    fn close(&self, fname: &[u8]) -> Result<Int>;
 }
 
 struct RootImpl<F> {
     handles: Mutex<HashMap<String, RawHandle>>,
     commands: Mutex<HashMap<Box<[u8]>, RawHandle>>,
+    coprocesses: Mutex<HashMap<Box<[u8]>, RawHandle>>,
     stdout_raw: RawHandle,
     file_factory: F,
 }
@@ -277,11 +389,13 @@ impl<F: FileFactory> RootImpl<F> {
         let local_factory = file_factory.clone();
         let stdout_raw = build_handle(
             move |_append| Ok(local_factory.stdout()),
-            /*is_stdout*/ true,
+            close_writer,
+            file_factory.line_buffer_stdout(),
         );
         RootImpl {
             handles: Default::default(),
             commands: Default::default(),
+            coprocesses: Default::default(),
             stdout_raw,
             file_factory,
         }
@@ -289,18 +403,19 @@ impl<F: FileFactory> RootImpl<F> {
 }
 
 impl<F: FileFactory> Root for RootImpl<F> {
-    fn close(&self, fname: &[u8]) -> Result<()> {
+    fn close(&self, fname: &[u8]) -> Result<Int> {
         let mut handle = None;
-        {
-            let cmds = self.commands.lock().unwrap();
-            if let Some(h) = cmds.get(fname) {
-                // We do this extra song and dance to avoid calling close with the lock held.
-                handle = Some(h.clone());
+        for cmds in [&self.commands, &self.coprocesses] {
+            {
+                let cmds = cmds.lock().unwrap();
+                if let Some(h) = cmds.get(fname) {
+                    // We do this extra song and dance to avoid calling close with the lock held.
+                    handle = Some(h.clone());
+                }
+            }
+            if let Some(h) = handle.take() {
+                return h.into_handle().close();
             }
-        }
-        if let Some(h) = handle.take() {
-            h.into_handle().close()?;
-            return Ok(());
         }
         {
             let fname = if let Ok(s) = std::str::from_utf8(fname) {
@@ -308,7 +423,7 @@ impl<F: FileFactory> Root for RootImpl<F> {
             } else {
                 // If this file name is invalid UTF8, we haven't opened it; no need to return an
                 // error.
-                return Ok(());
+                return Ok(-1);
             };
             let files = self.handles.lock().unwrap();
             if let Some(h) = files.get(fname) {
@@ -316,30 +431,31 @@ impl<F: FileFactory> Root for RootImpl<F> {
             }
         }
         if let Some(h) = handle.take() {
-            h.into_handle().close()?;
-            return Ok(());
+            return h.into_handle().close();
         }
-        Ok(())
+        Ok(-1)
     }
     fn get_command(&self, cmd: &[u8]) -> RawHandle {
-        let mut cmds = self.commands.lock().unwrap();
-        if let Some(h) = cmds.get(cmd) {
-            return h.clone();
-        }
-        let local_factory = self.file_factory.clone();
-        let local_name = Box::<[u8]>::from(cmd);
-        let global_name = local_name.clone();
-        let handle = build_handle(
-            move |_| local_factory.cmd(&*local_name),
-            /*is_stdout=*/ true,
-        );
-        let _old = cmds.insert(global_name, handle.clone());
-        debug_assert!(
-            _old.is_none(),
-            "duplicate insertion of command: {}",
-            String::from_utf8_lossy(cmd)
-        );
-        handle
+        get_pipe(
+            &self.commands,
+            &self.file_factory,
+            cmd,
+            F::cmd,
+            close_command,
+            /*line_buffer=*/ grep_cli::is_tty_stdout(),
+        )
+    }
+    fn get_coprocess(&self, cmd: &[u8]) -> RawHandle {
+        // Line-buffer writes to a coprocess: a script will often wait on the response to a line
+        // it has just written.
+        get_pipe(
+            &self.coprocesses,
+            &self.file_factory,
+            cmd,
+            F::coproc,
+            close_command,
+            /*line_buffer=*/ true,
+        )
     }
     fn get_handle(&self, fname: &str) -> RawHandle {
         let mut handles = self.handles.lock().unwrap();
@@ -351,7 +467,8 @@ impl<F: FileFactory> Root for RootImpl<F> {
         let global_name = local_name.clone();
         let handle = build_handle(
             move |append| local_factory.build(local_name.as_str(), append),
-            /*is_stdout=*/ false,
+            close_writer,
+            /*line_buffer=*/ false,
         );
         handles.insert(global_name, handle.clone());
         handle
@@ -361,6 +478,35 @@ impl<F: FileFactory> Root for RootImpl<F> {
     }
 }
 
+fn get_pipe<F: FileFactory>(
+    cmds: &Mutex<HashMap<Box<[u8]>, RawHandle>>,
+    file_factory: &F,
+    cmd: &[u8],
+    open: fn(&F, &[u8]) -> io::Result<CommandInput>,
+    close: fn(CommandInput) -> io::Result<Int>,
+    line_buffer: bool,
+) -> RawHandle {
+    let mut cmds = cmds.lock().unwrap();
+    if let Some(h) = cmds.get(cmd) {
+        return h.clone();
+    }
+    let local_factory = file_factory.clone();
+    let local_name = Box::<[u8]>::from(cmd);
+    let global_name = local_name.clone();
+    let handle = build_handle(
+        move |_| open(&local_factory, &*local_name),
+        close,
+        line_buffer,
+    );
+    let _old = cmds.insert(global_name, handle.clone());
+    debug_assert!(
+        _old.is_none(),
+        "duplicate insertion of command: {}",
+        String::from_utf8_lossy(cmd)
+    );
+    handle
+}
+
 /// FileHandle contains thread-local state around writing to and closing an output file.
 pub struct FileHandle {
     raw: RawHandle,
@@ -482,10 +628,18 @@ impl FileHandle {
         }
     }
 
-    pub fn close(&mut self) -> Result<()> {
+    /// Close the file, waiting for the writer thread to do so. Returns the exit status reported
+    /// by the writer thread, or -1 if the file was not open.
+    pub fn close(&mut self) -> Result<Int> {
         self.clear_batch(None)?;
-        self.raw.sender.send(Request::Close).unwrap();
-        Ok(())
+        let (n, req) = Request::close();
+        self.raw.sender.send(req).unwrap();
+        n.1.wait();
+        self.guards.clear();
+        if let RequestStatus::ERROR = n.0.read() {
+            return Err(self.read_error());
+        }
+        Ok(self.raw.exit_status.lock().unwrap().take().unwrap_or(-1))
     }
 }
 
@@ -546,7 +700,7 @@ enum Request {
         flush: bool,
     },
     Flush(Arc<(ErrorCode, Notification)>),
-    Close,
+    Close(Arc<(ErrorCode, Notification)>),
 }
 
 // This isn't implemented automatically because of the raw pointers in Write. Those pointers are
@@ -560,23 +714,27 @@ impl Request {
         let req = Request::Flush(notify.clone());
         (notify, req)
     }
+    fn close() -> (Arc<(ErrorCode, Notification)>, Request) {
+        let notify = Arc::new((ErrorCode::default(), Notification::default()));
+        let req = Request::Close(notify.clone());
+        (notify, req)
+    }
     fn size(&self) -> usize {
         match self {
             // NB, aside from the invariants we maintain about the validity of `data`, grabbing the
             // length here should _always_ be safe. This is tracked by the {const_}slice_ptr_len
             // feature.
             Request::Write { data, .. } => unsafe { &**data }.len(),
-            Request::Flush(_) | Request::Close => 0,
+            Request::Flush(_) | Request::Close(_) => 0,
         }
     }
     fn set_code(&self, mut f: impl FnMut(&ErrorCode)) {
         match self {
             Request::Write { status, .. } => f(unsafe { &**status }),
-            Request::Flush(n) => {
+            Request::Flush(n) | Request::Close(n) => {
                 f(&n.0);
                 n.1.notify();
             }
-            Request::Close => {}
         }
     }
 }
@@ -589,10 +747,9 @@ impl Drop for Request {
                 let status = unsafe { &**status }.read();
                 assert!(!matches!(status, RequestStatus::ONGOING));
             }
-            Request::Flush(n) => {
+            Request::Flush(n) | Request::Close(n) => {
                 assert!(n.1.has_been_notified());
             }
-            Request::Close => {}
         }
     }
 }
@@ -648,6 +805,9 @@ impl Drop for WriteGuard {
 #[derive(Clone)]
 struct RawHandle {
     error: Arc<Mutex<Option<CompileError>>>,
+    // The status of the last close performed by the writer thread, if there is one that has not
+    // been read yet.
+    exit_status: Arc<Mutex<Option<Int>>>,
     sender: Sender<Request>,
     line_buffer: bool,
 }
@@ -697,6 +857,8 @@ impl WriteBatch {
         self.n_writes
     }
 
+    /// Issue the writes in the batch. The caller is responsible for clearing the batch
+    /// afterwards, having closed `w` if this returns true.
     fn issue(&mut self, w: &mut impl Write) -> io::Result</*close=*/ bool> {
         if self.write_bytes > 0 {
             write_all(self, w)?;
@@ -704,9 +866,7 @@ impl WriteBatch {
         if self.flush || self.close {
             w.flush()?;
         }
-        let close = self.close;
-        self.clear();
-        Ok(close)
+        Ok(self.close)
     }
 
     fn get_spec(&self) -> FileSpec {
@@ -729,7 +889,7 @@ impl WriteBatch {
                 self.flush |= *flush;
             }
             Request::Flush(_) => self.flush = true,
-            Request::Close => self.close = true,
+            Request::Close(_) => self.close = true,
         };
         self.requests.push(req);
         self.flush || self.close
@@ -755,10 +915,12 @@ impl WriteBatch {
 fn receive_thread<W: io::Write>(
     receiver: Receiver<Request>,
     error: Arc<Mutex<Option<CompileError>>>,
-    f: impl Fn(FileSpec) -> io::Result<W>,
+    exit_status: Arc<Mutex<Option<Int>>>,
+    open: impl Fn(FileSpec) -> io::Result<W>,
+    close: impl Fn(W) -> io::Result<Int>,
 ) {
     let mut batch = WriteBatch::default();
-    if let Err(e) = receive_loop(&receiver, &mut batch, f) {
+    if let Err(e) = receive_loop(&receiver, &mut batch, &exit_status, open, close) {
         // We got an error! install it in the `error` mutex.
         {
             let mut err = error.lock().unwrap();
@@ -776,12 +938,14 @@ fn receive_thread<W: io::Write>(
 fn receive_loop<W: io::Write>(
     receiver: &Receiver<Request>,
     batch: &mut WriteBatch,
-    f: impl Fn(FileSpec) -> io::Result<W>,
+    exit_status: &Mutex<Option<Int>>,
+    open: impl Fn(FileSpec) -> io::Result<W>,
+    close: impl Fn(W) -> io::Result<Int>,
 ) -> io::Result<()> {
     const MAX_BATCH_BYTES: usize = 1 << 20;
     const MAX_BATCH_SIZE: usize = 1 << 10;
 
-    // Writer starts off closed. We use `f` to open it if a write appears.
+    // Writer starts off closed. We use `open` to open it if a write appears.
     let mut writer = None;
 
     while let Ok(req) = receiver.recv() {
@@ -815,11 +979,15 @@ fn receive_loop<W: io::Write>(
             }
             // We need to (re)open the file, the first write request will tell us whether or not
             // this is an append request.
-            writer = Some(f(batch.get_spec())?);
+            writer = Some(open(batch.get_spec())?);
         }
         if batch.issue(writer.as_mut().unwrap())? {
-            writer = None;
+            // Record the exit status before clearing the batch: that is what wakes up the thread
+            // waiting on the close request.
+            let status = close(writer.take().unwrap())?;
+            *exit_status.lock().unwrap() = Some(status);
         }
+        batch.clear();
     }
     Ok(())
 }
@@ -275,6 +275,7 @@
 //! [static analysis algorithms]: https://cs.au.dk/~amoeller/spa/
 //! [Hindley-Milner]: https://en.wikipedia.org/wiki/Hindley%E2%80%93Milner_type_system
 //! [`State`]: [crate::types::State]
+use crate::ast;
 use crate::builtins;
 use crate::cfg::{self, Function, Ident, ProgramContext};
 use crate::common::{self, FileSpec, NodeIx, NumTy, Result};
@@ -298,6 +299,8 @@ pub(crate) enum TVar<T> {
     Iter(T),
     Scalar(T),
     Map { key: T, val: T },
+    // A map whose values are themselves maps (with keys and values of type `ikey` and `ival`).
+    NestedMap { key: T, ikey: T, ival: T },
 }
 
 impl<T> TVar<T> {
@@ -311,6 +314,11 @@ impl<T> TVar<T> {
                 key: f(key),
                 val: f(val),
             },
+            NestedMap { key, ikey, ival } => NestedMap {
+                key: f(key),
+                ikey: f(ikey),
+                ival: f(ival),
+            },
         }
     }
 }
@@ -324,6 +332,7 @@ impl<T: Clone> TVar<T> {
 pub(crate) fn val_of(s: &State) -> Result<State> {
     match s {
         Some(TVar::Map { val, .. }) => Ok(Some(TVar::Scalar(val.clone()))),
+        Some(TVar::NestedMap { .. }) => err!("attempting to use an array of arrays as a scalar"),
         None => Ok(None),
         Some(TVar::Iter(_)) => err!("attempting to get value out of iterator state"),
         Some(TVar::Scalar(_)) => err!("attempting to get value out of iterator scalar"),
@@ -337,6 +346,9 @@ pub(crate) enum Constraint<T> {
     Key(T),
     ValIn(T),
     Val(T),
+    // Like ValIn, but only for values that are maps. This is how we learn that a map holds other
+    // maps, e.g. from `m[k1][k2] = v`.
+    MapValIn(T),
     IterVal(T),
     IterValIn(T),
     Flows(T),
@@ -356,6 +368,7 @@ impl<T> Constraint<T> {
             Constraint::Key(_) => Constraint::Key(s),
             Constraint::ValIn(_) => Constraint::ValIn(s),
             Constraint::Val(_) => Constraint::Val(s),
+            Constraint::MapValIn(_) => Constraint::MapValIn(s),
             Constraint::IterValIn(_) => Constraint::IterValIn(s),
             Constraint::IterVal(_) => Constraint::IterVal(s),
             Constraint::Flows(_) => Constraint::Flows(s),
@@ -388,7 +401,8 @@ impl Constraint<State> {
             Constraint::KeyIn(op) => err!("Non-scalar KeyIn constraint: {:?}", op),
 
             Constraint::Key(None) => Ok(None),
-            Constraint::Key(Some(TVar::Map { key: s, .. })) => Ok(Some(TVar::Scalar(s.clone()))),
+            Constraint::Key(Some(TVar::Map { key: s, .. }))
+            | Constraint::Key(Some(TVar::NestedMap { key: s, .. })) => Ok(Some(TVar::Scalar(*s))),
             Constraint::Key(op) => {
                 err!("invalid operand for Key constraint: {:?} (must be map)", op)
             }
@@ -403,12 +417,30 @@ impl Constraint<State> {
             })),
             Constraint::ValIn(op) => err!("Non-scalar ValIn constraint: {:?}", op),
 
-            Constraint::Val(None) => Ok(None),
+            // We do not know yet whether the values of a map with unknown values are scalars:
+            // they could be maps as well.
+            Constraint::Val(None) | Constraint::Val(Some(TVar::Map { val: None, .. })) => Ok(None),
             Constraint::Val(Some(TVar::Map { val: s, .. })) => Ok(Some(TVar::Scalar(s.clone()))),
+            Constraint::Val(Some(TVar::NestedMap { ikey, ival, .. })) => Ok(Some(TVar::Map {
+                key: *ikey,
+                val: *ival,
+            })),
             Constraint::Val(op) => {
                 err!("invalid operand for Val constraint: {:?} (must be map)", op)
             }
 
+            // Lookups do not constrain the key type of a map, and the outer map of an array of
+            // arrays is usually only ever read from. Key these maps by strings, as Awk does.
+            Constraint::MapValIn(Some(TVar::Map { key, val })) => Ok(Some(TVar::NestedMap {
+                key: Some(BaseTy::Str),
+                ikey: *key,
+                ival: *val,
+            })),
+            Constraint::MapValIn(Some(TVar::NestedMap { .. })) => {
+                err!("arrays may only be nested one level deep")
+            }
+            Constraint::MapValIn(_) => Ok(None),
+
             Constraint::IterValIn(None) => Ok(Some(TVar::Iter(None))),
             Constraint::IterValIn(Some(TVar::Scalar(v))) => Ok(Some(TVar::Iter(v.clone()))),
             Constraint::IterValIn(op) => err!("Non-scalar IterValIn constraint: {:?}", op),
@@ -471,6 +503,24 @@ impl Rule {
                 (Int, Int) => Int,
             }
         }
+        fn join_key(b1: BaseTy, b2: BaseTy) -> BaseTy {
+            use BaseTy::*;
+            match (b1, b2) {
+                (Float, _) | (_, Float) | (Str, _) | (_, Str) | (Null, _) | (_, Null) => Str,
+                (Int, _) => Int,
+            }
+        }
+        fn lift(
+            f: impl Fn(BaseTy, BaseTy) -> BaseTy,
+            o1: Option<BaseTy>,
+            o2: Option<BaseTy>,
+        ) -> Option<BaseTy> {
+            match (o1, o2) {
+                (Some(x), Some(y)) => Some(f(x, y)),
+                (Some(x), None) | (None, Some(x)) => Some(x),
+                (None, None) => None,
+            }
+        }
         if let Rule::Const(tv) = self {
             return Ok((tv != prev, tv.clone()));
         }
@@ -484,43 +534,57 @@ impl Rule {
                 (None, x) | (x, None) => x,
                 (Some(x), Some(y)) => match (x, y) {
                     (Iter(x), Iter(None)) | (Iter(None), Iter(x)) => Some(Iter(x)),
-                    (Iter(Some(x)), Iter(Some(y))) => {
-                        if x == y {
-                            cur
-                        } else {
-                            return err!("Incompatible iterator types: {:?} vs. {:?}", x, y);
-                        }
-                    }
+                    // Iterators follow the key type of their map, which can widen from Int to Str
+                    // after the iterator's type has first been computed. This happens when
+                    // iterating over the inner arrays of an array of arrays, whose key types are
+                    // only known once all of the constraints on the outer array have been solved.
+                    // See the `nested_iter_key_widening` test.
+                    (Iter(Some(x)), Iter(Some(y))) => Some(Iter(Some(join_key(x, y)))),
                     (Scalar(x), Scalar(None)) | (Scalar(None), Scalar(x)) => Some(Scalar(x)),
                     (Scalar(Some(x)), Scalar(Some(y))) => Some(Scalar(Some(value_rule(x, y)))),
-                    (Map { key: k1, val: v1 }, Map { key: k2, val: v2 }) => {
-                        fn join_key(b1: BaseTy, b2: BaseTy) -> BaseTy {
-                            use BaseTy::*;
-                            match (b1, b2) {
-                                (Float, _)
-                                | (_, Float)
-                                | (Str, _)
-                                | (_, Str)
-                                | (Null, _)
-                                | (_, Null) => Str,
-                                (Int, _) => Int,
-                            }
-                        }
-                        fn lift(
-                            f: impl Fn(BaseTy, BaseTy) -> BaseTy,
-                            o1: Option<BaseTy>,
-                            o2: Option<BaseTy>,
-                        ) -> Option<BaseTy> {
-                            match (o1, o2) {
-                                (Some(x), Some(y)) => Some(f(x, y)),
-                                (Some(x), None) | (None, Some(x)) => Some(x),
-                                (None, None) => None,
-                            }
-                        }
-                        Some(Map {
-                            key: lift(join_key, k1, k2),
-                            val: lift(value_rule, v1, v2),
-                        })
+                    (Map { key: k1, val: v1 }, Map { key: k2, val: v2 }) => Some(Map {
+                        key: lift(join_key, k1, k2),
+                        val: lift(value_rule, v1, v2),
+                    }),
+                    // A map whose value type is still unknown may turn out to hold maps.
+                    (
+                        Map { key: k1, val: None },
+                        NestedMap {
+                            key: k2,
+                            ikey,
+                            ival,
+                        },
+                    )
+                    | (
+                        NestedMap {
+                            key: k2,
+                            ikey,
+                            ival,
+                        },
+                        Map { key: k1, val: None },
+                    ) => Some(NestedMap {
+                        key: lift(join_key, k1, k2),
+                        ikey,
+                        ival,
+                    }),
+                    (
+                        NestedMap {
+                            key: k1,
+                            ikey: ik1,
+                            ival: iv1,
+                        },
+                        NestedMap {
+                            key: k2,
+                            ikey: ik2,
+                            ival: iv2,
+                        },
+                    ) => Some(NestedMap {
+                        key: lift(join_key, k1, k2),
+                        ikey: lift(join_key, ik1, ik2),
+                        ival: lift(value_rule, iv1, iv2),
+                    }),
+                    (Map { .. }, NestedMap { .. }) | (NestedMap { .. }, Map { .. }) => {
+                        return err!("array elements cannot be used as both scalars and arrays")
                     }
                     (t1, t2) => return err!("kinds do not match. {:?} vs {:?}", t1, t2),
                 },
@@ -543,37 +607,59 @@ fn concrete(state: State) -> TVar<BaseTy> {
 fn flatten(tv: TVar<BaseTy>) -> Result<compile::Ty> {
     use compile::Ty;
     use {BaseTy::*, TVar::*};
-    fn flatten_base(b: BaseTy) -> Ty {
-        match b {
-            Int => Ty::Int,
-            Float => Ty::Float,
-            Str => Ty::Str,
-            Null => Ty::Null,
-        }
-    }
     match tv {
         Scalar(b) => Ok(flatten_base(b)),
         Iter(Int) => Ok(Ty::IterInt),
         Iter(Null) | Iter(Str) => Ok(Ty::IterStr),
         Iter(x) => err!("Iterator over an unsupported type: {:?}", x),
-        Map { key, val } => {
-            let f = |ty| {
-                if ty == Null {
-                    Ty::Str
-                } else {
-                    flatten_base(ty)
-                }
-            };
-            match (f(key), f(val)) {
-                (Ty::Int, Ty::Int) => Ok(Ty::MapIntInt),
-                (Ty::Int, Ty::Float) => Ok(Ty::MapIntFloat),
-                (Ty::Int, Ty::Str) => Ok(Ty::MapIntStr),
-                (Ty::Str, Ty::Int) => Ok(Ty::MapStrInt),
-                (Ty::Str, Ty::Float) => Ok(Ty::MapStrFloat),
-                (Ty::Str, Ty::Str) => Ok(Ty::MapStrStr),
-                (k, v) => err!("Map with unsupported type (key={:?} val={:?})", k, v),
-            }
-        }
+        Map { key, val } => flatten_map(key, val),
+        NestedMap { key, ikey, ival } => match (flatten_elt(key), flatten_map(ikey, ival)?) {
+            (Ty::Int, Ty::MapIntInt) => Ok(Ty::MapIntMapIntInt),
+            (Ty::Int, Ty::MapIntFloat) => Ok(Ty::MapIntMapIntFloat),
+            (Ty::Int, Ty::MapIntStr) => Ok(Ty::MapIntMapIntStr),
+            (Ty::Int, Ty::MapStrInt) => Ok(Ty::MapIntMapStrInt),
+            (Ty::Int, Ty::MapStrFloat) => Ok(Ty::MapIntMapStrFloat),
+            (Ty::Int, Ty::MapStrStr) => Ok(Ty::MapIntMapStrStr),
+            (Ty::Str, Ty::MapIntInt) => Ok(Ty::MapStrMapIntInt),
+            (Ty::Str, Ty::MapIntFloat) => Ok(Ty::MapStrMapIntFloat),
+            (Ty::Str, Ty::MapIntStr) => Ok(Ty::MapStrMapIntStr),
+            (Ty::Str, Ty::MapStrInt) => Ok(Ty::MapStrMapStrInt),
+            (Ty::Str, Ty::MapStrFloat) => Ok(Ty::MapStrMapStrFloat),
+            (Ty::Str, Ty::MapStrStr) => Ok(Ty::MapStrMapStrStr),
+            (k, v) => err!("Map with unsupported type (key={:?} val={:?})", k, v),
+        },
+    }
+}
+
+fn flatten_base(b: BaseTy) -> compile::Ty {
+    use compile::Ty;
+    match b {
+        BaseTy::Int => Ty::Int,
+        BaseTy::Float => Ty::Float,
+        BaseTy::Str => Ty::Str,
+        BaseTy::Null => Ty::Null,
+    }
+}
+
+/// Map keys and values that are never assigned are treated as strings.
+fn flatten_elt(b: BaseTy) -> compile::Ty {
+    if b == BaseTy::Null {
+        compile::Ty::Str
+    } else {
+        flatten_base(b)
+    }
+}
+
+fn flatten_map(key: BaseTy, val: BaseTy) -> Result<compile::Ty> {
+    use compile::Ty;
+    match (flatten_elt(key), flatten_elt(val)) {
+        (Ty::Int, Ty::Int) => Ok(Ty::MapIntInt),
+        (Ty::Int, Ty::Float) => Ok(Ty::MapIntFloat),
+        (Ty::Int, Ty::Str) => Ok(Ty::MapIntStr),
+        (Ty::Str, Ty::Int) => Ok(Ty::MapStrInt),
+        (Ty::Str, Ty::Float) => Ok(Ty::MapStrFloat),
+        (Ty::Str, Ty::Str) => Ok(Ty::MapStrStr),
+        (k, v) => err!("Map with unsupported type (key={:?} val={:?})", k, v),
     }
 }
 
@@ -692,6 +778,132 @@ pub(crate) struct TypeInfo {
     pub var_tys: HashMap<(Ident, NumTy, SmallVec<compile::Ty>), compile::Ty>,
     // Map a particular function invocation to a return type.
     pub func_tys: HashMap<(NumTy, SmallVec<compile::Ty>), compile::Ty>,
+    // Identifiers (keyed by function, 0 for globals) that may hold strnums. See `strnum_vars`.
+    pub strnum_vars: HashSet<(Ident, NumTy)>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum StrnumNode {
+    Var(Ident, NumTy),
+    Ret(NumTy),
+}
+
+/// Compute the variables that may hold a "strnum": a string that came from input (a field, a
+/// getline result, an element of an array populated by split or patsplit, or an element of ARGV
+/// or ENVIRON). POSIX compares a strnum that looks like a number numerically; the static types
+/// assigned by inference cannot decide this, so the compiler uses this set to pick comparison
+/// instructions that check at runtime. For arrays, membership means that the array's values may be
+/// strnums.
+///
+/// The analysis is a simple reachability computation over assignments, ignoring the argument
+/// types of a function: any copy of a variable holding a strnum marks every copy.
+fn strnum_vars<'a>(
+    pc: &ProgramContext<'a, &'a str>,
+    var_tys: &HashMap<(Ident, NumTy, SmallVec<compile::Ty>), compile::Ty>,
+) -> HashSet<(Ident, NumTy)> {
+    use builtins::{Function::*, Variable};
+    use cfg::{PrimExpr, PrimStmt, PrimVal};
+    use StrnumNode::*;
+    let local_globals = pc.local_globals_ref();
+    let key = |id: Ident, func: NumTy| {
+        if id.is_global(local_globals) {
+            (id, 0)
+        } else {
+            (id, func)
+        }
+    };
+    let maps: HashSet<(Ident, NumTy)> = var_tys
+        .iter()
+        .filter(|(_, ty)| ty.is_array())
+        .map(|((id, func, _), _)| key(*id, *func))
+        .collect();
+    let mut edges = HashMap::<StrnumNode, SmallVec<StrnumNode>>::new();
+    let mut sources = Vec::<StrnumNode>::new();
+    // Arrays are passed by reference, so strnums stored through one name are visible through the
+    // other: flows between arrays go in both directions.
+    let mut flow = |from: StrnumNode, to: StrnumNode, alias: bool| {
+        edges.entry(from).or_insert_with(Default::default).push(to);
+        if alias {
+            edges.entry(to).or_insert_with(Default::default).push(from);
+        }
+    };
+    for (func_id, func) in pc.funcs.iter().enumerate() {
+        let func_id = func_id as NumTy;
+        let var = |id: Ident| {
+            let k = key(id, func_id);
+            (Var(k.0, k.1), maps.contains(&k))
+        };
+        for bb in func.cfg.raw_nodes() {
+            for stmt in bb.weight.q.iter() {
+                let (dst, expr) = match stmt {
+                    PrimStmt::AsgnVar(x, e) | PrimStmt::AsgnIndex(x, _, e) => (Some(var(*x)), e),
+                    PrimStmt::SetBuiltin(_, e) => (None, e),
+                    PrimStmt::Return(PrimVal::Var(y)) => {
+                        let (y, alias) = var(*y);
+                        flow(y, Ret(func_id), alias);
+                        continue;
+                    }
+                    _ => continue,
+                };
+                if let PrimExpr::CallBuiltin(Split | PatSplit, args) = expr {
+                    if let Some(PrimVal::Var(m)) = args.get(1) {
+                        sources.push(var(*m).0);
+                    }
+                }
+                let (dst, dst_map) = match dst {
+                    Some(d) => d,
+                    None => continue,
+                };
+                match expr {
+                    PrimExpr::Val(PrimVal::Var(y)) | PrimExpr::Index(PrimVal::Var(y), _) => {
+                        flow(var(*y).0, dst, dst_map)
+                    }
+                    PrimExpr::Phi(preds) => {
+                        for (_, y) in preds.iter() {
+                            flow(var(*y).0, dst, dst_map)
+                        }
+                    }
+                    PrimExpr::CallBuiltin(
+                        Unop(ast::Unop::Column)
+                        | Nextline
+                        | NextlineCmd
+                        | NextlineCoproc
+                        | NextlineStdin,
+                        _,
+                    )
+                    | PrimExpr::LoadBuiltin(Variable::ARGV | Variable::ENVIRON) => {
+                        sources.push(dst)
+                    }
+                    PrimExpr::CallUDF(f, args) => {
+                        flow(Ret(*f), dst, dst_map);
+                        let params = &pc.funcs[*f as usize].args;
+                        for (a, p) in args.iter().zip(params.iter()) {
+                            if let PrimVal::Var(y) = a {
+                                let (y, alias) = var(*y);
+                                let (p, _) = key(p.id, *f);
+                                flow(y, Var(p, *f), alias)
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    let mut res = HashSet::new();
+    let mut visited = HashSet::new();
+    while let Some(node) = sources.pop() {
+        if !visited.insert(node) {
+            continue;
+        }
+        if let Var(id, func) = node {
+            res.insert((id, func));
+        }
+        if let Some(succs) = edges.get(&node) {
+            sources.extend(succs.iter().cloned());
+        }
+    }
+    res
 }
 
 impl<'b, 'c> TypeContext<'b, 'c> {
@@ -781,7 +993,16 @@ impl<'b, 'c> TypeContext<'b, 'c> {
                 }
             }
         }
-        Ok(TypeInfo { var_tys, func_tys })
+        let strnum_vars = if pc.strnum_compare {
+            strnum_vars(pc, &var_tys)
+        } else {
+            Default::default()
+        };
+        Ok(TypeInfo {
+            var_tys,
+            func_tys,
+            strnum_vars,
+        })
     }
     fn solve(&mut self) -> Result<()> {
         let mut dep_indices: SmallVec<NodeIx> = Default::default();
@@ -813,7 +1034,7 @@ impl<'b, 'c> TypeContext<'b, 'c> {
             if !changed {
                 continue;
             }
-            if let Some(TVar::Map { .. }) = next {
+            if let Some(TVar::Map { .. } | TVar::NestedMap { .. }) = next {
                 // If we have a map node, then we need to make sure that anything that assigns to
                 // it winds up with the same type. Assignments are marked as `Flows` constraints;
                 // and we recorded the nodes that flow into `ix` in `dep_indices`. Now we just add
@@ -856,6 +1077,11 @@ impl<'b, 'c> TypeContext<'b, 'c> {
             }
         }
     }
+    /// Allocate a node with no initial constraints, for use in relating the types of a builtin's
+    /// arguments to one another.
+    pub(crate) fn fresh_var(&mut self) -> NodeIx {
+        self.nw.add_rule(Rule::Var)
+    }
     pub(crate) fn constrain_as_map(&mut self, ix: NodeIx) {
         // To be completely explicit, this function assigns a unique `Flows` constaint into a map
         // from the constant node that "just specifies the node is a Map".
@@ -1082,6 +1308,7 @@ impl<'b, 'c, 'd> View<'b, 'c, 'd> {
                 self.constrain_as_map(arr_ix);
                 self.nw.add_dep(arr_ix, ix_ix, Constraint::Key(()));
                 self.nw.add_dep(arr_ix, to, Constraint::Val(()));
+                self.nw.add_dep(to, arr_ix, Constraint::MapValIn(()));
             }
             IterBegin(arr) => {
                 let arr_ix = self.val_node(arr);
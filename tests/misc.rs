@@ -8,6 +8,15 @@ const BACKEND_ARGS: &'static [&'static str] = &["-binterp", "-bllvm", "-bcraneli
 #[cfg(not(feature = "llvm_backend"))]
 const BACKEND_ARGS: &'static [&'static str] = &["-binterp", "-bcranelift"];
 
+// Backends for parallel scripts that pass globals between stages. Cranelift is left out because of
+// a known issue: `call_inst` in src/codegen/clif.rs finds intrinsics in `external_funcs` by
+// function address, and that lookup fails for the slot intrinsics in release builds. Use
+// BACKEND_ARGS here once that lookup is fixed.
+#[cfg(feature = "llvm_backend")]
+const PARALLEL_SLOT_BACKEND_ARGS: &'static [&'static str] = &["-binterp", "-bllvm"];
+#[cfg(not(feature = "llvm_backend"))]
+const PARALLEL_SLOT_BACKEND_ARGS: &'static [&'static str] = &["-binterp"];
+
 // A simple function that looks for the "constant folded" regex instructions in the generated
 // output. This is a function that is possible to fool: test cases should be mindful of how it is
 // implemented to ensure it is testing what is intended.
@@ -73,6 +82,7 @@ BEGIN {
     BEGIN { x = "hi"; print("h" ~ x); }"#,
         );
         assert_folded(r#"BEGIN { x = "hi"; x = "there"; print("h" ~ x); }"#);
+        assert_folded(r#"BEGIN { IGNORECASE = 1; print("H" ~ /h/); }"#);
     }
 }
 
@@ -249,6 +259,27 @@ for (k in m) {
     }
 }
 
+#[test]
+fn iter_match_groups() {
+    let expected = "0 bc\n1 b\n2 c\n0\x1cstart 2\n0\x1clength 2\n1\x1cstart 2\n1\x1clength 1\n2\x1cstart 3\n2\x1clength 1\n";
+    let prog: String = r#"BEGIN {
+match("abc", /(b)(c)/, m);
+for (k in m) {
+    print k, m[k];
+}}"#
+    .into();
+    for backend_arg in BACKEND_ARGS {
+        let output = Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(prog.clone())
+            .output()
+            .unwrap()
+            .stdout;
+        unordered_output_equals(expected.as_bytes(), &output[..]);
+    }
+}
+
 #[test]
 fn iter_across_functions() {
     let input = ",,3,,4\n,,3,,6\n,,4,,5";
@@ -406,19 +437,675 @@ fn dont_reorder_files_with_f() {
     }
 }
 
+#[test]
+fn time_functions_honor_tz() {
+    let prog = r#"BEGIN {
+        print strftime("%F %T %Z %z", 0)
+        print mktime("1970 01 01 00 00 00")
+        print strptime("1970-01-01 00:00:00"), strptime("1970-01-01T00:00:00Z")
+        print strftime("%F %T %Z", 0, 1)
+    }"#;
+    // POSIX-style TZ values do not require a timezone database to be installed.
+    for (tz, expected) in [
+        (
+            "UTC0",
+            "1970-01-01 00:00:00 UTC +0000\n0\n0 0\n1970-01-01 00:00:00 UTC\n",
+        ),
+        (
+            "ABC+3",
+            "1969-12-31 21:00:00 ABC -0300\n10800\n10800 0\n1970-01-01 00:00:00 UTC\n",
+        ),
+    ] {
+        for backend_arg in BACKEND_ARGS {
+            Command::cargo_bin("frawk")
+                .unwrap()
+                .env("TZ", tz)
+                .arg(String::from(*backend_arg))
+                .arg(String::from(prog))
+                .assert()
+                .stdout(expected);
+        }
+    }
+}
+
+#[test]
+fn invalid_constant_format_rejected() {
+    for prog in [
+        r#"BEGIN { printf "%d%q\n", 1 }"#,
+        r#"BEGIN { x = sprintf("50%"); }"#,
+    ] {
+        for backend_arg in BACKEND_ARGS {
+            let assert = Command::cargo_bin("frawk")
+                .unwrap()
+                .arg(String::from(*backend_arg))
+                .arg(String::from(prog))
+                .assert()
+                .failure();
+            let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+            assert!(stderr.contains("invalid format string"), "{}", stderr);
+        }
+    }
+    // Format strings that are only known at runtime print malformed directives verbatim.
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from(
+                r#"BEGIN { pct = "%"; printf("50" pct "q %d\n", 1) }"#,
+            ))
+            .assert()
+            .stdout("50%q 1\n");
+    }
+}
+
+#[test]
+fn asort_string_keys_in_place() {
+    // Sorting a string-keyed array in place stores the indices as string keys.
+    for (prog, expected) in [
+        (
+            r#"BEGIN { m["x"] = "b"; m["y"] = "a"; n = asort(m); print n, m[1], m[2], ("x" in m); }"#,
+            "2 a b 0\n",
+        ),
+        (
+            r#"BEGIN { m["y"] = 1; m["x"] = 2; n = asorti(m); print n, m[1], m[2]; }"#,
+            "2 x y\n",
+        ),
+        (
+            r#"BEGIN { m["x"] = "b"; m["y"] = "a"; d["k"] = 1; n = asort(m, d); print n, d[1], d[2], ("k" in d); }"#,
+            "2 a b 0\n",
+        ),
+    ] {
+        for backend_arg in BACKEND_ARGS {
+            Command::cargo_bin("frawk")
+                .unwrap()
+                .arg(String::from(*backend_arg))
+                .arg(String::from(prog))
+                .assert()
+                .stdout(expected);
+        }
+    }
+}
+
+#[test]
+fn nested_iter_key_widening() {
+    // The first loop is typed while the inner keys of `m` are still inferred as integers; the
+    // later `m["x"]["b"]` widens them to strings. The loop iterators have to be widened along
+    // with them rather than rejected as incompatible.
+    let prog = r#"BEGIN { m["x"][1] = 1;
+for (j in m["x"]) n += (j == 1);
+m["x"]["b"] = 2;
+for (j in m["x"]) { n += (j == "b"); s += m["x"][j]; }
+print n, s; }"#;
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from(prog))
+            .assert()
+            .stdout("2 3\n");
+    }
+}
+
+#[test]
+fn indirect_call_unknown_function() {
+    let prog = r#"function f(x) { return x + 1 }
+{ name = $1; print @name(2) }"#;
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from(prog))
+            .write_stdin("f\n")
+            .assert()
+            .stdout("3\n");
+        let assert = Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from(prog))
+            .write_stdin("nosuchfn\n")
+            .assert()
+            .failure();
+        let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+        assert!(
+            stderr.contains("call to undefined function: nosuchfn"),
+            "{}",
+            stderr
+        );
+    }
+}
+
+#[test]
+fn switch_duplicate_default_rejected() {
+    let prog =
+        r#"{ switch ($1) { default: print "a"; case "x": print "x"; default: print "b"; } }"#;
+    for backend_arg in BACKEND_ARGS {
+        let assert = Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from(prog))
+            .write_stdin("x\n")
+            .assert()
+            .failure();
+        let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+        assert!(stderr.contains("duplicate default in switch"), "{}", stderr);
+    }
+}
+
+#[test]
+fn non_utf8_round_trip() {
+    let input: &[u8] = b"caf\xe9 \xff\xfe\x80\nlatin\xb1 x\n";
+    let prog = r#"{
+        print
+        printf "%s|%-6s|%.2s\n", $0, $1, $2
+        print_bytes sprintf("%s", $2), "\n"
+        print_bytes $1
+    }
+    END { printf "%c%c%c\n", 233, 177, 0x263a }"#;
+    let mut expected = Vec::new();
+    expected.extend_from_slice(b"caf\xe9 \xff\xfe\x80\n");
+    expected.extend_from_slice(b"caf\xe9 \xff\xfe\x80|caf\xe9  |\xff\xfe\n");
+    expected.extend_from_slice(b"\xff\xfe\x80\ncaf\xe9");
+    expected.extend_from_slice(b"latin\xb1 x\n");
+    expected.extend_from_slice(b"latin\xb1 x|latin\xb1|x\n");
+    expected.extend_from_slice(b"x\nlatin\xb1");
+    // %c writes values below 256 as a single byte, and larger code points as UTF-8.
+    expected.extend_from_slice(b"\xe9\xb1");
+    expected.extend_from_slice("\u{263a}\n".as_bytes());
+    let (_dir, data) = file_from_string("inputs", input);
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from(prog))
+            .arg(fname_to_string(&data))
+            .assert()
+            .stdout(expected.clone());
+    }
+}
+
+#[test]
+fn environ_serial_and_parallel() {
+    let prog = r#"BEGIN { ENVIRON["ADDED"] = "in begin"; }
+    { n += ($1 == ENVIRON["FRAWK_TEST_VAR"]); }
+    END { print n, ENVIRON["ADDED"], ENVIRON["FRAWK_TEST_VAR"], ("FRAWK_UNSET_VAR" in ENVIRON); }"#;
+    let mut text = String::default();
+    for i in 0..1000 {
+        text.push_str(if i % 10 == 0 { "hello\n" } else { "x\n" });
+    }
+    let (_dir, data) = file_from_string("inputs", &text);
+    for backend_arg in BACKEND_ARGS {
+        for par in &[None, Some("-pr")] {
+            if par.is_some() && !PARALLEL_SLOT_BACKEND_ARGS.contains(backend_arg) {
+                continue;
+            }
+            let mut cmd = Command::cargo_bin("frawk").unwrap();
+            cmd.env("FRAWK_TEST_VAR", "hello")
+                .env_remove("FRAWK_UNSET_VAR")
+                .arg(String::from(*backend_arg));
+            if let Some(par) = par {
+                cmd.arg(par).arg("-j2");
+            }
+            cmd.arg(String::from(prog))
+                .arg(fname_to_string(&data))
+                .assert()
+                .stdout("100 in begin hello 0\n");
+        }
+    }
+}
+
+#[test]
+fn environ_main_loop_parallel() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    // ENVIRON is only read in the main loop, so each worker populates it; values keep their bytes.
+    let prog = r#"{ n += ($1 == ENVIRON["FRAWK_TEST_VAR"]); } END { print n; }"#;
+    let mut text = Vec::new();
+    for i in 0..1000 {
+        text.extend_from_slice(if i % 10 == 0 { b"caf\xe9\n" } else { b"x\n" });
+    }
+    let (_dir, data) = file_from_string("inputs", &text);
+    for backend_arg in PARALLEL_SLOT_BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .env("FRAWK_TEST_VAR", OsStr::from_bytes(b"caf\xe9"))
+            .arg(String::from(*backend_arg))
+            .arg("-pr")
+            .arg("-j2")
+            .arg(String::from(prog))
+            .arg(fname_to_string(&data))
+            .assert()
+            .stdout("100\n");
+    }
+}
+
+#[test]
+fn subsep_assigned_and_parallel() {
+    // SUBSEP is a builtin variable: it can be set with -v, and its value is passed to each worker.
+    let (_dir, data) = file_from_string("inputs", "a b\nc d\na b\n");
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg("-vSUBSEP=-")
+            .arg(String::from(
+                r#"BEGIN { m[1,2] = 1; for (k in m) print k; }"#,
+            ))
+            .assert()
+            .stdout("1-2\n");
+        let output = Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg("-pr")
+            .arg("-j2")
+            .arg(String::from(
+                r#"BEGIN { SUBSEP = "/" } { m[$1,$2]++ } END { for (k in m) print k, m[k]; }"#,
+            ))
+            .arg(fname_to_string(&data))
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        unordered_output_equals(b"a/b 2\nc/d 1\n", &output.stdout[..]);
+    }
+}
+
+#[test]
+fn fixed_width_input() {
+    let prog = r#"{ print NF, $1, $3; n += $2 } END { print n }"#;
+    let (_dir, data) = file_from_string("inputs", "ab  12rest\ncd  34\nx\n");
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg("-ifixed:2,2:2,*")
+            .arg(String::from(prog))
+            .arg(fname_to_string(&data))
+            .assert()
+            .stdout("3 ab rest\n2 cd \n1 x \n46\n");
+    }
+    Command::cargo_bin("frawk")
+        .unwrap()
+        .arg("-ifixed:2,x")
+        .arg(String::from(prog))
+        .arg(fname_to_string(&data))
+        .assert()
+        .failure();
+}
+
+#[test]
+fn next_outside_main_loop_rejected() {
+    for prog in [
+        r#"function skip() { next } BEGIN { skip() }"#,
+        r#"function f() { g() } function g() { nextfile } END { f() }"#,
+        r#"END { next }"#,
+    ] {
+        for backend_arg in BACKEND_ARGS {
+            let assert = Command::cargo_bin("frawk")
+                .unwrap()
+                .arg(String::from(*backend_arg))
+                .arg(String::from(prog))
+                .write_stdin("x\n")
+                .assert()
+                .failure();
+            let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+            assert!(
+                stderr.contains("outside of the toplevel loop"),
+                "{}",
+                stderr
+            );
+        }
+    }
+}
+
+#[test]
+fn beginfile_endfile() {
+    let prog = r#"BEGINFILE { print "begin", FILENAME, FNR; if (FILENAME ~ /skip/) nextfile; }
+ENDFILE { print "end", FNR, $0 }
+{ n++ }
+END { print n, NR }"#;
+    let (_d1, f1) = file_from_string("one", "a\nb\n");
+    let (_d2, f2) = file_from_string("skip", "c\n");
+    let (_d3, f3) = file_from_string("three", "d\n");
+    let expected = format!(
+        "begin {} 0\nend 2 b\nbegin {} 0\nbegin {} 0\nend 1 d\n3 3\n",
+        fname_to_string(&f1),
+        fname_to_string(&f2),
+        fname_to_string(&f3),
+    );
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from(prog))
+            .arg(fname_to_string(&f1))
+            .arg(fname_to_string(&f2))
+            .arg(fname_to_string(&f3))
+            .assert()
+            .stdout(expected.clone());
+    }
+    Command::cargo_bin("frawk")
+        .unwrap()
+        .arg("-pr")
+        .arg(String::from(prog))
+        .arg(fname_to_string(&f1))
+        .assert()
+        .failure();
+}
+
+#[test]
+fn beginfile_endfile_parallel_files() {
+    let prog = r#"BEGINFILE { print "begin", FILENAME, FNR }
+ENDFILE { print "end", FILENAME, FNR }
+{ n++ }
+END { print n }"#;
+    let (_d1, f1) = file_from_string("one", "a\nb\n");
+    let (_d2, f2) = file_from_string("two", "c\n");
+    let (f1, f2) = (fname_to_string(&f1), fname_to_string(&f2));
+    // Files are processed by different workers, so their output may be interleaved.
+    let expected = format!(
+        "begin {0} 0\nend {0} 2\nbegin {1} 0\nend {1} 1\n3\n",
+        f1, f2
+    );
+    for backend_arg in PARALLEL_SLOT_BACKEND_ARGS {
+        let output = Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg("-pf")
+            .arg("-j2")
+            .arg(String::from(prog))
+            .arg(&f1)
+            .arg(&f2)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        unordered_output_equals(expected.as_bytes(), &output.stdout[..]);
+    }
+}
+
+#[test]
+fn beginfile_endfile_empty_file() {
+    let prog = r#"BEGINFILE { print "begin", FILENAME, FNR }
+ENDFILE { print "end", FILENAME, FNR }
+END { print NR }"#;
+    let (_d1, f1) = file_from_string("one", "a\nb\n");
+    let (_d2, f2) = file_from_string("empty", "");
+    let (_d3, f3) = file_from_string("three", "c\n");
+    let (f1, f2, f3) = (
+        fname_to_string(&f1),
+        fname_to_string(&f2),
+        fname_to_string(&f3),
+    );
+    let expected = format!(
+        "begin {0} 0\nend {0} 2\nbegin {1} 0\nend {1} 0\nbegin {2} 0\nend {2} 1\n3\n",
+        f1, f2, f3,
+    );
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from(prog))
+            .arg(&f1)
+            .arg(&f2)
+            .arg(&f3)
+            .assert()
+            .stdout(expected.clone());
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+#[test]
+fn coprocesses() {
+    // Read each line back from the coprocess as soon as it is written.
+    let interactive = r#"{ print toupper($0) |& "cat"; "cat" |& getline x; print NR, x }"#;
+    // Send all of the input, then half-close the coprocess to read the output.
+    let sorted = r#"{ print $2 |& "sort -n" }
+END { close("sort -n", "to"); while (("sort -n" |& getline x) > 0) printf "%s;", x; print "" }"#;
+    for (prog, expected) in [
+        (interactive, "1 B 10\n2 A 2\n3 C 1\n"),
+        (sorted, "1;2;10;\n"),
+    ] {
+        for backend_arg in BACKEND_ARGS {
+            Command::cargo_bin("frawk")
+                .unwrap()
+                .arg(String::from(*backend_arg))
+                .arg(String::from(prog))
+                .write_stdin("b 10\na 2\nc 1\n")
+                .assert()
+                .stdout(expected);
+        }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+#[test]
+fn close_exit_status() {
+    // The output of the commands goes to /dev/null so that the only output is the status codes.
+    let prog = r#"BEGIN {
+    print "x" | "cat >/dev/null; exit 3"
+    "echo y; exit 4" | getline y
+    print "z" |& "cat >/dev/null; exit 5"
+    print close("cat >/dev/null; exit 3"), close("echo y; exit 4"),
+        close("cat >/dev/null; exit 5"), close("cat >/dev/null; exit 3"), close("missing")
+}"#;
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from(prog))
+            .assert()
+            .stdout("3 4 5 -1 -1\n");
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+#[test]
+fn coprocess_half_close() {
+    // Closing the second end of a coprocess waits for it, after which it can be restarted.
+    let restart = r#"BEGIN {
+    cmd = "cat; exit 7";
+    print "a" |& cmd; close(cmd, "to"); cmd |& getline x; s = close(cmd, "from");
+    print "b" |& cmd; close(cmd, "to"); cmd |& getline y;
+    print x, y, s, close(cmd, "from");
+}"#;
+    // But an end cannot be reopened while the other is still open.
+    let reopen =
+        r#"BEGIN { print "a" |& "cat"; close("cat", "to"); print "b" |& "cat"; close("cat"); }"#;
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from(restart))
+            .assert()
+            .stdout("a b 7 7\n");
+        let assert = Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from(reopen))
+            .assert()
+            .failure();
+        let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+        assert!(stderr.contains("reopen a closed end"), "{}", stderr);
+    }
+}
+
+#[test]
+fn line_buffered_output() {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+    use std::sync::mpsc;
+    use std::time::Duration;
+    let prog = r#"{ print $2; printf "%s-", $1 } END { print "" }"#;
+    let (dir, data) = file_from_string("inputs", "a 1\nb 2\n");
+    for (i, backend_arg) in BACKEND_ARGS.iter().enumerate() {
+        // Each line of output has to arrive while stdin is still open.
+        let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("frawk"))
+            .arg(String::from(*backend_arg))
+            .arg("--line-buffered")
+            .arg(String::from(prog))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let (send, recv) = mpsc::channel();
+        let reader = std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                if send.send(line.unwrap()).is_err() {
+                    break;
+                }
+            }
+        });
+        let timeout = Duration::from_secs(10);
+        stdin.write_all(b"a 1\n").unwrap();
+        stdin.flush().unwrap();
+        assert_eq!(recv.recv_timeout(timeout).unwrap(), "1");
+        stdin.write_all(b"b 2\n").unwrap();
+        stdin.flush().unwrap();
+        assert_eq!(recv.recv_timeout(timeout).unwrap(), "a-2");
+        drop(stdin);
+        assert_eq!(recv.recv_timeout(timeout).unwrap(), "b-");
+        assert!(child.wait().unwrap().success());
+        reader.join().unwrap();
+
+        let out = dir.path().join(format!("out{}", i));
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg("--line-buffered")
+            .arg(format!("--out-file={}", fname_to_string(&out)))
+            .arg(String::from(prog))
+            .arg(fname_to_string(&data))
+            .assert()
+            .stdout("");
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "1\na-2\nb-\n");
+    }
+}
+
+#[test]
+fn char_semantics() {
+    let prog =
+        r#"{ print length($0), substr($0, 2, 3), index($0, "ü"), toupper($0), tolower("ÀÉ") }"#;
+    let (_dir, data) = file_from_string("inputs", "Müller-東京\n");
+    for flag in &["--chars", "--utf8"] {
+        for backend_arg in BACKEND_ARGS {
+            Command::cargo_bin("frawk")
+                .unwrap()
+                .arg(String::from(*backend_arg))
+                .arg(String::from(*flag))
+                .arg(String::from(prog))
+                .arg(fname_to_string(&data))
+                .assert()
+                .stdout("9 üll 2 MÜLLER-東京 àé\n");
+        }
+    }
+    // Without either flag, these builtins operate on bytes.
+    Command::cargo_bin("frawk")
+        .unwrap()
+        .arg(String::from(prog))
+        .arg(fname_to_string(&data))
+        .assert()
+        .stdout("14 ül 2 MüLLER-東京 ÀÉ\n");
+}
+
+#[test]
+fn include_directive() {
+    let tmp = tempdir().unwrap();
+    let write_file = |name: &str, contents: &str| {
+        let path = tmp.path().join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        File::create(&path)
+            .unwrap()
+            .write_all(contents.as_bytes())
+            .unwrap();
+        path
+    };
+    // helper.awk is found relative to the including file, and included only once.
+    write_file("lib/helper.awk", "function triple(x) { return 3 * x }\n");
+    write_file(
+        "lib/util.awk",
+        "@include \"helper.awk\"\nfunction double(x) { return 2 * x }\n",
+    );
+    write_file(
+        "path/pathlib.awk",
+        "@include \"../lib/util.awk\"\nfunction quad(x) { return 4 * x }\n",
+    );
+    write_file(
+        "bad.awk",
+        "function ok() { return 1 }\nfunction bad() { x = = 1 }\n",
+    );
+    let main = write_file(
+        "main.awk",
+        r#"@include "lib/util.awk"
+@include "lib/helper.awk"
+@include "pathlib.awk"
+BEGIN { print double(1), triple(1), quad(1) }
+"#,
+    );
+    let uses_bad = write_file("uses_bad.awk", "@include \"bad.awk\"\nBEGIN { print 1 }\n");
+    let unquoted = write_file("unquoted.awk", "BEGIN { print 1 }\n@include lib/util.awk\n");
+    let awk_path = fname_to_string(&tmp.path().join("path"));
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .env("AWKPATH", &awk_path)
+            .arg(String::from(*backend_arg))
+            .arg("-f")
+            .arg(fname_to_string(&main))
+            .assert()
+            .stdout("2 3 4\n");
+    }
+    // Errors are reported relative to the file that contains them.
+    let assert = Command::cargo_bin("frawk")
+        .unwrap()
+        .arg("-f")
+        .arg(fname_to_string(&uses_bad))
+        .assert()
+        .failure();
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("bad.awk, line 2, column 22"), "{}", stderr);
+    // The file name must be quoted.
+    let assert = Command::cargo_bin("frawk")
+        .unwrap()
+        .arg("-f")
+        .arg(fname_to_string(&unquoted))
+        .assert()
+        .failure();
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(
+        stderr.contains("unquoted.awk, line 2")
+            && stderr.contains("@include expects a quoted file name"),
+        "{}",
+        stderr
+    );
+    // AWKPATH is only consulted if it is set.
+    let assert = Command::cargo_bin("frawk")
+        .unwrap()
+        .env_remove("AWKPATH")
+        .arg("-f")
+        .arg(fname_to_string(&main))
+        .assert()
+        .failure();
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(
+        stderr.contains("could not find @include file \"pathlib.awk\""),
+        "{}",
+        stderr
+    );
+}
+
 fn fname_to_string(path: &std::path::PathBuf) -> String {
     path.clone().into_os_string().into_string().unwrap()
 }
 
 fn file_from_string(
     name: impl AsRef<str>,
-    s: impl AsRef<str>,
+    s: impl AsRef<[u8]>,
 ) -> (tempfile::TempDir, std::path::PathBuf) {
     let tmp = tempdir().unwrap();
     let file = tmp.path().join(name.as_ref());
     File::create(file.clone())
         .unwrap()
-        .write(s.as_ref().as_bytes())
+        .write(s.as_ref())
         .unwrap();
     (tmp, file)
 }